@@ -0,0 +1,161 @@
+use sdl2::VideoSubsystem;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::overlay::{draw_text, text_height};
+use crate::palette::Palette;
+use crate::tia::TIA;
+
+// The whole of each scanline is shown, HBLANK included, so that the beam can be seen wherever it is
+const CLOCKS: usize = 228;
+const HBLANK_CLOCKS: usize = 68;
+
+// Enough lines for a PAL frame, and a little over
+const LINES: usize = 320;
+
+const SCALE_X: i32 = 3;
+const SCALE_Y: i32 = 2;
+
+const HBLANK_COLOR: Color = Color { r: 24, g: 24, b: 24, a: 255 };
+const CROSSHAIR_COLOR: Color = Color { r: 255, g: 0, b: 255, a: 255 };
+
+// A second window that shows the frame as it's being built, with a crosshair where the beam is,
+// redrawn whenever the debugger stops, so it's easy to see exactly where the beam's got to when a
+// breakpoint's hit. Every scanline of the frame is shown, from the start of VSYNC, including the
+// HBLANK at the start of each one. The lines that the beam hasn't got to yet are the last frame's,
+// drawn dimmer.
+pub struct BeamWindow {
+    canvas: Canvas<Window>,
+    lines: BeamLines,
+}
+
+// The colour values of each scanline of the frame being built, and of the last frame
+struct BeamLines {
+    scanlines: Vec<Vec<u8>>,
+    last_frame: Vec<Vec<u8>>,
+}
+
+impl BeamLines {
+    // The visible pixels of a line of the window, with whether each one's from the last frame. The
+    // beam's on the line after the ones that have been built, as far as the given colour clock,
+    // and the pixels it's drawn so far are the ones the TIA's generated.
+    fn line(&self, y: usize, clock: usize, beam_pixels: &[u8]) -> Vec<(u8, bool)> {
+        let last = |x: usize| (self.last_frame.get(y).map_or(0, |pixels| pixels[x]), true);
+
+        (0 .. beam_pixels.len())
+            .map(|x| {
+                if y < self.scanlines.len() {
+                    (self.scanlines[y][x], false)
+                } else if y == self.scanlines.len() && x + HBLANK_CLOCKS < clock {
+                    (beam_pixels[x], false)
+                } else {
+                    last(x)
+                }
+            })
+            .collect()
+    }
+}
+
+impl BeamWindow {
+    pub fn new(video_subsystem: &VideoSubsystem) -> Self {
+        let window = video_subsystem.window("atari2600 beam", CLOCKS as u32 * SCALE_X as u32, LINES as u32 * SCALE_Y as u32)
+            .build()
+            .unwrap();
+
+        Self {
+            canvas: window.into_canvas().build().unwrap(),
+            lines: BeamLines {
+                scanlines: vec![],
+                last_frame: vec![],
+            },
+        }
+    }
+
+    pub fn push_scanline(&mut self, pixels: &[u8]) {
+        self.lines.scanlines.push(pixels.to_vec());
+    }
+
+    // Goes back to an earlier scanline, along with the frame, when the debugger steps backwards
+    pub fn rewind(&mut self, scanlines: usize) {
+        self.lines.scanlines.truncate(scanlines);
+    }
+
+    pub fn end_frame(&mut self) {
+        self.lines.last_frame = std::mem::take(&mut self.lines.scanlines);
+    }
+
+    // Draws the frame with the given number of scanlines generated, which can be fewer than it's
+    // been given when the debugger's gone back to an earlier one
+    pub fn draw(&mut self, tia: &TIA, palette: &Palette, scanlines: usize) {
+        self.rewind(scanlines);
+
+        let position = tia.position();
+        let clock = position.clock as usize;
+        let beam_line = self.lines.scanlines.len();
+
+        self.canvas.set_draw_color(HBLANK_COLOR);
+        self.canvas.clear();
+
+        for y in 0 .. LINES {
+            let line = self.lines.line(y, clock, tia.get_scanline_pixels());
+
+            // Runs of the same colour are drawn as one rectangle, as there are plenty of them
+            let mut x = 0;
+            while x < line.len() {
+                let run = line[x ..].iter().take_while(|&&pixel| pixel == line[x]).count();
+
+                let (value, dim) = line[x];
+                let color = palette.color(value);
+                self.canvas.set_draw_color(if dim { Color::RGB(color.r / 2, color.g / 2, color.b / 2) } else { color });
+                self.canvas.fill_rect(Rect::new((HBLANK_CLOCKS + x) as i32 * SCALE_X, y as i32 * SCALE_Y,
+                                                run as u32 * SCALE_X as u32, SCALE_Y as u32)).unwrap();
+
+                x += run;
+            }
+        }
+
+        let (width, height) = self.canvas.output_size().unwrap();
+        let (beam_x, beam_y) = (clock as i32 * SCALE_X, beam_line as i32 * SCALE_Y);
+
+        self.canvas.set_draw_color(CROSSHAIR_COLOR);
+        self.canvas.fill_rect(Rect::new(beam_x, 0, 1, height)).unwrap();
+        self.canvas.fill_rect(Rect::new(0, beam_y, width, 1)).unwrap();
+        self.canvas.fill_rect(Rect::new(beam_x - SCALE_X, beam_y - SCALE_Y, 3 * SCALE_X as u32, 3 * SCALE_Y as u32)).unwrap();
+
+        let label = format!("SCANLINE {} CLOCK {}", beam_line, clock);
+        let top = height as i32 - text_height(2) - 4;
+        draw_text(&mut self.canvas, 4, top, 2, &label, Color::RGB(255, 255, 255));
+
+        self.canvas.present();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line() {
+        let mut lines = vec![];
+        for y in 0 .. 3 {
+            lines.push(vec![y as u8; 160]);
+        }
+
+        // The BeamWindow's canvas needs a window, so the lines are worked out without one
+        let beam = BeamLines { scanlines: lines[.. 1].to_vec(), last_frame: lines.clone() };
+        let beam_pixels = vec![9; 160];
+
+        assert_eq!(beam.line(0, 100, &beam_pixels)[0], (0, false));
+        assert_eq!(beam.line(2, 100, &beam_pixels)[0], (2, true));
+
+        // The beam's line is new up to the beam, and the last frame's after it
+        let line = beam.line(1, HBLANK_CLOCKS + 10, &beam_pixels);
+        assert_eq!(line[9], (9, false));
+        assert_eq!(line[10], (1, true));
+
+        // There's nothing to show past the end of the last frame
+        assert_eq!(beam.line(5, 100, &beam_pixels)[0], (0, true));
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::movie::{letters, Input, Movie};
+use crate::riot::Switches;
+
+// The buttons in BizHawk's Atari 2600 input log, in the order they're logged, with the console's
+// buttons first and then each joystick's
+const LOG_KEY: &str = "LogKey:#Reset|Select|Power|Toggle Left Difficulty|Toggle Right Difficulty|\
+                       #P1 Up|P1 Down|P1 Left|P1 Right|P1 Button|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Button|";
+
+const CONSOLE: [char; 5] = ['r', 's', 'P', 'l', 'r'];
+const JOYSTICK: [char; 5] = ['U', 'D', 'L', 'R', 'B'];
+
+// BizHawk's input log, with a line for each frame like `|r....|U...B|.....|`. Its difficulty
+// switches are buttons that flip them, rather than where they are, so they're pressed on the frames
+// where they've changed. BizHawk has no switch for the TV type, so it's left out.
+fn input_log(movie: &Movie) -> String {
+    let mut log = format!("[Input]\n{}\n", LOG_KEY);
+    let mut difficulties = movie.frames().first().map(|input| {
+        (input.switches.p0_difficulty_a, input.switches.p1_difficulty_a)
+    });
+
+    for input in movie.frames() {
+        let switches = input.switches;
+        let (p0, p1) = difficulties.unwrap_or((switches.p0_difficulty_a, switches.p1_difficulty_a));
+        difficulties = Some((switches.p0_difficulty_a, switches.p1_difficulty_a));
+
+        let console = letters(&CONSOLE, [
+            switches.reset,
+            switches.select,
+            false,
+            switches.p0_difficulty_a != p0,
+            switches.p1_difficulty_a != p1,
+        ]);
+
+        let joystick = |player: usize| letters(&JOYSTICK, input.joystick(player));
+        log.push_str(&format!("|{}|{}|{}|\n", console, joystick(0), joystick(1)));
+    }
+
+    log.push_str("[/Input]\n");
+    log
+}
+
+// The input for each frame of a log, starting with the switches where they're set at `initial`.
+// Power isn't supported, and is left out.
+fn parse_input_log(log: &str, initial: Switches) -> Result<Vec<Input>, String> {
+    let mut switches = initial;
+    let mut frames = vec![];
+
+    for (i, line) in log.lines().filter(|line| line.starts_with('|')).enumerate() {
+        let groups = line.trim_matches('|').split('|')
+            .map(|group| group.chars().map(|c| c != '.').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        if groups.len() != 3 || groups.iter().any(|group| group.len() != 5) {
+            return Err(format!("frame {}: expected the console and two joysticks, not {:?}", i, line));
+        }
+
+        let console = &groups[0];
+        switches.reset = console[0];
+        switches.select = console[1];
+        switches.p0_difficulty_a ^= console[3];
+        switches.p1_difficulty_a ^= console[4];
+
+        let mut input = Input { pins: [0x0f; 2], fire: [true; 2], switches: switches };
+        input.set_joystick(0, &groups[1]);
+        input.set_joystick(1, &groups[2]);
+        frames.push(input);
+    }
+
+    Ok(frames)
+}
+
+// Writes a movie out as a BizHawk movie (a .bk2 file), which is a zip file with the input log in
+// it, along with a header naming the platform and the game. BizHawk starts its movies from power
+// on, so the movie's state isn't in it.
+pub fn export(movie: &Movie, path: &str) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("Header.txt", options)?;
+    write!(zip, "MovieVersion BizHawk v2.0.0\nPlatform A26\nCore Atari2600Hawk\nMD5 {}\nrerecordCount 0\n", movie.rom_md5())?;
+
+    zip.start_file("Input Log.txt", options)?;
+    zip.write_all(input_log(movie).as_bytes())?;
+
+    zip.finish()?;
+    Ok(())
+}
+
+// Reads a BizHawk movie in, to play back from `state`, which should be the machine at power on.
+// The movie's for the game with the MD5 in its header, or for `rom_md5` if it doesn't have one.
+pub fn import(path: &str, rom_md5: &str, state: Vec<u8>, switches: Switches) -> io::Result<Movie> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    let mut header = String::new();
+    zip.by_name("Header.txt")?.read_to_string(&mut header)?;
+    let md5 = header.lines()
+        .find_map(|line| line.strip_prefix("MD5 "))
+        .map_or(rom_md5, |md5| md5.trim());
+
+    let mut log = String::new();
+    zip.by_name("Input Log.txt")?.read_to_string(&mut log)?;
+    let frames = parse_input_log(&log, switches).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut movie = Movie::new(&md5.to_lowercase(), state);
+    for input in frames {
+        movie.record(input);
+    }
+
+    Ok(movie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWITCHES: Switches = Switches {
+        select: false,
+        reset: false,
+        color: true,
+        p0_difficulty_a: true,
+        p1_difficulty_a: true,
+    };
+
+    #[test]
+    fn test_input_log() {
+        let mut movie = Movie::new("0123456789abcdef0123456789abcdef", vec![]);
+        movie.record(Input { pins: [0b1110, 0b1111], fire: [true, false], switches: SWITCHES });
+        movie.record(Input { pins: [0b0111, 0b1111], fire: [false, true], switches: Switches { reset: true, p0_difficulty_a: false, ..SWITCHES } });
+
+        let log = input_log(&movie);
+        assert_eq!(log, format!("[Input]\n{}\n|.....|U....|....B|\n|r..l.|...RB|.....|\n[/Input]\n", LOG_KEY));
+
+        let frames = parse_input_log(&log, SWITCHES).unwrap();
+        assert_eq!(frames, movie.frames());
+
+        assert!(parse_input_log("|.....|U....|\n", SWITCHES).is_err());
+    }
+
+    #[test]
+    fn test_export_import() {
+        let path = std::env::temp_dir().join(format!("atari2600-movie-{}.bk2", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut movie = Movie::new("0123456789abcdef0123456789abcdef", vec![]);
+        movie.record(Input { pins: [0b1011, 0b1111], fire: [true, true], switches: SWITCHES });
+        export(&movie, path).unwrap();
+
+        let imported = import(path, "fedcba9876543210fedcba9876543210", vec![0x42], SWITCHES).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(imported.rom_md5(), movie.rom_md5());
+        assert_eq!(imported.state(), &[0x42]);
+        assert_eq!(imported.frames(), movie.frames());
+    }
+}
@@ -1,6 +1,5 @@
 use std::cell::RefCell;
-use std::io;
-use std::fs::File;
+use std::io::{self, Read, Write};
 use std::rc::Rc;
 
 use crate::riot::RIOT;
@@ -9,14 +8,46 @@ use crate::tia::TIA;
 pub trait Bus {
     fn read(&mut self, _address: u16) -> u8 { 0 }
     fn write(&mut self, _address: u16, _val: u8) { }
-    fn save(&self, _output: &mut File) -> io::Result<()> { Ok(()) }
-    fn load(&mut self, _input: &mut File) -> io::Result<()> { Ok(()) }
+    // Reads without any of the side effects that reading can have, e.g. for a debugger
+    fn peek(&self, _address: u16) -> u8 { 0 }
+    // The levels of the IRQ and NMI lines. The 6507 doesn't have them, so nothing in the console
+    // drives them, but hardware on the bus could
+    fn irq(&self) -> bool { false }
+    fn nmi(&self) -> bool { false }
+    fn save(&self, _output: &mut dyn Write) -> io::Result<()> { Ok(()) }
+    fn load(&mut self, _input: &mut dyn Read) -> io::Result<()> { Ok(()) }
+}
+
+// Reads the next `N` bytes of a saved state
+pub fn read_bytes<const N: usize>(input: &mut dyn Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// So that a boxed up bus, e.g. a `Box<dyn Bus>`, can be used wherever a bus can
+impl<B: Bus + ?Sized> Bus for Box<B> {
+    fn read(&mut self, address: u16) -> u8 { (**self).read(address) }
+    fn write(&mut self, address: u16, val: u8) { (**self).write(address, val) }
+    fn peek(&self, address: u16) -> u8 { (**self).peek(address) }
+    fn irq(&self) -> bool { (**self).irq() }
+    fn nmi(&self) -> bool { (**self).nmi() }
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> { (**self).save(output) }
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> { (**self).load(input) }
 }
 
 pub struct AtariBus {
     rom: Vec<u8>,
     tia: Rc<RefCell<TIA>>,
     riot: Rc<RefCell<RIOT>>,
+
+    // The last value driven on the data bus. Chips that don't drive all 8 data lines on a read
+    // leave the undriven bits floating at whatever was last on the bus.
+    data_bus: u8,
+
+    // Called with the address and value of each read and write that the CPU makes
+    read_fn: Option<Box<dyn Fn(u16, u8)>>,
+    write_fn: Option<Box<dyn Fn(u16, u8)>>,
 }
 
 impl AtariBus {
@@ -25,8 +56,27 @@ impl AtariBus {
             rom: rom,
             tia: tia,
             riot: riot,
+            data_bus: 0,
+            read_fn: None,
+            write_fn: None,
         }
     }
+
+    pub fn set_read_fn(&mut self, read_fn: impl Fn(u16, u8) + 'static) {
+        self.read_fn = Some(Box::new(read_fn));
+    }
+
+    pub fn set_write_fn(&mut self, write_fn: impl Fn(u16, u8) + 'static) {
+        self.write_fn = Some(Box::new(write_fn));
+    }
+
+    // The TIA only drives D7 and D6 when read, and nothing at all at the two addresses that don't
+    // have a register ($0E and $0F), so the rest of the bits are open bus, and are left over from
+    // the last value on the data bus
+    fn open_bus(&self, address: u16, val: u8) -> u8 {
+        let driven = if address & 0x0f >= 0x0e { 0b0000_0000 } else { 0b1100_0000 };
+        (val & driven) | (self.data_bus & !driven)
+    }
 }
 
 impl Bus for AtariBus {
@@ -37,7 +87,7 @@ impl Bus for AtariBus {
         let a9  = (address & 0b0000_0010_0000_0000) != 0;
         let a7  = (address & 0b0000_0000_1000_0000) != 0;
 
-        match (a12, a9, a7) {
+        let val = match (a12, a9, a7) {
             // Cartridge memory is selected by A12=1
             (true, _, _)         => self.rom[address as usize & 0xfff],
             // PIA I/O is selected by A12=0, A9=1, A7=1
@@ -45,13 +95,26 @@ impl Bus for AtariBus {
             // PIA RAM is selected by A12=0, A9=0, A7=1
             (false, false, true) => self.riot.borrow_mut().read(address & 0x7f),
             // The TIA chip is addressed by A12=0, A7=0
-            (false, _, false)    => self.tia.borrow_mut().read((address & 0x0f) | 0x30),
+            (false, _, false)    => {
+                let val = self.tia.borrow_mut().read(address & 0x3f);
+                self.open_bus(address, val)
+            },
+        };
+
+        self.data_bus = val;
+
+        if let Some(read_fn) = &self.read_fn {
+            read_fn(address, val);
         }
+
+        val
     }
 
     fn write(&mut self, address: u16, val: u8) {
         // https://problemkaputt.de/2k6specs.htm#memorymirrors
 
+        self.data_bus = val;
+
         let a12 = (address & 0b0001_0000_0000_0000) != 0;
         let a9  = (address & 0b0000_0010_0000_0000) != 0;
         let a7  = (address & 0b0000_0000_1000_0000) != 0;
@@ -66,5 +129,162 @@ impl Bus for AtariBus {
             // The TIA chip is addressed by A12=0, A7=0
             (false, _, false)    => self.tia.borrow_mut().write(address & 0x3f, val),
         }
+
+        if let Some(write_fn) = &self.write_fn {
+            write_fn(address, val);
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let a12 = (address & 0b0001_0000_0000_0000) != 0;
+        let a9  = (address & 0b0000_0010_0000_0000) != 0;
+        let a7  = (address & 0b0000_0000_1000_0000) != 0;
+
+        match (a12, a9, a7) {
+            (true, _, _)         => self.rom[address as usize & 0xfff],
+            (false, true, true)  => self.riot.borrow().peek(address & 0x2ff),
+            (false, false, true) => self.riot.borrow().peek(address & 0x7f),
+            (false, _, false)    => {
+                let val = self.tia.borrow().peek(address & 0x3f);
+                self.open_bus(address, val)
+            },
+        }
+    }
+
+    // The last value on the data bus, then the TIA, then the RIOT
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.data_bus])?;
+        self.tia.borrow().save(output)?;
+        self.riot.borrow().save(output)
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let mut data_bus = [0];
+        input.read_exact(&mut data_bus)?;
+        self.data_bus = data_bus[0];
+
+        self.tia.borrow_mut().load(input)?;
+        self.riot.borrow_mut().load(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::cpu6507::CPU6507;
+
+    #[test]
+    fn test_tia_read_open_bus() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let mut bus = AtariBus::new(tia, riot, vec![0; 4096]);
+
+        // INPT4 drives D7 high, and the rest of the bits are left over from the last write
+        bus.write(0x0080, 0xff);
+        assert_eq!(bus.read(0x000c), 0xbf);
+
+        bus.write(0x0080, 0x15);
+        assert_eq!(bus.read(0x000c), 0x95);
+
+        // Reading the collision registers also leaves the low bits alone
+        assert_eq!(bus.read(0x0000), 0x15);
+
+        // And the addresses without a register are all open bus
+        bus.write(0x0080, 0xc3);
+        assert_eq!((bus.peek(0x000e), bus.read(0x001f)), (0xc3, 0xc3));
+    }
+
+    #[test]
+    fn test_tia_read_mirrors() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let mut bus = AtariBus::new(tia, riot, vec![0; 4096]);
+
+        // INPT4 is readable at every address with the low 4 bits set to $C, as long as A12 and A7
+        // are both 0
+        for &address in [0x000c, 0x001c, 0x002c, 0x003c, 0x004c, 0x007c, 0x020c, 0x0c3c, 0x0f7c].iter() {
+            bus.write(0x0080, 0x00);
+            assert_eq!(bus.read(address), 0x80, "INPT4 at {:04x}", address);
+        }
+    }
+
+    #[test]
+    fn test_access_fns() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let mut bus = AtariBus::new(tia, riot, vec![0; 4096]);
+
+        let accesses = Rc::new(RefCell::new(vec![]));
+        let reads = accesses.clone();
+        bus.set_read_fn(move |address, value| reads.borrow_mut().push(('r', address, value)));
+        let writes = accesses.clone();
+        bus.set_write_fn(move |address, value| writes.borrow_mut().push(('w', address, value)));
+
+        // Peeking isn't an access
+        bus.write(0x0080, 0x42);
+        bus.read(0x0080);
+        bus.peek(0x0080);
+        assert_eq!(*accesses.borrow(), vec![('w', 0x0080, 0x42), ('r', 0x0080, 0x42)]);
+    }
+
+    #[test]
+    fn test_stack_in_riot_ram() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+
+        // LDA #$42; PHA; JSR $F010, which does LDA $FE; RTS
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x006].copy_from_slice(&[0xa9, 0x42, 0x48, 0x20, 0x10, 0xf0]);
+        rom[0x010 .. 0x013].copy_from_slice(&[0xa5, 0xfe, 0x60]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let bus = AtariBus::new(tia, riot.clone(), rom);
+        let mut cpu = CPU6507::new(bus);
+        cpu.reset();
+
+        for _ in 0 .. 3 {
+            cpu.step();
+        }
+
+        // The stack at $01FF down lands in the top of the RIOT's RAM, the same as $00FF down
+        let mut riot = riot.borrow_mut();
+        assert_eq!((riot.read(0x7f), riot.read(0x7e), riot.read(0x7d)), (0x42, 0xf0, 0x05));
+        drop(riot);
+
+        // Which zero page instructions see too
+        cpu.step();
+        assert_eq!(cpu.a, 0xf0);
+
+        cpu.step();
+        assert_eq!(cpu.pc, 0xf006);
+    }
+
+    #[test]
+    fn test_indexed_store_dummy_read() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+
+        // LDX #$80; STA $0204,X, which reads INTIM before writing to the same address
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x005].copy_from_slice(&[0xa2, 0x80, 0x9d, 0x04, 0x02]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let bus = AtariBus::new(tia, riot.clone(), rom);
+        let mut cpu = CPU6507::new(bus);
+        cpu.reset();
+
+        // Underflow the timer, setting the timer flag
+        riot.borrow_mut().write(0x0294, 0x00);
+        riot.borrow_mut().clock();
+        riot.borrow_mut().clock();
+
+        cpu.step();
+        cpu.step();
+
+        // Reading INTIM clears the timer flag
+        assert_eq!(riot.borrow_mut().read(0x0285), 0x00);
     }
 }
@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io;
+
+use gif::{EncodingError, Encoder, Frame, Repeat};
+
+use crate::palette::Palette;
+
+// The last few seconds of the picture, kept as the colour values of each frame, for writing out as
+// an animated GIF to share a bug or a game's progress
+pub struct Clip {
+    frames: VecDeque<Vec<Vec<u8>>>,
+    length: usize,
+}
+
+impl Clip {
+    // `length` is how many frames are kept, after which the oldest are dropped
+    pub fn new(length: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(length),
+            length: length,
+        }
+    }
+
+    // The frames a second change with the region, and so does how many it takes to fill the clip
+    pub fn set_length(&mut self, length: usize) {
+        self.length = length;
+        while self.frames.len() > length {
+            self.frames.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, picture: Vec<Vec<u8>>) {
+        if self.frames.len() >= self.length {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(picture);
+    }
+
+    // Writes the frames out, returning how many there were. The colour values are the indexes into
+    // the GIF's palette, which is the TIA's palette as it is now, and each pixel's doubled across,
+    // to be near enough the shape it is on a TV. GIFs are timed in hundredths of a second, so
+    // they're a little slower than the game at 60 frames a second.
+    pub fn write(&self, path: &str, palette: &Palette, fps: f64) -> io::Result<usize> {
+        let colors = (0 ..= 255)
+            .flat_map(|value| {
+                let color = palette.color(value);
+                vec![color.r, color.g, color.b]
+            })
+            .collect::<Vec<_>>();
+
+        let width = 160 * 2;
+        let height = self.frames.iter().map(|picture| picture.len()).max().unwrap_or(0);
+
+        let mut encoder = Encoder::new(File::create(path)?, width as u16, height as u16, &colors).map_err(io_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(io_error)?;
+
+        for picture in &self.frames {
+            let pixels = picture.iter()
+                .flat_map(|line| line.iter().flat_map(|&value| vec![value, value]))
+                .collect::<Vec<_>>();
+
+            encoder.write_frame(&Frame {
+                width: width as u16,
+                height: picture.len() as u16,
+                delay: (100.0 / fps).round() as u16,
+                buffer: Cow::Owned(pixels),
+                ..Frame::default()
+            }).map_err(io_error)?;
+        }
+
+        Ok(self.frames.len())
+    }
+}
+
+fn io_error(e: EncodingError) -> io::Error {
+    match e {
+        EncodingError::Io(e) => e,
+        e                    => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gif::{ColorOutput, DecodeOptions};
+
+    use crate::region::Region;
+
+    #[test]
+    fn test_clip() {
+        let path = std::env::temp_dir().join(format!("atari2600-clip-{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        // Only the last two frames are kept
+        let mut clip = Clip::new(2);
+        for value in &[0x0e, 0x44, 0x86] {
+            clip.push(vec![vec![*value; 160]; 3]);
+        }
+
+        let palette = Palette::for_region(Region::NTSC);
+        assert_eq!(clip.write(path, &palette, 60.0).unwrap(), 2);
+
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(path).unwrap()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (320, 3));
+
+        let color = palette.color(0x44);
+        assert_eq!(&decoder.global_palette().unwrap()[0x44 * 3 .. 0x44 * 3 + 3], &[color.r, color.g, color.b]);
+
+        let mut frames = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 2);
+            frames.push(frame.buffer.to_vec());
+        }
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(frames, vec![vec![0x44; 320 * 3], vec![0x86; 320 * 3]]);
+
+        // Shortening it drops the oldest frames
+        clip.set_length(1);
+        assert_eq!(clip.frames, vec![vec![vec![0x86; 160]; 3]]);
+    }
+}
@@ -1,104 +1,61 @@
-use std::env;
-use std::process;
+mod opcodes;
+#[cfg(test)]
+mod processor_tests;
 
-use crate::bus::Bus;
+use std::fmt;
+use std::str::FromStr;
+use std::io::{self, Read, Write};
+
+use crate::bus::{read_bytes, Bus};
+use crate::disasm::Line;
+
+pub use self::opcodes::{documented, AddressingMode, Instruction, Opcode, OPCODES};
 
 const STACK_INIT: u8 = 0xff;
 
-lazy_static!{
-    static ref CPU6507_DEBUG: bool = match env::var("CPU6507_DEBUG") {
-        Ok(val) => val != "" && val != "0",
-        Err(_)  => false,
-    };
-}
+// The stack is in page one, like any other 6502. On the 2600, that's a mirror of page zero, so the
+// stack ends up in the RIOT's RAM at $80-$FF.
+const STACK_PAGE: u16 = 0x0100;
 
-#[derive(Copy, Clone, Debug)]
-pub enum Instruction {
-    None,
-    ADC, ANC, AND, ASL, BCC, BCS, BEQ, BIT,
-    BMI, BNE, BPL, BRK, BVC, BVS, CLC, CLD,
-    CLI, CLV, CMP, CPX, CPY, DCP, DEC, DEX,
-    DEY, EOR, INC, INX, INY, ISB, JAM, JMP,
-    JSR, LAX, LDA, LDX, LDY, LSR, NOP, ORA,
-    PHA, PHP, PLA, PLP, RLA, ROL, ROR, RRA,
-    RTI, RTS, SAX, SBC, SEC, SED, SEI, SLO,
-    SRE, STA, STX, STY, TAX, TAY, TSX, TXA,
-    TXS, TYA,
-}
+const NMI_VECTOR: u16 = 0xfffa;
+const IRQ_VECTOR: u16 = 0xfffe;
 
-#[derive(Copy, Clone, Debug)]
-pub enum AddressingMode {
-    None,
-    Immediate,
-    Absolute,
-    Implied,
-    Accumulator,
-    AbsoluteX,
-    AbsoluteY,
-    ZeroPageIndexed,
-    ZeroPageX,
-    ZeroPageY,
-    Indirect,
-    IndexedIndirect,
-    IndirectIndexed,
-    Relative,
-}
+// Taking an interrupt takes as long as BRK
+const INTERRUPT_CYCLES: u64 = 7;
 
 fn pages_differ(addr_a: u16, addr_b: u16) -> bool {
     (addr_a & 0xff00) != (addr_b & 0xff00)
 }
 
 impl AddressingMode {
-    pub fn n_bytes(&self) -> usize {
-        match *self {
-              AddressingMode::Implied
-            | AddressingMode::Accumulator => 1,
-
-              AddressingMode::Immediate
-            | AddressingMode::ZeroPageIndexed
-            | AddressingMode::Relative
-            | AddressingMode::ZeroPageX
-            | AddressingMode::ZeroPageY
-            | AddressingMode::IndexedIndirect
-            | AddressingMode::IndirectIndexed => 2,
-
-              AddressingMode::Absolute
-            | AddressingMode::AbsoluteX
-            | AddressingMode::AbsoluteY
-            | AddressingMode::Indirect => 3,
-
-            _ => panic!("Bad addressing mode {:?}", *self),
-        }
-    }
-
-    pub fn get_bytes(&self, cpu: &mut CPU6507) -> Vec<u8> {
+    pub fn get_bytes<B: Bus>(&self, cpu: &CPU6507<B>) -> Vec<u8> {
         let n_bytes = self.n_bytes() as u16;
-        (0 .. n_bytes).map(|n| cpu.read(cpu.pc + n)).collect::<Vec<_>>()
+        (0 .. n_bytes).map(|n| cpu.peek(cpu.pc.wrapping_add(n))).collect::<Vec<_>>()
     }
 
-    pub fn get_data(&self, cpu: &mut CPU6507) -> (u16, bool) {
+    pub fn get_data<B: Bus>(&self, cpu: &mut CPU6507<B>) -> (u16, bool) {
         let pc = cpu.pc;
-        let next_pc = cpu.pc + self.n_bytes() as u16;
+        let next_pc = cpu.pc.wrapping_add(self.n_bytes() as u16);
 
         match *self {
             AddressingMode::Immediate => {
-                let addr = pc + 1;
+                let addr = pc.wrapping_add(1);
                 (addr, false)
             },
             AddressingMode::Absolute => {
-                let lo = cpu.read(pc + 1) as u16;
-                let hi = cpu.read(pc + 2) as u16;
+                let lo = cpu.read(pc.wrapping_add(1)) as u16;
+                let hi = cpu.read(pc.wrapping_add(2)) as u16;
                 let addr = (hi << 8) | lo;
                 (addr, false)
             },
             AddressingMode::Implied => (0, false),
             AddressingMode::Accumulator => (0, false),
             AddressingMode::ZeroPageIndexed => {
-                let addr = cpu.read(pc + 1) as u16;
+                let addr = cpu.read(pc.wrapping_add(1)) as u16;
                 (addr, false)
             },
             AddressingMode::Relative => {
-                let offset = cpu.read(pc + 1) as u16;
+                let offset = cpu.read(pc.wrapping_add(1)) as u16;
 
                 // NOTE This has to be based off the program counter, _after_
                 // it has been advanced, but before the instruction is
@@ -108,22 +65,22 @@ impl AddressingMode {
                 (((next_pc as i16) + (offset as i8 as i16)) as u16, false)
             },
             AddressingMode::AbsoluteX => {
-                let lo = cpu.read(pc + 1) as u16;
-                let hi = cpu.read(pc + 2) as u16;
+                let lo = cpu.read(pc.wrapping_add(1)) as u16;
+                let hi = cpu.read(pc.wrapping_add(2)) as u16;
                 let addr = (hi << 8) | lo;
                 let n_addr = addr.wrapping_add(cpu.x as u16);
                 (n_addr, pages_differ(addr, n_addr))
             },
             AddressingMode::AbsoluteY => {
-                let lo = cpu.read(pc + 1) as u16;
-                let hi = cpu.read(pc + 2) as u16;
+                let lo = cpu.read(pc.wrapping_add(1)) as u16;
+                let hi = cpu.read(pc.wrapping_add(2)) as u16;
                 let addr = (hi << 8) | lo;
                 let n_addr = addr.wrapping_add(cpu.y as u16);
                 (n_addr, pages_differ(addr, n_addr))
             },
             AddressingMode::Indirect => {
-                let lo = cpu.read(pc + 1) as u16;
-                let hi = cpu.read(pc + 2) as u16;
+                let lo = cpu.read(pc.wrapping_add(1)) as u16;
+                let hi = cpu.read(pc.wrapping_add(2)) as u16;
                 let addr = (hi << 8) | lo;
 
                 let lo = cpu.read(addr) as u16;
@@ -140,17 +97,17 @@ impl AddressingMode {
                 (addr, false)
             }
             AddressingMode::ZeroPageX => {
-                let addr = cpu.read(pc + 1)
+                let addr = cpu.read(pc.wrapping_add(1))
                     .wrapping_add(cpu.x) as u16;
                 (addr, false)
             },
             AddressingMode::ZeroPageY => {
-                let addr = cpu.read(pc + 1)
+                let addr = cpu.read(pc.wrapping_add(1))
                     .wrapping_add(cpu.y) as u16;
                 (addr, false)
             },
             AddressingMode::IndexedIndirect => {
-                let lo = cpu.read(pc + 1);
+                let lo = cpu.read(pc.wrapping_add(1));
                 let addr = lo.wrapping_add(cpu.x) as u16;
 
                 let lo = cpu.read(addr) as u16;
@@ -166,7 +123,7 @@ impl AddressingMode {
                 (addr, false)
             },
             AddressingMode::IndirectIndexed => {
-                let addr = cpu.read(pc + 1) as u16;
+                let addr = cpu.read(pc.wrapping_add(1)) as u16;
 
                 let lo = cpu.read(addr) as u16;
 
@@ -182,309 +139,120 @@ impl AddressingMode {
 
                 (n_addr, pages_differ(addr, n_addr))
             },
+        }
+    }
+}
+
+// The state of the CPU as it starts an instruction, passed to the trace function
+#[derive(Clone)]
+pub struct TraceRecord {
+    pub pc: u16,
+    // The opcode and its operands
+    pub bytes: Vec<u8>,
+
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+
+    // The total number of cycles executed before this instruction
+    pub cycles: u64,
+}
+
+// How trace records are written out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceFormat {
+    // The disassembly and registers
+    Default,
+    // The layout of nestest.log, which most 6502 emulators can write, with undocumented opcodes
+    // marked with a * and the cycle count at the end, so that traces can be diffed against them.
+    // The PPU column, and the memory values that nestest.log shows alongside some operands, are
+    // left out, as there's no PPU, and reading memory isn't free of side effects on the 2600.
+    Nestest,
+}
 
-            _ => panic!("Bad addressing mode {:?}", *self)
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(TraceFormat::Default),
+            "nestest" => Ok(TraceFormat::Nestest),
+            _         => Err(format!("unknown trace format: {}", s)),
         }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Opcode(Instruction,
-              AddressingMode,
-              u64,   // number of cycles
-              u64);  // number of extra cycles, if a page boundary is crossed
-
-const OPCODES: [Opcode; 256] = [
-    // 0x00
-    Opcode(Instruction::BRK, AddressingMode::Implied, 7, 0),
-    Opcode(Instruction::ORA, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::SLO, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::ORA, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::ASL, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::SLO, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::PHP, AddressingMode::Implied, 3, 0),
-    Opcode(Instruction::ORA, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::ASL, AddressingMode::Accumulator, 2, 0),
-    Opcode(Instruction::ANC, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::NOP, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::ORA, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::ASL, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::SLO, AddressingMode::Absolute, 6, 0),
-
-    // 0x10
-    Opcode(Instruction::BPL, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::ORA, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::SLO, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::ORA, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::ASL, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::SLO, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::CLC, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::ORA, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::SLO, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::ORA, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::ASL, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::SLO, AddressingMode::AbsoluteX, 7, 0),
-
-    // 0x20
-    Opcode(Instruction::JSR, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::AND, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::RLA, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::BIT, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::AND, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::ROL, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::RLA, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::PLP, AddressingMode::Implied, 4, 0),
-    Opcode(Instruction::AND, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::ROL, AddressingMode::Accumulator, 2, 0),
-    Opcode(Instruction::ANC, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::BIT, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::AND, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::ROL, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::RLA, AddressingMode::Absolute, 6, 0),
-
-    // 0x30
-    Opcode(Instruction::BMI, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::AND, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::RLA, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::AND, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::ROL, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::RLA, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::SEC, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::AND, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::RLA, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::AND, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::ROL, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::RLA, AddressingMode::AbsoluteX, 7, 0),
-
-    // 0x40
-    Opcode(Instruction::RTI, AddressingMode::Implied, 6, 0),
-    Opcode(Instruction::EOR, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::SRE, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::EOR, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::LSR, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::SRE, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::PHA, AddressingMode::Implied, 3, 0),
-    Opcode(Instruction::EOR, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::LSR, AddressingMode::Accumulator, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::JMP, AddressingMode::Absolute, 3, 0),
-    Opcode(Instruction::EOR, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::LSR, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::SRE, AddressingMode::Absolute, 6, 0),
-
-    // 0x50
-    Opcode(Instruction::BVC, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::EOR, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::SRE, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::EOR, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::LSR, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::SRE, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::CLI, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::EOR, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::SRE, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::EOR, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::LSR, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::SRE, AddressingMode::AbsoluteX, 7, 0),
-
-    // 0x60
-    Opcode(Instruction::RTS, AddressingMode::Implied, 6, 0),
-    Opcode(Instruction::ADC, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::RRA, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::ADC, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::ROR, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::RRA, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::PLA, AddressingMode::Implied, 4, 0),
-    Opcode(Instruction::ADC, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::ROR, AddressingMode::Accumulator, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::JMP, AddressingMode::Indirect, 5, 0),
-    Opcode(Instruction::ADC, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::ROR, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::RRA, AddressingMode::Absolute, 6, 0),
-
-    // 0x70
-    Opcode(Instruction::BVS, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::ADC, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::RRA, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::ADC, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::ROR, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::RRA, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::SEI, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::ADC, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::RRA, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::ADC, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::ROR, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::RRA, AddressingMode::AbsoluteX, 7, 0),
-
-    // 0x80
-    Opcode(Instruction::NOP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::STA, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::NOP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::SAX, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::STY, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::STA, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::STX, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::SAX, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::DEY, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::NOP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::TXA, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::STY, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::STA, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::STX, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::SAX, AddressingMode::Absolute, 4, 0),
-
-    // 0x90
-    Opcode(Instruction::BCC, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::STA, AddressingMode::IndirectIndexed, 6, 0),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::STY, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::STA, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::STX, AddressingMode::ZeroPageY, 4, 0),
-    Opcode(Instruction::SAX, AddressingMode::ZeroPageY, 4, 0),
-    Opcode(Instruction::TYA, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::STA, AddressingMode::AbsoluteY, 5, 0),
-    Opcode(Instruction::TXS, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::STA, AddressingMode::AbsoluteX, 5, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-
-    // 0xA0
-    Opcode(Instruction::LDY, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::LDA, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::LDX, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::LAX, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::LDY, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::LDA, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::LDX, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::LAX, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::TAY, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::LDA, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::TAX, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::LDY, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::LDA, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::LDX, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::LAX, AddressingMode::Absolute, 4, 0),
-
-    // 0xB0
-    Opcode(Instruction::BCS, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::LDA, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::LAX, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::LDY, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::LDA, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::LDX, AddressingMode::ZeroPageY, 4, 0),
-    Opcode(Instruction::LAX, AddressingMode::ZeroPageY, 4, 0),
-    Opcode(Instruction::CLV, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::LDA, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::TSX, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::LDY, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::LDA, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::LDX, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::LAX, AddressingMode::AbsoluteY, 4, 1),
-
-    // 0xC0
-    Opcode(Instruction::CPY, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::CMP, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::NOP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::DCP, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::CPY, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::CMP, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::DEC, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::DCP, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::INY, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::CMP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::DEX, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::None, AddressingMode::None, 0, 0),
-    Opcode(Instruction::CPY, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::CMP, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::DEC, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::DCP, AddressingMode::Absolute, 6, 0),
-
-    // 0xD0
-    Opcode(Instruction::BNE, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::CMP, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::DCP, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::CMP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::DEC, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::DCP, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::CLD, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::CMP, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::DCP, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::CMP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::DEC, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::DCP, AddressingMode::AbsoluteX, 7, 0),
-
-    // 0xE0
-    Opcode(Instruction::CPX, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::SBC, AddressingMode::IndexedIndirect, 6, 0),
-    Opcode(Instruction::NOP, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::ISB, AddressingMode::IndexedIndirect, 8, 0),
-    Opcode(Instruction::CPX, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::SBC, AddressingMode::ZeroPageIndexed, 3, 0),
-    Opcode(Instruction::INC, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::ISB, AddressingMode::ZeroPageIndexed, 5, 0),
-    Opcode(Instruction::INX, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::SBC, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::SBC, AddressingMode::Immediate, 2, 0),
-    Opcode(Instruction::CPX, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::SBC, AddressingMode::Absolute, 4, 0),
-    Opcode(Instruction::INC, AddressingMode::Absolute, 6, 0),
-    Opcode(Instruction::ISB, AddressingMode::Absolute, 6, 0),
-
-    // 0xF0
-    Opcode(Instruction::BEQ, AddressingMode::Relative, 2, 1),
-    Opcode(Instruction::SBC, AddressingMode::IndirectIndexed, 5, 1),
-    Opcode(Instruction::JAM, AddressingMode::Implied, 0, 0),
-    Opcode(Instruction::ISB, AddressingMode::IndirectIndexed, 8, 0),
-    Opcode(Instruction::NOP, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::SBC, AddressingMode::ZeroPageX, 4, 0),
-    Opcode(Instruction::INC, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::ISB, AddressingMode::ZeroPageX, 6, 0),
-    Opcode(Instruction::SED, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::SBC, AddressingMode::AbsoluteY, 4, 1),
-    Opcode(Instruction::NOP, AddressingMode::Implied, 2, 0),
-    Opcode(Instruction::ISB, AddressingMode::AbsoluteY, 7, 0),
-    Opcode(Instruction::NOP, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::SBC, AddressingMode::AbsoluteX, 4, 1),
-    Opcode(Instruction::INC, AddressingMode::AbsoluteX, 7, 0),
-    Opcode(Instruction::ISB, AddressingMode::AbsoluteX, 7, 0),
-];
-pub struct CPU6507 {
-    bus: Box<dyn Bus>,
+impl TraceRecord {
+    pub fn format(&self, format: TraceFormat) -> String {
+        let line = Line::decode(self.pc, &self.bytes);
+        let registers = format!("A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}", self.a, self.x, self.y, self.p, self.sp);
+
+        match format {
+            TraceFormat::Default => format!("{:48} {}", line.to_string(), registers),
+            TraceFormat::Nestest => {
+                let bytes = line.bytes.iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let marker = if documented(line.bytes[0]) { ' ' } else { '*' };
+
+                format!("{:04X}  {:8} {}{:32}{} CYC:{}", self.pc, bytes, marker, line.text(), registers, self.cycles)
+            },
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(TraceFormat::Default))
+    }
+}
+
+// The state of the CPU after it's finished an instruction, passed to the step function
+pub struct StepRecord {
+    // The address and opcode of the instruction that was executed, and the address of the one
+    // that's next
+    pub pc: u16,
+    pub opcode: u8,
+    pub next_pc: u16,
+
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+
+    // The total number of cycles executed, including this instruction's
+    pub cycles: u64,
+}
+
+impl fmt::Display for StepRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+               self.pc,
+               self.opcode,
+               self.a,
+               self.x,
+               self.y,
+               self.p,
+               self.sp,
+               self.cycles)
+    }
+}
+
+// Called with the state of the CPU before each instruction
+type TraceFn = dyn Fn(&TraceRecord);
+
+// Called with the state of the CPU after each instruction
+type StepFn = dyn Fn(&StepRecord);
+
+// The CPU is generic over its bus, so that memory accesses are statically dispatched, as they
+// happen several times per instruction. Any bus can still be boxed up as a `Box<dyn Bus>`, which is
+// the default.
+pub struct CPU6507<B: Bus = Box<dyn Bus>> {
+    bus: B,
 
     // Main registers
     pub a: u8,  // Accumulator
@@ -511,12 +279,41 @@ pub struct CPU6507 {
     cycles: u64,
 
     current_instruction: Option<Instruction>,
+    current_pc: u16,
+    current_opcode: u8,
     current_addr: u16,
     current_addr_mode: AddressingMode,
     current_cycles: u64,
+
+    // How many cycles the current instruction takes in total, to work out which of them is next
+    instruction_cycles: u64,
+
+    // The RDY line. While it's low, the CPU halts on the next read cycle, which can be partway
+    // through an instruction. Write cycles carry on regardless.
+    rdy: bool,
+
+    // The address of the JAM instruction that's locked up the CPU, until it's next reset
+    jammed: Option<u16>,
+
+    // The interrupt lines are driven by the bus. The 6507 doesn't have these pins, so they're
+    // unconnected unless something on the bus drives them. IRQ is level triggered and masked by
+    // the I flag, while NMI is edge triggered, and is taken once each time the line is asserted, so
+    // the NMI line's level is kept to see the edges.
+    nmi: bool,
+    nmi_pending: bool,
+
+    // The vector of the interrupt being taken, in place of an instruction
+    current_interrupt: Option<u16>,
+
+    // Called with the state of the CPU before each instruction, for tracing execution
+    trace_fn: Option<Box<TraceFn>>,
+
+    // Called in turn with the state of the CPU after each instruction, for scripting and
+    // instrumentation
+    step_fns: Vec<Box<StepFn>>,
 }
 
-impl Bus for CPU6507 {
+impl<B: Bus> Bus for CPU6507<B> {
     fn read(&mut self, addr: u16) -> u8 {
         // The 6507 only had 13 address lines connected.
         self.bus.read(addr & 0x1fff)
@@ -526,10 +323,72 @@ impl Bus for CPU6507 {
         // The 6507 only had 13 address lines connected.
         self.bus.write(addr & 0x1fff, val);
     }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr & 0x1fff)
+    }
+
+    // The registers, then the state of the instruction in progress (if the CPU's being clocked
+    // rather than stepped), then the state of everything on the bus
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.a, self.x, self.y, self.flags(), self.sp])?;
+        output.write_all(&self.pc.to_le_bytes())?;
+        output.write_all(&self.cycles.to_le_bytes())?;
+
+        output.write_all(&[self.current_instruction.is_some() as u8, self.current_opcode])?;
+        output.write_all(&self.current_pc.to_le_bytes())?;
+        output.write_all(&self.current_addr.to_le_bytes())?;
+        output.write_all(&self.current_cycles.to_le_bytes())?;
+        output.write_all(&self.instruction_cycles.to_le_bytes())?;
+
+        output.write_all(&[self.rdy as u8, self.jammed.is_some() as u8])?;
+        output.write_all(&self.jammed.unwrap_or(0).to_le_bytes())?;
+
+        output.write_all(&[self.nmi as u8, self.nmi_pending as u8])?;
+        output.write_all(&[self.current_interrupt.is_some() as u8])?;
+        output.write_all(&self.current_interrupt.unwrap_or(0).to_le_bytes())?;
+
+        self.bus.save(output)
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let registers = read_bytes::<5>(input)?;
+        self.a = registers[0];
+        self.x = registers[1];
+        self.y = registers[2];
+        self.set_flags(registers[3]);
+        self.sp = registers[4];
+        self.pc = u16::from_le_bytes(read_bytes(input)?);
+        self.cycles = u64::from_le_bytes(read_bytes(input)?);
+
+        // The instruction and its addressing mode come from the opcode table
+        let [in_progress, opcode] = read_bytes(input)?;
+        let Opcode(inst, addr_mode, _, _) = OPCODES[opcode as usize];
+        self.current_instruction = if in_progress != 0 { Some(inst) } else { None };
+        self.current_opcode = opcode;
+        self.current_addr_mode = addr_mode;
+        self.current_pc = u16::from_le_bytes(read_bytes(input)?);
+        self.current_addr = u16::from_le_bytes(read_bytes(input)?);
+        self.current_cycles = u64::from_le_bytes(read_bytes(input)?);
+        self.instruction_cycles = u64::from_le_bytes(read_bytes(input)?);
+
+        let [rdy, jammed] = read_bytes(input)?;
+        let jammed_pc = u16::from_le_bytes(read_bytes(input)?);
+        self.rdy = rdy != 0;
+        self.jammed = if jammed != 0 { Some(jammed_pc) } else { None };
+
+        let [nmi, nmi_pending, interrupting] = read_bytes(input)?;
+        let vector = u16::from_le_bytes(read_bytes(input)?);
+        self.nmi = nmi != 0;
+        self.nmi_pending = nmi_pending != 0;
+        self.current_interrupt = if interrupting != 0 { Some(vector) } else { None };
+
+        self.bus.load(input)
+    }
 }
 
-impl CPU6507 {
-    pub fn new(bus: Box<dyn Bus>) -> Self {
+impl<B: Bus> CPU6507<B> {
+    pub fn new(bus: B) -> Self {
         Self {
             bus: bus,
 
@@ -553,9 +412,26 @@ impl CPU6507 {
             cycles: 0,
 
             current_instruction: None,
+            current_pc: 0x0000,
+            current_opcode: 0x00,
             current_addr: 0x0000,
             current_addr_mode: AddressingMode::Accumulator,
             current_cycles: 0,
+
+            instruction_cycles: 0,
+
+            rdy: true,
+
+            jammed: None,
+
+            nmi: false,
+            nmi_pending: false,
+
+            current_interrupt: None,
+
+            trace_fn: None,
+
+            step_fns: vec![],
         }
     }
 
@@ -574,9 +450,29 @@ impl CPU6507 {
         self.y = 0;
 
         self.cycles = 0;
+
+        self.current_instruction = None;
+        self.current_cycles = 0;
+        self.jammed = None;
+
+        self.nmi_pending = false;
+        self.current_interrupt = None;
     }
 
-    fn flags(&self) -> u8 {
+    // A reset as well as releasing RDY and forgetting the NMI line's level, which is all that's
+    // different about the CPU when the console's turned on
+    pub fn power_cycle(&mut self) {
+        self.nmi = false;
+        self.rdy = true;
+        self.reset();
+    }
+
+    pub fn jammed(&self) -> Option<u16> { self.jammed }
+
+    pub fn sp(&self) -> u8 { self.sp }
+    pub fn cycles(&self) -> u64 { self.cycles }
+
+    pub fn flags(&self) -> u8 {
            (self.c as u8)
         | ((self.z as u8) << 1)
         | ((self.i as u8) << 2)
@@ -598,30 +494,66 @@ impl CPU6507 {
         self.s = (val >> 7 & 0x01) == 1;
     }
 
-    fn debug(&mut self, op: &Opcode) {
-        let Opcode(ref inst, ref addr_mode, _, _) = *op;
+    pub fn set_trace_fn(&mut self, trace_fn: impl Fn(&TraceRecord) + 'static) {
+        self.trace_fn = Some(Box::new(trace_fn));
+    }
+
+    pub fn clear_trace_fn(&mut self) {
+        self.trace_fn = None;
+    }
+
+    pub fn add_step_fn(&mut self, step_fn: impl Fn(&StepRecord) + 'static) {
+        self.step_fns.push(Box::new(step_fn));
+    }
+
+    fn trace(&self, op: &Opcode) {
+        let Opcode(_, addr_mode, _, _) = *op;
+
+        let record = TraceRecord {
+            pc: self.pc,
+            bytes: addr_mode.get_bytes(self),
+
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.flags(),
+            sp: self.sp,
+
+            cycles: self.cycles,
+        };
+
+        if let Some(trace_fn) = &self.trace_fn {
+            trace_fn(&record);
+        }
+    }
+
+    fn notify_step(&self) {
+        if self.step_fns.is_empty() {
+            return;
+        }
 
-        let raw_bytes = addr_mode.get_bytes(self);
+        let record = StepRecord {
+            pc: self.current_pc,
+            opcode: self.current_opcode,
+            next_pc: self.pc,
 
-        let bytes = raw_bytes.iter()
-            .map(|arg| String::from(format!("{:02X}", arg)))
-            .collect::<Vec<_>>()
-            .join(" ");
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            p: self.flags(),
+            sp: self.sp,
 
-        println!("{:04X}  {:8}  {:32?} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
-                 self.pc,
-                 bytes,
-                 inst,
-                 self.a,
-                 self.x,
-                 self.y,
-                 self.flags(),
-                 self.sp);
+            // Any cycles left are for taking a branch, which still count towards this instruction
+            cycles: self.cycles + self.current_cycles,
+        };
+
+        for step_fn in &self.step_fns {
+            step_fn(&record);
+        }
     }
 
     fn stack_push8(&mut self, val: u8) {
-        // The stack page exists from 0x0080 to 0x00FF
-        let addr = 0x0000 | (self.sp as u16);
+        let addr = STACK_PAGE | (self.sp as u16);
         self.write(addr, val);
 
         let n = self.sp.wrapping_sub(1);
@@ -632,8 +564,7 @@ impl CPU6507 {
         let n = self.sp.wrapping_add(1);
         self.sp = n;
 
-        // The stack page exists from 0x0080 to 0x00FF
-        let addr = 0x0000 | (self.sp as u16);
+        let addr = STACK_PAGE | (self.sp as u16);
         let val = self.read(addr);
 
         val
@@ -658,30 +589,91 @@ impl CPU6507 {
         self.z = val == 0;
     }
 
+    // Read-modify-write instructions write the unmodified value back while they're modifying it,
+    // so the address is written to twice. That first write is enough to trigger a TIA strobe.
+    fn read_for_modify(&mut self, addr: u16) -> u8 {
+        let val = self.read(addr);
+        self.write(addr, val);
+        val
+    }
+
     fn add_branch_cycles(&mut self, pc: u16, addr: u16) {
         self.current_cycles += 1;
-        self.cycles += 1;
 
         // It costs an extra cycle to branch to a different page.
         if (pc & 0xff00) != (addr & 0xff00) {
             self.current_cycles += 1;
-            self.cycles += 1;
         }
     }
 
+    // Interrupts are only checked for between instructions, with NMI taking priority over IRQ
+    fn start_instruction(&mut self) -> u64 {
+        let nmi = self.bus.nmi();
+        if nmi && !self.nmi {
+            self.nmi_pending = true;
+        }
+        self.nmi = nmi;
+
+        let vector =
+            if self.nmi_pending {
+                self.nmi_pending = false;
+                Some(NMI_VECTOR)
+            } else if self.bus.irq() && !self.i {
+                Some(IRQ_VECTOR)
+            } else {
+                None
+            };
+
+        match vector {
+            Some(vector) => {
+                self.current_interrupt = Some(vector);
+                INTERRUPT_CYCLES
+            },
+            None => self.fetch_and_decode(),
+        }
+    }
+
+    // The same as BRK, except the return address is the instruction that was interrupted, and
+    // the B flag is clear in the flags pushed to the stack
+    fn interrupt(&mut self, vector: u16) {
+        let pc = self.pc;
+        self.stack_push16(pc);
+
+        let flags = self.flags() & 0xef | 0x20;
+        self.stack_push8(flags);
+
+        self.i = true;
+
+        let lo = self.read(vector) as u16;
+        let hi = self.read(vector + 1) as u16;
+        self.pc = (hi << 8) | lo;
+    }
+
     fn fetch_and_decode(&mut self) -> u64 {
         let opcode = self.read(self.pc);
         let op = &OPCODES[opcode as usize];
 
-        if *CPU6507_DEBUG {
-            self.debug(&op);
+        if self.trace_fn.is_some() {
+            self.trace(op);
         }
 
         let &Opcode(ref inst, ref addr_mode, cycles, extra_cycles) = op;
         let (addr, page_crossed) = addr_mode.get_data(self);
 
-        self.pc += addr_mode.n_bytes() as u16;
+        // Indexing reads from the address before the carry into its high byte has been added, and
+        // then takes an extra cycle to read from the right address if a page was crossed. An
+        // instruction that writes can't take back a write to the wrong address, so it always takes
+        // that extra cycle (which is counted in the table, rather than as extra cycles), and always
+        // makes the dummy read, even when the address it reads from is the right one.
+        if addr_mode.indexed() && (page_crossed || inst.writes()) {
+            let uncarried = if page_crossed { addr.wrapping_sub(0x100) } else { addr };
+            self.read(uncarried);
+        }
+
+        self.current_pc = self.pc;
+        self.pc = self.pc.wrapping_add(addr_mode.n_bytes() as u16);
         self.current_instruction = Some(*inst);
+        self.current_opcode = opcode;
         self.current_addr = addr;
         self.current_addr_mode = *addr_mode;
 
@@ -694,14 +686,22 @@ impl CPU6507 {
     }
 
     fn execute(&mut self) {
+        if let Some(vector) = self.current_interrupt.take() {
+            self.interrupt(vector);
+            return;
+        }
+
         if let Some(inst) = self.current_instruction {
             let addr = self.current_addr;
             let addr_mode = self.current_addr_mode;
 
             match inst {
                 Instruction::ADC => self.adc(addr),
+                Instruction::ALR => self.alr(addr),
                 Instruction::ANC => self.anc(addr),
                 Instruction::AND => self.and(addr),
+                Instruction::ANE => self.ane(addr),
+                Instruction::ARR => self.arr(addr),
                 Instruction::ASL => self.asl(addr, addr_mode),
                 Instruction::BCC => self.bcc(addr),
                 Instruction::BCS => self.bcs(addr),
@@ -732,11 +732,13 @@ impl CPU6507 {
                 Instruction::JAM => self.jam(),
                 Instruction::JMP => self.jmp(addr),
                 Instruction::JSR => self.jsr(addr),
+                Instruction::LAS => self.las(addr),
                 Instruction::LAX => self.lax(addr),
                 Instruction::LDA => self.lda(addr),
                 Instruction::LDX => self.ldx(addr),
                 Instruction::LDY => self.ldy(addr),
                 Instruction::LSR => self.lsr(addr, addr_mode),
+                Instruction::LXA => self.lxa(addr),
                 Instruction::NOP => self.nop(),
                 Instruction::ORA => self.ora(addr),
                 Instruction::PHA => self.pha(),
@@ -751,43 +753,116 @@ impl CPU6507 {
                 Instruction::RTS => self.rts(),
                 Instruction::SAX => self.sax(addr),
                 Instruction::SBC => self.sbc(addr),
+                Instruction::SBX => self.sbx(addr),
                 Instruction::SEC => self.sec(),
                 Instruction::SED => self.sed(),
                 Instruction::SEI => self.sei(),
+                Instruction::SHA => self.sha(addr),
+                Instruction::SHX => self.shx(addr),
+                Instruction::SHY => self.shy(addr),
                 Instruction::SLO => self.slo(addr, addr_mode),
                 Instruction::SRE => self.sre(addr, addr_mode),
                 Instruction::STA => self.sta(addr),
                 Instruction::STX => self.stx(addr),
                 Instruction::STY => self.sty(addr),
+                Instruction::TAS => self.tas(addr),
                 Instruction::TAX => self.tax(),
                 Instruction::TAY => self.tay(),
                 Instruction::TSX => self.tsx(),
                 Instruction::TXA => self.txa(),
                 Instruction::TXS => self.txs(),
                 Instruction::TYA => self.tya(),
-                _ => panic!("unsupported instruction {:?}", inst),
             }
 
             self.current_instruction = None;
+            self.notify_step();
         }
     }
 
     pub fn step(&mut self) -> u64 {
+        if self.jammed.is_some() {
+            return 0;
+        }
+
         let start_cycles = self.cycles;
-        self.cycles += self.fetch_and_decode();
+        self.cycles += self.start_instruction();
         self.execute();
+
+        // Taking a branch adds cycles once the instruction's executed
+        self.cycles += self.current_cycles;
+        self.current_cycles = 0;
+
         self.cycles - start_cycles
     }
 
-    pub fn clock(&mut self) {
+    pub fn set_rdy(&mut self, rdy: bool) { self.rdy = rdy }
+
+    // Whether the next cycle of the current instruction writes to the bus. The opcode fetch, and
+    // any cycles after the instruction's been executed (e.g. for taking a branch), are reads.
+    fn write_cycle(&self) -> bool {
         if self.current_cycles == 0 {
-            self.current_cycles += self.fetch_and_decode();
+            return false;
         }
 
+        // Counting from 1, the opcode fetch being the first cycle
+        let cycle = self.instruction_cycles - self.current_cycles + 1;
+        let last = self.instruction_cycles;
+
+        // Pushing the return address and flags, like BRK
+        if self.current_interrupt.is_some() {
+            return (3 ..= 5).contains(&cycle);
+        }
+
+        let inst = match self.current_instruction {
+            Some(inst) => inst,
+            None       => return false,
+        };
+
+        match (inst, self.current_addr_mode) {
+            (_, AddressingMode::Accumulator) => false,
+
+            // Stores write on their last cycle
+              (Instruction::STA, _) | (Instruction::STX, _) | (Instruction::STY, _)
+            | (Instruction::SAX, _) | (Instruction::SHA, _) | (Instruction::SHX, _)
+            | (Instruction::SHY, _) | (Instruction::TAS, _)
+            | (Instruction::PHA, _) | (Instruction::PHP, _) => cycle == last,
+
+            // Read-modify-write instructions write the old value, then the new one
+              (Instruction::ASL, _) | (Instruction::LSR, _) | (Instruction::ROL, _)
+            | (Instruction::ROR, _) | (Instruction::INC, _) | (Instruction::DEC, _)
+            | (Instruction::SLO, _) | (Instruction::RLA, _) | (Instruction::SRE, _)
+            | (Instruction::RRA, _) | (Instruction::DCP, _) | (Instruction::ISB, _) => cycle >= last - 1,
+
+            // Pushing the return address, and the flags for BRK
+            (Instruction::JSR, _) => cycle == 4 || cycle == 5,
+            (Instruction::BRK, _) => (3 ..= 5).contains(&cycle),
+
+            _ => false,
+        }
+    }
+
+    // Returns whether an instruction was finished on this cycle, so the next one starts at the PC
+    pub fn clock(&mut self) -> bool {
+        if self.jammed.is_some() {
+            return false;
+        }
+
+        if !self.rdy && !self.write_cycle() {
+            return false;
+        }
+
+        if self.current_cycles == 0 {
+            self.current_cycles += self.start_instruction();
+            self.instruction_cycles = self.current_cycles;
+        }
+
+        self.cycles += 1;
         self.current_cycles -= 1;
         if self.current_cycles == 0 {
             self.execute();
         }
+
+        self.current_cycles == 0
     }
 
     //
@@ -798,28 +873,31 @@ impl CPU6507 {
         let val = self.read(addr);
 
         if self.d {
-            let mut lo = (self.a as u16 & 0x0f) + (val as u16 & 0x0f) + (self.c as u16);
-            let mut hi = (self.a as u16 & 0xf0) + (val as u16 & 0xf0);
-
-            // In BCD, values 0x0A to 0x0F are invalid, so we add 1 to the high nybble for the
-            // carry, and the low nybble has to skip 6 values for A-F.
-            if lo > 0x09 {
-                hi += 0x10;
-                lo += 0x06;
+            // http://www.6502.org/tutorials/decimal_mode.html#A
+            let mut lo = (self.a & 0x0f) as i16 + (val & 0x0f) as i16 + self.c as i16;
+
+            // In BCD, values 0x0A to 0x0F are invalid, so the low nybble skips 6 values for A-F,
+            // and carries into the high nybble.
+            if lo >= 0x0a {
+                lo = ((lo + 0x06) & 0x0f) + 0x10;
             }
 
-            self.s = (hi & 0x80) != 0;
-            self.z = ((lo + hi) & 0xff) != 0;
-            self.v = ((self.a ^ val) & 0x80 == 0) && ((self.a ^ hi as u8) & 0x80 != 0);
+            // N and V come from the sum before the high nybble is adjusted, treating the high
+            // nybbles as signed
+            let signed = (self.a & 0xf0) as i8 as i16 + (val & 0xf0) as i8 as i16 + lo;
+            self.s = signed & 0x80 != 0;
+            self.v = !(-128 ..= 127).contains(&signed);
 
-            // 0xA0 to 0xF0 are invalid for the high nybble, so we need to skip 6 values of the
-            // high nybble.
-            if hi > 0x90 {
-                hi += 0x60;
+            // 0xA0 to 0xF0 are invalid for the high nybble, so it skips 6 values too
+            let mut n = (self.a & 0xf0) as i16 + (val & 0xf0) as i16 + lo;
+            if n >= 0xa0 {
+                n += 0x60;
             }
 
-            self.c = (hi & 0xff00) != 0;
-            self.a = (lo & 0x0f) as u8 | (hi & 0xf0) as u8;
+            // Z is set as if it were a binary addition
+            self.z = self.a.wrapping_add(val).wrapping_add(self.c as u8) == 0;
+            self.c = n >= 0x100;
+            self.a = n as u8;
         } else {
             let n = (self.a as u16) + (val as u16) + (self.c as u16);
             let a = (n & 0x00ff) as u8;
@@ -848,7 +926,7 @@ impl CPU6507 {
     fn asl(&mut self, addr: u16, addr_mode: AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         self.c = val & 0x80 != 0;
@@ -919,7 +997,7 @@ impl CPU6507 {
     }
 
     fn brk(&mut self) {
-        let pc = self.pc + 1;
+        let pc = self.pc.wrapping_add(1);
         self.stack_push16(pc);
 
         self.b = true;
@@ -989,7 +1067,7 @@ impl CPU6507 {
     }
 
     fn dec(&mut self, addr: u16) {
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let n = val.wrapping_sub(1);
         self.update_sz(n);
         self.write(addr, n);
@@ -1015,7 +1093,7 @@ impl CPU6507 {
     }
 
     fn inc(&mut self, addr: u16) {
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let n = val.wrapping_add(1);
         self.write(addr, n);
         self.update_sz(n);
@@ -1038,7 +1116,7 @@ impl CPU6507 {
     }
 
     fn jsr(&mut self, addr: u16) {
-        let retaddr = self.pc - 1;
+        let retaddr = self.pc.wrapping_sub(1);
         self.stack_push16(retaddr);
         self.pc = addr;
     }
@@ -1064,7 +1142,7 @@ impl CPU6507 {
     fn lsr(&mut self, addr: u16, addr_mode: AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         self.c = val & 0x01 == 1;
@@ -1114,7 +1192,7 @@ impl CPU6507 {
     fn rol(&mut self, addr: u16, addr_mode: AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let n = (val << 1) | (self.c as u8);
@@ -1130,7 +1208,7 @@ impl CPU6507 {
     fn ror(&mut self, addr: u16, addr_mode: AddressingMode) {
         let val = match addr_mode {
             AddressingMode::Accumulator => self.a,
-            _ => self.read(addr),
+            _ => self.read_for_modify(addr),
         };
 
         let n = (val >> 1) | ((self.c as u8) << 7);
@@ -1153,49 +1231,41 @@ impl CPU6507 {
 
     fn rts(&mut self) {
         let retaddr = self.stack_pop16();
-        self.pc = retaddr + 1;
+        self.pc = retaddr.wrapping_add(1);
     }
 
     fn sbc(&mut self, addr: u16) {
         let val = self.read(addr);
 
-        if self.d {
-            // http://www.6502.org/tutorials/decimal_mode.html
-            let mut temp = (self.a as i16) - (val as i16) - (!self.c as i16);
-            let lo = ((self.a as i16) & 0x0f) - ((val as i16) & 0x0f) - (!self.c as i16);
+        // The flags are always set as if it were a binary subtraction, even in decimal mode
+        let inverted = ! val;
+        let n = (self.a as u16) + (inverted as u16) + (self.c as u16);
+        let binary = (n & 0x00ff) as u8;
 
-            if temp < 0 {
-                temp -= 0x60;
-            }
+        // See adc for an explanation of the overflow flag
+        let v = ((self.a ^ inverted) & 0x80 == 0) && ((self.a ^ binary) & 0x80 != 0);
 
+        let a = if self.d {
+            // http://www.6502.org/tutorials/decimal_mode.html#A
+            let mut lo = (self.a & 0x0f) as i16 - (val & 0x0f) as i16 + self.c as i16 - 1;
             if lo < 0 {
-                temp -= 0x06;
+                lo = ((lo - 0x06) & 0x0f) - 0x10;
             }
 
-            debug!("SBC  {:02X} - {:02X} - {:02X} = {:04X}", self.a, val, !self.c as u8, temp);
+            let mut n = (self.a & 0xf0) as i16 - (val & 0xf0) as i16 + lo;
+            if n < 0 {
+                n -= 0x60;
+            }
 
-            let a = (temp & 0xff) as u8;
-            self.update_sz(a);
-            self.v = ((self.a ^ val) & 0x80 == 0) && ((self.a ^ a) & 0x80 != 0);
-            self.c = temp >= 0;
-            self.a = a;
+            n as u8
         } else {
-            let val = ! val;
-            let n = (self.a as u16) + (val as u16) + (self.c as u16);
-            let a = (n & 0x00ff) as u8;
-
-            self.update_sz(a);
-            self.c = n > 0xff;
-
-            // The first condition checks if the sign of the accumulator and the
-            // the sign of value that we're adding are the same.
-            //
-            // The second condition checks if the result of the addition has a
-            // different sign to either of the values we added together.
-            self.v = ((self.a ^ val) & 0x80 == 0) && ((self.a ^ a) & 0x80 != 0);
+            binary
+        };
 
-            self.a = a;
-        }
+        self.update_sz(binary);
+        self.c = n > 0xff;
+        self.v = v;
+        self.a = a;
     }
 
     fn sec(&mut self) {
@@ -1282,7 +1352,7 @@ impl CPU6507 {
 
     fn dcp(&mut self, addr: u16) {
         // Copied from dec
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let n = val.wrapping_sub(1);
         self.update_sz(n);
         self.write(addr, n);
@@ -1295,7 +1365,7 @@ impl CPU6507 {
 
     fn isb(&mut self, addr: u16) {
         // Copied from inc
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let n = val.wrapping_add(1);
         self.write(addr, n);
         self.update_sz(n);
@@ -1315,7 +1385,7 @@ impl CPU6507 {
 
     fn slo(&mut self, addr: u16, addr_mode: AddressingMode) {
         // Copied from asl
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         self.c = val & 0x80 != 0;
         let n = (val << 1) & 0xff;
 
@@ -1335,7 +1405,7 @@ impl CPU6507 {
 
     fn rla(&mut self, addr: u16, addr_mode: AddressingMode) {
         // Copied from rol
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let c = self.c;
         self.c = val & 0x80 != 0;
         let n = (val << 1) | (c as u8);
@@ -1355,7 +1425,7 @@ impl CPU6507 {
 
     fn sre(&mut self, addr: u16, addr_mode: AddressingMode) {
         // Copied from lsr
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         self.c = val & 0x01 == 1;
         let n = val >> 1;
         self.update_sz(n);
@@ -1374,7 +1444,7 @@ impl CPU6507 {
 
     fn rra(&mut self, addr: u16, addr_mode: AddressingMode) {
         // Copied from ror
-        let val = self.read(addr);
+        let val = self.read_for_modify(addr);
         let c = self.c;
         self.c = val & 0x01 == 1;
         let n = (val >> 1) | ((c as u8) << 7);
@@ -1395,7 +1465,617 @@ impl CPU6507 {
         self.a = a;
     }
 
+    fn alr(&mut self, addr: u16) {
+        // AND, then LSR the accumulator
+        let val = self.read(addr) & self.a;
+        self.c = val & 0x01 == 1;
+        let n = val >> 1;
+        self.a = n;
+        self.update_sz(n);
+    }
+
+    fn arr(&mut self, addr: u16) {
+        // AND, then ROR the accumulator, but with the carry and overflow flags set from bits 6
+        // and 5 of the result, as it goes through the adder
+        let val = self.read(addr) & self.a;
+        let mut n = (val >> 1) | ((self.c as u8) << 7);
+
+        if self.d {
+            // http://www.6502.org/users/andre/petindex/local/64doc.txt
+            self.s = self.c;
+            self.z = n == 0;
+            self.v = (val ^ n) & 0x40 != 0;
+
+            if (val & 0x0f) + (val & 0x01) > 0x05 {
+                n = (n & 0xf0) | (n.wrapping_add(0x06) & 0x0f);
+            }
+
+            self.c = (val as u16 & 0xf0) + (val as u16 & 0x10) > 0x50;
+            if self.c {
+                n = n.wrapping_add(0x60);
+            }
+        } else {
+            self.update_sz(n);
+            self.c = n & 0x40 != 0;
+            self.v = ((n >> 6) ^ (n >> 5)) & 0x01 != 0;
+        }
+
+        self.a = n;
+    }
+
+    fn ane(&mut self, addr: u16) {
+        // The result depends on an unstable "magic" constant that varies between chips and even
+        // with temperature. 0xEE is what most of them use, and what other emulators settle on.
+        let val = self.read(addr);
+        let n = (self.a | 0xee) & self.x & val;
+        self.a = n;
+        self.update_sz(n);
+    }
+
+    fn las(&mut self, addr: u16) {
+        let val = self.read(addr) & self.sp;
+        self.a = val;
+        self.x = val;
+        self.sp = val;
+        self.update_sz(val);
+    }
+
+    fn lxa(&mut self, addr: u16) {
+        // Like ANE, this has the same unstable "magic" constant
+        let val = self.read(addr);
+        let n = (self.a | 0xee) & val;
+        self.a = n;
+        self.x = n;
+        self.update_sz(n);
+    }
+
+    fn sbx(&mut self, addr: u16) {
+        // Like CMP, but with A AND X, and keeping the result in X
+        let val = self.read(addr);
+        let ax = self.a & self.x;
+        let n = ax.wrapping_sub(val);
+        self.c = ax >= val;
+        self.x = n;
+        self.update_sz(n);
+    }
+
+    // The SHA, SHX, SHY, and TAS stores AND the value with the high byte of the base address plus
+    // one. When indexing crosses a page, the high byte of the address that's written to is
+    // replaced with the value too.
+    fn store_high_byte_and(&mut self, addr: u16, index: u8, val: u8) {
+        let base = addr.wrapping_sub(index as u16);
+        let hi = ((base >> 8) as u8).wrapping_add(1);
+        let n = val & hi;
+
+        let addr = if pages_differ(base, addr) {
+            ((n as u16) << 8) | (addr & 0x00ff)
+        } else {
+            addr
+        };
+
+        self.write(addr, n);
+    }
+
+    fn sha(&mut self, addr: u16) {
+        let val = self.a & self.x;
+        self.store_high_byte_and(addr, self.y, val);
+    }
+
+    fn shx(&mut self, addr: u16) {
+        self.store_high_byte_and(addr, self.y, self.x);
+    }
+
+    fn shy(&mut self, addr: u16) {
+        self.store_high_byte_and(addr, self.x, self.y);
+    }
+
+    fn tas(&mut self, addr: u16) {
+        self.sp = self.a & self.x;
+        self.store_high_byte_and(addr, self.y, self.sp);
+    }
+
     fn jam(&mut self) {
-        process::exit(0);
+        // The CPU locks up, and only a reset gets it going again
+        let pc = self.pc.wrapping_sub(1);
+        error!("CPU: JAM at ${:04X}", pc);
+        self.jammed = Some(pc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Memory covering the whole address space, recording every access made to it
+    struct Memory {
+        bytes: Vec<u8>,
+        reads: Vec<u16>,
+        writes: Vec<(u16, u8)>,
+        irq: bool,
+        nmi: bool,
+    }
+
+    struct Ram(Rc<RefCell<Memory>>);
+
+    impl Bus for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            let mut memory = self.0.borrow_mut();
+            memory.reads.push(address);
+            memory.bytes[address as usize]
+        }
+
+        fn write(&mut self, address: u16, val: u8) {
+            let mut memory = self.0.borrow_mut();
+            memory.writes.push((address, val));
+            memory.bytes[address as usize] = val;
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.0.borrow().bytes[address as usize]
+        }
+
+        fn irq(&self) -> bool { self.0.borrow().irq }
+        fn nmi(&self) -> bool { self.0.borrow().nmi }
+    }
+
+    // Run the given program from 0x1000, one instruction per step
+    fn run(program: &[u8], steps: usize) -> (CPU6507<Ram>, Rc<RefCell<Memory>>) {
+        let mut bytes = vec![0; 0x2000];
+        bytes[0x1000 .. 0x1000 + program.len()].copy_from_slice(program);
+        bytes[0x1ffc] = 0x00;
+        bytes[0x1ffd] = 0x10;
+
+        let ram = Rc::new(RefCell::new(Memory { bytes: bytes, reads: vec![], writes: vec![], irq: false, nmi: false }));
+
+        let mut cpu = CPU6507::new(Ram(ram.clone()));
+        cpu.reset();
+
+        for _ in 0 .. steps {
+            cpu.step();
+        }
+
+        (cpu, ram)
+    }
+
+    #[test]
+    fn test_alr() {
+        // LDA #$ff; ALR #$0f
+        let (cpu, _) = run(&[0xa9, 0xff, 0x4b, 0x0f], 2);
+        assert_eq!(cpu.a, 0x07);
+        assert!(cpu.c);
+        assert!(!cpu.z && !cpu.s);
+    }
+
+    #[test]
+    fn test_arr() {
+        // SEC; LDA #$ff; ARR #$c0
+        let (cpu, _) = run(&[0x38, 0xa9, 0xff, 0x6b, 0xc0], 3);
+        assert_eq!(cpu.a, 0xe0);
+        assert!(cpu.c);
+        assert!(!cpu.v);
+        assert!(cpu.s);
+
+        // CLC; LDA #$ff; ARR #$40
+        let (cpu, _) = run(&[0x18, 0xa9, 0xff, 0x6b, 0x40], 3);
+        assert_eq!(cpu.a, 0x20);
+        assert!(!cpu.c);
+        assert!(cpu.v);
+    }
+
+    #[test]
+    fn test_sbx() {
+        // LDA #$f0; LDX #$3c; SBX #$10
+        let (cpu, _) = run(&[0xa9, 0xf0, 0xa2, 0x3c, 0xcb, 0x10], 3);
+        assert_eq!(cpu.x, 0x20);
+        assert!(cpu.c);
+
+        // LDA #$f0; LDX #$3c; SBX #$40
+        let (cpu, _) = run(&[0xa9, 0xf0, 0xa2, 0x3c, 0xcb, 0x40], 3);
+        assert_eq!(cpu.x, 0xf0);
+        assert!(!cpu.c);
+        assert!(cpu.s);
+    }
+
+    #[test]
+    fn test_las() {
+        // LDY #$01; LAS $0080,Y
+        let (mut cpu, ram) = run(&[], 0);
+        ram.borrow_mut().bytes[0x1000 .. 0x1005].copy_from_slice(&[0xa0, 0x01, 0xbb, 0x80, 0x00]);
+        ram.borrow_mut().bytes[0x0081] = 0x5a;
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.a, 0x5a & STACK_INIT);
+        assert_eq!(cpu.x, 0x5a & STACK_INIT);
+        assert_eq!(cpu.sp, 0x5a & STACK_INIT);
+    }
+
+    #[test]
+    fn test_shx() {
+        // LDX #$ff; LDY #$01; SHX $0080,Y
+        let (_, ram) = run(&[0xa2, 0xff, 0xa0, 0x01, 0x9e, 0x80, 0x00], 3);
+        assert_eq!(ram.borrow().bytes[0x0081], 0x01);
+
+        // Crossing a page replaces the high byte of the address with the value written.
+        // LDX #$01; LDY #$ff; SHX $1080,Y
+        let (_, ram) = run(&[0xa2, 0x01, 0xa0, 0xff, 0x9e, 0x80, 0x10], 3);
+        assert_eq!(ram.borrow().bytes[0x017f], 0x01);
+        assert_eq!(ram.borrow().bytes[0x117f], 0x00);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_twice() {
+        // INC $80
+        let (_, ram) = run(&[0xe6, 0x80], 1);
+        assert_eq!(ram.borrow().writes, vec![(0x0080, 0x00), (0x0080, 0x01)]);
+    }
+
+    #[test]
+    fn test_indexed_dummy_reads() {
+        // LDX #$10; LDA $10f8,X reads from $1008 before $1108
+        let (_, ram) = run(&[0xa2, 0x10, 0xbd, 0xf8, 0x10], 2);
+        let reads = &ram.borrow().reads;
+        assert_eq!(reads[reads.len() - 2 ..], [0x1008, 0x1108]);
+
+        // Without crossing a page, there's no dummy read
+        // LDX #$01; LDA $1080,X
+        let (_, ram) = run(&[0xa2, 0x01, 0xbd, 0x80, 0x10], 2);
+        assert_eq!(ram.borrow().reads.iter().filter(|&&a| a == 0x1081).count(), 1);
+
+        // But stores always make one
+        // LDX #$01; STA $0080,X
+        let (_, ram) = run(&[0xa2, 0x01, 0x9d, 0x80, 0x00], 2);
+        assert!(ram.borrow().reads.contains(&0x0081));
+        assert_eq!(ram.borrow().writes, vec![(0x0081, 0x00)]);
+    }
+
+    #[test]
+    fn test_indexed_store_timing() {
+        // Stores take 5 cycles whether or not they cross a page, and only write to the right
+        // address, after the dummy read from the partially carried one
+        for &(x, uncarried, addr) in [(0x01, 0x10f9, 0x10f9), (0x10, 0x1008, 0x1108)].iter() {
+            // LDX #x; STA $10f8,X
+            let (mut cpu, ram) = run(&[0xa2, x, 0x9d, 0xf8, 0x10], 1);
+            let reads = ram.borrow().reads.len();
+
+            assert_eq!(cpu.step(), 5);
+            assert_eq!(ram.borrow().reads[reads + 3], uncarried);
+            assert_eq!(ram.borrow().writes, vec![(addr, 0x00)]);
+
+            // The same goes for STA (zp),Y, which always takes 6
+            // LDY #x; STA ($80),Y
+            let (mut cpu, ram) = run(&[0xa0, x, 0x91, 0x80], 1);
+            ram.borrow_mut().bytes[0x80] = 0xf8;
+            ram.borrow_mut().bytes[0x81] = 0x10;
+
+            assert_eq!(cpu.step(), 6);
+            assert!(ram.borrow().reads.contains(&uncarried));
+            assert_eq!(ram.borrow().writes, vec![(addr, 0x00)]);
+        }
+    }
+
+    #[test]
+    fn test_rdy_halts_on_read_cycles() {
+        // STA $80; LDA #$01
+        let (mut cpu, ram) = run(&[0x85, 0x80, 0xa9, 0x01], 0);
+        cpu.a = 0x42;
+
+        // Halted from the start, the opcode fetch doesn't happen
+        let reads = ram.borrow().reads.len();
+        cpu.set_rdy(false);
+        cpu.clock();
+        assert_eq!(ram.borrow().reads.len(), reads);
+
+        // Two cycles in, the next cycle is STA's write, which goes ahead regardless
+        cpu.set_rdy(true);
+        cpu.clock();
+        cpu.clock();
+        cpu.set_rdy(false);
+        cpu.clock();
+        assert_eq!(ram.borrow().writes, vec![(0x0080, 0x42)]);
+
+        // But the fetch of the next instruction waits
+        let pc = cpu.pc;
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.pc, pc);
+
+        cpu.set_rdy(true);
+        cpu.clock();
+        cpu.clock();
+        assert_eq!(cpu.a, 0x01);
+    }
+
+    #[test]
+    fn test_jam_halts_until_reset() {
+        // LDA #$01; JAM; LDA #$02
+        let (mut cpu, _) = run(&[0xa9, 0x01, 0x02, 0xa9, 0x02], 3);
+        assert_eq!(cpu.jammed(), Some(0x1002));
+        assert_eq!(cpu.a, 0x01);
+
+        for _ in 0 .. 10 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.a, 0x01);
+
+        cpu.reset();
+        assert_eq!(cpu.jammed(), None);
+        assert_eq!(cpu.pc, 0x1000);
+    }
+
+    fn bcd(n: u8) -> u8 { ((n / 10) << 4) | (n % 10) }
+
+    // Run ADC or SBC of `val` on the accumulator, returning the result, along with the C, Z, N,
+    // and V flags
+    fn add_or_subtract(cpu: &mut CPU6507<Ram>, ram: &Rc<RefCell<Memory>>, subtract: bool, decimal: bool,
+                       a: u8, val: u8, carry: bool) -> (u8, bool, bool, bool, bool) {
+        ram.borrow_mut().bytes[0x0080] = val;
+        cpu.a = a;
+        cpu.c = carry;
+        cpu.d = decimal;
+
+        if subtract {
+            cpu.sbc(0x0080);
+        } else {
+            cpu.adc(0x0080);
+        }
+
+        (cpu.a, cpu.c, cpu.z, cpu.s, cpu.v)
+    }
+
+    #[test]
+    fn test_decimal_adc() {
+        let (mut cpu, ram) = run(&[], 0);
+
+        for a in 0 .. 100 {
+            for b in 0 .. 100 {
+                for &c in [false, true].iter() {
+                    let sum = a + b + c as u8;
+                    let (result, carry, zero, _, _) = add_or_subtract(&mut cpu, &ram, false, true, bcd(a), bcd(b), c);
+
+                    assert_eq!(result, bcd(sum % 100), "{} + {} + {}", a, b, c);
+                    assert_eq!(carry, sum >= 100, "{} + {} + {}", a, b, c);
+
+                    // The zero flag is from the binary addition
+                    assert_eq!(zero, bcd(a).wrapping_add(bcd(b)).wrapping_add(c as u8) == 0);
+                }
+            }
+        }
+
+        // 99 + 1 is 0 with a carry, but the zero flag isn't set
+        assert_eq!(add_or_subtract(&mut cpu, &ram, false, true, 0x99, 0x01, false), (0x00, true, false, true, false));
+
+        // N and V come from the result before the high nybble is adjusted
+        assert_eq!(add_or_subtract(&mut cpu, &ram, false, true, 0x79, 0x00, true), (0x80, false, false, true, true));
+        assert_eq!(add_or_subtract(&mut cpu, &ram, false, true, 0x24, 0x56, false), (0x80, false, false, true, true));
+        assert_eq!(add_or_subtract(&mut cpu, &ram, false, true, 0x93, 0x82, false), (0x75, true, false, false, true));
+        assert_eq!(add_or_subtract(&mut cpu, &ram, false, true, 0x89, 0x76, false), (0x65, true, false, false, false));
+    }
+
+    #[test]
+    fn test_decimal_sbc() {
+        let (mut cpu, ram) = run(&[], 0);
+
+        for a in 0 .. 100 {
+            for b in 0 .. 100 {
+                for &c in [false, true].iter() {
+                    let difference = a as i16 - b as i16 - !c as i16;
+                    let (result, carry, _, _, _) = add_or_subtract(&mut cpu, &ram, true, true, bcd(a), bcd(b), c);
+
+                    assert_eq!(result, bcd(((difference + 100) % 100) as u8), "{} - {} - {}", a, b, !c);
+                    assert_eq!(carry, difference >= 0, "{} - {} - {}", a, b, !c);
+                }
+            }
+        }
+
+        // Every flag is from the binary subtraction, for any operands
+        for a in 0 ..= 255 {
+            for b in 0 ..= 255 {
+                for &c in [false, true].iter() {
+                    let (_, dc, dz, dn, dv) = add_or_subtract(&mut cpu, &ram, true, true, a, b, c);
+                    let (_, bc, bz, bn, bv) = add_or_subtract(&mut cpu, &ram, true, false, a, b, c);
+
+                    assert_eq!((dc, dz, dn, dv), (bc, bz, bn, bv), "{:02X} - {:02X} - {}", a, b, !c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trace_fn() {
+        // LDA #$42; TAX
+        let (mut cpu, _) = run(&[0xa9, 0x42, 0xaa], 0);
+
+        let trace = Rc::new(RefCell::new(vec![]));
+        let records = trace.clone();
+        cpu.set_trace_fn(move |record| records.borrow_mut().push(record.to_string()));
+
+        cpu.step();
+        cpu.step();
+        cpu.clear_trace_fn();
+        cpu.step();
+
+        assert_eq!(*trace.borrow(), vec![
+            "1000  A9 42     LDA #$42                         A:00 X:00 Y:00 P:24 SP:FF",
+            "1002  AA        TAX                              A:42 X:00 Y:00 P:24 SP:FF",
+        ]);
+    }
+
+    #[test]
+    fn test_trace_fn_peeks() {
+        // LDA $80; TAX
+        let program = [0xa5, 0x80, 0xaa];
+        let (mut cpu, ram) = run(&program, 0);
+        let (mut traced, traced_ram) = run(&program, 0);
+        traced.set_trace_fn(|_| { });
+
+        cpu.step();
+        cpu.step();
+        traced.step();
+        traced.step();
+
+        // The trace only peeks at the bytes, so tracing doesn't read anything more from the bus
+        assert_eq!(traced_ram.borrow().reads, ram.borrow().reads);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        // LDA #$42; STA $80; LDX #$07
+        let program = [0xa9, 0x42, 0x85, 0x80, 0xa2, 0x07];
+        let (mut cpu, ram) = run(&program, 0);
+
+        // Save partway through the STA
+        for _ in 0 .. 3 {
+            cpu.clock();
+        }
+
+        let path = std::env::temp_dir().join(format!("atari2600-cpu-state-{}", std::process::id()));
+        cpu.save(&mut File::create(&path).unwrap()).unwrap();
+
+        let (mut restored, restored_ram) = run(&program, 0);
+        restored.load(&mut File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((restored.a, restored.pc, restored.flags()), (0x42, 0x1004, cpu.flags()));
+
+        for _ in 0 .. 4 {
+            cpu.clock();
+            restored.clock();
+        }
+
+        assert_eq!((restored.a, restored.x, restored.pc, restored.cycles), (cpu.a, cpu.x, cpu.pc, cpu.cycles));
+        assert_eq!(restored_ram.borrow().bytes[0x80], 0x42);
+        assert_eq!(ram.borrow().bytes[0x80], 0x42);
+    }
+
+    #[test]
+    fn test_step_fn() {
+        // LDX #$01; DEX; BEQ +0; NOP
+        let program = [0xa2, 0x01, 0xca, 0xf0, 0x00, 0xea];
+
+        // Stepping and clocking through the program both report the same steps
+        for &clocked in [false, true].iter() {
+            let (mut cpu, _) = run(&program, 0);
+
+            let steps = Rc::new(RefCell::new(vec![]));
+            let records = steps.clone();
+            cpu.add_step_fn(move |record| records.borrow_mut().push(record.to_string()));
+
+            // Each step function's called, in the order they were added
+            let counted = Rc::new(RefCell::new(0));
+            let count = counted.clone();
+            cpu.add_step_fn(move |_| *count.borrow_mut() += 1);
+
+            if clocked {
+                for _ in 0 .. 9 {
+                    cpu.clock();
+                }
+            } else {
+                for _ in 0 .. 4 {
+                    cpu.step();
+                }
+            }
+
+            assert_eq!(*steps.borrow(), vec![
+                "1000  A2  A:00 X:01 Y:00 P:24 SP:FF CYC:2",
+                "1002  CA  A:00 X:00 Y:00 P:26 SP:FF CYC:4",
+                "1003  F0  A:00 X:00 Y:00 P:26 SP:FF CYC:7",
+                "1005  EA  A:00 X:00 Y:00 P:26 SP:FF CYC:9",
+            ]);
+            assert_eq!(*counted.borrow(), 4);
+        }
+    }
+
+    #[test]
+    fn test_irq() {
+        // CLI; NOP, with the IRQ handler at $1100
+        let (mut cpu, ram) = run(&[0x58, 0xea], 0);
+        ram.borrow_mut().bytes[0x1ffe] = 0x00;
+        ram.borrow_mut().bytes[0x1fff] = 0x11;
+
+        // Masked by the I flag
+        ram.borrow_mut().irq = true;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1001);
+
+        assert_eq!(cpu.step(), 7);
+        assert_eq!(cpu.pc, 0x1100);
+        assert!(cpu.i);
+
+        // The return address is the interrupted NOP, and B is clear in the pushed flags
+        let bytes = &ram.borrow().bytes;
+        assert_eq!((bytes[0x1ff], bytes[0x1fe], bytes[0x1fd]), (0x10, 0x01, 0x20));
+        assert_eq!(cpu.sp, 0xfc);
+    }
+
+    #[test]
+    fn test_nmi() {
+        // NOP; NOP, with the NMI handler at $1100, which is another NOP
+        let (mut cpu, ram) = run(&[0xea, 0xea], 0);
+        ram.borrow_mut().bytes[0x1100] = 0xea;
+        ram.borrow_mut().bytes[0x1ffa] = 0x00;
+        ram.borrow_mut().bytes[0x1ffb] = 0x11;
+
+        // Taken after the current instruction, even though the I flag is set, and only once
+        cpu.clock();
+        ram.borrow_mut().nmi = true;
+        for _ in 0 .. 10 {
+            cpu.clock();
+        }
+
+        assert_eq!((cpu.pc, cpu.current_cycles), (0x1101, 0));
+        assert!(!cpu.nmi_pending);
+        assert_eq!((ram.borrow().bytes[0x1fe], ram.borrow().bytes[0x1fd]), (0x01, 0x24));
+    }
+
+    #[test]
+    fn test_interrupt_writes_push_through_rdy() {
+        let (mut cpu, ram) = run(&[0xea], 0);
+        ram.borrow_mut().nmi = true;
+
+        // The first two cycles of the interrupt sequence are reads
+        cpu.set_rdy(false);
+        cpu.clock();
+        assert_eq!(cpu.sp, STACK_INIT);
+        assert_eq!(cpu.current_cycles, 0);
+
+        cpu.set_rdy(true);
+        cpu.clock();
+        cpu.clock();
+        cpu.set_rdy(false);
+
+        // Then the three pushes, which the RDY line doesn't stop
+        for _ in 0 .. 3 {
+            cpu.clock();
+        }
+        assert_eq!(cpu.current_cycles, 2);
+
+        cpu.clock();
+        assert_eq!(cpu.current_cycles, 2);
+    }
+
+    #[test]
+    fn test_trace_formats() {
+        // LDA #$42; SAX $80
+        let (mut cpu, _) = run(&[0xa9, 0x42, 0x87, 0x80], 0);
+
+        let trace = Rc::new(RefCell::new(vec![]));
+        let records = trace.clone();
+        cpu.set_trace_fn(move |record| records.borrow_mut().push(record.format(TraceFormat::Nestest)));
+
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(*trace.borrow(), vec![
+            "1000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FF CYC:0",
+            "1002  87 80    *SAX $80                         A:42 X:00 Y:00 P:24 SP:FF CYC:2",
+        ]);
+
+        assert_eq!("NESTEST".parse(), Ok(TraceFormat::Nestest));
+        assert!("nes".parse::<TraceFormat>().is_err());
     }
 }
@@ -0,0 +1,202 @@
+// Runs the per-opcode test vectors from https://github.com/TomHarte/ProcessorTests (the 6502
+// ones) against the CPU. Each opcode has a JSON file of 10,000 tests, each of which gives the
+// state of the registers and memory before and after running a single instruction, along with
+// every bus access it makes.
+//
+// These are opt-in, as the test vectors aren't part of this repository. To run them, point
+// PROCESSOR_TESTS at the directory of JSON files and run the ignored tests:
+//
+//     PROCESSOR_TESTS=ProcessorTests/6502/v1 cargo test processor_tests -- --ignored --nocapture
+//
+// The registers, memory, and number of cycles are checked. The bus accesses aren't checked yet,
+// as the CPU makes all of an instruction's accesses at once, rather than one per cycle.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use serde_json::Value;
+
+use crate::bus::Bus;
+use crate::cpu6507::CPU6507;
+
+// Opcodes whose results depend on unstable analog behaviour, and so vary between chips
+const UNSTABLE_OPCODES: [u8; 7] = [0x8b, 0x93, 0x9b, 0x9c, 0x9e, 0x9f, 0xab];
+
+// Opcodes that lock up the CPU
+const JAM_OPCODES: [u8; 12] = [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xb2, 0xd2, 0xf2];
+
+// The test vectors' values, which panic when they aren't what the tests expect
+fn number(value: &Value) -> u64 {
+    value.as_u64().unwrap_or_else(|| panic!("expected a number, found {}", value))
+}
+
+fn array(value: &Value) -> &[Value] {
+    value.as_array().unwrap_or_else(|| panic!("expected an array, found {}", value))
+}
+
+struct Ram(Rc<RefCell<Vec<u8>>>);
+
+impl Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 { self.0.borrow()[address as usize] }
+    fn write(&mut self, address: u16, val: u8) { self.0.borrow_mut()[address as usize] = val }
+}
+
+fn ram_entries(state: &Value) -> Vec<(u16, u8)> {
+    array(&state["ram"])
+        .iter()
+        .map(|entry| {
+            let entry = array(entry);
+            (number(&entry[0]) as u16, number(&entry[1]) as u8)
+        })
+        .collect()
+}
+
+// The 6507 only has 13 address lines, so tests that use two addresses that are the same in the
+// lower 13 bits can't be run against it
+fn addresses_alias(test: &Value) -> bool {
+    let mut addresses = ram_entries(&test["initial"]);
+    addresses.extend(ram_entries(&test["final"]));
+    addresses.extend(array(&test["cycles"]).iter().map(|c| (number(&array(c)[0]) as u16, 0)));
+
+    let mut seen = BTreeMap::new();
+    addresses.iter().any(|&(address, _)| *seen.entry(address & 0x1fff).or_insert(address) != address)
+}
+
+// Runs a single test, returning a description of what went wrong if it failed
+fn run_test(test: &Value) -> Result<(), String> {
+    let initial = &test["initial"];
+    let expected = &test["final"];
+
+    let memory = Rc::new(RefCell::new(vec![0; 0x2000]));
+    for (address, val) in ram_entries(initial) {
+        memory.borrow_mut()[(address & 0x1fff) as usize] = val;
+    }
+
+    let mut cpu = CPU6507::new(Ram(memory.clone()));
+    cpu.pc = number(&initial["pc"]) as u16;
+    cpu.sp = number(&initial["s"]) as u8;
+    cpu.a = number(&initial["a"]) as u8;
+    cpu.x = number(&initial["x"]) as u8;
+    cpu.y = number(&initial["y"]) as u8;
+    cpu.set_flags(number(&initial["p"]) as u8);
+
+    let cycles = cpu.step();
+
+    let registers = [
+        ("pc", cpu.pc as u64),
+        ("s", cpu.sp as u64),
+        ("a", cpu.a as u64),
+        ("x", cpu.x as u64),
+        ("y", cpu.y as u64),
+        ("p", cpu.flags() as u64),
+    ];
+
+    for &(name, actual) in registers.iter() {
+        let want = number(&expected[name]);
+        if actual != want {
+            return Err(format!("{} is {:02X}, expected {:02X}", name, actual, want));
+        }
+    }
+
+    for (address, want) in ram_entries(expected) {
+        let actual = memory.borrow()[(address & 0x1fff) as usize];
+        if actual != want {
+            return Err(format!("${:04X} is {:02X}, expected {:02X}", address, actual, want));
+        }
+    }
+
+    let want = array(&test["cycles"]).len() as u64;
+    if cycles != want {
+        return Err(format!("took {} cycles, expected {}", cycles, want));
+    }
+
+    Ok(())
+}
+
+// Runs every test for an opcode, returning how many passed, how many were skipped, and the first
+// failure
+fn run_opcode(tests: &Value) -> (usize, usize, Option<String>) {
+    let mut passed = 0;
+    let mut skipped = 0;
+    let mut failure = None;
+
+    for test in array(tests) {
+        if addresses_alias(test) {
+            skipped += 1;
+            continue;
+        }
+
+        match run_test(test) {
+            Ok(()) => passed += 1,
+            Err(e) => if failure.is_none() {
+                failure = Some(format!("{}: {}", test["name"].as_str().unwrap_or(""), e));
+            },
+        }
+    }
+
+    (passed, skipped, failure)
+}
+
+#[test]
+#[ignore]
+fn test_processor_tests() {
+    let dir = env::var("PROCESSOR_TESTS").expect("PROCESSOR_TESTS isn't set to the directory of test vectors");
+    let mut failed = vec![];
+
+    for opcode in 0 ..= 255u8 {
+        if UNSTABLE_OPCODES.contains(&opcode) || JAM_OPCODES.contains(&opcode) {
+            println!("{:02x}: skipped", opcode);
+            continue;
+        }
+
+        let path = Path::new(&dir).join(format!("{:02x}.json", opcode));
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                println!("{:02x}: unable to read {}: {}", opcode, path.display(), e);
+                continue;
+            },
+        };
+
+        let tests = serde_json::from_str(&text).expect("unable to parse test vectors");
+        let (passed, skipped, failure) = run_opcode(&tests);
+
+        match failure {
+            None => println!("{:02x}: pass ({} tests, {} skipped)", opcode, passed, skipped),
+            Some(e) => {
+                println!("{:02x}: FAIL ({} passed, {} skipped), first failure: {}", opcode, passed, skipped, e);
+                failed.push(opcode);
+            },
+        }
+    }
+
+    assert!(failed.is_empty(), "failing opcodes: {:02x?}", failed);
+}
+
+#[test]
+fn test_run_test_vector() {
+    // LDA #$42, in the same format as the test vectors
+    let tests = serde_json::from_str(r#"[
+        {
+            "name": "a9 42 00",
+            "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                         "ram": [ [4096, 169], [4097, 66] ] },
+            "final":   { "pc": 4098, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36,
+                         "ram": [ [4096, 169], [4097, 66] ] },
+            "cycles":  [ [4096, 169, "read"], [4097, 66, "read"] ]
+        },
+        {
+            "name": "aliased",
+            "initial": { "pc": 4096, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38,
+                         "ram": [ [4096, 169], [4097, 66], [12288, 0], [4096, 1] ] },
+            "final":   { "pc": 4098, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36, "ram": [] },
+            "cycles":  []
+        }
+    ]"#).unwrap();
+
+    assert_eq!(run_opcode(&tests), (1, 1, None));
+}
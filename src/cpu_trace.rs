@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::cpu6507::{Instruction, Opcode, TraceFormat, TraceRecord, OPCODES};
+
+// Records the instructions the CPU executes. They can be streamed out to a file as they're
+// executed, and the last few of them can be kept in a ring buffer, which is written out when the
+// CPU jams, or whenever it's asked for, so that the history leading up to a crash is there without
+// having had the whole trace written out the whole time.
+pub struct CpuTrace {
+    format: TraceFormat,
+
+    // Where every instruction's written as it's executed
+    stream: Option<BufWriter<File>>,
+
+    // The last instructions executed, oldest first, how many of them to keep, and where to write
+    // them out to
+    history: VecDeque<TraceRecord>,
+    history_length: usize,
+    history_path: Option<String>,
+}
+
+impl CpuTrace {
+    pub fn new(format: TraceFormat) -> Self {
+        Self {
+            format: format,
+            stream: None,
+            history: VecDeque::new(),
+            history_length: 0,
+            history_path: None,
+        }
+    }
+
+    pub fn stream_to(&mut self, file: File) {
+        self.stream = Some(BufWriter::new(file));
+    }
+
+    pub fn keep_history(&mut self, path: &str, length: usize) {
+        self.history_path = Some(path.to_string());
+        self.history_length = length;
+        self.history = VecDeque::with_capacity(length);
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        if let Some(stream) = &mut self.stream {
+            if let Err(e) = writeln!(stream, "{}", record.format(self.format)) {
+                error!("unable to write cpu trace: {}", e);
+            }
+        }
+
+        if self.history_length == 0 {
+            return;
+        }
+
+        if self.history.len() == self.history_length {
+            self.history.pop_front();
+        }
+        self.history.push_back(record.clone());
+
+        // The JAM is the last instruction the CPU will execute until it's reset
+        if let Opcode(Instruction::JAM, _, _, _) = OPCODES[record.bytes[0] as usize] {
+            self.write_history();
+        }
+    }
+
+    // Writes the history out, replacing whatever was written out last time
+    pub fn write_history(&self) {
+        let path = match &self.history_path {
+            Some(path) => path,
+            None       => return,
+        };
+
+        let result = File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            self.write_history_to(&mut out)?;
+            out.flush()
+        });
+
+        match result {
+            Ok(()) => info!("CPU: wrote the last {} instructions to {}", self.history.len(), path),
+            Err(e) => error!("unable to write cpu history to {}: {}", path, e),
+        }
+    }
+
+    fn write_history_to(&self, out: &mut impl Write) -> io::Result<()> {
+        for record in &self.history {
+            writeln!(out, "{}", record.format(self.format))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(pc: u16, bytes: Vec<u8>) -> TraceRecord {
+        TraceRecord {
+            pc: pc,
+            bytes: bytes,
+            a: 0,
+            x: 0,
+            y: 0,
+            p: 0x24,
+            sp: 0xfd,
+            cycles: 0,
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let mut trace = CpuTrace::new(TraceFormat::Nestest);
+        trace.history_length = 2;
+
+        trace.record(&record(0xf000, vec![0xea]));
+        trace.record(&record(0xf001, vec![0xe8]));
+        trace.record(&record(0xf002, vec![0xa9, 0x10]));
+
+        let mut out = vec![];
+        trace.write_history_to(&mut out).unwrap();
+
+        let lines = String::from_utf8(out).unwrap();
+        let pcs = lines.lines().map(|line| &line[.. 4]).collect::<Vec<_>>();
+        assert_eq!(pcs, vec!["F001", "F002"]);
+    }
+}
@@ -1,49 +1,1198 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
 
-use crate::tia::TIA;
+use crate::bus::Bus;
+use crate::cpu6507::CPU6507;
+use crate::disasm::{disassemble, disassemble_around};
+use crate::frame_diff::FrameDiff;
+use crate::gdb::{GdbStub, Request};
+use crate::hotspots::Hotspots;
+use crate::profile::Profile;
+use crate::riot::{self, RIOT};
+use crate::state_dump::machine_state;
+use crate::symbols::Symbols;
+use crate::tia::{self, TiaSnapshot, TIA};
+use crate::timeline::Timeline;
+use crate::tui::{Panes, Tui};
+use crate::watch::{Expression, Watches};
+
+// How many instructions are shown before and after the one the CPU's about to execute
+const WINDOW_BEFORE: usize = 4;
+const WINDOW_AFTER: usize = 5;
+
+// How many lines of commands and what they printed the TUI keeps
+const LOG_LENGTH: usize = 1000;
+
+// How many of the hottest routines and instructions are shown by default
+const HOTSPOTS: usize = 10;
+
+const CLOCKS_PER_CPU_CYCLE: usize = 3;
+const CLOCKS_PER_SCANLINE: usize = 228;
+
+// Where the debugger stops: when the CPU gets to an address, or when the beam gets to a colour
+// clock of a scanline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Breakpoint {
+    Address(u16),
+    Beam(u16, u8),
+}
+
+impl Breakpoint {
+    // The 6507 only sees 13 bits of the address, so an address covers all of its mirrors
+    fn covers(&self, other: &Breakpoint) -> bool {
+        match (*self, *other) {
+            (Breakpoint::Address(a), Breakpoint::Address(b)) => a & 0x1fff == b & 0x1fff,
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Address(address)      => write!(f, "${:04X}", address),
+            Breakpoint::Beam(scanline, clock) => write!(f, "scanline {}, clock {}", scanline, clock),
+        }
+    }
+}
+
+// What the picture's compared against, or that it isn't
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Diff {
+    Previous,
+    Save,
+    Off,
+}
+
+// The commands that can be typed in at the debugger's prompt, once it's broken into a game
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    Step(usize),
+    Back(usize),
+    Scanline(usize),
+    Clocks(usize),
+    Continue,
+    Registers,
+    Memory(u16),
+    Break(Breakpoint),
+    Delete(Breakpoint),
+    Breakpoints,
+    Tia,
+    Timeline(Option<u16>),
+    Diff(Diff),
+    Dump(String),
+    Watch(Expression),
+    Watches,
+    Unwatch(usize),
+    Hot(usize),
+    Poke(u16, u8),
+    Help,
+}
+
+const HELP: &str = "\
+step, s [<count>]                    run the next instruction, or that many of them
+back, bk [<count>]                   go back an instruction, or that many of them
+line, l [<count>]                    run to the end of the scanline, or that many scanlines
+clock, k [<count>]                   run a colour clock, or that many of them
+cont, c                              carry on until the next breakpoint
+regs, r                              show the CPU's registers
+mem, m <addr>                        show the 16 bytes from an address
+poke, p <addr> <value>               write to RAM or a TIA or RIOT register, by its name or
+                                     address, e.g. poke COLUBK $44 or poke 80 10
+break, b <addr>                      stop at an address
+break, b scanline <line> [<clock>]   stop when the beam gets to a scanline, and colour clock
+break, b                             list the breakpoints
+delete, d <addr>                     remove a breakpoint
+delete, d scanline <line> [<clock>]
+tia, t                               show the TIA's registers, objects, and collisions
+timeline, tl [<line>]                show the cycles each scanline's instructions ran on, or
+                                     the instructions on one scanline
+diff                                 highlight what's changed in the picture since the last frame
+diff save                            save the last frame, and highlight what's changed since it
+diff off                             stop highlighting what's changed
+dump <file>                          write the whole machine's state to a JSON file
+watch, w <expr>                      print a line whenever an expression's value changes, e.g.
+                                     $d3, a+x, or $80&#$0f
+watch, w                             list the watches
+unwatch <number>                     remove a watch
+hot [<count>]                        show the routines and instructions that took the most
+                                     cycles last frame, or the 10 of each (with --profile)
+help, h                              show this
+
+An empty line repeats the last command. Addresses are in hex, e.g. f000, $f000 or 0xf000, or the
+names of symbols, values are in decimal, or hex with a $ or 0x, and scanlines and colour clocks
+(0-227, with the picture starting at 68) are in decimal. Going back is as far as the start of the
+frame, and only while debugging's on (`), the same as the timeline.
+
+Watches are checked whenever it stops and at the end of each frame. An expression is made of the
+registers (a, x, y, p, sp, and pc), addresses, and numbers (#$10 or #16), with +, -, &, |, and ^
+worked out left to right.";
+
+// An address, by the name of a symbol, or in hex with or without a $ or 0x in front of it
+fn parse_address(s: &str, symbols: &Symbols) -> Result<u16, String> {
+    if let Some(address) = symbols.address(s) {
+        return Ok(address);
+    }
+
+    let digits = s.trim_start_matches('$').trim_start_matches("0x");
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address: {}", s))
+}
+
+// Somewhere to write to, which can be a TIA or RIOT register by name as well as an address, unless
+// there's a symbol with the same name
+fn parse_target(s: &str, symbols: &Symbols) -> Result<u16, String> {
+    match tia::register_address(s).or_else(|| riot::register_address(s)) {
+        Some(address) if symbols.address(s).is_none() => Ok(address),
+        _                                             => parse_address(s, symbols),
+    }
+}
+
+// A byte, in hex with a $ or 0x in front of it, or else in decimal
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let parsed = match s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None      => s.parse::<u8>(),
+    };
+
+    parsed.map_err(|_| format!("invalid value: {}", s))
+}
+
+// A number of times to do something, in decimal
+fn parse_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _                      => Err(format!("invalid count: {}", s)),
+    }
+}
+
+// A scanline, and a colour clock within it that defaults to the start of the scanline
+fn parse_beam(scanline: &str, clock: Option<&str>) -> Result<Breakpoint, String> {
+    let scanline = scanline.parse::<u16>().map_err(|_| format!("invalid scanline: {}", scanline))?;
+    let clock = match clock {
+        Some(clock) => match clock.parse::<u8>() {
+            Ok(c) if c < 228 => c,
+            _                => return Err(format!("invalid colour clock: {}", clock)),
+        },
+        None => 0,
+    };
+
+    Ok(Breakpoint::Beam(scanline, clock))
+}
+
+// A breakpoint, from the words after break or delete
+fn parse_breakpoint(words: &[&str], symbols: &Symbols) -> Result<Breakpoint, String> {
+    match *words {
+        ["scanline", scanline]        => parse_beam(scanline, None),
+        ["scanline", scanline, clock] => parse_beam(scanline, Some(clock)),
+        [address]                     => Ok(Breakpoint::Address(parse_address(address, symbols)?)),
+        _                             => Err(format!("invalid breakpoint: {}", words.join(" "))),
+    }
+}
+
+impl Command {
+    // The command names aren't case sensitive, but symbol names are
+    fn parse(s: &str, symbols: &Symbols) -> Result<Self, String> {
+        let words = s.split_whitespace().collect::<Vec<_>>();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (name.to_lowercase(), args),
+            None               => return Err(format!("unknown command: {}", s.trim())),
+        };
+
+        match (name.as_str(), args) {
+            ("step" | "s", [])               => Ok(Command::Step(1)),
+            ("step" | "s", [count])          => Ok(Command::Step(parse_count(count)?)),
+            ("back" | "bk", [])              => Ok(Command::Back(1)),
+            ("back" | "bk", [count])         => Ok(Command::Back(parse_count(count)?)),
+            ("line" | "l", [])               => Ok(Command::Scanline(1)),
+            ("line" | "l", [count])          => Ok(Command::Scanline(parse_count(count)?)),
+            ("clock" | "k", [])              => Ok(Command::Clocks(1)),
+            ("clock" | "k", [count])         => Ok(Command::Clocks(parse_count(count)?)),
+            ("cont" | "c", [])               => Ok(Command::Continue),
+            ("regs" | "r", [])               => Ok(Command::Registers),
+            ("mem" | "m", [address])         => Ok(Command::Memory(parse_address(address, symbols)?)),
+            ("poke" | "p", [target, value])  => Ok(Command::Poke(parse_target(target, symbols)?, parse_byte(value)?)),
+            ("break" | "b", [])              => Ok(Command::Breakpoints),
+            ("break" | "b", rest)            => Ok(Command::Break(parse_breakpoint(rest, symbols)?)),
+            ("delete" | "d", rest)           => Ok(Command::Delete(parse_breakpoint(rest, symbols)?)),
+            ("tia" | "t", [])                => Ok(Command::Tia),
+            ("timeline" | "tl", [])          => Ok(Command::Timeline(None)),
+            ("timeline" | "tl", [scanline])  => {
+                let scanline = scanline.parse::<u16>().map_err(|_| format!("invalid scanline: {}", scanline))?;
+                Ok(Command::Timeline(Some(scanline)))
+            },
+            ("diff", [])                     => Ok(Command::Diff(Diff::Previous)),
+            ("diff", ["save"])               => Ok(Command::Diff(Diff::Save)),
+            ("diff", ["off"])                => Ok(Command::Diff(Diff::Off)),
+            ("dump", [path])                 => Ok(Command::Dump(path.to_string())),
+            ("watch" | "w", [])              => Ok(Command::Watches),
+            ("watch" | "w", rest)            => {
+                Ok(Command::Watch(Expression::parse(&rest.join(" "), |s| parse_address(s, symbols))?))
+            },
+            ("unwatch", [number])            => Ok(Command::Unwatch(parse_count(number)?)),
+            ("hot", [])                      => Ok(Command::Hot(HOTSPOTS)),
+            ("hot", [count])                 => Ok(Command::Hot(parse_count(count)?)),
+            ("help" | "h", [])               => Ok(Command::Help),
+            _                                => Err(format!("unknown command: {}", s.trim())),
+        }
+    }
+}
+
+// The state of the whole machine as the CPU finished an instruction, for going back to
+struct Snapshot {
+    // The CPU, and everything on the bus, as the CPU saves them
+    cpu: Vec<u8>,
+    cycles: u64,
+    tia: TiaSnapshot,
+
+    // How many scanlines of the frame had been generated, and whether the one being generated
+    // started in VSYNC and VBLANK
+    scanlines: usize,
+    line_start: (bool, bool),
+
+    // How many instructions of the frame's timeline had been recorded
+    timeline: usize,
+}
 
 pub struct Debugger {
     tia: Rc<RefCell<TIA>>,
+    riot: Rc<RefCell<RIOT>>,
+    rom: Vec<u8>,
+    symbols: Symbols,
+    profile: Option<Rc<RefCell<Profile>>>,
+    hotspots: Option<Rc<RefCell<Hotspots>>>,
     enabled: bool,
 
     next_frame: bool,
+
+    // Where to break, and how many more instructions to run before breaking wherever they are
+    breakpoints: BTreeSet<Breakpoint>,
+    steps: usize,
+    last_command: Option<Command>,
+
+    // How many more colour clocks to run before breaking, wherever the CPU's got to
+    clocks: usize,
+
+    // How many more scanlines to run before stopping, and whether to bring the prompt back up
+    // then, when it's the prompt that's stepping through them rather than the keyboard
+    scanlines: usize,
+    scanline_prompt: bool,
+
+    // The state after each instruction of the frame so far, oldest first, kept while debugging's
+    // on. It's cleared at the end of each frame, as the frames before it have already been drawn.
+    history: Vec<Snapshot>,
+
+    // Which cycles of which scanlines the frame's instructions have been executed on, kept while
+    // debugging's on, the same as the history
+    timeline: Timeline,
+
+    // How many scanlines of the frame have been generated, and whether the one being generated
+    // started in VSYNC and VBLANK
+    frame_scanlines: usize,
+    line_start: (bool, bool),
+
+    // The scanline that going back has taken the frame back to, and whether it started in VSYNC
+    // and VBLANK, for the frame being generated to go back there too
+    rewound: Option<(usize, bool, bool)>,
+
+    // The expressions being watched, and the address of the instruction being executed, for
+    // finding which one changed the memory they read
+    watches: Watches,
+    current_pc: u16,
+
+    // A gdb frontend that's connected, which has control in place of the prompt
+    gdb: Option<GdbStub>,
+
+    // Whether the prompt's in the TUI, and the commands typed into it along with what they printed
+    tui: bool,
+    log: Vec<String>,
+
+    // The frontend's comparison of each frame's picture against an earlier one
+    frame_diff: Option<Rc<RefCell<FrameDiff>>>,
+
+    // Called whenever it stops, with how many scanlines of the frame have been generated, for the
+    // frontend to show where it's stopped
+    stop_fn: Option<Box<dyn FnMut(usize)>>,
 }
 
 impl Debugger {
-    pub fn new(tia: Rc<RefCell<TIA>>) -> Self {
+    pub fn new(tia: Rc<RefCell<TIA>>, riot: Rc<RefCell<RIOT>>, rom: Vec<u8>, symbols: Symbols, profile: Option<Rc<RefCell<Profile>>>) -> Self {
         Self {
             tia: tia,
+            riot: riot,
+            rom: rom,
+            symbols: symbols,
+            profile: profile,
+            hotspots: None,
             enabled: false,
 
             next_frame: false,
+
+            breakpoints: BTreeSet::new(),
+            steps: 0,
+            last_command: None,
+
+            clocks: 0,
+
+            scanlines: 0,
+            scanline_prompt: false,
+
+            history: vec![],
+            timeline: Timeline::new(),
+
+            frame_scanlines: 0,
+            line_start: (false, false),
+
+            rewound: None,
+
+            watches: Watches::new(),
+            current_pc: 0,
+
+            gdb: None,
+
+            tui: false,
+            log: vec![],
+
+            frame_diff: None,
+            stop_fn: None,
         }
     }
 
     // Enable/disable the debugger
     pub fn toggle(&mut self) {
         self.enabled = ! self.enabled;
+        self.timeline = Timeline::new();
 
         println!("Debugging is now: {}", if self.enabled { "on" } else { "off" });
     }
 
+    // Isolating audio channels
+    pub fn toggle_mute(&mut self, channel: usize) {
+        let muted = self.tia.borrow_mut().toggle_audio_mute(channel);
+
+        println!("Audio channel {} is now: {}", channel, if muted { "muted" } else { "unmuted" });
+    }
+
+    pub fn toggle_solo(&mut self, channel: usize) {
+        let solo = self.tia.borrow_mut().toggle_audio_solo(channel);
+
+        if solo {
+            println!("Audio channel {} is now: solo", channel);
+        } else {
+            println!("Audio solo is now: off");
+        }
+    }
+
+    pub fn print_objects(&self) {
+        println!("{}", self.tia.borrow().objects());
+    }
+
+    // Where the beam is, in scanlines and CPU cycles
+    pub fn print_position(&self) {
+        println!("{}", self.tia.borrow().position());
+    }
+
+    // Where each of the console switches is
+    pub fn print_switches(&self) {
+        println!("{}", self.riot.borrow().switches());
+    }
+
+    // The levels driven on the port A pins, with a dot for each bit that's an input
+    pub fn print_port_a(&self) {
+        let riot = self.riot.borrow();
+        let (output, mask) = (riot.port_a_output(), riot.port_a_output_mask());
+
+        let bits = (0 .. 8).rev()
+            .map(|bit| match (mask >> bit & 1, output >> bit & 1) {
+                (0, _) => '.',
+                (_, 0) => '0',
+                _      => '1',
+            })
+            .collect::<String>();
+
+        println!("Port A outputs: {}", bits);
+    }
+
+    // The next few instructions the CPU will execute, without following branches
+    pub fn print_disassembly<B: Bus>(&self, cpu: &CPU6507<B>) {
+        for line in disassemble(&|address| cpu.peek(address), cpu.pc, cpu.pc.saturating_add(31)) {
+            self.print_label(line.address, "");
+            println!("{}", line.format_with(&self.symbols));
+        }
+    }
+
+    // A line with the label for an address, if it has one, lined up with the instructions
+    fn print_label(&self, address: u16, indent: &str) {
+        if let Some(label) = self.symbols.label(address) {
+            println!("{}{}:", indent, label);
+        }
+    }
+
+    // The opcode counts so far, if profiling's turned on
+    pub fn print_profile(&self) {
+        match &self.profile {
+            Some(profile) => print!("{}", profile.borrow()),
+            None          => println!("Profiling is off, run with --profile to turn it on"),
+        }
+    }
+
+    // The routines and instructions that took the most cycles per frame, over all of the frames
+    pub fn print_hotspots<B: Bus>(&self, cpu: &CPU6507<B>) {
+        if let Some(hotspots) = &self.hotspots {
+            let (routine, instruction) = self.describe_hotspots(cpu);
+            for line in hotspots.borrow().per_frame(HOTSPOTS, routine, instruction) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    // Routines are named by their labels, and instructions are disassembled
+    fn describe_hotspots<'a, B: Bus>(&'a self, cpu: &'a CPU6507<B>) -> (impl Fn(u16) -> String + 'a, impl Fn(u16) -> String + 'a) {
+        let symbols = &self.symbols;
+        let routine = move |address| symbols.label(address).unwrap_or("").to_string();
+        let instruction = move |pc| disassemble(&|address| cpu.peek(address), pc, pc)[0].text_with(symbols);
+
+        (routine, instruction)
+    }
+
     pub fn debug(&self) {
         if !self.enabled { return }
         self.tia.borrow().debug();
     }
 
     // Controlling frame stepping
+    pub fn enabled(&self) -> bool { self.enabled }
+
     pub fn next_frame(&self) -> bool {
         if !self.enabled { return true }
 
-        self.next_frame
+        self.next_frame || self.scanlines > 0
     }
 
     pub fn step_frame(&mut self) {
         self.next_frame = true;
     }
 
-    pub fn end_frame(&mut self) {
+    pub fn end_frame<B: Bus>(&mut self, cpu: &CPU6507<B>) {
+        for line in self.watches.check(cpu) {
+            println!("{}", line);
+        }
+
         self.next_frame = false;
+        self.frame_scanlines = 0;
+        self.history.clear();
+        self.timeline.end_frame();
+    }
+
+    // Controlling scanline stepping, which stops part way through the frame so that the picture
+    // can be drawn as far as it's got
+    pub fn step_scanline(&mut self) {
+        self.scanlines = 1;
+        self.scanline_prompt = false;
+    }
+
+    // Called after each scanline, and returns whether to stop there
+    pub fn end_scanline(&mut self) -> bool {
+        if self.gdb.as_mut().is_some_and(|gdb| gdb.interrupted()) {
+            self.steps = 1;
+        }
+
+        self.frame_scanlines += 1;
+        self.line_start = {
+            let tia = self.tia.borrow();
+            (tia.in_vsync(), tia.in_vblank())
+        };
+
+        if self.scanlines == 0 {
+            return false;
+        }
+
+        self.scanlines -= 1;
+        self.scanlines == 0
+    }
+
+    // Called once the picture's been drawn after stopping
+    pub fn scanline_stopped<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        self.print_position();
+
+        if self.scanline_prompt {
+            self.scanline_prompt = false;
+            self.prompt(cpu);
+        } else {
+            self.stopped();
+        }
+    }
+
+    //
+    // Breakpoints and the command prompt
+    //
+
+    // Turns debugging on and stops before the CPU's executed anything, at the reset vector, so the
+    // game can be stepped through from its first instruction
+    pub fn start_halted<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        self.enabled = true;
+        self.timeline.record(cpu.pc, cpu.cycles(), 0);
+        let snapshot = self.snapshot(cpu);
+        self.history.push(snapshot);
+        self.current_pc = cpu.pc;
+
+        if !self.tui {
+            self.print_window(cpu);
+            println!("{}", self.registers(cpu));
+        }
+        self.prompt(cpu);
+    }
+
+    // Stops at the next instruction, to bring up the prompt
+    pub fn break_in(&mut self) {
+        println!("Breaking at the next instruction");
+        self.steps = 1;
+    }
+
+    // Called as the CPU finishes each instruction, with all of its cycles of TIA and RIOT activity,
+    // to break if it's run all of the instructions it was stepping through or has reached a
+    // breakpoint
+    pub fn instruction<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        if self.enabled {
+            let position = self.tia.borrow().position();
+            let clock = position.scanline as usize * CLOCKS_PER_SCANLINE + position.clock as usize;
+            self.timeline.record(cpu.pc, cpu.cycles(), clock / CLOCKS_PER_CPU_CYCLE + 1);
+
+            let snapshot = self.snapshot(cpu);
+            self.history.push(snapshot);
+        }
+
+        if !self.watches.is_empty() {
+            self.watches.instruction(self.current_pc, cpu);
+        }
+        self.current_pc = cpu.pc;
+
+        let stepped = self.steps == 1;
+        self.steps = self.steps.saturating_sub(1);
+
+        let address = Breakpoint::Address(cpu.pc);
+        if !stepped && !self.breakpoints.iter().any(|b| b.covers(&address)) {
+            return;
+        }
+
+        self.steps = 0;
+        if !self.tui {
+            self.print_window(cpu);
+            println!("{}", self.registers(cpu));
+        }
+        self.prompt(cpu);
+    }
+
+    // Called after each colour clock, to break if it's run all of the colour clocks it was stepping
+    // through or the beam's reached a breakpoint. The CPU might be part way through an instruction.
+    pub fn color_clock<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        let stepped = self.clocks == 1;
+        self.clocks = self.clocks.saturating_sub(1);
+
+        let position = self.tia.borrow().position();
+        if !stepped && !self.breakpoints.contains(&Breakpoint::Beam(position.scanline, position.clock)) {
+            return;
+        }
+
+        self.clocks = 0;
+        if !self.tui {
+            self.print_beam();
+        }
+        self.prompt(cpu);
+    }
+
+    // Where the beam is, down to the colour clock
+    fn print_beam(&self) {
+        let position = self.tia.borrow().position();
+        println!("{}, clock {}", position, position.clock);
+    }
+
+    pub fn set_tui(&mut self, tui: bool) { self.tui = tui }
+
+    pub fn set_hotspots(&mut self, hotspots: Rc<RefCell<Hotspots>>) {
+        self.hotspots = Some(hotspots);
+    }
+
+    pub fn set_frame_diff(&mut self, frame_diff: Rc<RefCell<FrameDiff>>) {
+        self.frame_diff = Some(frame_diff);
+    }
+
+    pub fn set_stop_fn(&mut self, stop_fn: impl FnMut(usize) + 'static) {
+        self.stop_fn = Some(Box::new(stop_fn));
+    }
+
+    fn stopped(&mut self) {
+        if let Some(stop_fn) = self.stop_fn.as_mut() {
+            stop_fn(self.frame_scanlines);
+        }
+    }
+
+    // Hands control over to a gdb frontend, stopping at the first instruction for it to take over
+    pub fn attach_gdb(&mut self, gdb: GdbStub) {
+        self.gdb = Some(gdb);
+        self.steps = 1;
+    }
+
+    // Reads commands until one of them carries on running the game, which stands still until then
+    fn prompt<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        self.stopped();
+
+        let changes = self.watches.check(cpu);
+        if self.tui {
+            self.log.extend(changes);
+        } else {
+            for line in changes {
+                println!("{}", line);
+            }
+        }
+
+        if let Some(gdb) = self.gdb.take() {
+            return self.serve_gdb(cpu, gdb);
+        }
+
+        if self.tui {
+            return self.tui_prompt(cpu);
+        }
+
+        loop {
+            print!("> ");
+            io::stdout().flush().ok();
+
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                // With nothing left to read, there's no way to stop again after carrying on
+                Ok(0) | Err(_) => {
+                    self.breakpoints.clear();
+                    return;
+                },
+                Ok(_) => { },
+            }
+
+            let command = match self.command(&line) {
+                Ok(Some(command)) => command,
+                Ok(None)          => continue,
+                Err(e) => {
+                    println!("{}, try help", e);
+                    continue;
+                },
+            };
+
+            let mut output = vec![];
+            let carry_on = self.execute(cpu, command, &mut output);
+
+            for line in output {
+                println!("{}", line);
+            }
+
+            if carry_on {
+                return;
+            }
+        }
+    }
+
+    // The same as the prompt, but with the machine's state shown in the panes of the TUI
+    fn tui_prompt<B: Bus>(&mut self, cpu: &mut CPU6507<B>) {
+        let mut tui = match Tui::start() {
+            Ok(tui) => tui,
+            Err(e) => {
+                error!("unable to start the TUI, using the prompt instead: {}", e);
+                self.tui = false;
+                return self.prompt(cpu);
+            },
+        };
+
+        loop {
+            let position = self.tia.borrow().position();
+            let panes = Panes {
+                disassembly: self.window(cpu),
+                registers: vec![self.registers(cpu), format!("{}, clock {}", position, position.clock)],
+                ram: (0x80 ..= 0xf0).step_by(16).map(|address| self.memory(cpu, address)).collect(),
+                tia: self.tia.borrow().state().to_string(),
+                log: &self.log,
+            };
+
+            let line = match tui.read_line(&panes) {
+                Ok(Some(line)) => line,
+                // As with the end of the prompt's input, carry on without the breakpoints
+                _ => {
+                    self.breakpoints.clear();
+                    return;
+                },
+            };
+
+            self.log.push(format!("> {}", line));
+
+            let command = match self.command(&line) {
+                Ok(Some(command)) => command,
+                Ok(None)          => continue,
+                Err(e) => {
+                    self.log.push(format!("{}, try help", e));
+                    continue;
+                },
+            };
+
+            let mut output = vec![];
+            let carry_on = self.execute(cpu, command, &mut output);
+
+            self.log.extend(output);
+            if self.log.len() > LOG_LENGTH {
+                self.log.drain(.. self.log.len() - LOG_LENGTH);
+            }
+
+            if carry_on {
+                return;
+            }
+        }
+    }
+
+    // The command typed in on a line, or the last one again for an empty line, if there was one
+    fn command(&mut self, line: &str) -> Result<Option<Command>, String> {
+        if line.trim().is_empty() {
+            return Ok(self.last_command.clone());
+        }
+
+        let command = Command::parse(line, &self.symbols)?;
+        self.last_command = Some(command.clone());
+
+        Ok(Some(command))
+    }
+
+    // Carries out a command, with what it prints added to the output, and returns whether the game
+    // carries on running
+    fn execute<B: Bus>(&mut self, cpu: &mut CPU6507<B>, command: Command, output: &mut Vec<String>) -> bool {
+        match command {
+            Command::Step(count) => {
+                self.steps = count;
+                return true;
+            },
+            Command::Scanline(count) => {
+                self.scanlines = count;
+                self.scanline_prompt = true;
+                return true;
+            },
+            Command::Clocks(count) => {
+                self.clocks = count;
+                return true;
+            },
+            Command::Continue => return true,
+            Command::Back(count) => self.step_back(cpu, count, output),
+
+            Command::Registers => output.push(self.registers(cpu)),
+            Command::Memory(address) => output.push(self.memory(cpu, address)),
+
+            // Written the same as the CPU would, so it takes effect from the next colour clock
+            Command::Poke(address, value) => {
+                cpu.write(address, value);
+                output.push(format!("Wrote ${:02X} to ${:04X}", value, address));
+            },
+
+            Command::Break(breakpoint) => {
+                self.breakpoints.insert(breakpoint);
+                output.push(format!("Breaking at {}", breakpoint));
+            },
+            Command::Delete(breakpoint) => {
+                let count = self.breakpoints.len();
+                self.breakpoints.retain(|b| !b.covers(&breakpoint));
+
+                if self.breakpoints.len() < count {
+                    output.push(format!("Removed the breakpoint at {}", breakpoint));
+                } else {
+                    output.push(format!("There's no breakpoint at {}", breakpoint));
+                }
+            },
+            Command::Breakpoints => {
+                if self.breakpoints.is_empty() {
+                    output.push("There are no breakpoints".to_string());
+                }
+
+                for breakpoint in &self.breakpoints {
+                    let label = match *breakpoint {
+                        Breakpoint::Address(address) => self.symbols.label(address),
+                        Breakpoint::Beam(..)         => None,
+                    };
+
+                    match label {
+                        Some(label) => output.push(format!("{} ({})", breakpoint, label)),
+                        None        => output.push(breakpoint.to_string()),
+                    }
+                }
+            },
+
+            Command::Timeline(scanline) => {
+                let symbols = &self.symbols;
+                let describe = |pc| disassemble(&|address| cpu.peek(address), pc, pc)[0].text_with(symbols);
+
+                match scanline {
+                    _ if self.timeline.is_empty() => {
+                        output.push("There's no timeline, it's recorded from the start of the frame while debugging's on".to_string())
+                    },
+                    Some(scanline) => output.extend(self.timeline.scanline(scanline as usize, describe)),
+                    None           => output.extend(self.timeline.scanlines(describe)),
+                }
+            },
+
+            Command::Diff(diff) => match &self.frame_diff {
+                Some(frame_diff) => {
+                    let mut frame_diff = frame_diff.borrow_mut();
+                    match diff {
+                        Diff::Previous => frame_diff.show_previous(),
+                        Diff::Save     => frame_diff.save_reference(),
+                        Diff::Off      => frame_diff.hide(),
+                    }
+
+                    if diff != Diff::Off {
+                        output.push(frame_diff.summary());
+                    }
+                },
+                None => output.push("There's no picture to compare".to_string()),
+            },
+
+            Command::Dump(path) => match self.dump(cpu, &path, None) {
+                Ok(()) => output.push(format!("Wrote the machine's state to {}", path)),
+                Err(e) => output.push(format!("Unable to write {}: {}", path, e)),
+            },
+
+            Command::Watch(expression) => output.push(self.watches.add(expression, cpu)),
+            Command::Watches => {
+                if self.watches.is_empty() {
+                    output.push("There are no watches".to_string());
+                }
+                output.extend(self.watches.list());
+            },
+            Command::Unwatch(number) => match self.watches.remove(number) {
+                Some(expression) => output.push(format!("Removed the watch on {}", expression)),
+                None             => output.push(format!("There's no watch {}", number)),
+            },
+
+            Command::Hot(count) => match &self.hotspots {
+                Some(hotspots) => {
+                    let (routine, instruction) = self.describe_hotspots(cpu);
+                    output.extend(hotspots.borrow().last_frame(count, routine, instruction));
+                },
+                None => output.push("Profiling is off, run with --profile to turn it on".to_string()),
+            },
+
+            Command::Tia => output.extend(self.tia.borrow().state().to_string().lines().map(String::from)),
+            Command::Help => output.extend(HELP.lines().map(String::from)),
+        }
+
+        false
+    }
+
+    // Answers gdb's requests until it steps or continues. When it goes away, the game carries on
+    // without its breakpoints.
+    fn serve_gdb<B: Bus>(&mut self, cpu: &mut CPU6507<B>, mut gdb: GdbStub) {
+        let result = gdb.stopped().and_then(|_| loop {
+            let reply = match gdb.request()? {
+                Request::HaltReason => "S05".to_string(),
+                Request::ReadRegisters => {
+                    // A, X, Y, P, S, and the PC, low byte first
+                    [cpu.a, cpu.x, cpu.y, cpu.flags(), cpu.sp(), cpu.pc as u8, (cpu.pc >> 8) as u8].iter()
+                        .map(|b| format!("{:02x}", b))
+                        .collect()
+                },
+                Request::ReadMemory(address, length) => {
+                    (0 .. length)
+                        .map(|i| format!("{:02x}", cpu.peek(address.wrapping_add(i as u16))))
+                        .collect()
+                },
+                Request::WriteMemory(address, bytes) => {
+                    for (i, &b) in bytes.iter().enumerate() {
+                        cpu.write(address.wrapping_add(i as u16), b);
+                    }
+                    "OK".to_string()
+                },
+                Request::InsertBreakpoint(address) => {
+                    self.breakpoints.insert(Breakpoint::Address(address));
+                    "OK".to_string()
+                },
+                Request::RemoveBreakpoint(address) => {
+                    self.breakpoints.retain(|b| !b.covers(&Breakpoint::Address(address)));
+                    "OK".to_string()
+                },
+                Request::Step => {
+                    self.steps = 1;
+                    gdb.resume();
+                    return Ok(true);
+                },
+                Request::Continue => {
+                    gdb.resume();
+                    return Ok(true);
+                },
+                Request::Detach => {
+                    gdb.reply("OK")?;
+                    return Ok(false);
+                },
+                Request::Supported | Request::Attached | Request::Unsupported | Request::TooLarge => "".to_string(),
+            };
+
+            gdb.reply(&reply)?;
+        });
+
+        match result {
+            Ok(true) => self.gdb = Some(gdb),
+            Ok(false) => {
+                info!("gdb: detached");
+                self.breakpoints.clear();
+            },
+            Err(e) => {
+                info!("gdb: disconnected: {}", e);
+                self.breakpoints.clear();
+            },
+        }
+    }
+
+    fn snapshot<B: Bus>(&self, cpu: &CPU6507<B>) -> Snapshot {
+        let mut saved = vec![];
+        cpu.save(&mut saved).expect("unable to save the CPU's state");
+
+        Snapshot {
+            cpu: saved,
+            cycles: cpu.cycles(),
+            tia: self.tia.borrow().snapshot(),
+            scanlines: self.frame_scanlines,
+            line_start: self.line_start,
+            timeline: self.timeline.len(),
+        }
+    }
+
+    // Writes the whole machine's state out as JSON, along with the last frame's picture, and the
+    // message the emulator panicked with, if it did
+    pub fn dump<B: Bus>(&self, cpu: &CPU6507<B>, path: &str, panic: Option<&str>) -> io::Result<()> {
+        let picture = match &self.frame_diff {
+            Some(frame_diff) => frame_diff.borrow().picture().to_vec(),
+            None             => vec![],
+        };
+
+        let state = machine_state(cpu, &self.tia.borrow(), &self.riot.borrow(), &self.rom, &picture, panic);
+        writeln!(File::create(path)?, "{:#}", state)
+    }
+
+    // Puts the machine back how it was a number of instructions ago. Part way through an
+    // instruction, going back one goes back to where the last one finished.
+    fn step_back<B: Bus>(&mut self, cpu: &mut CPU6507<B>, count: usize, output: &mut Vec<String>) {
+        let current = self.history.last().is_some_and(|snapshot| snapshot.cycles == cpu.cycles());
+        let available = self.history.len() - current as usize;
+
+        if available == 0 {
+            output.push("There's nothing to go back to, it's kept from the start of the frame while debugging's on".to_string());
+            return;
+        } else if count > available {
+            output.push(format!("It can only go back {} instructions, to the start of the frame", available));
+            return;
+        }
+
+        self.history.truncate(self.history.len() - count + 1 - current as usize);
+        if let Some(snapshot) = self.history.last() {
+            cpu.load(&mut snapshot.cpu.as_slice()).expect("unable to restore the CPU's state");
+            self.tia.borrow_mut().restore(&snapshot.tia);
+
+            let (vsync, vblank) = snapshot.line_start;
+            self.rewound = Some((snapshot.scanlines, vsync, vblank));
+            self.frame_scanlines = snapshot.scanlines;
+            self.line_start = snapshot.line_start;
+            self.timeline.rewind(snapshot.timeline, cpu.pc, cpu.cycles());
+        }
+
+        output.extend(self.window(cpu));
+        output.push(self.registers(cpu));
+        self.stopped();
+    }
+
+    // Where going back has taken the frame back to, if it's gone back since this was last called
+    pub fn take_rewind(&mut self) -> Option<(usize, bool, bool)> { self.rewound.take() }
+
+    // The instructions around the one the CPU's about to execute, which is marked with an arrow,
+    // and with a star by each one that has a breakpoint, along with their labels
+    fn window<B: Bus>(&self, cpu: &CPU6507<B>) -> Vec<String> {
+        let mut lines = vec![];
+
+        for line in disassemble_around(&|address| cpu.peek(address), cpu.pc, WINDOW_BEFORE, WINDOW_AFTER) {
+            let current = if line.address == cpu.pc { "=>" } else { "  " };
+            let address = Breakpoint::Address(line.address);
+            let breakpoint = if self.breakpoints.iter().any(|b| b.covers(&address)) { "*" } else { " " };
+
+            if let Some(label) = self.symbols.label(line.address) {
+                lines.push(format!("   {}:", label));
+            }
+            lines.push(format!("{}{} {}", current, breakpoint, line.format_with(&self.symbols)));
+        }
+
+        lines
+    }
+
+    fn print_window<B: Bus>(&self, cpu: &CPU6507<B>) {
+        for line in self.window(cpu) {
+            println!("{}", line);
+        }
+    }
+
+    // The registers, with the flags that are set in capitals
+    fn registers<B: Bus>(&self, cpu: &CPU6507<B>) -> String {
+        let p = cpu.flags();
+        let flags = "NV-BDIZC".chars()
+            .enumerate()
+            .map(|(i, flag)| if p & (0x80 >> i) != 0 { flag } else { flag.to_ascii_lowercase() })
+            .collect::<String>();
+
+        format!("PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} ({}) SP:{:02X} CYC:{}",
+                cpu.pc, cpu.a, cpu.x, cpu.y, p, flags, cpu.sp(), cpu.cycles())
+    }
+
+    // A line of memory, read without side effects, so it doesn't change how the game runs
+    fn memory<B: Bus>(&self, cpu: &CPU6507<B>, address: u16) -> String {
+        let bytes = (0 .. 16)
+            .map(|i| format!("{:02X}", cpu.peek(address.wrapping_add(i))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!("{:04X}  {}", address, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::bus::AtariBus;
+
+    fn parse(s: &str) -> Result<Command, String> {
+        Command::parse(s, &Symbols::new())
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(parse("step"), Ok(Command::Step(1)));
+        assert_eq!(parse("s 10"), Ok(Command::Step(10)));
+        assert_eq!(parse(" C \n"), Ok(Command::Continue));
+        assert_eq!(parse("mem 80"), Ok(Command::Memory(0x80)));
+        assert_eq!(parse("b $F00A"), Ok(Command::Break(Breakpoint::Address(0xf00a))));
+        assert_eq!(parse("break"), Ok(Command::Breakpoints));
+        assert_eq!(parse("d 0x1000"), Ok(Command::Delete(Breakpoint::Address(0x1000))));
+
+        assert_eq!(parse("break scanline 120"), Ok(Command::Break(Breakpoint::Beam(120, 0))));
+        assert_eq!(parse("b scanline 40 68"), Ok(Command::Break(Breakpoint::Beam(40, 68))));
+        assert!(parse("b scanline 40 228").is_err());
+        assert!(parse("b scanline").is_err());
+
+        assert_eq!(parse("line 3"), Ok(Command::Scanline(3)));
+        assert_eq!(parse("back"), Ok(Command::Back(1)));
+        assert_eq!(parse("BK 20"), Ok(Command::Back(20)));
+
+        assert_eq!(parse("clock"), Ok(Command::Clocks(1)));
+        assert_eq!(parse("k 8"), Ok(Command::Clocks(8)));
+
+        assert_eq!(parse("timeline"), Ok(Command::Timeline(None)));
+        assert_eq!(parse("tl 40"), Ok(Command::Timeline(Some(40))));
+        assert!(parse("tl -1").is_err());
+
+        assert_eq!(parse("diff"), Ok(Command::Diff(Diff::Previous)));
+        assert_eq!(parse("diff save"), Ok(Command::Diff(Diff::Save)));
+        assert!(parse("diff on").is_err());
+
+        assert_eq!(parse("dump Bug.json"), Ok(Command::Dump("Bug.json".to_string())));
+        assert!(parse("dump").is_err());
+
+        assert_eq!(parse("watch"), Ok(Command::Watches));
+        assert!(matches!(parse("w a + x"), Ok(Command::Watch(_))));
+        assert_eq!(parse("unwatch 2"), Ok(Command::Unwatch(2)));
+        assert!(parse("watch a+").is_err());
+
+        assert_eq!(parse("hot"), Ok(Command::Hot(10)));
+        assert_eq!(parse("hot 3"), Ok(Command::Hot(3)));
+
+        assert_eq!(parse("poke COLUBK $44"), Ok(Command::Poke(0x09, 0x44)));
+        assert_eq!(parse("p tim64t 0x10"), Ok(Command::Poke(0x0296, 0x10)));
+        assert_eq!(parse("poke $80 10"), Ok(Command::Poke(0x80, 10)));
+        assert!(parse("poke 80 256").is_err());
+        assert!(parse("poke 80").is_err());
+
+        assert!(parse("step 0").is_err());
+        assert!(parse("mem").is_err());
+        assert!(parse("mem zz").is_err());
+        assert!(parse("regs 80").is_err());
+        assert!(parse("go").is_err());
+    }
+
+    #[test]
+    fn test_step_back() {
+        // A ROM full of INXs and STXs to $80, starting at $F000
+        let mut rom = [0xe8, 0x86, 0x80].iter().cycle().take(0x1000).cloned().collect::<Vec<_>>();
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        riot.borrow_mut().write(0x0296, 0x10); // TIM64T
+        let mut cpu = CPU6507::new(AtariBus::new(tia.clone(), riot.clone(), rom.clone()));
+        cpu.reset();
+
+        let mut debugger = Debugger::new(tia.clone(), riot.clone(), rom, Symbols::new(), None);
+        debugger.enabled = true;
+
+        for c in 0 .. 228 * 3 {
+            if c % 3 == 0 {
+                riot.borrow_mut().clock();
+            }
+            tia.borrow_mut().clock();
+            if c % 3 == 2 && cpu.clock() {
+                debugger.instruction(&mut cpu);
+            }
+        }
+
+        let (x, ram, clocks) = (cpu.x, cpu.peek(0x80), tia.borrow().clocks());
+
+        // The timeline has every instruction that's finished since the first one, and every
+        // scanline they ran on, without any halted cycles
+        assert_eq!(debugger.timeline.len(), debugger.history.len() - 1);
+        let lines = debugger.timeline.scanlines(|_| "INX".to_string());
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|line| !line.contains('.')));
+
+        // Going back three instructions takes X, the RAM, and the beam back with them
+        let mut output = vec![];
+        debugger.step_back(&mut cpu, 3, &mut output);
+        assert!(output.iter().any(|line| line.starts_with("PC:")));
+        assert_eq!(cpu.x, x.wrapping_sub(1));
+        assert_eq!(cpu.peek(0x80), ram.wrapping_sub(1));
+        assert!(tia.borrow().clocks() < clocks);
+        assert_eq!(debugger.take_rewind(), Some((0, false, false)));
+        assert_eq!(debugger.timeline.len(), debugger.history.len() - 1);
+
+        // It can't go back past the start of the frame
+        let available = debugger.history.len() - 1;
+        let mut output = vec![];
+        debugger.step_back(&mut cpu, available + 1, &mut output);
+        assert_eq!(cpu.x, x.wrapping_sub(1));
+        assert_eq!(output, vec![format!("It can only go back {} instructions, to the start of the frame", available)]);
+
+        debugger.end_frame(&cpu);
+        debugger.step_back(&mut cpu, 1, &mut vec![]);
+        assert_eq!(cpu.x, x.wrapping_sub(1));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // An address covers its mirrors, but the beam has to be in exactly the same place
+        assert!(Breakpoint::Address(0xf00a).covers(&Breakpoint::Address(0x100a)));
+        assert!(!Breakpoint::Address(0xf00a).covers(&Breakpoint::Address(0xf00b)));
+        assert!(Breakpoint::Beam(40, 68).covers(&Breakpoint::Beam(40, 68)));
+        assert!(!Breakpoint::Beam(40, 68).covers(&Breakpoint::Beam(40, 0)));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let symbols = Symbols::parse("\
+Frame                    0080
+StartFrame               f01a
+");
+
+        assert_eq!(Command::parse("break StartFrame", &symbols), Ok(Command::Break(Breakpoint::Address(0xf01a))));
+        assert_eq!(Command::parse("m Frame", &symbols), Ok(Command::Memory(0x80)));
+        assert!(Command::parse("break startframe", &symbols).is_err());
     }
 }
@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::cpu6507::{AddressingMode, Instruction, Opcode, OPCODES};
+use crate::symbols::Symbols;
+
+// A single disassembled instruction
+pub struct Line {
+    pub address: u16,
+    // The opcode and its operands
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+    pub addr_mode: AddressingMode,
+}
+
+impl Line {
+    // Decodes the instruction at the start of `bytes`, which must hold all of its operands
+    pub fn decode(address: u16, bytes: &[u8]) -> Self {
+        let Opcode(inst, addr_mode, _, _) = OPCODES[bytes[0] as usize];
+
+        Self {
+            address: address,
+            bytes: bytes[.. addr_mode.n_bytes()].to_vec(),
+            instruction: inst,
+            addr_mode: addr_mode,
+        }
+    }
+
+    // The instruction and its operand, e.g. "LDA #$42"
+    pub fn text(&self) -> String { self.text_with(&Symbols::new()) }
+
+    // The operand, in the usual assembler syntax, e.g. "($80),Y", with addresses that have labels
+    // shown by their labels
+    fn operand_with(&self, symbols: &Symbols) -> String {
+        let byte = || self.bytes[1];
+        let word = || (self.bytes[2] as u16) << 8 | self.bytes[1] as u16;
+
+        let zero_page = || symbols.label(byte() as u16).map_or(format!("${:02X}", byte()), |l| l.to_string());
+        let absolute = |address: u16| symbols.label(address).map_or(format!("${:04X}", address), |l| l.to_string());
+
+        match self.addr_mode {
+            AddressingMode::Implied         => String::new(),
+            AddressingMode::Accumulator     => "A".to_string(),
+            AddressingMode::Immediate       => format!("#${:02X}", byte()),
+            AddressingMode::ZeroPageIndexed => zero_page(),
+            AddressingMode::ZeroPageX       => format!("{},X", zero_page()),
+            AddressingMode::ZeroPageY       => format!("{},Y", zero_page()),
+            AddressingMode::Absolute        => absolute(word()),
+            AddressingMode::AbsoluteX       => format!("{},X", absolute(word())),
+            AddressingMode::AbsoluteY       => format!("{},Y", absolute(word())),
+            AddressingMode::Indirect        => format!("({})", absolute(word())),
+            AddressingMode::IndexedIndirect => format!("({},X)", zero_page()),
+            AddressingMode::IndirectIndexed => format!("({}),Y", zero_page()),
+            AddressingMode::Relative        => {
+                // Branches are relative to the address of the next instruction
+                absolute(self.address.wrapping_add(2).wrapping_add(byte() as i8 as u16))
+            },
+        }
+    }
+
+    pub fn text_with(&self, symbols: &Symbols) -> String {
+        format!("{:?} {}", self.instruction, self.operand_with(symbols)).trim_end().to_string()
+    }
+
+    // The whole line, e.g. "F001  A9 42     LDA #$42", with labels for the addresses that have them
+    pub fn format_with(&self, symbols: &Symbols) -> String {
+        let bytes = self.bytes.iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!("{:04X}  {:8}  {}", self.address, bytes, self.text_with(symbols))
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_with(&Symbols::new()))
+    }
+}
+
+// Disassembles memory from `start` to `end` inclusive, reading it with `peek`, which mustn't have
+// any side effects, so that code running from RAM is disassembled as well as the cartridge's.
+// Operands past the top of memory wrap around to the bottom, the same as the CPU reads them.
+pub fn disassemble(peek: &dyn Fn(u16) -> u8, start: u16, end: u16) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+        let bytes = (0 .. 3).map(|n| peek((address + n) as u16)).collect::<Vec<_>>();
+        let line = Line::decode(address as u16, &bytes);
+
+        address += line.bytes.len() as u32;
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Disassembles the `before` instructions leading up to `pc`, the one at `pc`, and the `after`
+// instructions following it. As instructions are different lengths, there's no telling for sure
+// where the ones before `pc` start, so this starts from as far back as it can that still lines up
+// with an instruction at `pc`.
+pub fn disassemble_around(peek: &dyn Fn(u16) -> u8, pc: u16, before: usize, after: usize) -> Vec<Line> {
+    let start = (1 ..= before * 3).rev()
+        .filter_map(|offset| pc.checked_sub(offset as u16))
+        .find(|&start| disassemble(peek, start, pc).last().map(|line| line.address) == Some(pc))
+        .unwrap_or(pc);
+
+    let mut lines = disassemble(peek, start, pc);
+    lines.drain(.. lines.len().saturating_sub(before + 1));
+
+    let next = pc.saturating_add(lines.last().map_or(1, |line| line.bytes.len() as u16));
+    if next > pc {
+        let following = disassemble(peek, next, next.saturating_add(after as u16 * 3));
+        lines.extend(following.into_iter().take(after));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The cartridge ROM, mirrored across all of memory
+    fn rom(bytes: &[u8]) -> impl Fn(u16) -> u8 + '_ {
+        move |address| bytes[(address as usize & 0xfff) % bytes.len()]
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let bytes = [
+            0x78,             // SEI
+            0xa9, 0x42,       // LDA #$42
+            0x95, 0x80,       // STA $80,X
+            0x9d, 0x00, 0x10, // STA $1000,X
+            0xb1, 0x82,       // LDA ($82),Y
+            0x6c, 0xfc, 0xff, // JMP ($FFFC)
+            0x0a,             // ASL A
+            0xd0, 0xf1,       // BNE $F001
+        ];
+
+        let lines = disassemble(&rom(&bytes), 0xf000, 0xf00f)
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines, vec![
+            "F000  78        SEI",
+            "F001  A9 42     LDA #$42",
+            "F003  95 80     STA $80,X",
+            "F005  9D 00 10  STA $1000,X",
+            "F008  B1 82     LDA ($82),Y",
+            "F00A  6C FC FF  JMP ($FFFC)",
+            "F00D  0A        ASL A",
+            "F00E  D0 F1     BNE $F001",
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_wraps_around() {
+        // The last instruction's operands wrap around to the bottom of memory
+        let bytes = [0x10, 0x20, 0x30, 0x4c];
+
+        let lines = disassemble(&rom(&bytes), 0xffff, 0xffff);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "FFFF  4C 10 20  JMP $2010");
+    }
+
+    #[test]
+    fn test_disassemble_ram() {
+        // Code running from RAM is read from there, not from the cartridge
+        let ram = |address| if address == 0x0080 { 0xe8 } else { 0x00 };
+        assert_eq!(disassemble(&ram, 0x0080, 0x0080)[0].to_string(), "0080  E8        INX");
+    }
+
+    #[test]
+    fn test_disassemble_around() {
+        let bytes = [
+            0x78,             // SEI
+            0xa9, 0x42,       // LDA #$42
+            0x9d, 0x00, 0x10, // STA $1000,X
+            0xe8,             // INX
+            0xd0, 0xf9,       // BNE $F001
+            0xea,             // NOP
+        ];
+
+        let addresses = |before, after| disassemble_around(&rom(&bytes), 0xf006, before, after)
+            .iter()
+            .map(|line| line.address)
+            .collect::<Vec<_>>();
+
+        assert_eq!(addresses(2, 2), vec![0xf001, 0xf003, 0xf006, 0xf007, 0xf009]);
+        assert_eq!(addresses(1, 0), vec![0xf003, 0xf006]);
+        assert_eq!(addresses(0, 0), vec![0xf006]);
+    }
+
+    #[test]
+    fn test_labels() {
+        let symbols = Symbols::parse("\
+Counter                  0080
+Loop                     f002
+");
+
+        let bytes = [
+            0xa2, 0x10,       // LDX #$10
+            0xd6, 0x80,       // DEC Counter,X
+            0xd0, 0xfc,       // BNE Loop
+            0x4c, 0x02, 0xf0, // JMP Loop
+        ];
+
+        let lines = disassemble(&rom(&bytes), 0xf000, 0xf008)
+            .iter()
+            .map(|line| line.format_with(&symbols))
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines, vec![
+            "F000  A2 10     LDX #$10",
+            "F002  D6 80     DEC Counter,X",
+            "F004  D0 FC     BNE Loop",
+            "F006  4C 02 F0  JMP Loop",
+        ]);
+    }
+}
@@ -0,0 +1,510 @@
+use crate::region::Region;
+
+// How many frames in a row the picture needs to be a new height before it's re-centred, so that
+// games that wobble by a line or two every other frame don't make the picture jump around.
+const RECENTER_FRAMES: usize = 3;
+
+// The longest a frame can be before it's cut short, for games that are VSYNCing, but too slowly
+const MAX_SCANLINES: usize = 400;
+
+// How far from the standard number of scanlines a frame can be before a TV can't lock on to its
+// VSYNC, and the picture starts to roll
+const SYNC_RANGE: usize = 10;
+
+// How quickly the picture rolls when there's no VSYNC for the TV to lock on to at all
+const FREE_RUN_ROLL: usize = 8;
+
+// How many frames in a row need to alternate between two lengths a scanline apart before the game
+// is treated as interlaced
+const INTERLACE_FRAMES: usize = 4;
+
+// Collects the scanlines of a frame as the TIA generates them, and lays the picture out vertically
+// centred in a fixed number of display lines. Pixels are kept as the TIA's colour values, for the
+// frontend to convert to RGB with whichever palette it's using.
+//
+// The vertical structure of the frame comes from what the game does with VSYNC and VBLANK, rather
+// than from fixed scanline numbers: a frame ends when the game starts VSYNC, and the picture is the
+// scanlines that aren't in VBLANK. Games don't all draw the standard 192 (NTSC) or 228 (PAL)
+// visible scanlines; plenty draw a few more or less, and some vary from frame to frame.
+//
+// For games that don't use VSYNC or VBLANK (e.g. while they're starting up), the region's standard
+// frame structure is used instead.
+//
+// Optionally, frames that are too long or short, or that don't VSYNC at all, can be made to roll
+// the picture vertically the way a real TV would, rather than being snapped into place.
+//
+// Also optionally, games that alternate between frames a scanline apart in length (e.g. 262 and
+// 263 scanlines) to get an interlaced picture can have every other field drawn half a scanline
+// lower, the way a TV would draw them.
+pub struct Frame {
+    region: Region,
+    frames: usize,
+
+    // How many lines are being displayed
+    lines: usize,
+
+    // Every scanline of the frame being generated, along with whether it was in VSYNC and VBLANK
+    scanlines: Vec<(Vec<u8>, bool, bool)>,
+    used_vsync: bool,
+    used_vblank: bool,
+    last_vsync: bool,
+
+    // The number of scanlines in each part of the frame being generated
+    vsync: usize,
+    vblank: usize,
+    visible: usize,
+    overscan: usize,
+
+    // The last complete picture, and the scanline of the frame that it started on
+    picture: Vec<Vec<u8>>,
+    picture_start: usize,
+
+    // The height of the picture used for centring, and a new height that's waiting to settle
+    height: usize,
+    new_height: usize,
+    new_height_frames: usize,
+
+    // Whether out of spec frames roll the picture, how many scanlines it's currently rolled by,
+    // and every scanline of the last frame, with the blanked ones blacked out, to roll through
+    rolling: bool,
+    roll: usize,
+    field: Vec<Vec<u8>>,
+
+    // Whether interlacing is detected, the length of the last frame, how many frames in a row
+    // have alternated in length, and whether the last frame was the lower field
+    interlace: bool,
+    last_total: usize,
+    alternations: usize,
+    lower_field: bool,
+}
+
+impl Frame {
+    pub fn new(region: Region) -> Self {
+        let lines = region.visible_scanlines();
+
+        Self {
+            region: region,
+            frames: 0,
+            lines: lines,
+
+            scanlines: vec![],
+            used_vsync: false,
+            used_vblank: false,
+            last_vsync: false,
+
+            vsync: 0,
+            vblank: 0,
+            visible: 0,
+            overscan: 0,
+
+            picture: vec![],
+            picture_start: 0,
+
+            height: lines,
+            new_height: lines,
+            new_height_frames: 0,
+
+            rolling: false,
+            roll: 0,
+            field: vec![],
+
+            interlace: false,
+            last_total: 0,
+            alternations: 0,
+            lower_field: false,
+        }
+    }
+
+    // The detected height of the picture, in scanlines
+    pub fn height(&self) -> usize { self.height }
+
+    // The number of scanlines in the frame being generated
+    pub fn scanlines(&self) -> usize { self.scanlines.len() }
+
+    pub fn set_rolling(&mut self, rolling: bool) {
+        self.rolling = rolling;
+        self.roll = 0;
+    }
+
+    pub fn set_interlace(&mut self, interlace: bool) {
+        self.interlace = interlace;
+        self.alternations = 0;
+        self.lower_field = false;
+    }
+
+    // Whether the last frame is the lower field of an interlaced picture, and should be drawn half
+    // a scanline lower than the other
+    pub fn lower_field(&self) -> bool { self.lower_field }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.lines = region.visible_scanlines();
+    }
+
+    // Called before generating each scanline, with the current state of VSYNC. Returns whether the
+    // frame is complete, i.e. the game has just started VSYNC, or the frame has run too long.
+    pub fn complete(&self, vsync: bool) -> bool {
+        let lines = self.scanlines.len();
+
+        if lines == 0 {
+            return false;
+        }
+
+        if vsync && !self.last_vsync {
+            return true;
+        }
+
+        if self.used_vsync {
+            lines >= MAX_SCANLINES
+        } else {
+            lines >= self.region.scanlines()
+        }
+    }
+
+    pub fn push_scanline(&mut self, pixels: Vec<u8>, vsync: bool, vblank: bool) {
+        self.used_vsync |= vsync;
+        self.used_vblank |= vblank;
+        self.last_vsync = vsync;
+
+        if vsync {
+            self.vsync += 1;
+        } else if !vblank {
+            self.visible += 1;
+        } else if self.visible == 0 {
+            self.vblank += 1;
+        } else {
+            self.overscan += 1;
+        }
+
+        self.scanlines.push((pixels, vsync, vblank));
+    }
+
+    // Goes back to an earlier scanline of the frame being generated, dropping the scanlines after
+    // it, for when the debugger steps backwards
+    pub fn rewind(&mut self, scanlines: usize) {
+        let mut kept = std::mem::take(&mut self.scanlines);
+        kept.truncate(scanlines);
+
+        self.used_vsync = false;
+        self.used_vblank = false;
+        self.vsync = 0;
+        self.vblank = 0;
+        self.visible = 0;
+        self.overscan = 0;
+
+        for (pixels, vsync, vblank) in kept {
+            self.push_scanline(pixels, vsync, vblank);
+        }
+    }
+
+    // Finish off the current frame, and start collecting the next one
+    pub fn finish(&mut self) {
+        self.frames += 1;
+
+        let total = self.scanlines.len();
+        if total != self.region.scanlines() {
+            debug!("frame {}: {} scanlines ({} vsync, {} vblank, {} visible, {} overscan), expected {} for {:?}, picture height {}",
+                   self.frames, total, self.vsync, self.vblank, self.visible, self.overscan,
+                   self.region.scanlines(), self.region, self.height);
+        }
+
+        let scanlines = std::mem::take(&mut self.scanlines);
+
+        if self.interlace {
+            self.update_field(total);
+        }
+
+        if self.rolling {
+            self.update_roll(total);
+
+            self.field = scanlines
+                .iter()
+                .map(|(pixels, vsync, vblank)| if *vsync || *vblank { vec![0; pixels.len()] } else { pixels.clone() })
+                .collect();
+        }
+
+        self.picture_start = if self.used_vblank {
+            scanlines.iter().position(|(_, vsync, vblank)| !vsync && !vblank).unwrap_or(0)
+        } else {
+            self.region.picture_top()
+        };
+
+        self.picture = if self.used_vblank {
+            scanlines
+                .into_iter()
+                .filter(|(_, vsync, vblank)| !vsync && !vblank)
+                .map(|(pixels, _, _)| pixels)
+                .collect()
+        } else {
+            scanlines
+                .into_iter()
+                .skip(self.region.picture_top())
+                .take(self.region.picture_scanlines())
+                .map(|(pixels, _, _)| pixels)
+                .collect()
+        };
+
+        self.used_vsync = false;
+        self.used_vblank = false;
+
+        self.vsync = 0;
+        self.vblank = 0;
+        self.visible = 0;
+        self.overscan = 0;
+
+        self.update_height();
+    }
+
+    // A TV's vertical oscillator runs at close to the standard frame rate by itself, and each VSYNC
+    // pulls it into line. When VSYNC comes too early or too late for the TV to lock on to, the
+    // picture moves up or down by the difference each frame, and rolls around the screen.
+    fn update_roll(&mut self, total: usize) {
+        let standard = self.region.scanlines() as isize;
+
+        let drift = if self.used_vsync {
+            total as isize - standard
+        } else {
+            FREE_RUN_ROLL as isize
+        };
+
+        if self.used_vsync && drift.abs() <= SYNC_RANGE as isize {
+            // Close enough, so the TV locks straight back on to the picture
+            self.roll = 0;
+        } else {
+            self.roll = (self.roll as isize + drift).rem_euclid(standard) as usize;
+        }
+    }
+
+    fn update_field(&mut self, total: usize) {
+        if total == self.last_total + 1 || total + 1 == self.last_total {
+            self.alternations += 1;
+        } else {
+            self.alternations = 0;
+        }
+
+        // The longer frame is the one that finishes half a scanline later on a TV, so its field
+        // sits half a scanline lower
+        self.lower_field = self.alternations >= INTERLACE_FRAMES && total > self.last_total;
+        self.last_total = total;
+    }
+
+    fn update_height(&mut self) {
+        let height = self.picture.len();
+
+        if height == self.height {
+            self.new_height_frames = 0;
+        } else if height == self.new_height {
+            self.new_height_frames += 1;
+
+            if self.new_height_frames >= RECENTER_FRAMES {
+                debug!("picture height changed from {} to {} scanlines", self.height, height);
+                self.height = height;
+                self.new_height_frames = 0;
+            }
+        } else {
+            self.new_height = height;
+            self.new_height_frames = 1;
+        }
+    }
+
+    // Returns the colour value of the pixel at the given position of the display, or black if the
+    // picture doesn't cover that line.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        if self.roll != 0 && !self.field.is_empty() {
+            let line = (self.region.picture_top() + y + self.roll) % self.field.len();
+            return self.field[line][x];
+        }
+
+        // When the picture is taller than the display, this crops the top and bottom evenly
+        let offset = (self.lines as isize - self.height as isize) / 2;
+        let line = y as isize - offset;
+
+        if line < 0 {
+            return 0;
+        }
+
+        match self.picture.get(line as usize) {
+            Some(pixels) => pixels[x],
+            None         => 0,
+        }
+    }
+
+    // The same as `pixel`, but with the scanlines of the frame being generated drawn over the last
+    // complete picture as far as they've got, for looking at a frame part way through
+    pub fn partial_pixel(&self, x: usize, y: usize) -> u8 {
+        match self.scanline_at(y) {
+            Some(line) if line < self.scanlines.len() => self.scanlines[line].0[x],
+            _ => self.pixel(x, y),
+        }
+    }
+
+    // Which scanline of the frame is shown on the given line of the display, going by the last
+    // complete picture, or None if the picture doesn't cover that line
+    pub fn scanline_at(&self, y: usize) -> Option<usize> {
+        if self.roll != 0 && !self.field.is_empty() {
+            return Some((self.region.picture_top() + y + self.roll) % self.field.len());
+        }
+
+        let offset = (self.lines as isize - self.height as isize) / 2;
+        let line = y as isize - offset;
+
+        if line < 0 || line as usize >= self.picture.len() {
+            return None;
+        }
+
+        Some(self.picture_start + line as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generate a frame with the given number of VSYNC, VBLANK, picture, and overscan lines. Each
+    // picture line is coloured with its line number, starting from 1.
+    fn frame(frame: &mut Frame, vsync: usize, vblank: usize, picture: usize, overscan: usize) {
+        let phases = [(vsync, true, true), (vblank, false, true), (picture, false, false), (overscan, false, true)];
+        let mut y = 0;
+
+        for &(lines, vsync, vblank) in phases.iter() {
+            for _ in 0 .. lines {
+                assert!(!frame.complete(vsync));
+                y += 1;
+                frame.push_scanline(vec![y as u8; 160], vsync, vblank);
+            }
+        }
+
+        assert!(frame.complete(true));
+        frame.finish();
+    }
+
+    fn picture(f: &mut Frame, height: usize) {
+        frame(f, 3, 37, height, 30);
+    }
+
+    #[test]
+    fn test_short_picture_is_centered() {
+        let mut frame = Frame::new(Region::NTSC);
+
+        for _ in 0 .. RECENTER_FRAMES + 1 {
+            picture(&mut frame, 192);
+        }
+
+        assert_eq!(frame.height(), 192);
+        assert_eq!(frame.pixel(0, 3), 0);
+        assert_eq!(frame.pixel(0, 4), 41);
+        assert_eq!(frame.pixel(0, 195), 232);
+        assert_eq!(frame.pixel(0, 196), 0);
+
+        assert_eq!(frame.scanline_at(3), None);
+        assert_eq!(frame.scanline_at(4), Some(40));
+        assert_eq!(frame.scanline_at(195), Some(231));
+        assert_eq!(frame.scanline_at(196), None);
+    }
+
+    #[test]
+    fn test_wobbling_picture_is_not_recentered() {
+        let mut frame = Frame::new(Region::NTSC);
+
+        for i in 0 .. 10 {
+            picture(&mut frame, if i % 2 == 0 { 200 } else { 201 });
+        }
+
+        assert_eq!(frame.height(), 200);
+    }
+
+    #[test]
+    fn test_picture_from_vblank() {
+        let mut f = Frame::new(Region::NTSC);
+
+        // A non-standard amount of VBLANK moves the picture down
+        frame(&mut f, 3, 50, 200, 9);
+        assert_eq!(f.pixel(0, 0), 54);
+        assert_eq!(f.scanline_at(0), Some(53));
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut f = Frame::new(Region::NTSC);
+        frame(&mut f, 3, 50, 200, 9);
+
+        // The next frame's first 60 scanlines replace the top 7 lines of the last picture
+        for y in 0 .. 60 {
+            f.push_scanline(vec![100 + y as u8; 160], false, y < 53);
+        }
+
+        assert_eq!(f.partial_pixel(0, 0), 153);
+        assert_eq!(f.partial_pixel(0, 6), 159);
+        assert_eq!(f.partial_pixel(0, 7), 61);
+        assert_eq!(f.pixel(0, 0), 54);
+
+        // Going back drops the scanlines after it, and the last picture shows through again
+        f.rewind(55);
+        assert_eq!(f.scanlines(), 55);
+        assert_eq!(f.partial_pixel(0, 1), 154);
+        assert_eq!(f.partial_pixel(0, 2), 56);
+    }
+
+    #[test]
+    fn test_frame_without_vsync_or_vblank() {
+        let mut f = Frame::new(Region::NTSC);
+
+        // The frame is cut off after the standard number of scanlines, and the picture is the
+        // standard 192 scanlines after the standard VSYNC and VBLANK
+        for y in 0 .. 262 {
+            assert!(!f.complete(false));
+            f.push_scanline(vec![y as u8; 160], false, false);
+        }
+
+        assert!(f.complete(false));
+        f.finish();
+
+        assert_eq!(f.pixel(0, 0), 40);
+    }
+
+    #[test]
+    fn test_rolling() {
+        let mut f = Frame::new(Region::NTSC);
+        f.set_rolling(true);
+
+        // Standard frames stay put
+        for _ in 0 .. RECENTER_FRAMES + 1 {
+            picture(&mut f, 192);
+        }
+        let top = f.pixel(0, 10);
+        picture(&mut f, 192);
+        assert_eq!(f.pixel(0, 10), top);
+
+        // Frames that are too long roll the picture up by the extra scanlines each frame
+        frame(&mut f, 3, 37, 192, 50);
+        let rolled = f.pixel(0, 10);
+        assert_ne!(rolled, top);
+        frame(&mut f, 3, 37, 192, 50);
+        assert_eq!(f.pixel(0, 10), rolled + 20);
+
+        // Once the frames are back in range, the TV locks back on
+        picture(&mut f, 192);
+        assert_eq!(f.pixel(0, 10), top);
+    }
+
+    #[test]
+    fn test_interlace() {
+        let mut f = Frame::new(Region::NTSC);
+        f.set_interlace(true);
+
+        // Frames of the same length aren't interlaced
+        for _ in 0 .. INTERLACE_FRAMES + 1 {
+            picture(&mut f, 192);
+            assert!(!f.lower_field());
+        }
+
+        // Alternating 262 and 263 scanline frames are, once they've alternated for long enough
+        let fields = (0 .. 2 * INTERLACE_FRAMES).map(|i| {
+            picture(&mut f, 192 + i % 2);
+            f.lower_field()
+        }).collect::<Vec<_>>();
+
+        assert!(fields[.. INTERLACE_FRAMES - 1].iter().all(|&lower| !lower));
+        assert_eq!(fields[INTERLACE_FRAMES ..], [false, true, false, true]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::mem;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+// What the picture's being compared against
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Against {
+    Previous,
+    Reference,
+}
+
+const UNCHANGED_COLOR: Color = Color { r: 0, g: 0, b: 0, a: 176 };
+const CHANGED_COLOR: Color = Color { r: 255, g: 0, b: 255, a: 96 };
+
+// Compares the picture as it's displayed against the frame before it, or against a reference frame
+// that's been saved, and highlights the pixels that are different by darkening all of the others,
+// which makes flicker and pictures that have moved by a scanline jump out. The pictures are kept
+// as the TIA's colour values, one row of 160 for each line of the display.
+pub struct FrameDiff {
+    against: Option<Against>,
+
+    // The last frame's picture and the one before it, and the saved reference
+    current: Vec<Vec<u8>>,
+    previous: Vec<Vec<u8>>,
+    reference: Option<Vec<Vec<u8>>>,
+}
+
+impl FrameDiff {
+    pub fn new() -> Self {
+        Self {
+            against: None,
+            current: vec![],
+            previous: vec![],
+            reference: None,
+        }
+    }
+
+    pub fn end_frame(&mut self, picture: Vec<Vec<u8>>) {
+        self.previous = mem::replace(&mut self.current, picture);
+    }
+
+    // The last frame's picture
+    pub fn picture(&self) -> &[Vec<u8>] { &self.current }
+
+    pub fn show_previous(&mut self) {
+        self.against = Some(Against::Previous);
+    }
+
+    // Saves the last frame to compare the ones after it against
+    pub fn save_reference(&mut self) {
+        self.reference = Some(self.current.clone());
+        self.against = Some(Against::Reference);
+    }
+
+    pub fn hide(&mut self) {
+        self.against = None;
+    }
+
+    // Whether each pixel of the last frame is different from the one it's being compared against,
+    // or None when it's not being compared
+    fn changes(&self) -> Option<Vec<Vec<bool>>> {
+        let other = match self.against? {
+            Against::Previous  => &self.previous,
+            Against::Reference => self.reference.as_ref()?,
+        };
+
+        let changes = self.current.iter()
+            .enumerate()
+            .map(|(y, row)| match other.get(y) {
+                Some(other) => row.iter().zip(other).map(|(a, b)| a != b).collect(),
+                None        => vec![true; row.len()],
+            })
+            .collect();
+
+        Some(changes)
+    }
+
+    // How many pixels are different, and on which lines of the display
+    pub fn summary(&self) -> String {
+        let changes = match self.changes() {
+            Some(changes) => changes,
+            None          => return "The frames aren't being compared".to_string(),
+        };
+
+        let lines = changes.iter()
+            .enumerate()
+            .filter(|(_, row)| row.contains(&true))
+            .map(|(y, _)| y)
+            .collect::<Vec<_>>();
+        let pixels = changes.iter().flatten().filter(|&&changed| changed).count();
+
+        match (lines.first(), lines.last()) {
+            (Some(first), Some(last)) => {
+                format!("{} pixels are different, on {} lines from line {} to {}", pixels, lines.len(), first, last)
+            },
+            _ => "The frames are the same".to_string(),
+        }
+    }
+
+    // Highlights the differences in a picture drawn with each pixel 5 by 3 on the screen, and
+    // `offset` rows further down for the lower field of an interlaced picture
+    pub fn draw(&self, canvas: &mut Canvas<Window>, offset: i32) {
+        let changes = match self.changes() {
+            Some(changes) => changes,
+            None          => return,
+        };
+
+        let (pixel_width, pixel_height) = (5, 3);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        for (y, row) in changes.iter().enumerate() {
+            let mut x = 0;
+            while x < row.len() {
+                let run = row[x ..].iter().take_while(|&&changed| changed == row[x]).count();
+
+                canvas.set_draw_color(if row[x] { CHANGED_COLOR } else { UNCHANGED_COLOR });
+                canvas.fill_rect(Rect::new(x as i32 * pixel_width, y as i32 * pixel_height + offset,
+                                           (run as i32 * pixel_width) as u32, pixel_height as u32)).unwrap();
+
+                x += run;
+            }
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let mut diff = FrameDiff::new();
+        assert_eq!(diff.summary(), "The frames aren't being compared");
+
+        diff.end_frame(vec![vec![0; 160]; 4]);
+        diff.save_reference();
+
+        // A sprite that moves down a line from one frame to the next
+        let mut picture = vec![vec![0; 160]; 4];
+        picture[1][10] = 0x1e;
+        diff.end_frame(picture.clone());
+        picture[1][10] = 0;
+        picture[2][10] = 0x1e;
+        diff.end_frame(picture);
+
+        diff.show_previous();
+        assert_eq!(diff.summary(), "2 pixels are different, on 2 lines from line 1 to 2");
+
+        diff.save_reference();
+        assert_eq!(diff.summary(), "The frames are the same");
+
+        diff.hide();
+        assert_eq!(diff.changes(), None);
+    }
+}
@@ -0,0 +1,337 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+
+use crate::peripheral::{Direction, Joystick, Source};
+use crate::riot::RIOT;
+use crate::tia::{PlayerType, TIA};
+
+// How a game controller's analog axes are used. One axis each drives the joystick's horizontal
+// and vertical directions, or the paddle. An axis is ignored until it's pushed past the dead zone,
+// which for the directions is how far it has to be pushed before it counts, and for the paddle
+// keeps it still around the middle. Past the saturation point, it counts as pushed all the way.
+// Both are fractions of the axis' full travel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StickConfig {
+    x: Axis,
+    y: Axis,
+    paddle: Axis,
+    deadzone: Option<f32>,
+    saturation: f32,
+}
+
+// The dead zones used unless one's given, half way for the directions so that they don't come on
+// by accident, and none for the paddle so it can be turned smoothly
+const DIRECTION_DEADZONE: f32 = 0.5;
+const PADDLE_DEADZONE: f32 = 0.0;
+
+impl StickConfig {
+    pub fn new() -> Self {
+        Self {
+            x: Axis::LeftX,
+            y: Axis::LeftY,
+            paddle: Axis::LeftX,
+            deadzone: None,
+            saturation: 1.0,
+        }
+    }
+
+    // An axis' value, scaled to between -1.0 and 1.0, with the dead zone scaled out
+    fn scale(&self, value: i16, deadzone: f32) -> f32 {
+        let value = (value as f32 / 32767.0).max(-1.0);
+        let deadzone = self.deadzone.unwrap_or(deadzone);
+
+        let magnitude = ((value.abs() - deadzone) / (self.saturation - deadzone)).clamp(0.0, 1.0);
+        magnitude.copysign(value)
+    }
+
+    // The paddle's position for an axis' value, turning it clockwise as the axis goes left
+    fn paddle_position(&self, value: i16) -> u8 {
+        ((1.0 - self.scale(value, PADDLE_DEADZONE)) * 127.5).round() as u8
+    }
+}
+
+fn axis(name: &str) -> Result<Axis, String> {
+    match name.to_lowercase().as_str() {
+        "leftx"        => Ok(Axis::LeftX),
+        "lefty"        => Ok(Axis::LeftY),
+        "rightx"       => Ok(Axis::RightX),
+        "righty"       => Ok(Axis::RightY),
+        "triggerleft"  => Ok(Axis::TriggerLeft),
+        "triggerright" => Ok(Axis::TriggerRight),
+        _              => Err(format!("unknown axis: {}", name)),
+    }
+}
+
+// A comma separated list of settings, e.g. "x=rightx,y=righty,deadzone=0.25", with the rest left
+// at their defaults
+impl FromStr for StickConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = StickConfig::new();
+
+        for setting in s.split(',').map(|setting| setting.trim()).filter(|setting| !setting.is_empty()) {
+            let (name, value) = match setting.find('=') {
+                Some(i) => (&setting[.. i], &setting[i + 1 ..]),
+                None    => return Err(format!("missing value: {}", setting)),
+            };
+
+            let fraction = || match value.parse::<f32>() {
+                Ok(fraction) if (0.0 ..= 1.0).contains(&fraction) => Ok(fraction),
+                _ => Err(format!("invalid {}: {}", name, value)),
+            };
+
+            match name.to_lowercase().as_str() {
+                "x"          => config.x = axis(value)?,
+                "y"          => config.y = axis(value)?,
+                "paddle"     => config.paddle = axis(value)?,
+                "deadzone"   => config.deadzone = Some(fraction()?),
+                "saturation" => config.saturation = fraction()?,
+                _            => return Err(format!("unknown gamepad setting: {}", name)),
+            }
+        }
+
+        // Without a dead zone of its own, the directions' is half way, so the saturation has to
+        // be past that too
+        let deadzones = [DIRECTION_DEADZONE, PADDLE_DEADZONE].map(|deadzone| config.deadzone.unwrap_or(deadzone));
+        if deadzones.iter().any(|&deadzone| deadzone >= config.saturation) {
+            return Err(format!("the dead zone has to be less than the saturation: {}", s));
+        }
+
+        Ok(config)
+    }
+}
+
+// The controllers that are plugged in, by their joystick instance ids. Each one keeps its slot
+// (which player or paddle it is) until it's unplugged, rather than the ones after it moving up.
+struct Slots<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Slots<T> {
+    fn new(len: usize) -> Self {
+        Self {
+            slots: (0 .. len).map(|_| None).collect(),
+        }
+    }
+
+    // The first slot that's free, if any are
+    fn free(&self) -> Option<usize> {
+        self.slots.iter().position(Option::is_none)
+    }
+
+    fn insert(&mut self, index: usize, which: u32, controller: T) {
+        self.slots[index] = Some((which, controller));
+    }
+
+    fn remove(&mut self, which: u32) -> Option<(usize, T)> {
+        let index = self.index(which)?;
+        self.slots[index].take().map(|(_, controller)| (index, controller))
+    }
+
+    // Which slot an event's controller is in, from its joystick instance id
+    fn index(&self, which: u32) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.as_ref().is_some_and(|&(id, _)| id == which))
+    }
+}
+
+// Game controllers, opened as they're plugged in. The first two are the players' joysticks, with
+// the d-pad or a stick for the directions, A for the fire button (and B too, unless it's the
+// Genesis pad's C button), and Back and Start for the select and reset switches. With paddles,
+// each of the first four turns a paddle with a stick instead.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: Slots<GameController>,
+
+    // The axis settings for each controller in the order they're plugged in, with the last one
+    // used for the rest
+    configs: Vec<StickConfig>,
+
+    paddles: bool,
+    genesis: bool,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem, configs: Vec<StickConfig>, paddles: bool, genesis: bool) -> Self {
+        Self {
+            subsystem: subsystem,
+            controllers: Slots::new(if paddles { 4 } else { 2 }),
+            configs: configs,
+            paddles: paddles,
+            genesis: genesis,
+        }
+    }
+
+    fn config(&self, index: usize) -> StickConfig {
+        self.configs.get(index).or(self.configs.last()).cloned().unwrap_or_else(StickConfig::new)
+    }
+
+    fn player(index: usize) -> PlayerType {
+        if index == 0 { PlayerType::Player0 } else { PlayerType::Player1 }
+    }
+
+    // Lets go of everything that an unplugged controller was holding down
+    fn let_go(paddles: bool, index: usize, riot: &mut RIOT, joysticks: &[Rc<RefCell<Joystick>>; 2]) {
+        if paddles {
+            riot.paddle_fire(index, false);
+        } else if index < 2 {
+            let mut joystick = joysticks[index].borrow_mut();
+            for &direction in [Direction::Up, Direction::Down, Direction::Left, Direction::Right].iter() {
+                joystick.set_direction(Source::Controller, direction, false);
+            }
+            joystick.set_fire(Source::Controller, false);
+        }
+    }
+
+    // Handles a controller being plugged in or unplugged, or one of its sticks or buttons moving.
+    // Any other events are ignored.
+    pub fn handle_event(&mut self, event: &Event, riot: &mut RIOT, tia: &mut TIA, joysticks: &[Rc<RefCell<Joystick>>; 2]) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let index = match self.controllers.free() {
+                    Some(index) => index,
+                    None        => return,
+                };
+
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        info!("Controllers: {} plugged in as {} {}",
+                              controller.name(),
+                              if self.paddles { "paddle" } else { "player" },
+                              index);
+                        self.controllers.insert(index, controller.instance_id() as u32, controller);
+                    },
+                    Err(e) => warn!("unable to open game controller {}: {}", which, e),
+                }
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some((index, controller)) = self.controllers.remove(which) {
+                    info!("Controllers: {} unplugged", controller.name());
+                    Self::let_go(self.paddles, index, riot, joysticks);
+                }
+            },
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let index = match self.controllers.index(which) {
+                    Some(index) => index,
+                    None        => return,
+                };
+
+                let config = self.config(index);
+
+                if self.paddles {
+                    // Turns the paddle clockwise as the stick moves left, the same as the mouse
+                    if axis == config.paddle {
+                        tia.set_paddle(index, config.paddle_position(value));
+                    }
+                } else if axis == config.x || axis == config.y {
+                    let position = config.scale(value, DIRECTION_DEADZONE);
+                    let (negative, positive) = if axis == config.x {
+                        (Direction::Left, Direction::Right)
+                    } else {
+                        (Direction::Up, Direction::Down)
+                    };
+
+                    let mut joystick = joysticks[index].borrow_mut();
+                    joystick.set_direction(Source::Controller, negative, position < 0.0);
+                    joystick.set_direction(Source::Controller, positive, position > 0.0);
+                }
+            },
+            Event::ControllerButtonDown { which, button, .. } => {
+                if let Some(index) = self.controllers.index(which) {
+                    self.button(index, button, true, riot, tia, joysticks);
+                }
+            },
+            Event::ControllerButtonUp { which, button, .. } => {
+                if let Some(index) = self.controllers.index(which) {
+                    self.button(index, button, false, riot, tia, joysticks);
+                }
+            },
+            _ => { },
+        }
+    }
+
+    fn button(&self, index: usize, button: Button, pressed: bool, riot: &mut RIOT, tia: &mut TIA, joysticks: &[Rc<RefCell<Joystick>>; 2]) {
+        match button {
+            Button::Back  => riot.select(pressed),
+            Button::Start => riot.reset(pressed),
+
+            Button::A if self.paddles => riot.paddle_fire(index, pressed),
+            _ if self.paddles         => { },
+
+            Button::B if self.genesis => tia.genesis_button_c(Self::player(index), pressed),
+            Button::A | Button::B     => joysticks[index].borrow_mut().set_fire(Source::Controller, pressed),
+
+            Button::DPadUp    => joysticks[index].borrow_mut().set_direction(Source::Controller, Direction::Up, pressed),
+            Button::DPadDown  => joysticks[index].borrow_mut().set_direction(Source::Controller, Direction::Down, pressed),
+            Button::DPadLeft  => joysticks[index].borrow_mut().set_direction(Source::Controller, Direction::Left, pressed),
+            Button::DPadRight => joysticks[index].borrow_mut().set_direction(Source::Controller, Direction::Right, pressed),
+
+            _ => { },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripheral::Peripheral;
+
+    #[test]
+    fn test_stick_config() {
+        assert_eq!("".parse::<StickConfig>(), Ok(StickConfig::new()));
+
+        let config = "x=RightX, y=righty,paddle=triggerleft,deadzone=0.25,saturation=0.75".parse::<StickConfig>().unwrap();
+        assert_eq!((config.x, config.y, config.paddle), (Axis::RightX, Axis::RightY, Axis::TriggerLeft));
+        assert_eq!((config.deadzone, config.saturation), (Some(0.25), 0.75));
+
+        assert!("x=middle".parse::<StickConfig>().is_err());
+        assert!("deadzone=2".parse::<StickConfig>().is_err());
+        assert!("deadzone=0.5,saturation=0.5".parse::<StickConfig>().is_err());
+        assert!("saturation=0.3".parse::<StickConfig>().is_err());
+        assert!("deadzone=0.1,saturation=0.3".parse::<StickConfig>().is_ok());
+        assert!("sensitivity=1".parse::<StickConfig>().is_err());
+    }
+
+    #[test]
+    fn test_scale() {
+        let config = "deadzone=0.25,saturation=0.75".parse::<StickConfig>().unwrap();
+        assert_eq!(config.scale(8000, DIRECTION_DEADZONE), 0.0);
+        assert!((config.scale(-16384, DIRECTION_DEADZONE) + 0.5).abs() < 0.001);
+        assert_eq!(config.scale(30000, DIRECTION_DEADZONE), 1.0);
+        assert_eq!(config.scale(-32768, DIRECTION_DEADZONE), -1.0);
+
+        // The default dead zones
+        let config = StickConfig::new();
+        assert_eq!(config.scale(16000, DIRECTION_DEADZONE), 0.0);
+        assert_eq!(config.paddle_position(-32768), 255);
+        assert_eq!(config.paddle_position(0), 128);
+        assert_eq!(config.paddle_position(32767), 0);
+    }
+
+    #[test]
+    fn test_unplug() {
+        let mut slots = Slots::new(2);
+        slots.insert(0, 10, "pad 0");
+        slots.insert(1, 11, "pad 1");
+        assert_eq!(slots.free(), None);
+
+        let mut riot = RIOT::new();
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(1))), Rc::new(RefCell::new(Joystick::new(1)))];
+        joysticks[0].borrow_mut().set_direction(Source::Controller, Direction::Left, true);
+        joysticks[1].borrow_mut().set_direction(Source::Controller, Direction::Up, true);
+
+        // Pad 1 stays player 1 when pad 0's unplugged, and is still holding up
+        let (index, _) = slots.remove(10).unwrap();
+        Gamepads::let_go(false, index, &mut riot, &joysticks);
+        assert_eq!(slots.index(11), Some(1));
+        assert_eq!((joysticks[0].borrow().pins(), joysticks[1].borrow().pins()), (0b1111, 0b1110));
+
+        // And the next one that's plugged in is player 0
+        assert_eq!(slots.free(), Some(0));
+    }
+}
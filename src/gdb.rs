@@ -0,0 +1,252 @@
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// The most that's sent in a packet, which gdb's told about, so that it doesn't ask for more memory
+// than fits in a reply
+const PACKET_SIZE: usize = 0x1000;
+
+// What gdb asks for, from the packets it sends
+#[derive(Debug, PartialEq)]
+pub enum Request {
+    HaltReason,
+    ReadRegisters,
+    ReadMemory(u16, usize),
+    WriteMemory(u16, Vec<u8>),
+    Step,
+    Continue,
+    InsertBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    Detach,
+
+    // Answered without needing the machine
+    Supported,
+    Attached,
+    Unsupported,
+    // A read of more memory than fits in a packet, as two hex digits a byte
+    TooLarge,
+}
+
+fn parse_hex<T: TryFrom<u32>>(s: &str) -> Option<T> {
+    u32::from_str_radix(s, 16).ok().and_then(|n| T::try_from(n).ok())
+}
+
+// An address and a length, e.g. "f000,10"
+fn parse_range(s: &str) -> Option<(u16, usize)> {
+    let mut parts = s.split(',');
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(address), Some(length), None) => Some((parse_hex(address)?, parse_hex(length)?)),
+        _                                   => None,
+    }
+}
+
+// Bytes written out as pairs of hex digits
+fn parse_bytes(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0 .. s.len()).step_by(2).map(|i| parse_hex(&s[i .. i + 2])).collect()
+}
+
+// A software or hardware breakpoint's address, e.g. "0,f000,1". They're both the same here.
+fn parse_breakpoint(s: &str) -> Option<u16> {
+    match *s.split(',').collect::<Vec<_>>() {
+        ["0" | "1", address, _] => parse_hex(address),
+        _                       => None,
+    }
+}
+
+impl Request {
+    fn parse(packet: &str) -> Self {
+        if packet.is_empty() || !packet.is_ascii() {
+            return Request::Unsupported;
+        }
+
+        let (command, args) = packet.split_at(1);
+
+        let request = match command {
+            "?" => Some(Request::HaltReason),
+            "g" => Some(Request::ReadRegisters),
+            "m" => parse_range(args).map(|(address, length)| {
+                if length > PACKET_SIZE / 2 { Request::TooLarge } else { Request::ReadMemory(address, length) }
+            }),
+            "M" => {
+                let (range, data) = args.split_at(args.find(':').unwrap_or(args.len()));
+                match (parse_range(range), parse_bytes(data.trim_start_matches(':'))) {
+                    (Some((address, length)), Some(bytes)) if bytes.len() == length => {
+                        Some(Request::WriteMemory(address, bytes))
+                    },
+                    _ => None,
+                }
+            },
+            // Stepping or continuing from somewhere else isn't supported, so the address is ignored
+            "s" => Some(Request::Step),
+            "c" => Some(Request::Continue),
+            "Z" => parse_breakpoint(args).map(Request::InsertBreakpoint),
+            "z" => parse_breakpoint(args).map(Request::RemoveBreakpoint),
+            "D" | "k" => Some(Request::Detach),
+            "q" if args.starts_with("Supported") => Some(Request::Supported),
+            "q" if args == "Attached" => Some(Request::Attached),
+            _ => None,
+        };
+
+        request.unwrap_or(Request::Unsupported)
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &b| sum.wrapping_add(b))
+}
+
+fn packet(data: &str) -> String {
+    format!("${}#{:02x}", data, checksum(data.as_bytes()))
+}
+
+// A server for the GDB remote serial protocol, for debugging a game from gdb, or any debugger
+// frontend that speaks the protocol and knows the 6502. The frontend connects over TCP, and sends
+// packets of the form $<data>#<checksum>, each of which is acknowledged with a + and answered with
+// a packet of the same form.
+//
+// The game stands still while the frontend has control, until it steps or continues, and the
+// frontend's told when it stops again. It can stop the game while it's running by sending a byte
+// of 0x03.
+pub struct GdbStub {
+    stream: TcpStream,
+
+    // Whether the game's running after a step or continue, so that the frontend's told when it
+    // stops
+    running: bool,
+}
+
+impl GdbStub {
+    // Waits for a frontend to connect, only from this machine
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        info!("gdb: waiting for a connection on port {}", port);
+
+        let (stream, address) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        info!("gdb: connected from {}", address);
+
+        Ok(Self {
+            stream: stream,
+            running: false,
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection was closed")),
+            _ => Ok(byte[0]),
+        }
+    }
+
+    // The next packet's data, once it's been acknowledged. Acknowledgements from the frontend are
+    // skipped over, and a packet with the wrong checksum is asked for again.
+    fn read_packet(&mut self) -> io::Result<String> {
+        loop {
+            while self.read_byte()? != b'$' { }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    b    => data.push(b),
+                }
+            }
+
+            let sum = [self.read_byte()?, self.read_byte()?];
+            let valid = std::str::from_utf8(&sum).ok().and_then(parse_hex::<u8>) == Some(checksum(&data));
+
+            if valid {
+                self.stream.write_all(b"+")?;
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    pub fn reply(&mut self, data: &str) -> io::Result<()> {
+        self.stream.write_all(packet(data).as_bytes())
+    }
+
+    // The next request that needs the machine, answering the ones that don't along the way
+    pub fn request(&mut self) -> io::Result<Request> {
+        loop {
+            match Request::parse(&self.read_packet()?) {
+                Request::Supported   => self.reply(&format!("PacketSize={:x}", PACKET_SIZE))?,
+                Request::Attached    => self.reply("1")?,
+                Request::Unsupported => self.reply("")?,
+                Request::TooLarge    => self.reply("E01")?,
+                request              => return Ok(request),
+            }
+        }
+    }
+
+    pub fn resume(&mut self) { self.running = true }
+
+    // Tells the frontend that the game's stopped, if it was running
+    pub fn stopped(&mut self) -> io::Result<()> {
+        if !self.running {
+            return Ok(());
+        }
+
+        self.running = false;
+        self.reply("S05")
+    }
+
+    // Whether the frontend's asked to stop the game while it's running
+    pub fn interrupted(&mut self) -> bool {
+        if !self.running || self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+
+        let mut byte = [0];
+        let interrupted = match self.stream.read(&mut byte) {
+            Ok(1) => byte[0] == 0x03,
+            _     => false,
+        };
+
+        self.stream.set_nonblocking(false).ok();
+        interrupted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(Request::parse("?"), Request::HaltReason);
+        assert_eq!(Request::parse("g"), Request::ReadRegisters);
+        assert_eq!(Request::parse("m80,10"), Request::ReadMemory(0x80, 16));
+        assert_eq!(Request::parse("m0,800"), Request::ReadMemory(0, 0x800));
+        assert_eq!(Request::parse("m0,801"), Request::TooLarge);
+        assert_eq!(Request::parse("m0,ffffffff"), Request::TooLarge);
+        assert_eq!(Request::parse("Mf0,2:1eff"), Request::WriteMemory(0xf0, vec![0x1e, 0xff]));
+        assert_eq!(Request::parse("s"), Request::Step);
+        assert_eq!(Request::parse("c"), Request::Continue);
+        assert_eq!(Request::parse("Z0,f00a,1"), Request::InsertBreakpoint(0xf00a));
+        assert_eq!(Request::parse("z1,f00a,1"), Request::RemoveBreakpoint(0xf00a));
+        assert_eq!(Request::parse("qSupported:multiprocess+;swbreak+"), Request::Supported);
+        assert_eq!(Request::parse("D"), Request::Detach);
+
+        assert_eq!(Request::parse("m80"), Request::Unsupported);
+        assert_eq!(Request::parse("Mf0,2:1e"), Request::Unsupported);
+        assert_eq!(Request::parse("Z2,80,1"), Request::Unsupported);
+        assert_eq!(Request::parse("m10000,1"), Request::Unsupported);
+        assert_eq!(Request::parse("vMustReplyEmpty"), Request::Unsupported);
+        assert_eq!(Request::parse(""), Request::Unsupported);
+    }
+
+    #[test]
+    fn test_packet() {
+        assert_eq!(packet("OK"), "$OK#9a");
+        assert_eq!(packet(""), "$#00");
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::mem;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+
+// How many routines deep the calls are followed. Games that return with a JMP, or jump by pushing
+// an address and returning to it, can leave calls that never return, so the oldest are dropped.
+const MAX_CALLS: usize = 64;
+
+// A frame with this many cycles in it is taken to be stuck, e.g. in a loop that's waiting for
+// something that never happens, rather than still running
+const STUCK_CYCLES: u64 = 2 * 262 * 76;
+
+// The cycles spent at each instruction address, and in each routine, by the address it was called
+// at, or None for the code that isn't in a routine
+#[derive(Default)]
+struct Cycles {
+    pcs: HashMap<u16, u64>,
+    routines: HashMap<Option<u16>, u64>,
+    total: u64,
+}
+
+impl Cycles {
+    fn add(&mut self, other: &Cycles) {
+        for (&pc, &cycles) in &other.pcs {
+            *self.pcs.entry(pc).or_insert(0) += cycles;
+        }
+        for (&routine, &cycles) in &other.routines {
+            *self.routines.entry(routine).or_insert(0) += cycles;
+        }
+        self.total += other.total;
+    }
+}
+
+// Counts the cycles the CPU spends at each instruction address, and in each routine, following
+// JSRs and RTSs, for finding the code that's worth optimising, and the loop a game's stuck in. A
+// routine's cycles include the cycles of the routines it calls.
+pub struct Hotspots {
+    // The CPU's cycle count as the last instruction finished
+    last_cycles: Option<u64>,
+
+    // The routines that have been called, innermost last, and whether the last instruction was a
+    // JSR, which makes the next one the start of a routine
+    calls: Vec<u16>,
+    calling: bool,
+
+    frame: Cycles,
+    last_frame: Cycles,
+    all: Cycles,
+    frames: u64,
+}
+
+impl Hotspots {
+    pub fn new() -> Self {
+        Self {
+            last_cycles: None,
+            calls: vec![],
+            calling: false,
+            frame: Cycles::default(),
+            last_frame: Cycles::default(),
+            all: Cycles::default(),
+            frames: 0,
+        }
+    }
+
+    // Called as each instruction finishes, with the CPU's cycle count including it
+    pub fn record(&mut self, pc: u16, opcode: u8, cycles: u64) {
+        if self.calling {
+            self.calling = false;
+            if self.calls.len() == MAX_CALLS {
+                self.calls.remove(0);
+            }
+            self.calls.push(pc);
+        }
+
+        // Going back in the debugger takes the cycle count back too
+        let spent = cycles.saturating_sub(self.last_cycles.unwrap_or(cycles));
+        self.last_cycles = Some(cycles);
+
+        *self.frame.pcs.entry(pc).or_insert(0) += spent;
+        for routine in self.calls.iter().map(|&r| Some(r)).chain(Some(None)) {
+            *self.frame.routines.entry(routine).or_insert(0) += spent;
+        }
+        self.frame.total += spent;
+
+        match opcode {
+            JSR => self.calling = true,
+            RTS => { self.calls.pop(); },
+            _   => {},
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.last_frame = mem::take(&mut self.frame);
+        self.all.add(&self.last_frame);
+        self.frames += 1;
+    }
+
+    // The hottest routines and instructions of the last frame, or of the frame so far when it's
+    // gone on so long that the game looks to be stuck. `routine` names a routine by its address,
+    // and `instruction` gives the instruction at an address.
+    pub fn last_frame(&self, count: usize, routine: impl Fn(u16) -> String, instruction: impl Fn(u16) -> String) -> Vec<String> {
+        if self.frame.total > STUCK_CYCLES {
+            let mut lines = vec![format!("The frame's been running for {} cycles, which is where they've gone:", self.frame.total)];
+            lines.extend(report(&self.frame, 1, count, &routine, &instruction));
+            return lines;
+        }
+
+        if self.frames == 0 {
+            return vec!["There hasn't been a frame yet".to_string()];
+        }
+
+        report(&self.last_frame, 1, count, &routine, &instruction)
+    }
+
+    // The hottest routines and instructions, averaged over all of the frames so far
+    pub fn per_frame(&self, count: usize, routine: impl Fn(u16) -> String, instruction: impl Fn(u16) -> String) -> Vec<String> {
+        report(&self.all, self.frames.max(1), count, &routine, &instruction)
+    }
+}
+
+// The cycles per frame of the hottest routines and instructions, with the percentage of the
+// frame's cycles that each one takes up
+fn report(cycles: &Cycles, frames: u64, count: usize, routine: &dyn Fn(u16) -> String, instruction: &dyn Fn(u16) -> String) -> Vec<String> {
+    let percent = |c: u64| if cycles.total == 0 { 0.0 } else { c as f64 * 100.0 / cycles.total as f64 };
+
+    let mut routines = cycles.routines.iter().map(|(&r, &c)| (r, c)).collect::<Vec<_>>();
+    routines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut pcs = cycles.pcs.iter().map(|(&pc, &c)| (pc, c)).collect::<Vec<_>>();
+    pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut lines = vec!["; routines, in cycles per frame".to_string()];
+    for (r, c) in routines.into_iter().take(count) {
+        let name = match r {
+            Some(address) => format!("{:04X}  {}", address, routine(address)),
+            None          => "      (everything)".to_string(),
+        };
+        lines.push(format!("{:>10}  {:5.1}%  {}", c / frames, percent(c), name).trim_end().to_string());
+    }
+
+    lines.push("; instructions, in cycles per frame".to_string());
+    for (pc, c) in pcs.into_iter().take(count) {
+        lines.push(format!("{:>10}  {:5.1}%  {:04X}  {}", c / frames, percent(c), pc, instruction(pc)).trim_end().to_string());
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(address: u16) -> String {
+        match address {
+            0xf100 => "Kernel".to_string(),
+            _      => "".to_string(),
+        }
+    }
+
+    fn instruction(pc: u16) -> String {
+        match pc {
+            0xf100 => "LDA INTIM".to_string(),
+            _      => "".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hotspots() {
+        let mut hotspots = Hotspots::new();
+        assert_eq!(hotspots.last_frame(5, label, instruction), vec!["There hasn't been a frame yet"]);
+
+        // A JSR to $F100, which loops 3 times and returns, and a NOP after it
+        hotspots.record(0xf000, 0xea, 100);
+        hotspots.record(0xf001, JSR, 106);
+        for cycles in &[110, 114, 118] {
+            hotspots.record(0xf100, 0xad, *cycles);
+        }
+        hotspots.record(0xf103, RTS, 124);
+        hotspots.record(0xf004, 0xea, 126);
+        hotspots.end_frame();
+
+        assert_eq!(hotspots.last_frame(2, label, instruction), vec![
+            "; routines, in cycles per frame",
+            "        26  100.0%        (everything)",
+            "        18   69.2%  F100  Kernel",
+            "; instructions, in cycles per frame",
+            "        12   46.2%  F100  LDA INTIM",
+            "         6   23.1%  F001",
+        ]);
+
+        // Averaged over two frames, the second without the call
+        hotspots.record(0xf004, 0xea, 128);
+        hotspots.end_frame();
+        assert_eq!(hotspots.per_frame(2, label, instruction)[2], "         9   64.3%  F100  Kernel");
+
+        // A frame that's gone on too long is reported on before it's finished
+        hotspots.record(0xf004, 0xea, 128 + STUCK_CYCLES + 1);
+        assert!(hotspots.last_frame(2, label, instruction)[0].starts_with("The frame's been running"));
+    }
+}
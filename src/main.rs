@@ -1,64 +1,354 @@
 #[macro_use] extern crate log;
 #[macro_use] extern crate lazy_static;
 
+mod beam_window;
+mod bk2;
 mod bus;
+mod clip;
 mod cpu6507;
+mod cpu_trace;
 mod debugger;
+mod disasm;
+mod frame;
+mod frame_diff;
+mod gdb;
+mod hotspots;
+mod gamepad;
+mod ntsc;
+mod options;
+mod overlay;
+mod md5;
+mod movie;
+mod palette;
+mod peripheral;
+mod pointing;
+mod profile;
+mod properties;
+mod region;
 mod riot;
+mod save_state;
+mod savekey;
+mod script;
+mod sound;
+mod state_dump;
+mod symbols;
 mod tia;
+mod timeline;
+mod tui;
+mod watch;
 
 use std::cell::RefCell;
-use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::process;
 use std::rc::Rc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::bus::AtariBus;
+use crate::beam_window::BeamWindow;
+use crate::bus::{AtariBus, Bus};
+use crate::clip::Clip;
 use crate::cpu6507::CPU6507;
+use crate::cpu_trace::CpuTrace;
 use crate::debugger::Debugger;
-use crate::riot::RIOT;
-use crate::tia::TIA;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::{Color, PixelFormatEnum};
-
-const ATARI_FPS: f64 = 60.0;
-const FRAME_DURATION: Duration = Duration::from_millis(((1.0 / ATARI_FPS) * 1000.0) as u64);
-const CLOCKS_PER_SCANLINE: usize = 228;
+use crate::frame::Frame;
+use crate::frame_diff::FrameDiff;
+use crate::gdb::GdbStub;
+use crate::gamepad::Gamepads;
+use crate::hotspots::Hotspots;
+use crate::ntsc::NtscFilter;
+use crate::options::{Controller, Filter, Options};
+use crate::overlay::{draw_message, AudioOverlay, KeypadOverlay, ObjectOverlay, PlayfieldOverlay};
+use crate::md5::md5;
+use crate::movie::{Input, Movie, Playback};
+use crate::palette::{load_palette, Palette};
+use crate::peripheral::{Direction, Joystick, Keypad, Peripheral, Source};
+use crate::pointing::PointingDevice;
+use crate::profile::Profile;
+use crate::properties::Properties;
+use crate::region::{Region, RegionDetector};
+use crate::riot::{RamPattern, RIOT};
+use crate::save_state::SaveStates;
+use crate::savekey::SaveKey;
+use crate::script::Script;
+use crate::sound::Sound;
+use crate::symbols::Symbols;
+use crate::tia::{PlayerType, TIA};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::PixelFormatEnum;
+
+const CLOCKS_PER_SCANLINE: u64 = 228;
+
+// Where the machine's state is written if the emulator panics
+const PANIC_DUMP: &str = "atari2600-panic.json";
 
 fn main() {
     env_logger::init();
 
-    let rom_path = env::args().skip(1).next()
-        .expect("missing argument: rom file");
+    let options = Options::from_args();
+    let rom_path = &options.rom_path;
 
-    let mut fh = File::open(&rom_path).expect("unable to open rom");
+    // Unless the region is given, start off assuming NTSC, and detect it from the game's frames
+    let mut region = options.region.unwrap_or(Region::NTSC);
+    let mut region_detector = match options.region {
+        Some(_) => None,
+        None    => Some(RegionDetector::new()),
+    };
+    let mut frame_duration = frame_time(region);
+
+    let mut fh = File::open(rom_path).expect("unable to open rom");
 
     let mut rom = vec![];
     let bytes = fh.read_to_end(&mut rom).expect("unable to read rom data");
     info!("ROM: {} ({} bytes)", rom_path, bytes);
 
+    let rom_md5 = md5(&rom);
+    info!("ROM: MD5 {}", rom_md5);
+
+    let properties = options.properties.as_ref().map(|path| {
+        Properties::load(path).expect("unable to load properties")
+    });
+    let property = |key| properties.as_ref().and_then(|p| p.get(&rom_md5, key));
+
+    if let Some(name) = property("Cart.Name") {
+        info!("ROM: {}", name);
+    }
+
+    // The symbol file given on the command line, or else the one next to the ROM, if there is one
+    let symbols_path = options.symbols.clone().or_else(|| {
+        let path = Path::new(rom_path).with_extension("sym");
+        if path.exists() { Some(path.to_string_lossy().to_string()) } else { None }
+    });
+
+    let symbols = match &symbols_path {
+        Some(path) => {
+            let symbols = Symbols::load(path).expect("unable to load symbols");
+            info!("Debugger: loaded {} symbols from {}", symbols.len(), path);
+            symbols
+        },
+        None => Symbols::new(),
+    };
+
+    // The controllers given on the command line, or else the ones the game uses, going by the
+    // properties database
+    let (controller, paddles, port) = match (options.controller, options.paddles) {
+        (None, false) => Controller::from_properties(property("Controller.Left"), property("Controller.Right")),
+        (controller, paddles) => (controller.unwrap_or(Controller::Joystick), paddles, 0),
+    };
+
+    // Movies only have the joysticks in them, so with any other controller they wouldn't play back
+    // the way they were played
+    if (options.record.is_some() || options.play.is_some()) && (controller != Controller::Joystick || paddles) {
+        let other = if paddles { "paddles".to_string() } else { format!("{:?}", controller).to_lowercase() };
+        error!("Movie: only joysticks are recorded, so movies can't be recorded or played back with {}", other);
+        process::exit(1);
+    }
+
+    // Some games read player 0's controller from the right port
+    let mut swap_ports = options.swap_ports || property("Controller.SwapPorts") == Some("YES");
+    if swap_ports {
+        info!("Controllers: ports swapped");
+    }
+
     info!("RIOT: init");
     let riot = Rc::new(RefCell::new(RIOT::new()));
-    riot.borrow_mut().up(false);
-    riot.borrow_mut().down(false);
-    riot.borrow_mut().left(false);
-    riot.borrow_mut().right(false);
     riot.borrow_mut().select(false);
     riot.borrow_mut().reset(false);
 
+    // Without a seed, random RAM is different every time, but the seed's logged so that a run can
+    // be repeated
+    let ram_seed = options.ram_seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)
+    });
+    if options.ram == RamPattern::Random {
+        info!("RIOT: random RAM, with seed {}", ram_seed);
+    }
+    riot.borrow_mut().set_ram_pattern(options.ram, ram_seed);
+
+    let save_states = SaveStates::new(options.states.clone().into(), &rom_md5);
+
+    // A SaveKey takes the right controller's place
+    let savekey = options.savekey.as_ref().map(|path| {
+        info!("RIOT: SaveKey in the right controller port, saving to {}", path);
+        Rc::new(RefCell::new(SaveKey::open(path.into()).expect("unable to open savekey file")))
+    });
+
     info!("TIA: init");
     let tia = Rc::new(RefCell::new(TIA::new()));
-    tia.borrow_mut().joystick_fire(false);
 
-    let bus = AtariBus::new(tia.clone(), riot.clone(), rom);
+    // A joystick in each port, or a keypad. The other controllers pull the same pins low, so
+    // they're plugged in alongside the joysticks, which are left alone.
+    let joysticks = [
+        Rc::new(RefCell::new(Joystick::new(options.autofire_rate))),
+        Rc::new(RefCell::new(Joystick::new(options.autofire_rate))),
+    ];
+    let keypads = [Rc::new(RefCell::new(Keypad::new())), Rc::new(RefCell::new(Keypad::new()))];
+    let controllers = [0, 1].map(|port| -> Rc<RefCell<dyn Peripheral>> {
+        match (&savekey, controller) {
+            (Some(savekey), _) if port == 1 => savekey.clone(),
+            (_, Controller::Keypad)         => keypads[port].clone(),
+            _                               => joysticks[port].clone(),
+        }
+    });
+    for (port, peripheral) in controllers.iter().enumerate() {
+        riot.borrow_mut().plug_in(port, peripheral.clone());
+        tia.borrow_mut().plug_in(port, peripheral.clone());
+    }
+    let mut autofire = false;
+
+    riot.borrow_mut().set_swap_ports(swap_ports);
+    tia.borrow_mut().set_swap_ports(swap_ports);
+
+    // Shared with the beam window, which draws with it whenever the debugger stops
+    let palette = Rc::new(RefCell::new(match &options.palette {
+        Some(name) => {
+            info!("Graphics: using palette {}", name);
+            Palette::new(load_palette(name).expect("unable to load palette"))
+        },
+        None => Palette::for_region(region),
+    }));
+
+    if controller == Controller::Genesis {
+        tia.borrow_mut().genesis_button_c(PlayerType::Player0, false);
+        tia.borrow_mut().genesis_button_c(PlayerType::Player1, false);
+    }
+
+    if let Some(path) = &options.audio_log {
+        info!("TIA: recording audio to {}", path);
+        let fh = File::create(path).expect("unable to create audio log");
+        tia.borrow_mut().record_audio(fh, region).expect("unable to write audio log");
+    }
+
+    if let Some(path) = &options.trace {
+        info!("TIA: tracing register writes to {}", path);
+        let fh = File::create(path).expect("unable to create trace");
+        tia.borrow_mut().trace_writes(fh, &options.trace_registers).expect("unable to start trace");
+    }
+
+    let script = options.script.as_ref().map(|path| {
+        info!("Script: running {}", path);
+        let script = Script::load(path, tia.clone(), riot.clone(), rom.clone(), &joysticks).expect("unable to load script");
+        Rc::new(RefCell::new(script))
+    });
+
+    let mut bus = AtariBus::new(tia.clone(), riot.clone(), rom.clone());
+
+    // Every read and write goes through the bus, so it's only hooked into when the script needs it
+    if let Some(script) = &script {
+        if script.borrow().has_hook("on_read") {
+            let script = script.clone();
+            bus.set_read_fn(move |address, value| script.borrow_mut().on_read(address, value));
+        }
+
+        if script.borrow().has_hook("on_write") {
+            let script = script.clone();
+            bus.set_write_fn(move |address, value| script.borrow_mut().on_write(address, value));
+        }
+    }
 
     info!("CPU: init");
-    let mut cpu = CPU6507::new(Box::new(bus));
-    cpu.reset();
+    let cpu = RefCell::new(CPU6507::new(bus));
+
+    let cpu_trace_format = options.cpu_trace_format;
+
+    // Streaming the trace out and keeping the history both go through the CPU's trace function
+    let cpu_trace = if options.cpu_trace.is_some() || options.cpu_history.is_some() {
+        let mut trace = CpuTrace::new(cpu_trace_format);
+
+        if let Some(path) = &options.cpu_trace {
+            info!("CPU: tracing instructions to {}", path);
+            trace.stream_to(File::create(path).expect("unable to create cpu trace"));
+        }
+
+        if let Some(path) = &options.cpu_history {
+            info!("CPU: keeping the last {} instructions for {}", options.cpu_history_length, path);
+            trace.keep_history(path, options.cpu_history_length);
+        }
+
+        let trace = Rc::new(RefCell::new(trace));
+        let recorder = trace.clone();
+        cpu.borrow_mut().set_trace_fn(move |record| recorder.borrow_mut().record(record));
+
+        Some(trace)
+    } else {
+        None
+    };
+
+    let profile = if options.profile {
+        Some(Rc::new(RefCell::new(Profile::new(options.profile_pcs))))
+    } else {
+        None
+    };
+
+    // Profiling counts the cycles spent in each routine and at each instruction as well
+    let hotspots = profile.as_ref().map(|_| Rc::new(RefCell::new(Hotspots::new())));
+
+    if let (Some(profile), Some(hotspots)) = (&profile, &hotspots) {
+        let (counts, cycles) = (profile.clone(), hotspots.clone());
+        cpu.borrow_mut().add_step_fn(move |record| {
+            counts.borrow_mut().record(record.pc, record.opcode);
+            cycles.borrow_mut().record(record.pc, record.opcode, record.cycles);
+        });
+    }
+
+    // The script's called before each instruction, as the one before it finishes
+    if let Some(script) = script.as_ref().filter(|script| script.borrow().has_hook("on_instruction")) {
+        let script = script.clone();
+        cpu.borrow_mut().add_step_fn(move |record| {
+            script.borrow_mut().on_instruction(record.next_pc, record.a, record.x, record.y)
+        });
+    }
+
+    cpu.borrow_mut().reset();
+
+    if options.autosave {
+        match save_states.resume(&mut cpu.borrow_mut()) {
+            Ok(true)  => info!("State: resumed from {}", save_states.autosave_path().display()),
+            Ok(false) => {},
+            Err(e)    => error!("State: unable to resume: {}", e),
+        }
+    }
+
+    // A movie's played back from the state it was recorded from, with its own joysticks plugged in
+    let mut playback = options.play.as_ref().map(|path| {
+        // BizHawk's movies start from power on, which is where the machine is now
+        let movie = if path.ends_with(".bk2") {
+            let mut state = vec![];
+            cpu.borrow().save(&mut state).expect("unable to save the CPU's state");
+            bk2::import(path, &rom_md5, state, riot.borrow().switches()).expect("unable to import movie")
+        } else {
+            Movie::load(path).expect("unable to load movie")
+        };
+        if movie.rom_md5() != rom_md5 {
+            error!("Movie: {} was recorded with another ROM, whose MD5 is {}", path, movie.rom_md5());
+            process::exit(1);
+        }
+
+        info!("Movie: playing back {} frames from {}", movie.len(), path);
+        cpu.borrow_mut().load(&mut movie.state()).expect("unable to load the movie's state");
+
+        let mut playback = Playback::new(movie);
+        for port in 0 .. 2 {
+            riot.borrow_mut().plug_in(port, playback.joystick(port));
+            tia.borrow_mut().plug_in(port, playback.joystick(port));
+        }
+        playback.next_frame(&mut riot.borrow_mut());
+        playback
+    });
+
+    let mut recording = options.record.as_ref().map(|path| {
+        info!("Movie: recording to {}", path);
+        let mut state = vec![];
+        cpu.borrow().save(&mut state).expect("unable to save the CPU's state");
+        Movie::new(&rom_md5, state)
+    });
+
+    // The last few seconds of the picture, which V writes out as a GIF
+    let mut clip = options.gif.map(|seconds| Clip::new(seconds * region.fps() as usize));
 
     //
     // SDL-related stuffs
@@ -66,7 +356,8 @@ fn main() {
 
     info!("Graphics: init");
     let width  = 160 * 5;
-    let height = 200 * 3;
+    let mut lines = region.visible_scanlines();
+    let height = lines as u32 * 3;
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
@@ -103,96 +394,308 @@ fn main() {
     canvas.copy(&texture, None, None).unwrap();
     canvas.present();
 
+    info!("Audio: init");
+    let audio_subsystem = sdl_context.audio().unwrap();
+
+    let mut sound = Sound::new(&audio_subsystem, region);
+
+    info!("  audio driver: {}", audio_subsystem.current_audio_driver());
+
+    let mut gamepads = Gamepads::new(
+        sdl_context.game_controller().unwrap(),
+        options.gamepads.clone(),
+        paddles,
+        controller == Controller::Genesis,
+    );
+
+    // A Trak-Ball, mouse, or driving controller is driven by how far the host mouse moves, so the
+    // mouse is kept in the window rather than stopping at its edges
+    let mut pointing_device = controller.protocol().map(PointingDevice::new);
+    let pointing_player = if port == 0 { PlayerType::Player0 } else { PlayerType::Player1 };
+    if let Some(device) = &pointing_device {
+        sdl_context.mouse().set_relative_mouse_mode(true);
+        riot.borrow_mut().set_pins(pointing_player, device.pins());
+    }
+
+    // Where the mouse is in the window, for aiming the light gun
+    let light_gun = controller == Controller::LightGun;
+    let mut light_gun_aim = (0, 0);
+
     let mut event_pump = sdl_context.event_pump().unwrap();
     let mut fps_start = Instant::now();
 
+    let mut audio_overlay = AudioOverlay::new();
+    let mut playfield_overlay = PlayfieldOverlay::new();
+    let object_overlay = RefCell::new(ObjectOverlay::new());
+    let frame_diff = Rc::new(RefCell::new(FrameDiff::new()));
+    let mut keypad_overlay = KeypadOverlay::new(controller == Controller::Keypad);
+
+    // The keypad key that's been clicked on, which is held down until the mouse button's let go of
+    let mut clicked_key = None;
+
     let mut debugger = Rc::new(RefCell::new(Debugger::new(
         tia.clone(),
+        riot.clone(),
+        rom,
+        symbols,
+        profile.clone(),
     )));
 
-    let mut scanline = || {
-        for c in 0 .. CLOCKS_PER_SCANLINE {
-            if (c % 3) == 0 {
+    if let Some(port) = options.gdb {
+        let gdb = GdbStub::listen(port).expect("unable to listen for gdb");
+        debugger.borrow_mut().attach_gdb(gdb);
+    }
+
+    debugger.borrow_mut().set_tui(options.tui);
+    debugger.borrow_mut().set_frame_diff(frame_diff.clone());
+    if let Some(hotspots) = &hotspots {
+        debugger.borrow_mut().set_hotspots(hotspots.clone());
+    }
+
+    let beam_window = if options.beam_window {
+        Some(Rc::new(RefCell::new(BeamWindow::new(&video_subsystem))))
+    } else {
+        None
+    };
+
+    if let Some(window) = &beam_window {
+        let (window, tia, palette) = (window.clone(), tia.clone(), palette.clone());
+        debugger.borrow_mut().set_stop_fn(move |scanlines| {
+            window.borrow_mut().draw(&tia.borrow(), &palette.borrow(), scanlines)
+        });
+    }
+
+    if options.debug {
+        debugger.borrow_mut().start_halted(&mut cpu.borrow_mut());
+    }
+
+    // The colour clocks are counted by the TIA rather than here, so that when the debugger goes
+    // back to part way through a scanline, this carries on from there to the end of it
+    let scanline = || {
+        loop {
+            let c = tia.borrow().clocks() % CLOCKS_PER_SCANLINE;
+
+            if c.is_multiple_of(3) {
                 riot.borrow_mut().clock();
             }
 
             tia.borrow_mut().clock();
             debugger.borrow_mut().debug();
 
-            if !tia.borrow().cpu_halt() && (c % 3) == 2 {
-                cpu.clock();
+            if (c % 3) == 2 {
+                let mut cpu = cpu.borrow_mut();
+                cpu.set_rdy(tia.borrow().rdy());
+                if cpu.clock() {
+                    debugger.borrow_mut().instruction(&mut cpu);
+                }
+            }
+
+            debugger.borrow_mut().color_clock(&mut cpu.borrow_mut());
+
+            if tia.borrow().clocks().is_multiple_of(CLOCKS_PER_SCANLINE) {
+                break;
             }
         }
 
         return tia.borrow().get_scanline_pixels().clone();
     };
 
-    let mut frames = 0;
-
-    let mut vsync = 0;
-    let mut vblank = 0;
-    let mut visible = 0;
-    let mut overscan = 0;
+    let mut frame = Frame::new(region);
+    frame.set_rolling(options.roll);
+    frame.set_interlace(options.interlace);
+
+    let ntsc_filter = NtscFilter::new();
+
+    // When the debugger goes back to an earlier scanline, the frame goes back with it, and the
+    // scanline being generated is the one it went back to, along with whether it started in VSYNC
+    // and VBLANK
+    let rewind = |frame: &mut Frame, vsync: bool, vblank: bool| {
+        match debugger.borrow_mut().take_rewind() {
+            Some((scanlines, vsync, vblank)) => {
+                frame.rewind(scanlines);
+                object_overlay.borrow_mut().rewind(scanlines);
+                if let Some(window) = &beam_window {
+                    window.borrow_mut().rewind(scanlines);
+                }
+                (vsync, vblank)
+            },
+            None => (vsync, vblank),
+        }
+    };
 
-    let mut frame_pixels = vec![vec![Color::RGB(0, 0, 0); 160]; 200];
+    let mut paused = false;
+    let mut printing_cpu_trace = false;
 
     'running: loop {
-        if debugger.borrow().next_frame() {
-            // Generate one full frame
-
-            // VSync
-            while tia.borrow().in_vsync() {
-                scanline();
-                vsync += 1;
+        if !paused && debugger.borrow().next_frame() {
+            // Frames stepped through in the debugger are silent, and only running freely is heard
+            if debugger.borrow().enabled() {
+                sound.pause();
+            } else {
+                sound.resume();
             }
 
-            // VBlank
-            while tia.borrow().in_vblank() {
-                scanline();
-                vblank += 1;
+            // Generate one full frame, unless the debugger stops part way through it
+            let mut complete = false;
+            loop {
+                let (vsync, vblank) = {
+                    let tia = tia.borrow();
+                    rewind(&mut frame, tia.in_vsync(), tia.in_vblank())
+                };
+
+                if frame.complete(vsync) {
+                    complete = true;
+                    break;
+                }
+
+                if let Some(device) = pointing_device.as_mut() {
+                    device.step();
+                    riot.borrow_mut().set_pins(pointing_player, device.pins());
+                }
+
+                // The light gun's aimed at the pixel under the mouse, on whichever scanline of the
+                // frame was drawn there last time
+                if light_gun {
+                    let (x, y) = light_gun_aim;
+                    let aimed = frame.scanline_at(y / 3) == Some(frame.scanlines());
+                    tia.borrow_mut().set_light_gun(if aimed { Some(x / 5) } else { None });
+                }
+
+                // If the emulator panics, the machine's state is written out for the bug report,
+                // once the panic's let go of everything it had borrowed
+                let pixels = match panic::catch_unwind(AssertUnwindSafe(&scanline)) {
+                    Ok(pixels) => pixels,
+                    Err(payload) => {
+                        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                            .or_else(|| payload.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+
+                        match debugger.borrow().dump(&cpu.borrow(), PANIC_DUMP, Some(&message)) {
+                            Ok(()) => error!("Wrote the machine's state to {}", PANIC_DUMP),
+                            Err(e) => error!("Unable to write the machine's state to {}: {}", PANIC_DUMP, e),
+                        }
+
+                        panic::resume_unwind(payload);
+                    },
+                };
+                let (vsync, vblank) = rewind(&mut frame, vsync, vblank);
+                object_overlay.borrow_mut().push_scanline(tia.borrow().get_scanline_objects());
+                if let Some(window) = &beam_window {
+                    window.borrow_mut().push_scanline(&pixels);
+                }
+                frame.push_scanline(pixels, vsync, vblank);
+
+                if debugger.borrow_mut().end_scanline() {
+                    break;
+                }
             }
 
-            // Picture
-            let mut y = 0;
-            while !tia.borrow().in_vblank() {
-                let pixels = scanline();
-                if y < frame_pixels.len() {
-                    frame_pixels[y] = pixels;
+            let total = frame.scanlines();
+            if complete {
+                frame.finish();
+
+                // The movie's recorded before autofire moves on to the next frame. While one's
+                // being played, it's recorded as it was played.
+                if let Some(movie) = recording.as_mut() {
+                    let played = playback.as_ref().and_then(|p| p.input());
+                    movie.record(played.unwrap_or_else(|| Input::capture(&joysticks, riot.borrow().switches())));
+                }
+                for joystick in joysticks.iter() {
+                    joystick.borrow_mut().end_frame();
+                }
+                object_overlay.borrow_mut().end_frame();
+                if let Some(hotspots) = &hotspots {
+                    hotspots.borrow_mut().end_frame();
+                }
+                if let Some(window) = &beam_window {
+                    window.borrow_mut().end_frame();
+                }
+                if let Some(script) = &script {
+                    script.borrow_mut().on_frame();
                 }
-                y += 1;
 
-                visible += 1;
+                let finished = playback.as_mut().is_some_and(|p| !p.next_frame(&mut riot.borrow_mut()));
+                if finished {
+                    info!("Movie: finished after {} frames, handing the controllers back", playback.unwrap().frame());
+                    playback = None;
+                    for (port, peripheral) in controllers.iter().enumerate() {
+                        riot.borrow_mut().plug_in(port, peripheral.clone());
+                        tia.borrow_mut().plug_in(port, peripheral.clone());
+                    }
+                }
+                if playback.is_some() {
+                    let color = riot.borrow().color_switch();
+                    palette.borrow_mut().set_black_and_white(!color);
+                }
             }
 
-            // Overscan
-            while !tia.borrow().in_vsync() {
-                scanline();
-                overscan += 1;
+            if let Some(detected) = region_detector.as_mut().filter(|_| complete).and_then(|d| d.frame(total)) {
+                info!("Region: detected {:?} from {} scanline frames, with a {} scanline picture",
+                      detected, total, frame.height());
+
+                if detected != region {
+                    region = detected;
+                    frame_duration = frame_time(region);
+
+                    if options.palette.is_none() {
+                        let bw = !riot.borrow().color_switch();
+                        let mut palette = palette.borrow_mut();
+                        *palette = Palette::for_region(region);
+                        palette.set_black_and_white(bw);
+                    }
+
+                    lines = region.visible_scanlines();
+                    let height = lines as u32 * 3;
+
+                    frame.set_region(region);
+                    tia.borrow_mut().set_audio_region(region);
+                    sound.set_region(region);
+                    if let (Some(clip), Some(seconds)) = (clip.as_mut(), options.gif) {
+                        clip.set_length(seconds * region.fps() as usize);
+                    }
+                    canvas.window_mut().set_size(width, height).unwrap();
+                    texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                        .unwrap();
+                }
             }
 
-            frames += 1;
+            // Each scanline is 3 rows of the screen, so the lower field of an interlaced picture is
+            // drawn a row lower, as near to half a scanline as it can be
+            let field_offset = if frame.lower_field() { 1 } else { 0 };
 
-            vsync = 0;
-            vblank = 0;
-            visible = 0;
-            overscan = 0;
+            // The colour values of the picture, for comparing against the next frame's
+            let mut picture = vec![];
 
+            let palette = palette.borrow();
             texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-                for y in 0 .. 200 {
-                    for x in 0 .. 160 {
-                        let color  = frame_pixels[y][x];
-                        let offset = 3 * (y * pitch) + 5 * (x * 3);
-
+                buffer[.. field_offset * pitch].fill(0);
+
+                for y in 0 .. lines {
+                    let values = (0 .. 160)
+                        .map(|x| if complete { frame.pixel(x, y) } else { frame.partial_pixel(x, y) })
+                        .collect::<Vec<_>>();
+                    let pixels = values.iter().map(|&value| palette.color(value)).collect::<Vec<_>>();
+                    picture.push(values);
+
+                    // Each pixel is 5 pixels wide on the screen. With the NTSC filter, each of those
+                    // 5 is filtered separately, otherwise they're all the same colour.
+                    let row = match options.filter {
+                        Filter::Sharp => pixels.iter().flat_map(|&c| vec![c; 5]).collect(),
+                        Filter::NTSC  => ntsc_filter.filter(&pixels),
+                    };
+
+                    for (x, color) in row.iter().enumerate() {
                         for y2 in 0 .. 3 {
-                            let offset = offset + (y2 * pitch);
+                            let screen_y = (y * 3) + y2 + field_offset;
+                            if screen_y >= lines * 3 {
+                                break;
+                            }
 
-                            for x2 in 0 .. 5 {
-                                let offset = offset + (x2 * 3);
+                            let offset = (screen_y * pitch) + (x * 3);
 
-                                buffer[offset]   = color.r;
-                                buffer[offset+1] = color.g;
-                                buffer[offset+2] = color.b;
-                            }
+                            buffer[offset]   = color.r;
+                            buffer[offset+1] = color.g;
+                            buffer[offset+2] = color.b;
                         }
                     }
                 }
@@ -200,42 +703,222 @@ fn main() {
 
             canvas.clear();
             canvas.copy(&texture, None, None).unwrap();
+            audio_overlay.draw(&mut canvas, &tia.borrow());
+            playfield_overlay.draw(&mut canvas, &tia.borrow());
+            object_overlay.borrow().draw(&mut canvas, &frame, lines, complete, field_offset as i32);
+
+            // The differences are between complete frames, so they're not shown part way through one
+            if complete {
+                if let Some(clip) = clip.as_mut() {
+                    clip.push(picture.clone());
+                }
+                frame_diff.borrow_mut().end_frame(picture);
+                frame_diff.borrow().draw(&mut canvas, field_offset as i32);
+            }
+            keypad_overlay.draw(&mut canvas, &[&keypads[0].borrow(), &keypads[1].borrow()]);
+            if let Some(script) = &script {
+                script.borrow().draw(&mut canvas);
+            }
+
+            if let Some(pc) = cpu.borrow().jammed() {
+                draw_message(&mut canvas, &format!("CPU JAMMED AT ${:04X} - F12 TO RESET", pc));
+            }
             canvas.present();
 
-            debugger.borrow_mut().end_frame();
+            if complete {
+                let samples = tia.borrow_mut().take_audio_samples();
+                sound.play(&samples);
+
+                debugger.borrow_mut().end_frame(&cpu.borrow());
+            } else {
+                debugger.borrow_mut().scanline_stopped(&mut cpu.borrow_mut());
+            }
+        } else {
+            sound.pause();
         }
 
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => { break 'running },
-                Event::KeyDown { keycode: Some(key), .. } => {
+                // With the beam window open as well, closing either window doesn't quit by itself
+                Event::Window { win_event: WindowEvent::Close, .. } => { break 'running },
+                Event::KeyDown { keycode: Some(key), keymod, .. } => {
                     match key {
                         // Joystick controls
-                        Keycode::W => riot.borrow_mut().up(true),
-                        Keycode::A => riot.borrow_mut().left(true),
-                        Keycode::S => riot.borrow_mut().down(true),
-                        Keycode::D => riot.borrow_mut().right(true),
-                        Keycode::N => tia.borrow_mut().joystick_fire(true),
+                        Keycode::W => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Up, true),
+                        Keycode::A => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Left, true),
+                        Keycode::S => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Down, true),
+                        Keycode::D => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Right, true),
+                        Keycode::N => joysticks[0].borrow_mut().set_fire(Source::Keyboard, true),
+                        Keycode::RCtrl => joysticks[1].borrow_mut().set_fire(Source::Keyboard, true),
+                        Keycode::M if controller == Controller::Genesis => {
+                            tia.borrow_mut().genesis_button_c(PlayerType::Player0, true)
+                        },
+                        Keycode::RShift if controller == Controller::Genesis => {
+                            tia.borrow_mut().genesis_button_c(PlayerType::Player1, true)
+                        },
 
                         // Console switches
                         Keycode::F1 => riot.borrow_mut().select(true),
                         Keycode::F2 => riot.borrow_mut().reset(true),
-                        Keycode::F3 => riot.borrow_mut().color(),
+                        Keycode::F3 => {
+                            riot.borrow_mut().color();
+                            let color = riot.borrow().color_switch();
+                            palette.borrow_mut().set_black_and_white(!color);
+                        },
+
+                        Keycode::P => paused = !paused,
+                        Keycode::T => {
+                            autofire = !autofire;
+                            info!("Controllers: autofire {}", if autofire { "on" } else { "off" });
+                            for joystick in joysticks.iter() {
+                                joystick.borrow_mut().set_autofire(autofire);
+                            }
+                        },
+                        Keycode::K => keypad_overlay.toggle(),
+                        Keycode::Tab => {
+                            swap_ports = !swap_ports;
+                            info!("Controllers: ports {}", if swap_ports { "swapped" } else { "unswapped" });
+                            riot.borrow_mut().set_swap_ports(swap_ports);
+                            tia.borrow_mut().set_swap_ports(swap_ports);
+                        },
+                        Keycode::F12 if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) => {
+                            tia.borrow_mut().power_cycle();
+                            riot.borrow_mut().power_cycle();
+                            cpu.borrow_mut().power_cycle();
+                        },
+                        Keycode::F12 => cpu.borrow_mut().reset(),
+
+                        // Save states, Shift and a number to save to that slot, and the number on
+                        // its own to load it
+                        Keycode::Num0 | Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 |
+                        Keycode::Num5 | Keycode::Num6 | Keycode::Num7 | Keycode::Num8 | Keycode::Num9 => {
+                            let slot = (key as i32 - Keycode::Num0 as i32) as usize;
+
+                            if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                                match save_states.save(slot, &cpu.borrow()) {
+                                    Ok(()) => info!("State: saved to slot {}", slot),
+                                    Err(e) => error!("State: unable to save to slot {}: {}", slot, e),
+                                }
+                            } else {
+                                match save_states.load(slot, &mut cpu.borrow_mut()) {
+                                    Ok(()) => info!("State: loaded slot {}", slot),
+                                    Err(e) => error!("State: unable to load slot {}: {}", slot, e),
+                                }
+                            }
+                        },
+
+                        Keycode::V => if let Some(clip) = &clip {
+                            let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                            let path = format!("atari2600-{}.gif", secs);
+                            match clip.write(&path, &palette.borrow(), region.fps()) {
+                                Ok(frames) => info!("GIF: wrote {} frames to {}", frames, path),
+                                Err(e) => error!("GIF: unable to write {}: {}", path, e),
+                            }
+                        },
 
                         // Debugger
                         Keycode::Backquote => debugger.borrow_mut().toggle(),
                         Keycode::Space     => debugger.borrow_mut().step_frame(),
+                        Keycode::Pause     => debugger.borrow_mut().break_in(),
+                        Keycode::L         => debugger.borrow_mut().step_scanline(),
+                        Keycode::F4        => {
+                            printing_cpu_trace = !printing_cpu_trace;
+
+                            if printing_cpu_trace {
+                                cpu.borrow_mut().set_trace_fn(move |record| println!("{}", record.format(cpu_trace_format)));
+                            } else if let Some(trace) = &cpu_trace {
+                                let recorder = trace.clone();
+                                cpu.borrow_mut().set_trace_fn(move |record| recorder.borrow_mut().record(record));
+                            } else {
+                                cpu.borrow_mut().clear_trace_fn();
+                            }
+                        },
+                        Keycode::F5        => debugger.borrow_mut().toggle_mute(0),
+                        Keycode::F6        => debugger.borrow_mut().toggle_mute(1),
+                        Keycode::F7        => debugger.borrow_mut().toggle_solo(0),
+                        Keycode::F8        => debugger.borrow_mut().toggle_solo(1),
+                        Keycode::F9        => audio_overlay.toggle(),
+                        Keycode::F10       => debugger.borrow().print_objects(),
+                        Keycode::F11       => playfield_overlay.toggle(),
+                        Keycode::G         => object_overlay.borrow_mut().toggle(),
+                        Keycode::B         => debugger.borrow().print_position(),
+                        Keycode::C         => debugger.borrow().print_switches(),
+                        Keycode::I         => debugger.borrow().print_disassembly(&cpu.borrow()),
+                        Keycode::O         => debugger.borrow().print_profile(),
+                        Keycode::R         => debugger.borrow().print_port_a(),
+                        Keycode::H         => {
+                            if let Some(trace) = &cpu_trace {
+                                trace.borrow().write_history();
+                            }
+                        },
 
                         _ => {},
                     }
                 },
+                // Paddle controls. The mouse's position across the window sets the position of
+                // the first paddle in their port, turning it clockwise as the mouse moves left.
+                Event::MouseMotion { x, .. } if paddles => {
+                    let x = x.max(0).min(width as i32 - 1);
+                    let position = 255 - (x * 256 / width as i32) as u8;
+                    tia.borrow_mut().set_paddle(2 * port, position);
+                },
+                Event::MouseButtonDown { x, y, .. } if controller == Controller::Keypad => {
+                    let (window_width, window_height) = canvas.output_size().unwrap();
+                    clicked_key = keypad_overlay.key_at(window_width, window_height, x, y);
+
+                    if let Some((keypad, key)) = clicked_key {
+                        keypads[keypad].borrow_mut().set_key(key, true);
+                    }
+                },
+                Event::MouseButtonUp { .. } if controller == Controller::Keypad => {
+                    if let Some((keypad, key)) = clicked_key.take() {
+                        keypads[keypad].borrow_mut().set_key(key, false);
+                    }
+                },
+                Event::MouseButtonDown { .. } if paddles => riot.borrow_mut().paddle_fire(2 * port, true),
+                Event::MouseButtonUp { .. } if paddles => riot.borrow_mut().paddle_fire(2 * port, false),
+                // Light gun controls, for player 0. The trigger is on pin 1 (up), and any mouse
+                // button pulls it.
+                Event::MouseMotion { x, y, .. } if light_gun => {
+                    let x = x.max(0).min(width as i32 - 1);
+                    let y = y.max(0).min(height as i32 - 1);
+                    light_gun_aim = (x as usize, y as usize);
+                },
+                Event::MouseButtonDown { .. } if light_gun => joysticks[0].borrow_mut().set_direction(Source::Mouse, Direction::Up, true),
+                Event::MouseButtonUp { .. } if light_gun => joysticks[0].borrow_mut().set_direction(Source::Mouse, Direction::Up, false),
+                // Trak-Ball, mouse, and driving controller controls, for the port it's plugged into.
+                // Any mouse button is the fire button.
+                Event::MouseMotion { xrel, yrel, .. } if pointing_device.is_some() => {
+                    pointing_device.as_mut().unwrap().move_by(xrel, yrel);
+                },
+                Event::MouseButtonDown { .. } if pointing_device.is_some() => {
+                    joysticks[port].borrow_mut().set_fire(Source::Mouse, true)
+                },
+                Event::MouseButtonUp { .. } if pointing_device.is_some() => {
+                    joysticks[port].borrow_mut().set_fire(Source::Mouse, false)
+                },
+                Event::ControllerDeviceAdded { .. } |
+                Event::ControllerDeviceRemoved { .. } |
+                Event::ControllerAxisMotion { .. } |
+                Event::ControllerButtonDown { .. } |
+                Event::ControllerButtonUp { .. } => {
+                    gamepads.handle_event(&event, &mut riot.borrow_mut(), &mut tia.borrow_mut(), &joysticks);
+                },
                 Event::KeyUp { keycode: Some(key), .. } => {
                     match key {
-                        Keycode::W => riot.borrow_mut().up(false),
-                        Keycode::A => riot.borrow_mut().left(false),
-                        Keycode::S => riot.borrow_mut().down(false),
-                        Keycode::D => riot.borrow_mut().right(false),
-                        Keycode::N => tia.borrow_mut().joystick_fire(false),
+                        Keycode::W => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Up, false),
+                        Keycode::A => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Left, false),
+                        Keycode::S => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Down, false),
+                        Keycode::D => joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Right, false),
+                        Keycode::N => joysticks[0].borrow_mut().set_fire(Source::Keyboard, false),
+                        Keycode::RCtrl => joysticks[1].borrow_mut().set_fire(Source::Keyboard, false),
+                        Keycode::M if controller == Controller::Genesis => {
+                            tia.borrow_mut().genesis_button_c(PlayerType::Player0, false)
+                        },
+                        Keycode::RShift if controller == Controller::Genesis => {
+                            tia.borrow_mut().genesis_button_c(PlayerType::Player1, false)
+                        },
 
                         Keycode::F1 => riot.borrow_mut().select(false),
                         Keycode::F2 => riot.borrow_mut().reset(false),
@@ -247,10 +930,37 @@ fn main() {
             }
         }
 
-        if let Some(delay) = FRAME_DURATION.checked_sub(fps_start.elapsed()) {
+        if let Some(delay) = frame_duration.checked_sub(fps_start.elapsed()) {
             thread::sleep(delay);
         }
 
         fps_start = Instant::now();
     }
+
+    if let (Some(movie), Some(path)) = (&recording, &options.record) {
+        let saved = if path.ends_with(".bk2") { bk2::export(movie, path) } else { movie.save(path) };
+        match saved {
+            Ok(()) => info!("Movie: recorded {} frames to {}", movie.len(), path),
+            Err(e) => error!("Movie: unable to write {}: {}", path, e),
+        }
+    }
+
+    if options.autosave {
+        if let Err(e) = save_states.autosave(&cpu.borrow()) {
+            error!("State: unable to save on exit: {}", e);
+        }
+    }
+
+    if let Some(profile) = &profile {
+        print!("{}", profile.borrow());
+        debugger.borrow().print_hotspots(&cpu.borrow());
+    }
+
+    if let Some(trace) = &cpu_trace {
+        trace.borrow().write_history();
+    }
+}
+
+fn frame_time(region: Region) -> Duration {
+    Duration::from_millis(((1.0 / region.fps()) * 1000.0) as u64)
 }
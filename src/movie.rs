@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+use crate::peripheral::{Joystick, Peripheral};
+use crate::riot::{Switches, RIOT};
+
+const HEADER: &str = "atari2600 movie";
+
+// The letters for each joystick direction, from pin 1 to pin 4, and then the fire button
+const JOYSTICK: [char; 5] = ['U', 'D', 'L', 'R', 'F'];
+
+// The letters for the console switches: reset and select pressed, the TV type on colour, and the
+// left and right difficulties on A
+const SWITCHES: [char; 5] = ['R', 'S', 'C', 'L', 'R'];
+
+// A letter for each of five buttons that's on, and a dot for each one that's off, e.g. `U..RF`
+pub fn letters(letters: &[char; 5], on: [bool; 5]) -> String {
+    letters.iter().zip(on.iter()).map(|(&letter, &on)| if on { letter } else { '.' }).collect()
+}
+
+// What the game could see of the joysticks and the console switches through one frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Input {
+    // The levels of each joystick's data pins and fire button pin, which are low while they're
+    // pushed and pressed
+    pub pins: [u8; 2],
+    pub fire: [bool; 2],
+    pub switches: Switches,
+}
+
+impl Input {
+    // The joysticks' levels are taken after autofire, so the movie doesn't depend on it
+    pub fn capture(joysticks: &[Rc<RefCell<Joystick>>; 2], switches: Switches) -> Self {
+        Self {
+            pins: [joysticks[0].borrow().pins(), joysticks[1].borrow().pins()],
+            fire: [joysticks[0].borrow().fire(), joysticks[1].borrow().fire()],
+            switches: switches,
+        }
+    }
+
+    // Which of a joystick's directions are pushed, from pin 1 to pin 4, and whether its fire
+    // button's pressed
+    pub fn joystick(&self, player: usize) -> [bool; 5] {
+        let pushed = |pin: u8| self.pins[player] & (1 << pin) == 0;
+        [pushed(0), pushed(1), pushed(2), pushed(3), !self.fire[player]]
+    }
+
+    pub fn set_joystick(&mut self, player: usize, on: &[bool]) {
+        self.pins[player] = (0 .. 4).filter(|&pin| !on[pin]).fold(0, |pins, pin| pins | (1 << pin));
+        self.fire[player] = !on[4];
+    }
+
+    // Each joystick's directions and fire button, and then the switches, e.g. `U..RF|.....|....R`
+    fn to_line(self) -> String {
+        let s = self.switches;
+        let switches = letters(&SWITCHES, [s.reset, s.select, s.color, s.p0_difficulty_a, s.p1_difficulty_a]);
+
+        format!("{}|{}|{}", letters(&JOYSTICK, self.joystick(0)), letters(&JOYSTICK, self.joystick(1)), switches)
+    }
+
+    fn parse(line: &str) -> Result<Self, String> {
+        let fields = line.split('|').map(|field| {
+            let on = field.chars().map(|c| c != '.').collect::<Vec<_>>();
+            if on.len() == 5 { Ok(on) } else { Err(format!("expected 5 letters, not {:?}", field)) }
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        if fields.len() != 3 {
+            return Err(format!("expected two joysticks and the switches, not {:?}", line));
+        }
+
+        let switches = &fields[2];
+        let mut input = Self {
+            pins: [0x0f; 2],
+            fire: [true; 2],
+            switches: Switches {
+                reset: switches[0],
+                select: switches[1],
+                color: switches[2],
+                p0_difficulty_a: switches[3],
+                p1_difficulty_a: switches[4],
+            },
+        };
+        input.set_joystick(0, &fields[0]);
+        input.set_joystick(1, &fields[1]);
+
+        Ok(input)
+    }
+}
+
+// A recording of the input for each frame of a game, for playing it back exactly as it was played.
+// It's kept as text, a line for each frame, after the MD5 of the ROM, and the machine's state when
+// the recording started, which has the RAM that it was powered on with, so that the game starts
+// from the same place every time:
+//
+//     atari2600 movie
+//     rom 0123456789abcdef0123456789abcdef
+//     state 4200ff...
+//     .....|.....|..CLR
+//     U...F|.....|..CLR
+pub struct Movie {
+    rom_md5: String,
+    state: Vec<u8>,
+    frames: Vec<Input>,
+}
+
+impl Movie {
+    // `state` is the CPU's save of itself and everything on the bus
+    pub fn new(rom_md5: &str, state: Vec<u8>) -> Self {
+        Self {
+            rom_md5: rom_md5.to_string(),
+            state: state,
+            frames: vec![],
+        }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("it isn't a movie".to_string());
+        }
+
+        let mut field = |name: &str| match lines.next().and_then(|line| line.strip_prefix(name)) {
+            Some(value) => Ok(value.trim().to_string()),
+            None        => Err(format!("there's no {} line", name.trim())),
+        };
+
+        let rom_md5 = field("rom ")?;
+        let state = field("state ")?;
+        let state = (0 .. state.len()).step_by(2)
+            .map(|i| state.get(i .. i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("the state isn't in hex")?;
+
+        let frames = lines.enumerate()
+            .map(|(i, line)| Input::parse(line).map_err(|e| format!("frame {}: {}", i, e)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            rom_md5: rom_md5,
+            state: state,
+            frames: frames,
+        })
+    }
+
+    pub fn rom_md5(&self) -> &str { &self.rom_md5 }
+    pub fn state(&self) -> &[u8] { &self.state }
+    pub fn len(&self) -> usize { self.frames.len() }
+    pub fn frames(&self) -> &[Input] { &self.frames }
+
+    pub fn record(&mut self, input: Input) {
+        self.frames.push(input);
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {}", self.rom_md5)?;
+        writeln!(f, "state {}", self.state.iter().map(|b| format!("{:02x}", b)).collect::<String>())?;
+
+        for input in &self.frames {
+            writeln!(f, "{}", input.to_line())?;
+        }
+
+        Ok(())
+    }
+}
+
+// A stand-in for a joystick, which gives the game the levels that a movie recorded its pins at
+pub struct RecordedJoystick {
+    pins: u8,
+    fire: bool,
+}
+
+impl Peripheral for RecordedJoystick {
+    fn pins(&self) -> u8 { self.pins }
+    fn fire(&self) -> bool { self.fire }
+}
+
+// Plays a movie back a frame at a time, through a recorded joystick in each controller port in
+// place of the real ones
+pub struct Playback {
+    movie: Movie,
+    frame: usize,
+    joysticks: [Rc<RefCell<RecordedJoystick>>; 2],
+}
+
+impl Playback {
+    pub fn new(movie: Movie) -> Self {
+        let joystick = || Rc::new(RefCell::new(RecordedJoystick { pins: 0x0f, fire: true }));
+
+        Self {
+            movie: movie,
+            frame: 0,
+            joysticks: [joystick(), joystick()],
+        }
+    }
+
+    pub fn joystick(&self, port: usize) -> Rc<RefCell<RecordedJoystick>> { self.joysticks[port].clone() }
+    pub fn frame(&self) -> usize { self.frame }
+
+    // The input the game's been given for the frame being played
+    pub fn input(&self) -> Option<Input> {
+        self.frame.checked_sub(1).and_then(|frame| self.movie.frames.get(frame)).cloned()
+    }
+
+    // Gives the game the input for the next frame, returning false once there are no more frames
+    pub fn next_frame(&mut self, riot: &mut RIOT) -> bool {
+        let input = match self.movie.frames.get(self.frame).cloned() {
+            Some(input) => input,
+            None        => return false,
+        };
+
+        for (joystick, (&pins, &fire)) in self.joysticks.iter().zip(input.pins.iter().zip(input.fire.iter())) {
+            let mut joystick = joystick.borrow_mut();
+            joystick.pins = pins;
+            joystick.fire = fire;
+        }
+        riot.set_switches(input.switches);
+
+        self.frame += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::peripheral::{Direction, Source};
+
+    #[test]
+    fn test_input() {
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(3))), Rc::new(RefCell::new(Joystick::new(3)))];
+        joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Up, true);
+        joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Right, true);
+        joysticks[1].borrow_mut().set_fire(Source::Keyboard, true);
+
+        let mut riot = RIOT::new();
+        riot.select(false);
+        riot.reset(false);
+        let input = Input::capture(&joysticks, riot.switches());
+        assert_eq!(input.to_line(), "U..R.|....F|..CLR");
+        assert_eq!(Input::parse("U..R.|....F|..CLR"), Ok(input));
+
+        assert!(Input::parse("U..R.|....F").is_err());
+        assert!(Input::parse("U..R|....F|..CLR").is_err());
+    }
+
+    #[test]
+    fn test_movie() {
+        let mut movie = Movie::new("0123456789abcdef0123456789abcdef", vec![0x42, 0x00, 0xff]);
+        let text = "atari2600 movie\nrom 0123456789abcdef0123456789abcdef\nstate 4200ff\n.....|.....|..CLR\nU...F|.....|R.CLR\n";
+        for line in text.lines().skip(3) {
+            movie.record(Input::parse(line).unwrap());
+        }
+        assert_eq!(movie.to_string(), text);
+
+        let loaded = Movie::parse(text).unwrap();
+        assert_eq!((loaded.rom_md5(), loaded.state(), loaded.len()), (movie.rom_md5(), movie.state(), 2));
+        assert_eq!(Movie::parse("atari2600 movie\nrom 01\nstate 4\n").err(), Some("the state isn't in hex".to_string()));
+        assert_eq!(Movie::parse(&text.replace("R.CLR", "R.CL")).err(), Some("frame 1: expected 5 letters, not \"R.CL\"".to_string()));
+
+        // Each frame's input is given to the game in turn
+        let mut riot = RIOT::new();
+        let mut playback = Playback::new(loaded);
+        assert!(playback.next_frame(&mut riot));
+        assert!(!riot.switches().reset);
+        assert!(playback.next_frame(&mut riot));
+        assert!(riot.switches().reset);
+        assert_eq!(playback.input(), Some(Input::parse("U...F|.....|R.CLR").unwrap()));
+        assert_eq!(playback.joystick(0).borrow().pins(), 0b1110);
+        assert!(!playback.joystick(0).borrow().fire());
+        assert!(!playback.next_frame(&mut riot));
+    }
+}
@@ -0,0 +1,147 @@
+use std::f32::consts::PI;
+
+use sdl2::pixels::Color;
+
+// How many samples of the composite signal to generate per pixel. This is also how many pixels wide
+// each TIA pixel is drawn, so the filtered scanline can be copied straight into the texture.
+pub const SAMPLES_PER_PIXEL: usize = 5;
+
+// How many samples to average over when separating out the luma and chroma. The TIA's colour clock
+// is the same frequency as the NTSC colour subcarrier, so one pixel is exactly one subcarrier
+// cycle. Averaging the luma over a single cycle filters out the chroma entirely, and averaging the
+// chroma over two cycles blurs it the way a TV's narrower chroma bandwidth does.
+const LUMA_SAMPLES: usize = SAMPLES_PER_PIXEL;
+const CHROMA_SAMPLES: usize = SAMPLES_PER_PIXEL * 2;
+
+// An NTSC composite video filter, in the style of Blargg's. Rather than copying each pixel's colour
+// straight to the screen, each scanline is encoded into a composite video signal, the way the
+// console sends it to the TV, and then decoded again, the way the TV does. The decoding can't fully
+// separate the brightness and colour, so colours bleed into their neighbours, and sharp changes in
+// brightness pick up fringes of colour, which some games were designed around.
+pub struct NtscFilter {
+    // The subcarrier at each sample of a pixel
+    cos: [f32; SAMPLES_PER_PIXEL],
+    sin: [f32; SAMPLES_PER_PIXEL],
+}
+
+impl NtscFilter {
+    pub fn new() -> Self {
+        let mut cos = [0.0; SAMPLES_PER_PIXEL];
+        let mut sin = [0.0; SAMPLES_PER_PIXEL];
+
+        for i in 0 .. SAMPLES_PER_PIXEL {
+            let phase = 2.0 * PI * i as f32 / SAMPLES_PER_PIXEL as f32;
+            cos[i] = phase.cos();
+            sin[i] = phase.sin();
+        }
+
+        Self {
+            cos: cos,
+            sin: sin,
+        }
+    }
+
+    // Filter one scanline of pixels, returning SAMPLES_PER_PIXEL output pixels for each one
+    pub fn filter(&self, pixels: &[Color]) -> Vec<Color> {
+        let len = pixels.len() * SAMPLES_PER_PIXEL;
+
+        // Encode
+        let signal = (0 .. len)
+            .map(|k| {
+                let (y, i, q) = to_yiq(pixels[k / SAMPLES_PER_PIXEL]);
+                let phase = k % SAMPLES_PER_PIXEL;
+                y + i * self.cos[phase] + q * self.sin[phase]
+            })
+            .collect::<Vec<_>>();
+
+        // Anything beyond the edges of the scanline is black
+        let sample = |k: isize| -> f32 {
+            if k < 0 || k >= len as isize { 0.0 } else { signal[k as usize] }
+        };
+
+        // Decode
+        (0 .. len)
+            .map(|k| {
+                let k = k as isize;
+
+                let start = k - (LUMA_SAMPLES / 2) as isize;
+                let y = (start .. start + LUMA_SAMPLES as isize)
+                    .map(sample)
+                    .sum::<f32>() / LUMA_SAMPLES as f32;
+
+                let start = k - (CHROMA_SAMPLES / 2) as isize;
+                let (mut i, mut q) = (0.0, 0.0);
+                for j in start .. start + CHROMA_SAMPLES as isize {
+                    let phase = j.rem_euclid(SAMPLES_PER_PIXEL as isize) as usize;
+                    i += sample(j) * self.cos[phase];
+                    q += sample(j) * self.sin[phase];
+                }
+
+                let i = 2.0 * i / CHROMA_SAMPLES as f32;
+                let q = 2.0 * q / CHROMA_SAMPLES as f32;
+
+                from_yiq(y, i, q)
+            })
+            .collect()
+    }
+}
+
+fn to_yiq(c: Color) -> (f32, f32, f32) {
+    let (r, g, b) = (c.r as f32, c.g as f32, c.b as f32);
+
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let i = 0.596 * r - 0.274 * g - 0.322 * b;
+    let q = 0.211 * r - 0.523 * g + 0.312 * b;
+
+    (y, i, q)
+}
+
+fn from_yiq(y: f32, i: f32, q: f32) -> Color {
+    let clamp = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+
+    let r = y + 0.956 * i + 0.621 * q;
+    let g = y - 0.272 * i - 0.647 * q;
+    let b = y - 1.106 * i + 1.703 * q;
+
+    Color::RGB(clamp(r), clamp(g), clamp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Color, b: Color) -> bool {
+        let diff = |x: u8, y: u8| (x as i32 - y as i32).abs();
+        diff(a.r, b.r) <= 3 && diff(a.g, b.g) <= 3 && diff(a.b, b.b) <= 3
+    }
+
+    #[test]
+    fn test_solid_color_survives() {
+        let filter = NtscFilter::new();
+        let color = Color::RGB(0xa0, 0x60, 0x30);
+
+        let output = filter.filter(&vec![color; 160]);
+        assert_eq!(output.len(), 160 * SAMPLES_PER_PIXEL);
+
+        // Away from the edges, a solid colour decodes back to itself
+        for &c in output[20 .. 780].iter() {
+            assert!(close(c, color), "{:?} != {:?}", c, color);
+        }
+    }
+
+    #[test]
+    fn test_color_bleeds() {
+        let filter = NtscFilter::new();
+        let red = Color::RGB(0xff, 0x00, 0x00);
+        let black = Color::RGB(0x00, 0x00, 0x00);
+
+        let mut pixels = vec![black; 160];
+        pixels[80] = red;
+
+        let output = filter.filter(&pixels);
+
+        // The single red pixel spreads out over more than its own 5 output pixels
+        let lit = output.iter().filter(|c| **c != black).count();
+        assert!(lit > SAMPLES_PER_PIXEL, "{} lit", lit);
+    }
+}
@@ -0,0 +1,453 @@
+use std::env;
+use std::process;
+use std::str::FromStr;
+
+use crate::cpu6507::TraceFormat;
+use crate::gamepad::StickConfig;
+use crate::pointing::Protocol;
+use crate::region::Region;
+use crate::riot::RamPattern;
+
+const USAGE: &str = "usage: atari2600 [options] <rom file>
+
+options:
+    --audio-log <file>    record all audio register writes to <file>
+    --cpu-trace <file>    record every instruction the CPU executes, along with its registers, to
+                          <file>
+    --cpu-history <file>  keep the last instructions the CPU executes, and write them to <file> when
+                          the CPU jams, when H is pressed, and on exit
+    --cpu-history-length <instructions>
+                          how many instructions --cpu-history keeps (defaults to 10000)
+    --cpu-trace-format <format>
+                          how to write the cpu trace, default, or nestest for the layout of
+                          nestest.log that most 6502 emulators can write, for diffing against them
+    --trace <file>        record TIA register writes to <file>, with the frame, scanline, and
+                          colour clock they happened on
+    --trace-registers <registers>
+                          only trace writes to these registers, a comma separated list of
+                          register names, e.g. GRP0,GRP1,HMOVE (defaults to every register)
+    --profile             count how many times each opcode is executed, and print the counts
+                          on exit
+    --profile-pcs         count how many times each instruction address is executed as well
+    --ram <pattern>       what the RAM holds at power on, zero (the default), ff, alternating, or
+                          random, which is different each time unless --ram-seed is given
+    --ram-seed <seed>     the seed for random RAM, a number, to get the same RAM every time
+    --gdb <port>          wait for a gdb frontend to connect to <port> on this machine, and let it
+                          debug the game in place of the debugger's prompt
+    --debug               start with debugging on, stopped at the debugger's prompt before the
+                          first instruction
+    --tui                 show the debugger in a full screen terminal UI, with panes for the
+                          disassembly, registers, RAM, and TIA, in place of printing lines
+    --beam-window         open a second window showing the frame being built, with a crosshair
+                          where the beam is, whenever the debugger stops
+    --script <file>       run a Rhai script alongside the game, with hooks on each frame,
+                          instruction, and memory access
+    --symbols <file>      a DASM symbol file (from dasm's -s option), for using the game's labels
+                          in the debugger, which defaults to the ROM's name with .sym on the end
+    --properties <file>   a database of game properties, in the format of Stella's stella.pro, for
+                          picking the controllers a game uses
+    --controller <type>   the controllers plugged in, joystick (the default), genesis for a Sega
+                          Genesis pad, with a second button, trakball, atarimouse, amigamouse, or
+                          driving for player 0 to use the mouse as a Trak-Ball, mouse, or driving
+                          controller, lightgun to aim player 0's light gun with the mouse, or keypad
+                          for keypads, pressed by clicking on the keypad overlay
+    --savekey <file>      plug a SaveKey into the right controller port, for games to save high
+                          scores to, keeping its contents in <file>
+    --states <directory>  where to keep the save states, a file for each game's slot (defaults to
+                          states, in the current directory)
+    --autosave            save the state on exit, and carry on from it the next time the same game
+                          is started
+    --record <file>       record the joysticks and console switches on every frame to a movie in
+                          <file>, written on exit, for playing back with --play, or as a BizHawk
+                          movie when <file> ends with .bk2 (only with joysticks)
+    --play <file>         play back a movie recorded with --record, from the state it was recorded
+                          from, or a BizHawk movie (.bk2) from power on, handing the joysticks back
+                          once it's finished
+    --gif <seconds>       keep the last <seconds> of the picture, for writing out as an animated
+                          GIF with V
+    --paddles             control the paddles instead of the joystick, paddle 0 with the mouse, and
+                          one paddle per game controller, with its left stick
+    --gamepad <settings>  how a game controller's sticks are used, a comma separated list of
+                          x=<axis> and y=<axis> for the joystick directions, paddle=<axis>,
+                          deadzone=<fraction>, and saturation=<fraction>, where an axis is one of
+                          leftx, lefty, rightx, righty, triggerleft, or triggerright. Given more
+                          than once, each applies to the next controller plugged in
+    --autofire-rate <frames>
+                          how many frames autofire holds the fire button down for, and then lets
+                          go of it for (defaults to 3)
+    --swap-ports          plug the left controller into the right port, and the right into the
+                          left, for games that read player 0 from the right port
+    --region <region>     the TV standard to emulate, ntsc or pal, instead of detecting it
+    --filter <filter>     how to draw the picture, sharp (the default) for plain square pixels, or
+                          ntsc to simulate the blurring and colour artifacts of a composite signal
+    --roll                roll the picture when frames have too many or too few scanlines, or
+                          no VSYNC, like a TV would, rather than keeping it still
+    --interlace           draw every other field half a scanline lower for games that alternate
+                          between frames a scanline apart in length, for an interlaced picture
+    --palette <palette>   the colour palette to use, one of ntsc, pal, secam, warm, or cool, or
+                          the path to a .pal file of 128 RGB triplets (defaults to the region's)";
+
+// How the picture is drawn to the screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // Each pixel is drawn as a solid block
+    Sharp,
+    // Each scanline is run through a simulated NTSC composite signal
+    NTSC,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sharp" => Ok(Filter::Sharp),
+            "ntsc"  => Ok(Filter::NTSC),
+            _       => Err(format!("unknown filter: {}", s)),
+        }
+    }
+}
+
+// The kind of controller plugged into both controller ports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Controller {
+    Joystick,
+    // A Sega Genesis pad, which is a joystick with a second button (C) on INPT1/INPT3, as
+    // supported by plenty of homebrew games
+    Genesis,
+    // Trak-Balls and mice, driven by the host mouse
+    TrakBall,
+    AtariMouse,
+    AmigaMouse,
+    // A driving controller, turned by moving the host mouse left and right
+    Driving,
+    // An XG-1 light gun, aimed with the mouse
+    LightGun,
+    // A 12-key keypad (keyboard controller) in each port, pressed through the keypad overlay
+    Keypad,
+}
+
+impl Controller {
+    // The controller named by a game's Controller.Left or Controller.Right property in the
+    // properties database, and whether it's paddles
+    pub fn from_property(value: &str) -> (Controller, bool) {
+        match value.to_uppercase().as_str() {
+            "JOYSTICK"   => (Controller::Joystick, false),
+            "GENESIS"    => (Controller::Genesis, false),
+            "TRAKBALL"   => (Controller::TrakBall, false),
+            "ATARIMOUSE" => (Controller::AtariMouse, false),
+            "AMIGAMOUSE" => (Controller::AmigaMouse, false),
+            "DRIVING"    => (Controller::Driving, false),
+            "LIGHTGUN"   => (Controller::LightGun, false),
+            "KEYBOARD"   => (Controller::Keypad, false),
+            "PADDLES" | "PADDLES_IAXIS" | "PADDLES_IAXDR" => (Controller::Joystick, true),
+            _ => {
+                warn!("{} controllers aren't supported, using a joystick instead", value);
+                (Controller::Joystick, false)
+            },
+        }
+    }
+
+    // The controller a game uses, going by its Controller.Left and Controller.Right properties,
+    // along with whether it's paddles, and the port it's plugged into. Only one port can have
+    // something other than a joystick in it, so the right port's is only used when the left's is
+    // a joystick. Light guns are only read through the left port.
+    pub fn from_properties(left: Option<&str>, right: Option<&str>) -> (Controller, bool, usize) {
+        let left = left.map_or((Controller::Joystick, false), Controller::from_property);
+        let right = right.map_or((Controller::Joystick, false), Controller::from_property);
+
+        match (left, right) {
+            ((Controller::Joystick, false), (Controller::LightGun, _)) => {
+                warn!("light guns aren't supported in the right port, using a joystick instead");
+                (Controller::Joystick, false, 0)
+            },
+            ((Controller::Joystick, false), (controller, paddles)) => (controller, paddles, 1),
+            ((controller, paddles), _) => (controller, paddles, 0),
+        }
+    }
+
+    // How a Trak-Ball, mouse, or driving controller reports its movement
+    pub fn protocol(&self) -> Option<Protocol> {
+        match self {
+            Controller::TrakBall   => Some(Protocol::TrakBall),
+            Controller::AtariMouse => Some(Protocol::AtariMouse),
+            Controller::AmigaMouse => Some(Protocol::AmigaMouse),
+            Controller::Driving    => Some(Protocol::Driving),
+            _                      => None,
+        }
+    }
+}
+
+impl FromStr for Controller {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "joystick"   => Ok(Controller::Joystick),
+            "genesis"    => Ok(Controller::Genesis),
+            "trakball"   => Ok(Controller::TrakBall),
+            "atarimouse" => Ok(Controller::AtariMouse),
+            "amigamouse" => Ok(Controller::AmigaMouse),
+            "driving"    => Ok(Controller::Driving),
+            "lightgun"   => Ok(Controller::LightGun),
+            "keypad"     => Ok(Controller::Keypad),
+            _            => Err(format!("unknown controller: {}", s)),
+        }
+    }
+}
+
+pub struct Options {
+    pub rom_path: String,
+    pub audio_log: Option<String>,
+    pub cpu_trace: Option<String>,
+    pub cpu_history: Option<String>,
+    pub cpu_history_length: usize,
+    pub cpu_trace_format: TraceFormat,
+    pub trace: Option<String>,
+    pub trace_registers: Vec<String>,
+    pub profile: bool,
+    pub profile_pcs: bool,
+    pub ram: RamPattern,
+    pub ram_seed: Option<u64>,
+    pub gdb: Option<u16>,
+    pub debug: bool,
+    pub tui: bool,
+    pub beam_window: bool,
+    pub script: Option<String>,
+    pub symbols: Option<String>,
+    pub properties: Option<String>,
+    pub controller: Option<Controller>,
+    pub savekey: Option<String>,
+    pub states: String,
+    pub autosave: bool,
+    pub record: Option<String>,
+    pub play: Option<String>,
+    pub gif: Option<usize>,
+    pub paddles: bool,
+    pub swap_ports: bool,
+    pub autofire_rate: usize,
+    pub gamepads: Vec<StickConfig>,
+    pub region: Option<Region>,
+    pub palette: Option<String>,
+    pub filter: Filter,
+    pub roll: bool,
+    pub interlace: bool,
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        let mut rom_path = None;
+        let mut audio_log = None;
+        let mut cpu_trace = None;
+        let mut cpu_history = None;
+        let mut cpu_history_length = 10000;
+        let mut cpu_trace_format = TraceFormat::Default;
+        let mut trace = None;
+        let mut trace_registers = vec![];
+        let mut profile = false;
+        let mut profile_pcs = false;
+        let mut ram = RamPattern::Zero;
+        let mut ram_seed = None;
+        let mut gdb = None;
+        let mut debug = false;
+        let mut tui = false;
+        let mut beam_window = false;
+        let mut script = None;
+        let mut symbols = None;
+        let mut properties = None;
+        let mut controller = None;
+        let mut savekey = None;
+        let mut states = "states".to_string();
+        let mut autosave = false;
+        let mut record = None;
+        let mut play = None;
+        let mut gif = None;
+        let mut paddles = false;
+        let mut swap_ports = false;
+        let mut autofire_rate = 3;
+        let mut gamepads = vec![];
+        let mut region = None;
+        let mut palette = None;
+        let mut filter = Filter::Sharp;
+        let mut roll = false;
+        let mut interlace = false;
+
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--audio-log" => audio_log = Some(value(&mut args, &arg)),
+                "--cpu-trace" => cpu_trace = Some(value(&mut args, &arg)),
+                "--cpu-history" => cpu_history = Some(value(&mut args, &arg)),
+                "--cpu-history-length" => {
+                    let length = value(&mut args, &arg);
+                    cpu_history_length = match length.parse() {
+                        Ok(length) if length > 0 => length,
+                        _ => {
+                            eprintln!("invalid cpu history length: {}", length);
+                            usage()
+                        },
+                    }
+                },
+                "--cpu-trace-format" => {
+                    cpu_trace_format = value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    })
+                },
+                "--trace" => trace = Some(value(&mut args, &arg)),
+                "--trace-registers" => {
+                    trace_registers = value(&mut args, &arg)
+                        .split(',')
+                        .map(|r| r.trim().to_string())
+                        .filter(|r| !r.is_empty())
+                        .collect()
+                },
+                "--profile" => profile = true,
+                "--profile-pcs" => {
+                    profile = true;
+                    profile_pcs = true;
+                },
+                "--ram" => {
+                    ram = value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    })
+                },
+                "--ram-seed" => {
+                    let seed = value(&mut args, &arg);
+                    ram_seed = Some(seed.parse().unwrap_or_else(|_| {
+                        eprintln!("invalid ram seed: {}", seed);
+                        usage()
+                    }))
+                },
+                "--gdb" => {
+                    let port = value(&mut args, &arg);
+                    gdb = Some(port.parse().unwrap_or_else(|_| {
+                        eprintln!("invalid gdb port: {}", port);
+                        usage()
+                    }))
+                },
+                "--debug" => debug = true,
+                "--tui" => tui = true,
+                "--beam-window" => beam_window = true,
+                "--script" => script = Some(value(&mut args, &arg)),
+                "--symbols" => symbols = Some(value(&mut args, &arg)),
+                "--properties" => properties = Some(value(&mut args, &arg)),
+                "--controller" => {
+                    controller = Some(value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    }))
+                },
+                "--savekey" => savekey = Some(value(&mut args, &arg)),
+                "--states" => states = value(&mut args, &arg),
+                "--autosave" => autosave = true,
+                "--record" => record = Some(value(&mut args, &arg)),
+                "--play" => play = Some(value(&mut args, &arg)),
+                "--gif" => {
+                    let seconds = value(&mut args, &arg);
+                    gif = match seconds.parse() {
+                        Ok(seconds) if seconds > 0 => Some(seconds),
+                        _ => {
+                            eprintln!("invalid gif length: {}", seconds);
+                            usage()
+                        },
+                    }
+                },
+                "--paddles" => paddles = true,
+                "--swap-ports" => swap_ports = true,
+                "--gamepad" => {
+                    gamepads.push(value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    }))
+                },
+                "--autofire-rate" => {
+                    let rate = value(&mut args, &arg);
+                    autofire_rate = match rate.parse() {
+                        Ok(rate) if rate > 0 => rate,
+                        _ => {
+                            eprintln!("invalid autofire rate: {}", rate);
+                            usage()
+                        },
+                    }
+                },
+                "--region" => {
+                    region = Some(value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    }))
+                },
+                "--roll" => roll = true,
+                "--interlace" => interlace = true,
+                "--palette" => palette = Some(value(&mut args, &arg)),
+                "--filter" => {
+                    filter = value(&mut args, &arg).parse().unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        usage()
+                    })
+                },
+                "-h" | "--help" => usage(),
+                _ if arg.starts_with("-") => {
+                    eprintln!("unknown option: {}", arg);
+                    usage();
+                },
+                _ => rom_path = Some(arg),
+            }
+        }
+
+        Self {
+            rom_path: rom_path.unwrap_or_else(|| {
+                eprintln!("missing argument: rom file");
+                usage()
+            }),
+            audio_log: audio_log,
+            cpu_trace: cpu_trace,
+            cpu_history: cpu_history,
+            cpu_history_length: cpu_history_length,
+            cpu_trace_format: cpu_trace_format,
+            trace: trace,
+            trace_registers: trace_registers,
+            profile: profile,
+            profile_pcs: profile_pcs,
+            ram: ram,
+            ram_seed: ram_seed,
+            gdb: gdb,
+            debug: debug,
+            tui: tui,
+            beam_window: beam_window,
+            script: script,
+            symbols: symbols,
+            properties: properties,
+            controller: controller,
+            savekey: savekey,
+            states: states,
+            autosave: autosave,
+            record: record,
+            play: play,
+            gif: gif,
+            paddles: paddles,
+            swap_ports: swap_ports,
+            autofire_rate: autofire_rate,
+            gamepads: gamepads,
+            region: region,
+            palette: palette,
+            filter: filter,
+            roll: roll,
+            interlace: interlace,
+        }
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, option: &str) -> String {
+    args.next().unwrap_or_else(|| {
+        eprintln!("missing value for option: {}", option);
+        usage()
+    })
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
@@ -0,0 +1,425 @@
+use std::mem;
+
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use crate::frame::Frame;
+use crate::peripheral::{Keypad, KEYPAD_KEYS};
+use crate::tia::{register_bit, Object, TIA};
+
+// A tiny 3x5 pixel font, just enough to print register names and values on top of the game
+// image without needing SDL_ttf. Each glyph is 5 rows of 3 bits, most significant bit leftmost.
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '$' => [0b011, 0b110, 0b010, 0b011, 0b110],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        _   => [0b000, 0b000, 0b000, 0b000, 0b000],
+    }
+}
+
+// Draw a string at the given position, with each font pixel drawn as a `scale` x `scale` square
+pub fn draw_text(canvas: &mut Canvas<Window>, x: i32, y: i32, scale: i32, text: &str, color: Color) {
+    canvas.set_draw_color(color);
+
+    for (i, c) in text.chars().enumerate() {
+        let glyph_x = x + (i as i32) * (GLYPH_WIDTH + 1) * scale;
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0 .. GLYPH_WIDTH {
+                if (bits & (1 << (GLYPH_WIDTH - 1 - col))) != 0 {
+                    let px = glyph_x + col * scale;
+                    let py = y + (row as i32) * scale;
+                    canvas.fill_rect(Rect::new(px, py, scale as u32, scale as u32)).unwrap();
+                }
+            }
+        }
+    }
+}
+
+pub fn text_height(scale: i32) -> i32 {
+    (GLYPH_HEIGHT + 1) * scale
+}
+
+// Draws a message along the bottom of the window
+pub fn draw_message(canvas: &mut Canvas<Window>, message: &str) {
+    let scale = 3;
+    let (_, height) = canvas.output_size().unwrap();
+    let width = message.len() as i32 * (GLYPH_WIDTH + 1) * scale + 8;
+    let top = height as i32 - text_height(scale) - 8;
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+    canvas.fill_rect(Rect::new(0, top, width as u32, text_height(scale) as u32 + 8)).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+
+    draw_text(canvas, 4, top + 4, scale, message, Color::RGB(255, 64, 64));
+}
+
+// Shows the audio registers of both channels, along with a scrolling trace of each channel's
+// output, in the bottom left corner of the window.
+pub struct AudioOverlay {
+    enabled: bool,
+}
+
+const AUDIO_OVERLAY_SCALE: i32 = 2;
+const WAVEFORM_HEIGHT: i32 = 32;
+
+impl AudioOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, tia: &TIA) {
+        if !self.enabled { return }
+
+        let audio = tia.audio();
+        let (_, height) = canvas.output_size().unwrap();
+
+        let waveform_width = audio.waveform(0).len() as i32;
+        let channel_height = text_height(AUDIO_OVERLAY_SCALE) + WAVEFORM_HEIGHT + 4;
+        let top = height as i32 - 2 * channel_height - 8;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        canvas.fill_rect(Rect::new(0, top, waveform_width as u32 + 8, 2 * channel_height as u32 + 8)).unwrap();
+
+        for channel in 0 .. 2 {
+            let x = 4;
+            let y = top + 4 + (channel as i32) * channel_height;
+
+            let label = format!("AUDC{}:{:X} AUDF{}:{:02X} AUDV{}:{:X}",
+                                channel, audio.audc(channel),
+                                channel, audio.audf(channel),
+                                channel, audio.audv(channel));
+            draw_text(canvas, x, y, AUDIO_OVERLAY_SCALE, &label, Color::RGB(255, 255, 255));
+
+            // Each sample is a 4-bit volume, so scale it up to fill the waveform's height
+            let base = y + text_height(AUDIO_OVERLAY_SCALE) + WAVEFORM_HEIGHT;
+            let points = audio.waveform(channel).iter()
+                .enumerate()
+                .map(|(i, v)| Point::new(x + i as i32, base - (*v as i32 * WAVEFORM_HEIGHT / 16)))
+                .collect::<Vec<_>>();
+
+            canvas.set_draw_color(if channel == 0 { Color::RGB(0, 255, 0) } else { Color::RGB(255, 255, 0) });
+            canvas.draw_lines(&points[..]).unwrap();
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+// Draws a grid over the picture marking out each of the 40 playfield bits across the screen,
+// labelled with the register and bit that's drawn there, so it's easy to see how PF0, PF1, and PF2
+// map onto the screen. The bits currently set are filled in along the top, and the right half is
+// labelled with whether it repeats or reflects the left half.
+pub struct PlayfieldOverlay {
+    enabled: bool,
+}
+
+const PLAYFIELD_OVERLAY_SCALE: i32 = 2;
+
+// The colours of the grid lines and labels for PF0, PF1, and PF2
+const PLAYFIELD_REGISTER_COLORS: [Color; 3] = [
+    Color { r: 255, g: 96,  b: 96,  a: 160 },
+    Color { r: 96,  g: 255, b: 96,  a: 160 },
+    Color { r: 96,  g: 160, b: 255, a: 160 },
+];
+
+impl PlayfieldOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, tia: &TIA) {
+        if !self.enabled { return }
+
+        let playfield = tia.playfield();
+        let (width, height) = canvas.output_size().unwrap();
+
+        // Each playfield bit is 4 pixels wide, and there are 40 of them across the screen
+        let column_width = width as i32 / 40;
+        let label_height = 2 * text_height(PLAYFIELD_OVERLAY_SCALE);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        canvas.fill_rect(Rect::new(0, 0, width, (label_height + column_width / 2 + 4) as u32)).unwrap();
+
+        for column in 0 .. 40 {
+            let bit = playfield.column_bit(column);
+            let (register, register_bit) = register_bit(bit);
+            let color = PLAYFIELD_REGISTER_COLORS[register];
+            let x = column as i32 * column_width;
+
+            canvas.set_draw_color(color);
+            canvas.draw_line(Point::new(x, 0), Point::new(x, height as i32)).unwrap();
+
+            // The register on the first line, and the bit on the second
+            let text_x = x + 2;
+            draw_text(canvas, text_x, 2, PLAYFIELD_OVERLAY_SCALE, &register.to_string(), color);
+            draw_text(canvas, text_x, 2 + text_height(PLAYFIELD_OVERLAY_SCALE), PLAYFIELD_OVERLAY_SCALE,
+                      &register_bit.to_string(), color);
+
+            let cell = Rect::new(x + 2, label_height + 2, (column_width - 4) as u32, (column_width / 2 - 2) as u32);
+            canvas.set_draw_color(color);
+            if playfield.bit(bit) {
+                canvas.fill_rect(cell).unwrap();
+            } else {
+                canvas.draw_rect(cell).unwrap();
+            }
+        }
+
+        // Mark the middle of the screen, and whether the right half is reflected
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        canvas.fill_rect(Rect::new(width as i32 / 2 - 1, 0, 2, height)).unwrap();
+
+        let label = if playfield.reflected { "REFLECTED" } else { "REPEATED" };
+        draw_text(canvas, width as i32 / 2 + 4, label_height + column_width / 2 + 8, PLAYFIELD_OVERLAY_SCALE,
+                  label, Color::RGB(255, 255, 255));
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+// Draws the keypads in the bottom corners of the window, the left controller's on the left and the
+// right's on the right, with the keys that are pressed lit up. The keys can be clicked on with
+// the mouse to press them, as the keypads are hard to play without seeing which key's which
+// (the real ones came with an overlay for each game).
+pub struct KeypadOverlay {
+    enabled: bool,
+}
+
+const KEYPAD_OVERLAY_SCALE: i32 = 4;
+const KEYPAD_KEY_WIDTH: i32 = 40;
+const KEYPAD_KEY_HEIGHT: i32 = 32;
+const KEYPAD_GAP: i32 = 4;
+
+impl KeypadOverlay {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    // Where a keypad's key is drawn in a window of the given size
+    fn key_rect(width: u32, height: u32, keypad: usize, key: usize) -> Rect {
+        let pad_width = 3 * KEYPAD_KEY_WIDTH + 4 * KEYPAD_GAP;
+        let pad_height = 4 * KEYPAD_KEY_HEIGHT + 5 * KEYPAD_GAP;
+        let left = if keypad == 0 { 0 } else { width as i32 - pad_width };
+        let top = height as i32 - pad_height;
+
+        let (row, column) = (key as i32 / 3, key as i32 % 3);
+        Rect::new(left + KEYPAD_GAP + column * (KEYPAD_KEY_WIDTH + KEYPAD_GAP),
+                  top + KEYPAD_GAP + row * (KEYPAD_KEY_HEIGHT + KEYPAD_GAP),
+                  KEYPAD_KEY_WIDTH as u32, KEYPAD_KEY_HEIGHT as u32)
+    }
+
+    // The keypad and key at a point in a window of the given size, if the overlay's showing
+    pub fn key_at(&self, width: u32, height: u32, x: i32, y: i32) -> Option<(usize, usize)> {
+        if !self.enabled { return None }
+
+        (0 .. 2)
+            .flat_map(|keypad| (0 .. KEYPAD_KEYS.len()).map(move |key| (keypad, key)))
+            .find(|&(keypad, key)| Self::key_rect(width, height, keypad, key).contains_point(Point::new(x, y)))
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, keypads: &[&Keypad; 2]) {
+        if !self.enabled { return }
+
+        let (width, height) = canvas.output_size().unwrap();
+
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        for (index, keypad) in keypads.iter().enumerate() {
+            for (key, &label) in KEYPAD_KEYS.iter().enumerate() {
+                let rect = Self::key_rect(width, height, index, key);
+
+                let (background, foreground) = if keypad.pressed(key) {
+                    (Color::RGBA(255, 224, 64, 224), Color::RGB(0, 0, 0))
+                } else {
+                    (Color::RGBA(0, 0, 0, 192), Color::RGB(255, 255, 255))
+                };
+
+                canvas.set_draw_color(background);
+                canvas.fill_rect(rect).unwrap();
+                canvas.set_draw_color(Color::RGBA(255, 255, 255, 160));
+                canvas.draw_rect(rect).unwrap();
+
+                let text_x = rect.x() + (KEYPAD_KEY_WIDTH - GLYPH_WIDTH * KEYPAD_OVERLAY_SCALE) / 2;
+                let text_y = rect.y() + (KEYPAD_KEY_HEIGHT - GLYPH_HEIGHT * KEYPAD_OVERLAY_SCALE) / 2;
+                draw_text(canvas, text_x, text_y, KEYPAD_OVERLAY_SCALE, &label.to_string(), foreground);
+            }
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+// Tints each pixel of the picture with a colour for the object that drew it, so that it's easy to
+// see which object a stray pixel belongs to, with a key along the top. The objects are recorded for
+// each scanline of the frame as it's generated, and laid out in the same way as the picture.
+pub struct ObjectOverlay {
+    enabled: bool,
+
+    // The object that drew each pixel of each scanline of the frame being generated, and of the
+    // last complete frame
+    scanlines: Vec<Vec<Option<Object>>>,
+    last_frame: Vec<Vec<Option<Object>>>,
+}
+
+const OBJECT_OVERLAY_SCALE: i32 = 2;
+
+// The tint for each object, and its name in the key
+const OBJECT_COLORS: [(Object, &str, Color); 6] = [
+    (Object::Player0,   "P0", Color { r: 255, g: 64,  b: 64,  a: 144 }),
+    (Object::Player1,   "P1", Color { r: 64,  g: 96,  b: 255, a: 144 }),
+    (Object::Missile0,  "M0", Color { r: 255, g: 160, b: 0,   a: 144 }),
+    (Object::Missile1,  "M1", Color { r: 0,   g: 224, b: 255, a: 144 }),
+    (Object::Ball,      "BL", Color { r: 255, g: 255, b: 0,   a: 144 }),
+    (Object::Playfield, "PF", Color { r: 64,  g: 255, b: 64,  a: 144 }),
+];
+
+fn object_color(object: Object) -> Color {
+    OBJECT_COLORS.iter().find(|&&(o, _, _)| o == object).map(|&(_, _, color)| color).unwrap()
+}
+
+impl ObjectOverlay {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            scanlines: vec![],
+            last_frame: vec![],
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn push_scanline(&mut self, objects: &[Option<Object>]) {
+        self.scanlines.push(objects.to_vec());
+    }
+
+    // Goes back to an earlier scanline, along with the frame, when the debugger steps backwards
+    pub fn rewind(&mut self, scanlines: usize) {
+        self.scanlines.truncate(scanlines);
+    }
+
+    pub fn end_frame(&mut self) {
+        self.last_frame = mem::take(&mut self.scanlines);
+    }
+
+    // The objects on a line of the display, going by which of the frame's scanlines is shown there.
+    // Part way through a frame, the scanlines that have been generated so far are drawn over the
+    // last frame's, as they are in the picture.
+    fn line(&self, frame: &Frame, y: usize, complete: bool) -> Option<&Vec<Option<Object>>> {
+        match frame.scanline_at(y) {
+            Some(line) if !complete && line < self.scanlines.len() => Some(&self.scanlines[line]),
+            Some(line) => self.last_frame.get(line),
+            None => None,
+        }
+    }
+
+    // Tints the picture's lines, which are `offset` rows further down for the lower field of an
+    // interlaced picture
+    pub fn draw(&self, canvas: &mut Canvas<Window>, frame: &Frame, lines: usize, complete: bool, offset: i32) {
+        if !self.enabled { return }
+
+        let (pixel_width, pixel_height) = (5, 3);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        for y in 0 .. lines {
+            let objects = match self.line(frame, y, complete) {
+                Some(objects) => objects,
+                None          => continue,
+            };
+
+            // Runs of pixels drawn by the same object are tinted with one rectangle
+            let mut x = 0;
+            while x < objects.len() {
+                let run = objects[x ..].iter().take_while(|&&object| object == objects[x]).count();
+
+                if let Some(object) = objects[x] {
+                    canvas.set_draw_color(object_color(object));
+                    canvas.fill_rect(Rect::new(x as i32 * pixel_width, y as i32 * pixel_height + offset,
+                                               (run as i32 * pixel_width) as u32, pixel_height as u32)).unwrap();
+                }
+
+                x += run;
+            }
+        }
+
+        // The key
+        let width = OBJECT_COLORS.len() as i32 * 3 * (GLYPH_WIDTH + 1) * OBJECT_OVERLAY_SCALE + 8;
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 192));
+        canvas.fill_rect(Rect::new(0, 0, width as u32, (text_height(OBJECT_OVERLAY_SCALE) + 8) as u32)).unwrap();
+
+        for (i, &(_, name, color)) in OBJECT_COLORS.iter().enumerate() {
+            let x = 4 + i as i32 * 3 * (GLYPH_WIDTH + 1) * OBJECT_OVERLAY_SCALE;
+            draw_text(canvas, x, 4, OBJECT_OVERLAY_SCALE, name, Color::RGB(color.r, color.g, color.b));
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
@@ -0,0 +1,446 @@
+// http://www.qotile.net/minidig/docs/tia_color.html
+
+use std::fs::File;
+use std::io::{self, Read};
+
+use sdl2::pixels::Color;
+
+use crate::region::Region;
+
+// Converts the colour values the TIA generates into the RGB colours displayed on screen
+pub struct Palette {
+    colors: Vec<Color>,
+
+    // Whether the console's TV Type switch is set to B&W
+    black_and_white: bool,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<Color>) -> Self {
+        Self {
+            colors: colors,
+            black_and_white: false,
+        }
+    }
+
+    // The standard palette for the given region
+    pub fn for_region(region: Region) -> Self {
+        match region {
+            Region::NTSC => Self::new(NTSC_PALETTE.clone()),
+            Region::PAL  => Self::new(PAL_PALETTE.clone()),
+        }
+    }
+
+    pub fn set_black_and_white(&mut self, bw: bool) { self.black_and_white = bw }
+
+    pub fn color(&self, value: u8) -> Color {
+        // A black and white TV only picks up the luminance, so drop the hue bits and use the
+        // greys from the first row of the palette.
+        if self.black_and_white {
+            self.colors[(value & 0x0f) as usize]
+        } else {
+            self.colors[value as usize]
+        }
+    }
+}
+
+lazy_static!{
+    static ref NTSC_PALETTE: Vec<Color> = expand(&[
+            0x000000,		// 00
+            0x404040,		// 02
+            0x6c6c6c,		// 04
+            0x909090,		// 06
+            0xb0b0b0,		// 08
+            0xc8c8c8,		// 0A
+            0xdcdcdc,		// 0C
+            0xececec,		// 0E
+
+            0x444400,		// 10
+            0x646410,		// 12
+            0x848424,		// 14
+            0xa0a034,		// 16
+            0xb8b840,		// 18
+            0xd0d050,		// 1A
+            0xe8e85c,		// 1C
+            0xfcfc68,		// 1E
+
+            0x702800,		// 20
+            0x844414,		// 22
+            0x985c28,		// 24
+            0xac783c,		// 26
+            0xbc8c4c,		// 28
+            0xcca05c,		// 2A
+            0xdcb468,		// 2C
+            0xecc878,		// 2E
+
+            0x841800,		// 30
+            0x983418,		// 32
+            0xac5030,		// 34
+            0xc06848,		// 36
+            0xd0805c,		// 38
+            0xe09470,		// 3A
+            0xeca880,		// 3C
+            0xfcbc94,		// 3E
+
+            0x880000,		// 40
+            0x9c2020,		// 42
+            0xb03c3c,		// 44
+            0xc05858,		// 46
+            0xd07070,		// 48
+            0xe08888,		// 4A
+            0xeca0a0,		// 4C
+            0xfcb4b4,		// 4E
+
+            0x78005c,		// 50
+            0x8c2074,		// 52
+            0xa03c88,		// 54
+            0xb0589c,		// 56
+            0xc070b0,		// 58
+            0xd084c0,		// 5A
+            0xdc9cd0,		// 5C
+            0xecb0e0,		// 5E
+
+            0x480078,		// 60
+            0x602090,		// 62
+            0x783ca4,		// 64
+            0x8c58b8,		// 66
+            0xa070cc,		// 68
+            0xb484dc,		// 6A
+            0xc49cec,		// 6C
+            0xd4b0fc,		// 6E
+
+            0x140084,		// 70
+            0x302098,		// 72
+            0x4c3cac,		// 74
+            0x6858c0,		// 76
+            0x7c70d0,		// 78
+            0x9488e0,		// 7A
+            0xa8a0ec,		// 7C
+            0xbcb4fc,		// 7E
+
+            0x000088,		// 80
+            0x1c209c,		// 82
+            0x3840b0,		// 84
+            0x505cc0,		// 86
+            0x6874d0,		// 88
+            0x7c8ce0,		// 8A
+            0x90a4ec,		// 8C
+            0xa4b8fc,		// 8E
+
+            0x00187c,		// 90
+            0x1c3890,		// 92
+            0x3854a8,		// 94
+            0x5070bc,		// 96
+            0x6888cc,		// 98
+            0x7c9cdc,		// 9A
+            0x90b4ec,		// 9C
+            0xa4c8fc,		// 9E
+
+            0x002c5c,		// A0
+            0x1c4c78,		// A2
+            0x386890,		// A4
+            0x5084ac,		// A6
+            0x689cc0,		// A8
+            0x7cb4d4,		// AA
+            0x90cce8,		// AC
+            0xa4e0fc,		// AE
+
+            0x003c2c,		// B0
+            0x1c5c48,		// B2
+            0x387c64,		// B4
+            0x509c80,		// B6
+            0x68b494,		// B8
+            0x7cd0ac,		// BA
+            0x90e4c0,		// BC
+            0xa4fcd4,		// BE
+
+            0x003c00,		// C0
+            0x205c20,		// C2
+            0x407c40,		// C4
+            0x5c9c5c,		// C6
+            0x74b474,		// C8
+            0x8cd08c,		// CA
+            0xa4e4a4,		// CC
+            0xb8fcb8,		// CE
+
+            0x143800,		// D0
+            0x345c1c,		// D2
+            0x507c38,		// D4
+            0x6c9850,		// D6
+            0x84b468,		// D8
+            0x9ccc7c,		// DA
+            0xb4e490,		// DC
+            0xc8fca4,		// DE
+
+            0x2c3000,		// E0
+            0x4c501c,		// E2
+            0x687034,		// E4
+            0x848c4c,		// E6
+            0x9ca864,		// E8
+            0xb4c078,		// EA
+            0xccd488,		// EC
+            0xe0ec9c,		// EE
+
+            0x442800,		// F0
+            0x644818,		// F2
+            0x846830,		// F4
+            0xa08444,		// F6
+            0xb89c58,		// F8
+            0xd0b46c,		// FA
+            0xe8cc7c,		// FC
+            0xfce08c		// FE
+        ]);
+
+    static ref PAL_PALETTE: Vec<Color> = expand(&[
+            0x000000,		// 00
+            0x282828,		// 02
+            0x505050,		// 04
+            0x747474,		// 06
+            0x949494,		// 08
+            0xb4b4b4,		// 0A
+            0xd0d0d0,		// 0C
+            0xececec,		// 0E
+
+            0x000000,		// 10
+            0x282828,		// 12
+            0x505050,		// 14
+            0x747474,		// 16
+            0x949494,		// 18
+            0xb4b4b4,		// 1A
+            0xd0d0d0,		// 1C
+            0xececec,		// 1E
+
+            0x805800,		// 20
+            0x947020,		// 22
+            0xa8843c,		// 24
+            0xbc9c58,		// 26
+            0xccac70,		// 28
+            0xdcc084,		// 2A
+            0xecd09c,		// 2C
+            0xfce0b0,		// 2E
+
+            0x445c00,		// 30
+            0x5c7820,		// 32
+            0x74903c,		// 34
+            0x8cac58,		// 36
+            0xa0c070,		// 38
+            0xb0d484,		// 3A
+            0xc4e89c,		// 3C
+            0xd4fcb0,		// 3E
+
+            0x703400,		// 40
+            0x885020,		// 42
+            0xa0683c,		// 44
+            0xb48458,		// 46
+            0xc89870,		// 48
+            0xdcac84,		// 4A
+            0xecc09c,		// 4C
+            0xfcd4b0,		// 4E
+
+            0x006414,		// 50
+            0x208034,		// 52
+            0x3c9850,		// 54
+            0x58b06c,		// 56
+            0x70c484,		// 58
+            0x84d89c,		// 5A
+            0x9ce8b4,		// 5C
+            0xb0fcc8,		// 5E
+
+            0x700014,		// 60
+            0x882034,		// 62
+            0xa03c50,		// 64
+            0xb4586c,		// 66
+            0xc87084,		// 68
+            0xdc849c,		// 6A
+            0xec9cb4,		// 6C
+            0xfcb0c8,		// 6E
+
+            0x005c5c,		// 70
+            0x207474,		// 72
+            0x3c8c8c,		// 74
+            0x58a4a4,		// 76
+            0x70b8b8,		// 78
+            0x84c8c8,		// 7A
+            0x9cdcdc,		// 7C
+            0xb0ecec,		// 7E
+
+            0x70005c,		// 80
+            0x842074,		// 82
+            0x943c88,		// 84
+            0xa8589c,		// 86
+            0xb470b0,		// 88
+            0xc484c0,		// 8A
+            0xd09cd0,		// 8C
+            0xe0b0e0,		// 8E
+
+            0x003c70,		// 90
+            0x1c5888,		// 92
+            0x3874a0,		// 94
+            0x508cb4,		// 96
+            0x68a4c8,		// 98
+            0x7cb8dc,		// 9A
+            0x90ccec,		// 9C
+            0xa4e0fc,		// 9E
+
+            0x580070,		// A0
+            0x6c2088,		// A2
+            0x803ca0,		// A4
+            0x9458b4,		// A6
+            0xa470c8,		// A8
+            0xb484dc,		// AA
+            0xc49cec,		// AC
+            0xd4b0fc,		// AE
+
+            0x002070,		// B0
+            0x1c3c88,		// B2
+            0x3858a0,		// B4
+            0x5074b4,		// B6
+            0x6888c8,		// B8
+            0x7ca0dc,		// BA
+            0x90b4ec,		// BC
+            0xa4c8fc,		// BE
+
+            0x3c0080,		// C0
+            0x542094,		// C2
+            0x6c3ca8,		// C4
+            0x8058bc,		// C6
+            0x9470cc,		// C8
+            0xa884dc,		// CA
+            0xb89cec,		// CC
+            0xc8b0fc,		// CE
+
+            0x000088,		// D0
+            0x20209c,		// D2
+            0x3c3cb0,		// D4
+            0x5858c0,		// D6
+            0x7070d0,		// D8
+            0x8484e0,		// DA
+            0x9c9cec,		// DC
+            0xb0b0fc,		// DE
+
+            0x000000,		// E0
+            0x282828,		// E2
+            0x505050,		// E4
+            0x747474,		// E6
+            0x949494,		// E8
+            0xb4b4b4,		// EA
+            0xd0d0d0,		// EC
+            0xececec,		// EE
+
+            0x000000,		// F0
+            0x282828,		// F2
+            0x505050,		// F4
+            0x747474,		// F6
+            0x949494,		// F8
+            0xb4b4b4,		// FA
+            0xd0d0d0,		// FC
+            0xececec		// FE
+        ]);
+}
+
+// SECAM TVs only ever display 8 colours, selected by the luminance bits of the colour registers,
+// regardless of the hue bits.
+const SECAM_COLORS: [u32; 8] = [
+    0x000000, 0x2121ff, 0xf03c79, 0xff50ff, 0x7fff00, 0x7fffff, 0xffff3f, 0xffffff,
+];
+
+// How many colours a palette file contains. Each colour is three bytes: red, green, then blue.
+const PALETTE_FILE_COLORS: usize = 128;
+
+// Returns one of the built-in palettes by name, or otherwise loads a palette from the file at the
+// given path. A palette file is 128 RGB triplets, one for each even colour register value; any
+// data after that (e.g. the PAL and SECAM palettes of a Stella palette file) is ignored.
+pub fn load_palette(name: &str) -> io::Result<Vec<Color>> {
+    match name.to_lowercase().as_str() {
+        "ntsc"  => Ok(NTSC_PALETTE.clone()),
+        "pal"   => Ok(PAL_PALETTE.clone()),
+        "secam" => Ok(secam_palette()),
+        "warm"  => Ok(tint(&NTSC_PALETTE, 1.1, 0.85)),
+        "cool"  => Ok(tint(&NTSC_PALETTE, 0.85, 1.1)),
+        _ => {
+            let mut data = vec![];
+            File::open(name)?.read_to_end(&mut data)?;
+            from_bytes(&data)
+        },
+    }
+}
+
+fn secam_palette() -> Vec<Color> {
+    let colors = (0 .. 128).map(|i| SECAM_COLORS[i & 0x07]).collect::<Vec<_>>();
+    expand(&colors)
+}
+
+// Shift a palette's colour temperature by scaling its red and blue components
+fn tint(palette: &[Color], red: f64, blue: f64) -> Vec<Color> {
+    let scale = |c: u8, factor: f64| (c as f64 * factor).min(255.0) as u8;
+
+    palette
+        .iter()
+        .map(|c| Color::RGBA(scale(c.r, red), c.g, scale(c.b, blue), 255))
+        .collect()
+}
+
+fn from_bytes(data: &[u8]) -> io::Result<Vec<Color>> {
+    if data.len() < PALETTE_FILE_COLORS * 3 {
+        let msg = format!("palette file is {} bytes, expected at least {}", data.len(), PALETTE_FILE_COLORS * 3);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+
+    let colors = data
+        .chunks(3)
+        .take(PALETTE_FILE_COLORS)
+        .map(|rgb| ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | rgb[2] as u32)
+        .collect::<Vec<_>>();
+
+    Ok(expand(&colors))
+}
+
+// The palettes above only list the colours for the even values, as the lowest bit of the colour
+// registers isn't used, so each colour is repeated to allow indexing by the register value.
+fn expand(colors: &[u32]) -> Vec<Color> {
+    colors
+        .iter()
+        .flat_map(|&c| {
+            let r = (c >> 16) as u8;
+            let g = (c >> 8) as u8;
+            let b = c as u8;
+            vec![Color::RGBA(r, g, b, 255), Color::RGBA(r, g, b, 255)]
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_from_bytes() {
+        let data = (0 .. 128 * 3).map(|i| i as u8).collect::<Vec<_>>();
+        let palette = from_bytes(&data).unwrap();
+
+        assert_eq!(palette.len(), 256);
+        assert_eq!(palette[0x00], Color::RGB(0, 1, 2));
+        assert_eq!(palette[0x01], Color::RGB(0, 1, 2));
+        assert_eq!(palette[0x02], Color::RGB(3, 4, 5));
+
+        assert!(from_bytes(&data[.. 100]).is_err());
+    }
+
+    #[test]
+    fn test_secam_palette() {
+        let palette = secam_palette();
+
+        // Only the luminance bits select the colour
+        assert_eq!(palette[0x0e], palette[0xfe]);
+        assert_eq!(palette[0x02], Color::RGB(0x21, 0x21, 0xff));
+    }
+
+    #[test]
+    fn test_black_and_white() {
+        let mut palette = Palette::for_region(Region::NTSC);
+        assert_eq!(palette.color(0x46), NTSC_PALETTE[0x46]);
+
+        palette.set_black_and_white(true);
+        assert_eq!(palette.color(0x46), NTSC_PALETTE[0x06]);
+    }
+}
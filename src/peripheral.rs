@@ -0,0 +1,271 @@
+// Controllers and other peripherals plugged into the console's two controller ports. Each port
+// has four data pins (pins 1 to 4) on port A of the RIOT, which the game can set as inputs or
+// outputs through SWACNT, and a fire button pin (pin 6) that's read through INPT4 or INPT5 of the
+// TIA. The left port's data pins are D7-D4 of SWCHA, and the right's are D3-D0.
+//
+// The pins are pulled high, and a peripheral pulls them low, so a port's pins are low when either
+// the peripheral or anything else connected to them pulls them low.
+pub trait Peripheral {
+    // The levels the peripheral leaves the data pins at, with pin 1 in D0 through to pin 4 in D3
+    fn pins(&self) -> u8;
+
+    // The level the peripheral leaves the fire button pin at
+    fn fire(&self) -> bool { true }
+
+    // The levels of pins 9 and 5, which are read through INPT0 and INPT1 for the left port, and
+    // INPT2 and INPT3 for the right, for peripherals that connect them straight to the supply or
+    // to ground. They're usually left to the paddles' potentiometers, which is what None means.
+    fn pot_pins(&self) -> Option<[bool; 2]> { None }
+
+    // Called when the game writes to port A, with the levels the data pins are driven at. Pins
+    // that the game has set as inputs are left high.
+    fn write(&mut self, _pins: u8) { }
+
+    // Called at the end of each frame
+    fn end_frame(&mut self) { }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// Where a joystick's being controlled from. Each of them has its own say in which directions are
+// pushed and whether the fire button's pressed, so that keys, a game controller, and a script can
+// be used together without one letting go of what another's holding down.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Source {
+    Keyboard,
+    Controller,
+    Mouse,
+    Script,
+}
+
+// A joystick switches one data pin to ground for each direction it's pushed in, with up on pin 1
+// through to right on pin 4, and switches the fire button pin to ground while the button's
+// pressed.
+//
+// Its fire button has autofire, for games that only fire once per press of the button. With
+// autofire on, the button's pressed and let go of every few frames while it's held down, so the
+// game sees a fresh press each time. It's timed in frames rather than real time, as games poll
+// the button once a frame, and would miss presses that came and went in between.
+pub struct Joystick {
+    // The directions being pushed from each source, in the same bits as their pins
+    directions: [u8; 4],
+
+    // Whether the fire button's being pressed from each source, and how many frames it's been held
+    // down for, by any of them
+    fire: [bool; 4],
+    fire_frames: usize,
+
+    autofire: bool,
+    // How many frames autofire presses the button for, and then lets go of it for
+    autofire_rate: usize,
+}
+
+impl Joystick {
+    pub fn new(autofire_rate: usize) -> Self {
+        Self {
+            directions: [0; 4],
+            fire: [false; 4],
+            fire_frames: 0,
+            autofire: false,
+            autofire_rate: autofire_rate.max(1),
+        }
+    }
+
+    pub fn set_direction(&mut self, source: Source, direction: Direction, pressed: bool) {
+        let bit = match direction {
+            Direction::Up    => 0b0001,
+            Direction::Down  => 0b0010,
+            Direction::Left  => 0b0100,
+            Direction::Right => 0b1000,
+        };
+
+        let directions = &mut self.directions[source as usize];
+        if pressed {
+            *directions |= bit;
+        } else {
+            *directions &= !bit;
+        }
+    }
+
+    // A press always reaches the game straight away, and autofire starts from there
+    pub fn set_fire(&mut self, source: Source, pressed: bool) {
+        if pressed && !self.fire_held() {
+            self.fire_frames = 0;
+        }
+
+        self.fire[source as usize] = pressed;
+    }
+
+    fn fire_held(&self) -> bool { self.fire.iter().any(|&fire| fire) }
+
+    pub fn set_autofire(&mut self, autofire: bool) { self.autofire = autofire }
+}
+
+impl Peripheral for Joystick {
+    fn pins(&self) -> u8 { !self.directions.iter().fold(0, |pushed, &d| pushed | d) & 0x0f }
+
+    fn fire(&self) -> bool {
+        if self.fire_held() {
+            self.autofire && !(self.fire_frames / self.autofire_rate).is_multiple_of(2)
+        } else {
+            true
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if self.fire_held() {
+            self.fire_frames += 1;
+        }
+    }
+}
+
+// The keypad (keyboard) controller has 12 keys in 4 rows of 3. The game selects a row by driving
+// its pin low, with pins 1 to 4 selecting the rows from the top, and a pressed key in that row
+// connects its column to ground: the left column (1, 4, 7, *) to pin 9, read through INPT0 or
+// INPT2, the middle (2, 5, 8, 0) to pin 5, read through INPT1 or INPT3, and the right to pin 6.
+pub struct Keypad {
+    // Whether each key's pressed, in the order they're laid out, from 1, 2, 3 through to *, 0, #
+    keys: [bool; 12],
+
+    // The levels that the row select pins are driven at
+    rows: u8,
+}
+
+pub const KEYPAD_KEYS: [char; 12] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '*', '0', '#'];
+
+impl Keypad {
+    pub fn new() -> Self {
+        Self {
+            keys: [false; 12],
+            rows: 0x0f,
+        }
+    }
+
+    pub fn set_key(&mut self, key: usize, pressed: bool) { self.keys[key] = pressed }
+    pub fn pressed(&self, key: usize) -> bool { self.keys[key] }
+
+    // Whether any pressed key in a column is in a selected row
+    fn column_grounded(&self, column: usize) -> bool {
+        (0 .. 4).any(|row| self.keys[row * 3 + column] && (self.rows & (1 << row)) == 0)
+    }
+}
+
+impl Peripheral for Keypad {
+    fn pins(&self) -> u8 { 0x0f }
+
+    fn fire(&self) -> bool { !self.column_grounded(2) }
+
+    fn pot_pins(&self) -> Option<[bool; 2]> {
+        Some([!self.column_grounded(0), !self.column_grounded(1)])
+    }
+
+    fn write(&mut self, pins: u8) { self.rows = pins }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_joystick() {
+        let mut joystick = Joystick::new(2);
+        assert_eq!(joystick.pins(), 0b1111);
+        assert!(joystick.fire());
+
+        joystick.set_direction(Source::Keyboard, Direction::Up, true);
+        joystick.set_direction(Source::Keyboard, Direction::Right, true);
+        joystick.set_direction(Source::Keyboard, Direction::Up, false);
+        assert_eq!(joystick.pins(), 0b0111);
+
+        joystick.set_fire(Source::Keyboard, true);
+        assert!(!joystick.fire());
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut joystick = Joystick::new(2);
+
+        // Letting go of a direction or the fire button from one source leaves the others' alone
+        joystick.set_direction(Source::Keyboard, Direction::Left, true);
+        joystick.set_direction(Source::Controller, Direction::Left, true);
+        joystick.set_direction(Source::Controller, Direction::Down, true);
+        joystick.set_direction(Source::Controller, Direction::Left, false);
+        assert_eq!(joystick.pins(), 0b1001);
+
+        joystick.set_fire(Source::Mouse, true);
+        joystick.set_fire(Source::Controller, false);
+        assert!(!joystick.fire());
+
+        // Autofire carries on from the first press
+        joystick.set_autofire(true);
+        joystick.end_frame();
+        joystick.end_frame();
+        joystick.set_fire(Source::Keyboard, true);
+        assert!(joystick.fire());
+
+        joystick.set_fire(Source::Mouse, false);
+        joystick.set_fire(Source::Keyboard, false);
+        assert!(joystick.fire());
+    }
+
+    #[test]
+    fn test_keypad() {
+        let mut keypad = Keypad::new();
+        keypad.set_key(0, true); // 1
+        keypad.set_key(4, true); // 5
+        keypad.set_key(11, true); // #
+
+        // Nothing's connected until their rows are selected
+        assert_eq!(keypad.pot_pins(), Some([true, true]));
+        assert!(keypad.fire());
+
+        // The left column's on pin 9, and the middle's on pin 5
+        keypad.write(0b1110);
+        assert_eq!(keypad.pot_pins(), Some([false, true]));
+        assert!(keypad.fire());
+
+        keypad.write(0b1101);
+        assert_eq!(keypad.pot_pins(), Some([true, false]));
+        assert!(keypad.fire());
+
+        keypad.write(0b0111);
+        assert_eq!(keypad.pot_pins(), Some([true, true]));
+        assert!(!keypad.fire());
+    }
+
+    // The fire button pin's level at the end of each of the frames
+    fn fire(joystick: &mut Joystick, frames: usize) -> Vec<bool> {
+        (0 .. frames)
+            .map(|_| {
+                joystick.end_frame();
+                joystick.fire()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_autofire() {
+        let mut joystick = Joystick::new(2);
+
+        // Held down without autofire
+        joystick.set_fire(Source::Keyboard, true);
+        assert_eq!(fire(&mut joystick, 3), vec![false; 3]);
+
+        // Held down with it, pressed for 2 frames and let go of for 2, from the press
+        joystick.set_autofire(true);
+        joystick.set_fire(Source::Keyboard, false);
+        joystick.set_fire(Source::Keyboard, true);
+        assert!(!joystick.fire());
+        assert_eq!(fire(&mut joystick, 6), vec![false, true, true, false, false, true]);
+
+        // Letting go stops it
+        joystick.set_fire(Source::Keyboard, false);
+        assert_eq!(fire(&mut joystick, 4), vec![true; 4]);
+    }
+}
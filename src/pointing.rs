@@ -0,0 +1,143 @@
+// Trak-Balls, mice, and driving controllers, which report their movement on the joystick direction
+// pins of a controller port rather than as a position. Each axis has an encoder that games count the
+// pulses of, so the host mouse's movement is fed to the encoders a step at a time.
+
+// How far the host mouse has to move, in pixels, for one step of an encoder
+const PIXELS_PER_STEP: i32 = 2;
+
+// The Gray code that quadrature encoders step through, so that only one signal changes per step
+const QUADRATURE: [u8; 4] = [0b00, 0b01, 0b11, 0b10];
+
+// How the movement is reported on the pins
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    // The CX22 Trak-Ball in trak-ball mode. One pin for each axis gives the direction, and
+    // another pulses as it moves: pins 1 and 2 for X, and pins 3 and 4 for Y.
+    TrakBall,
+    // An Atari ST mouse, with a pair of quadrature signals for each axis: pins 1 and 2 for X, and
+    // pins 3 and 4 for Y
+    AtariMouse,
+    // An Amiga mouse, which has the same signals as the ST mouse on different pins: V and H on
+    // pins 1 and 2, and VQ and HQ on pins 3 and 4
+    AmigaMouse,
+    // A driving controller, which only turns, with a pair of quadrature signals on pins 1 and 2.
+    // It's turned by the host mouse's movement across.
+    Driving,
+}
+
+pub struct PointingDevice {
+    protocol: Protocol,
+
+    // The positions of the encoders
+    x: i32,
+    y: i32,
+
+    // How far the host mouse has moved that the encoders haven't caught up with yet, in pixels
+    pending_x: i32,
+    pending_y: i32,
+
+    // The last direction moved in on each axis, which the Trak-Ball keeps reporting once it stops
+    left: bool,
+    down: bool,
+}
+
+impl PointingDevice {
+    pub fn new(protocol: Protocol) -> Self {
+        Self {
+            protocol: protocol,
+            x: 0,
+            y: 0,
+            pending_x: 0,
+            pending_y: 0,
+            left: false,
+            down: false,
+        }
+    }
+
+    pub fn move_by(&mut self, dx: i32, dy: i32) {
+        self.pending_x += dx;
+        self.pending_y += dy;
+    }
+
+    // Moves each encoder at most one step towards where the host mouse has moved to. This is
+    // called once per scanline, which is about as often as games poll the pins, so that they
+    // don't miss any steps.
+    pub fn step(&mut self) {
+        if self.pending_x.abs() >= PIXELS_PER_STEP {
+            let direction = self.pending_x.signum();
+            self.x += direction;
+            self.pending_x -= direction * PIXELS_PER_STEP;
+            self.left = direction < 0;
+        }
+
+        if self.pending_y.abs() >= PIXELS_PER_STEP {
+            let direction = self.pending_y.signum();
+            self.y += direction;
+            self.pending_y -= direction * PIXELS_PER_STEP;
+            self.down = direction > 0;
+        }
+    }
+
+    // The levels of the four direction pins, with pin 1 (up) in D0 through to pin 4 (right) in D3
+    pub fn pins(&self) -> u8 {
+        let x = QUADRATURE[(self.x & 0b11) as usize];
+        let y = QUADRATURE[(self.y & 0b11) as usize];
+
+        match self.protocol {
+            Protocol::TrakBall => {
+                (self.left as u8) | ((self.x & 1) as u8) << 1 | (self.down as u8) << 2 | ((self.y & 1) as u8) << 3
+            },
+            Protocol::AtariMouse => x | y << 2,
+            Protocol::AmigaMouse => (y & 1) | (x & 1) << 1 | (y >> 1) << 2 | (x >> 1) << 3,
+            Protocol::Driving    => x | 0b1100,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pins(protocol: Protocol, dx: i32, dy: i32, steps: usize) -> Vec<u8> {
+        let mut device = PointingDevice::new(protocol);
+        device.move_by(dx, dy);
+
+        (0 .. steps)
+            .map(|_| {
+                device.step();
+                device.pins()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_atari_mouse() {
+        // Right, stepping through the quadrature signals on pins 1 and 2
+        assert_eq!(pins(Protocol::AtariMouse, 8, 0, 5), vec![0b0001, 0b0011, 0b0010, 0b0000, 0b0000]);
+
+        // Up, backwards through the signals on pins 3 and 4
+        assert_eq!(pins(Protocol::AtariMouse, 0, -6, 3), vec![0b1000, 0b1100, 0b0100]);
+    }
+
+    #[test]
+    fn test_amiga_mouse() {
+        assert_eq!(pins(Protocol::AmigaMouse, 6, 0, 3), vec![0b0010, 0b1010, 0b1000]);
+        assert_eq!(pins(Protocol::AmigaMouse, 0, 6, 3), vec![0b0001, 0b0101, 0b0100]);
+    }
+
+    #[test]
+    fn test_driving() {
+        // Only moving across turns it, and pins 3 and 4 are left high
+        assert_eq!(pins(Protocol::Driving, 6, 4, 3), vec![0b1101, 0b1111, 0b1110]);
+        assert_eq!(pins(Protocol::Driving, -4, 0, 2), vec![0b1110, 0b1111]);
+    }
+
+    #[test]
+    fn test_trakball() {
+        // Left, pulsing pin 2 with pin 1 high for the direction
+        assert_eq!(pins(Protocol::TrakBall, -6, 0, 4), vec![0b0011, 0b0001, 0b0011, 0b0011]);
+
+        // Down, pulsing pin 4 with pin 3 high
+        assert_eq!(pins(Protocol::TrakBall, 0, 4, 2), vec![0b1100, 0b0100]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cpu6507::{Opcode, OPCODES};
+
+// Counts how many times each opcode is executed, and optionally each instruction address, for
+// profiling games and seeing which instructions they actually use
+pub struct Profile {
+    opcodes: Vec<u64>,
+    pcs: Option<HashMap<u16, u64>>,
+}
+
+impl Profile {
+    pub fn new(count_pcs: bool) -> Self {
+        Self {
+            opcodes: vec![0; 256],
+            pcs: if count_pcs { Some(HashMap::new()) } else { None },
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, opcode: u8) {
+        self.opcodes[opcode as usize] += 1;
+
+        if let Some(pcs) = &mut self.pcs {
+            *pcs.entry(pc).or_insert(0) += 1;
+        }
+    }
+
+    pub fn opcode_count(&self, opcode: u8) -> u64 { self.opcodes[opcode as usize] }
+}
+
+// The counts, most executed first, leaving out anything that was never executed
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut opcodes = (0 ..= 255u8)
+            .map(|op| (op, self.opcode_count(op)))
+            .filter(|&(_, count)| count > 0)
+            .collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        writeln!(f, "; opcodes")?;
+        for (op, count) in opcodes {
+            let Opcode(inst, addr_mode, _, _) = OPCODES[op as usize];
+            writeln!(f, "{:>12}  {:02X}  {:?} {:?}", count, op, inst, addr_mode)?;
+        }
+
+        if let Some(pcs) = &self.pcs {
+            let mut pcs = pcs.iter().map(|(&pc, &count)| (pc, count)).collect::<Vec<_>>();
+            pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+            writeln!(f, "; addresses")?;
+            for (pc, count) in pcs {
+                writeln!(f, "{:>12}  {:04X}", count, pc)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let mut profile = Profile::new(true);
+        profile.record(0xf000, 0xa9);
+        profile.record(0xf002, 0xea);
+        profile.record(0xf003, 0xea);
+        profile.record(0xf002, 0xea);
+
+        assert_eq!(profile.opcode_count(0xea), 3);
+        assert_eq!(profile.opcode_count(0x00), 0);
+
+        assert_eq!(profile.to_string(), "\
+; opcodes
+           3  EA  NOP Implied
+           1  A9  LDA Immediate
+; addresses
+           2  F002
+           1  F000
+           1  F003
+");
+
+        // Only opcodes are counted by default
+        let mut profile = Profile::new(false);
+        profile.record(0xf000, 0xa9);
+        assert!(!profile.to_string().contains("; addresses"));
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+// A database of game properties, such as which controllers a game uses, in the format of Stella's
+// stella.pro. Each game's entry is a list of quoted key and value pairs, one pair per line, and
+// ends with a line of just "". Games are looked up by the MD5 of their ROM.
+//
+//     "Cart.MD5" "0123456789abcdef0123456789abcdef"
+//     "Cart.Name" "Some Game"
+//     "Controller.Left" "PADDLES"
+//     ""
+pub struct Properties {
+    carts: HashMap<String, HashMap<String, String>>,
+}
+
+impl Properties {
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut carts = HashMap::new();
+        let mut cart: HashMap<String, String> = HashMap::new();
+
+        for line in text.lines() {
+            let strings = quoted_strings(line);
+
+            match strings.as_slice() {
+                [end] if end.is_empty() => {
+                    let properties = std::mem::take(&mut cart);
+
+                    if let Some(md5) = properties.get("Cart.MD5") {
+                        carts.insert(md5.to_lowercase(), properties);
+                    }
+                },
+                [key, value] => { cart.insert(key.clone(), value.clone()); },
+                _ => { },
+            }
+        }
+
+        Self {
+            carts: carts,
+        }
+    }
+
+    // The value of one of a game's properties, e.g. "Controller.Left"
+    pub fn get(&self, md5: &str, key: &str) -> Option<&str> {
+        self.carts.get(md5).and_then(|cart| cart.get(key)).map(|value| value.as_str())
+    }
+}
+
+// The strings between each pair of double quotes in a line, with backslash escapes
+fn quoted_strings(line: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+
+        let mut string = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"'  => break,
+                '\\' => string.extend(chars.next()),
+                _    => string.push(c),
+            }
+        }
+
+        strings.push(string);
+    }
+
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_properties() {
+        let properties = Properties::parse(r#"
+"Cart.MD5" "0123456789ABCDEF0123456789abcdef"
+"Cart.Name" "A \"Paddle\" Game"
+"Controller.Left" "PADDLES"
+""
+
+"Cart.MD5" "fedcba9876543210fedcba9876543210"
+"Controller.SwapPorts" "YES"
+""
+"#);
+
+        let md5 = "0123456789abcdef0123456789abcdef";
+        assert_eq!(properties.get(md5, "Cart.Name"), Some("A \"Paddle\" Game"));
+        assert_eq!(properties.get(md5, "Controller.Left"), Some("PADDLES"));
+        assert_eq!(properties.get(md5, "Controller.SwapPorts"), None);
+
+        assert_eq!(properties.get("fedcba9876543210fedcba9876543210", "Controller.SwapPorts"), Some("YES"));
+        assert_eq!(properties.get("00000000000000000000000000000000", "Controller.Left"), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+// The TV standard that a cartridge was made for. This determines the colours that the TIA
+// generates, as well as how many scanlines make up a frame, and how many frames are drawn per
+// second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Region {
+    NTSC,
+    PAL,
+}
+
+impl Region {
+    pub fn fps(&self) -> f64 {
+        match self {
+            Region::NTSC => 60.0,
+            Region::PAL  => 50.0,
+        }
+    }
+
+    // The colour clock frequency, in Hz
+    pub fn clock_rate(&self) -> u64 {
+        match self {
+            Region::NTSC => 3_579_545,
+            Region::PAL  => 3_546_894,
+        }
+    }
+
+    // Total scanlines per frame, including VSYNC, VBLANK, and overscan
+    pub fn scanlines(&self) -> usize {
+        match self {
+            Region::NTSC => 262,
+            Region::PAL  => 312,
+        }
+    }
+
+    // How many scanlines of the picture to display. This is a little more than the standard 192
+    // (NTSC) or 228 (PAL) lines, as plenty of games draw a few more.
+    pub fn visible_scanlines(&self) -> usize {
+        match self {
+            Region::NTSC => 200,
+            Region::PAL  => 240,
+        }
+    }
+
+    // The scanline that the picture starts on, in a standard frame: after 3 lines of VSYNC, and
+    // 37 (NTSC) or 45 (PAL) lines of VBLANK
+    pub fn picture_top(&self) -> usize {
+        match self {
+            Region::NTSC => 40,
+            Region::PAL  => 48,
+        }
+    }
+
+    // The number of scanlines in the picture of a standard frame
+    pub fn picture_scanlines(&self) -> usize {
+        match self {
+            Region::NTSC => 192,
+            Region::PAL  => 228,
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ntsc" => Ok(Region::NTSC),
+            "pal"  => Ok(Region::PAL),
+            _      => Err(format!("unknown region: {}", s)),
+        }
+    }
+}
+
+// Frames to skip before measuring, while the game starts up and its frame timing settles
+const SETTLE_FRAMES: usize = 3;
+
+// How many consecutive frames need to be the same height before deciding on the region
+const STABLE_FRAMES: usize = 5;
+
+// How many scanlines the frame height can vary by and still be considered stable
+const TOLERANCE: usize = 2;
+
+// Give up if the frame height hasn't settled by this many frames
+const MAX_FRAMES: usize = 300;
+
+// Detects which TV standard a game was made for, by watching how many scanlines it draws per frame
+// once it has settled down. NTSC games draw ~262 scanlines per frame, and PAL games ~312.
+pub struct RegionDetector {
+    frames: usize,
+    heights: Vec<usize>,
+    finished: bool,
+}
+
+impl RegionDetector {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            heights: vec![],
+            finished: false,
+        }
+    }
+
+    // Record the number of scanlines in a frame. Returns the detected region once, as soon as the
+    // frame height has been stable for long enough.
+    pub fn frame(&mut self, scanlines: usize) -> Option<Region> {
+        if self.finished { return None }
+
+        self.frames += 1;
+
+        if self.frames > MAX_FRAMES {
+            warn!("unable to detect the region, frame height never settled");
+            self.finished = true;
+            return None;
+        }
+
+        if self.frames <= SETTLE_FRAMES { return None }
+
+        self.heights.push(scanlines);
+        if self.heights.len() > STABLE_FRAMES {
+            self.heights.remove(0);
+        }
+
+        if self.heights.len() < STABLE_FRAMES { return None }
+
+        let min = *self.heights.iter().min().unwrap();
+        let max = *self.heights.iter().max().unwrap();
+        if max - min > TOLERANCE { return None }
+
+        self.finished = true;
+
+        let average = self.heights.iter().sum::<usize>() / self.heights.len();
+        let midpoint = (Region::NTSC.scanlines() + Region::PAL.scanlines()) / 2;
+
+        if average < midpoint {
+            Some(Region::NTSC)
+        } else {
+            Some(Region::PAL)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(heights: &[usize]) -> Option<Region> {
+        let mut detector = RegionDetector::new();
+        heights.iter().filter_map(|&h| detector.frame(h)).next()
+    }
+
+    #[test]
+    fn test_detect_region() {
+        assert_eq!(detect(&[0, 400, 10, 262, 262, 263, 262, 261]), Some(Region::NTSC));
+        assert_eq!(detect(&[312; 8]), Some(Region::PAL));
+
+        // Not enough stable frames
+        assert_eq!(detect(&[262, 262, 262, 262, 262, 312, 262, 262, 262]), None);
+    }
+}
@@ -1,4 +1,91 @@
-use crate::bus::Bus;
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::bus::{read_bytes, Bus};
+use crate::peripheral::Peripheral;
+use crate::tia::PlayerType;
+
+// The flags in INSTAT: D7 is set when the timer underflows, and D6 is the PA7 edge detect flag
+const TIMER_FLAG: u8 = 0b1000_0000;
+const PA7_FLAG: u8   = 0b0100_0000;
+
+// What the RAM holds when the console's turned on. The RAM in a real console comes up
+// semi-random, and some games depend on that, e.g. Combat's hidden game variations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RamPattern {
+    // All zeroes
+    Zero,
+    // All ones (0xFF)
+    Ones,
+    // Alternating 0x00 and 0xFF bytes
+    Alternating,
+    // Random bytes, from a seed so that the same seed always gives the same RAM
+    Random,
+}
+
+impl FromStr for RamPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zero"        => Ok(RamPattern::Zero),
+            "ff"          => Ok(RamPattern::Ones),
+            "alternating" => Ok(RamPattern::Alternating),
+            "random"      => Ok(RamPattern::Random),
+            _             => Err(format!("unknown ram pattern: {}", s)),
+        }
+    }
+}
+
+impl RamPattern {
+    fn fill(&self, ram: &mut [u8], seed: u64) {
+        for (i, byte) in ram.iter_mut().enumerate() {
+            *byte = match self {
+                RamPattern::Zero        => 0x00,
+                RamPattern::Ones        => 0xff,
+                RamPattern::Alternating => if i % 2 == 0 { 0x00 } else { 0xff },
+                RamPattern::Random      => splitmix64(seed.wrapping_add(i as u64)) as u8,
+            }
+        }
+    }
+}
+
+// A small, well mixed hash of a number, which is plenty for making up some RAM contents
+// (http://prng.di.unimi.it/splitmix64.c)
+fn splitmix64(n: u64) -> u64 {
+    let mut z = n.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// The position of each of the console's switches, for showing the switch panel. Difficulty A is
+// the "pro" setting, and B is the "amateur" setting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Switches {
+    pub select: bool,
+    pub reset: bool,
+    pub color: bool,
+    pub p0_difficulty_a: bool,
+    pub p1_difficulty_a: bool,
+}
+
+impl fmt::Display for Switches {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pressed = |p| if p { "pressed" } else { "released" };
+        let difficulty = |a| if a { "A" } else { "B" };
+
+        write!(f, "select: {}, reset: {}, tv type: {}, left difficulty: {}, right difficulty: {}",
+               pressed(self.select),
+               pressed(self.reset),
+               if self.color { "color" } else { "b/w" },
+               difficulty(self.p0_difficulty_a),
+               difficulty(self.p1_difficulty_a))
+    }
+}
 
 // The RIOT (RAM/IO/Timer) chip. Also known as the PIA. It's a MOS 6532 chip.
 pub struct RIOT {
@@ -12,12 +99,35 @@ pub struct RIOT {
     intim: u8,
     instat: u8,
 
-    // Internal things
+    // Internal things. The levels of port A's pins that aren't driven by the peripherals, for
+    // the controllers that aren't peripherals yet.
     port_a: u8,
     port_b: u8,
 
+    // The timer's interval, in clocks, and how many clocks are left until it's next decremented.
+    // It counts at the slowest interval until a game sets it.
     resolution: usize,
     cycle_count: usize,
+
+    // Set for the cycle that the timer underflows on, as reading INTIM on that cycle doesn't
+    // clear the timer flag
+    underflowed: bool,
+
+    // PA7 edge detection. The level of PA7 is kept to spot when it changes, and the PA7 flag is
+    // set on a positive (low to high) or negative (high to low) edge, as selected by writing to
+    // $0284-$0287.
+    pa7: bool,
+    pa7_positive_edge: bool,
+
+    // How the RAM is filled at power on, and the seed for a random fill
+    ram_pattern: RamPattern,
+    ram_seed: u64,
+
+    // Whether the controllers are plugged into each other's ports
+    swap_ports: bool,
+
+    // The peripherals plugged into the left and right controller ports
+    peripherals: [Option<Rc<RefCell<dyn Peripheral>>>; 2],
 }
 
 impl RIOT {
@@ -37,13 +147,52 @@ impl RIOT {
             intim: 0,
             instat: 0,
 
-            port_a: 0,
+            port_a: 0xff,
             port_b: port_b,
-            resolution: 0,
-            cycle_count: 0,
+            resolution: 1024,
+            cycle_count: 1024,
+
+            underflowed: false,
+
+            pa7: true,
+            pa7_positive_edge: false,
+
+            ram_pattern: RamPattern::Zero,
+            ram_seed: 0,
+
+            swap_ports: false,
+
+            peripherals: [None, None],
         }
     }
 
+    // Sets how the RAM is filled at power on, and fills it
+    pub fn set_ram_pattern(&mut self, pattern: RamPattern, seed: u64) {
+        self.ram_pattern = pattern;
+        self.ram_seed = seed;
+        pattern.fill(&mut self.ram, seed);
+    }
+
+    // Clears the RAM, the timer, and the ports' registers, as when the console's turned on. The
+    // console switches and controllers are physical, so they stay as they are. The RAM is filled
+    // with the same pattern as before, so a random fill comes up the same each time.
+    pub fn power_cycle(&mut self) {
+        let riot = Self::new();
+
+        *self = Self {
+            port_a: self.port_a,
+            port_b: self.port_b,
+            pa7: self.pa7,
+            ram_pattern: self.ram_pattern,
+            ram_seed: self.ram_seed,
+            swap_ports: self.swap_ports,
+            peripherals: self.peripherals.clone(),
+            ..riot
+        };
+
+        self.set_ram_pattern(self.ram_pattern, self.ram_seed);
+    }
+
     //
     // Console switches
     //
@@ -55,6 +204,19 @@ impl RIOT {
         }
     }
 
+    pub fn color_switch(&self) -> bool { (self.port_b & 0b0000_1000) != 0 }
+
+    // The switches are read straight from port B, where select and reset are active low
+    pub fn switches(&self) -> Switches {
+        Switches {
+            select: (self.port_b & 0b0000_0010) == 0,
+            reset: (self.port_b & 0b0000_0001) == 0,
+            color: self.color_switch(),
+            p0_difficulty_a: (self.port_b & 0b0100_0000) != 0,
+            p1_difficulty_a: (self.port_b & 0b1000_0000) != 0,
+        }
+    }
+
     pub fn reset(&mut self, pressed: bool) {
         if pressed {
             self.port_b &= 0b1111_1110;
@@ -71,42 +233,153 @@ impl RIOT {
         }
     }
 
+    // Sets every switch at once, e.g. when playing back a movie
+    pub fn set_switches(&mut self, switches: Switches) {
+        let bits = [
+            (0b0000_0001, !switches.reset),
+            (0b0000_0010, !switches.select),
+            (0b0000_1000, switches.color),
+            (0b0100_0000, switches.p0_difficulty_a),
+            (0b1000_0000, switches.p1_difficulty_a),
+        ];
+
+        for &(bit, high) in bits.iter() {
+            if high {
+                self.port_b |= bit;
+            } else {
+                self.port_b &= !bit;
+            }
+        }
+    }
+
     //
-    // Player 0 joystick controls
+    // Controller ports
     //
-    pub fn up(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1110_1111
-        } else {
-            self.port_a |= 0b0001_0000
+    pub fn plug_in(&mut self, port: usize, peripheral: Rc<RefCell<dyn Peripheral>>) {
+        self.peripherals[port] = Some(peripheral);
+        self.update_peripherals();
+        self.detect_pa7_edge();
+    }
+
+    // Plugs the controllers into each other's ports, for games that read the "wrong" one. The
+    // controllers keep the state they're in, so anything held down stays held down.
+    pub fn set_swap_ports(&mut self, swap: bool) {
+        if swap != self.swap_ports {
+            self.swap_ports = swap;
+            self.port_a = self.port_a.rotate_left(4);
+            self.peripherals.swap(0, 1);
+            self.update_peripherals();
+            self.detect_pa7_edge();
         }
     }
 
-    pub fn down(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1101_1111
-        } else {
-            self.port_a |= 0b0010_0000
+    // Whether a player's controller is plugged into the left controller port, which is on the high
+    // nibble of port A. The right controller port is on the low nibble.
+    fn left_port(&self, player: PlayerType) -> bool {
+        match player {
+            PlayerType::Player0 => !self.swap_ports,
+            PlayerType::Player1 => self.swap_ports,
         }
     }
 
-    pub fn left(&mut self, pressed: bool) {
-        if pressed {
-            self.port_a &= 0b1011_1111
+    // Sets all four of a player's direction pins at once, with pin 1 (up) in D0 through to pin 4
+    // (right) in D3, for controllers that don't just switch the pins to ground
+    pub fn set_pins(&mut self, player: PlayerType, pins: u8) {
+        if self.left_port(player) {
+            self.port_a = (self.port_a & 0b0000_1111) | (pins << 4);
         } else {
-            self.port_a |= 0b0100_0000
+            self.port_a = (self.port_a & 0b1111_0000) | (pins & 0b0000_1111);
         }
+        self.detect_pa7_edge();
     }
 
-    pub fn right(&mut self, pressed: bool) {
+    //
+    // Paddle controls
+    //
+    // The fire buttons of paddles 0 and 1 share port A with the right and left joystick
+    // directions, and paddles 2 and 3 (plugged into the right controller port) do the same with
+    // player 1's joystick.
+    pub fn paddle_fire(&mut self, paddle: usize, pressed: bool) {
+        let paddle = if self.swap_ports { paddle ^ 2 } else { paddle };
+
+        let bit = match paddle {
+            0 => 0b1000_0000,
+            1 => 0b0100_0000,
+            2 => 0b0000_1000,
+            _ => 0b0000_0100,
+        };
+
         if pressed {
-            self.port_a &= 0b0111_1111
+            self.port_a &= !bit
         } else {
-            self.port_a |= 0b1000_0000
+            self.port_a |= bit
+        }
+
+        self.detect_pa7_edge();
+    }
+
+    // The value of port A. The bits of SWACNT set the data direction for the corresponding bits
+    // of SWCHA, 0 being for input, and 1 for output.
+    fn swcha_value(&self) -> u8 {
+        (self.swcha & self.swacnt) | (self.port_a_input() & (self.swacnt ^ 0xff))
+    }
+
+    // The levels of port A's pins from the controllers' side, with each peripheral pulling its
+    // port's pins low along with anything else on them
+    fn port_a_input(&self) -> u8 {
+        let pins = |port: usize| match &self.peripherals[port] {
+            Some(peripheral) => peripheral.borrow().pins() & 0x0f,
+            None             => 0x0f,
+        };
+
+        self.port_a & (pins(0) << 4 | pins(1))
+    }
+
+    // The levels on the port A pins, as seen by the controllers plugged into them, for peripherals
+    // that listen to the game, like the keyboard controller's row select, or the AtariVox's
+    // serial line. The output bits are driven with what the game wrote to SWCHA, and the input
+    // bits are pulled high.
+    pub fn port_a_output(&self) -> u8 { self.swcha | (self.swacnt ^ 0xff) }
+
+    // Which bits of port A are outputs, set through SWACNT. The player 0 controller is on D7-D4,
+    // and player 1 is on D3-D0.
+    pub fn port_a_output_mask(&self) -> u8 { self.swacnt }
+
+    // The same goes for port B, which only has the console switches on it, but still has output
+    // bits that read back what was written to them
+    fn swchb_value(&self) -> u8 {
+        (self.swchb & self.swbcnt) | (self.port_b & (self.swbcnt ^ 0xff))
+    }
+
+    // Lets the peripherals know the levels their data pins are driven at
+    fn update_peripherals(&self) {
+        let pins = self.port_a_output();
+
+        for (port, peripheral) in self.peripherals.iter().enumerate() {
+            if let Some(peripheral) = peripheral {
+                let shift = if port == 0 { 4 } else { 0 };
+                peripheral.borrow_mut().write((pins >> shift) & 0x0f);
+            }
         }
     }
 
+    // Called whenever the level of PA7 might have changed
+    fn detect_pa7_edge(&mut self) {
+        let pa7 = (self.swcha_value() & 0x80) != 0;
+
+        let edge = if self.pa7_positive_edge { pa7 && !self.pa7 } else { !pa7 && self.pa7 };
+        if edge {
+            self.instat |= PA7_FLAG;
+        }
+
+        self.pa7 = pa7;
+    }
+
     pub fn clock(&mut self) {
+        // The peripherals can change their pins at any time
+        self.detect_pa7_edge();
+
+        self.underflowed = false;
         self.cycle_count -= 1;
 
         if self.cycle_count == 0 {
@@ -115,21 +388,27 @@ impl RIOT {
     }
 
     // Initialises the timer at a certain resolution. The resolution determines how many clocks of
-    // the RIOT are required to decrement the timer value denoted by the INTIM register.
+    // the RIOT are required to decrement the timer value denoted by the INTIM register. Setting
+    // the timer also clears the timer flag.
+    //
+    // The value's loaded as written, and the first decrement happens on the next clock, after
+    // which the prescaler starts counting the interval again from the beginning.
     fn init_timer(&mut self, val: u8, resolution: usize) {
         self.intim = val;
         self.resolution = resolution;
-        self.decrement();
+        self.instat &= !TIMER_FLAG;
+        self.cycle_count = 1;
     }
 
     fn decrement(&mut self) {
         let (new_intim, underflowed) = self.intim.overflowing_sub(1);
         self.intim = new_intim;
 
-        // If we've successfully decremented the timer down to zero, set a flag in the INSTAT
-        // register to record this fact.
+        // If we've decremented the timer past zero, set the timer flag in the INSTAT register to
+        // record this fact. It stays set until INTIM is read, or the timer is set again.
         if underflowed {
-            self.instat = 0b1100_0000;
+            self.instat |= TIMER_FLAG;
+            self.underflowed = true;
 
             // Once when the timer does underflow, it restarts at FFh, and is then decremented once
             // per clock cycle, regardless of the selected interval.
@@ -140,49 +419,116 @@ impl RIOT {
     }
 }
 
+// The registers that can be written to, by name
+const REGISTERS: [(&str, u16); 8] = [
+    ("SWCHA", 0x0280), ("SWACNT", 0x0281), ("SWCHB", 0x0282), ("SWBCNT", 0x0283),
+    ("TIM1T", 0x0294), ("TIM8T", 0x0295), ("TIM64T", 0x0296), ("T1024T", 0x0297),
+];
+
+pub fn register_address(name: &str) -> Option<u16> {
+    REGISTERS.iter().find(|(r, _)| r.eq_ignore_ascii_case(name)).map(|&(_, address)| address)
+}
+
+// The 6532 only decodes a few of the address lines for its I/O registers, so each register is
+// mirrored throughout $0280-$02FF. With A2 clear, A1 and A0 select one of the port registers.
+// With A2 set, reads use A0 to select INTIM or INSTAT, and writes use A4 to select the timer
+// (with A1 and A0 selecting the interval) or the edge detect control. A3 enables the timer
+// interrupt, which isn't connected on the 2600, so it's ignored.
+fn register(address: u16, write: bool) -> u16 {
+    if address < 0x0280 {
+        return address;
+    }
+
+    let a4 = (address & 0b0001_0000) != 0;
+    let a2 = (address & 0b0000_0100) != 0;
+
+    match (a2, write, a4) {
+        (false, _, _)       => 0x0280 | (address & 0b11),
+        (true, false, _)    => 0x0284 | (address & 0b01),
+        (true, true, true)  => 0x0294 | (address & 0b11),
+        (true, true, false) => 0x0284 | (address & 0b11),
+    }
+}
+
 impl Bus for RIOT {
     fn read(&mut self, address: u16) -> u8 {
-        match address {
+        let val = self.peek(address);
+
+        match register(address, false) {
+            // Reading the timer clears the timer flag, unless it's only just underflowed
+            0x0284 if !self.underflowed => self.instat &= !TIMER_FLAG,
+
+            // Reading the flags only clears the PA7 flag
+            0x0285 => self.instat &= !PA7_FLAG,
+
+            _ => { },
+        }
+
+        val
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match register(address, false) {
             // RAM
             0x0000 ..= 0x007f => self.ram[address as usize],
 
             // SWCHA   11111111  Port A; input or output  (read or write)
-            0x0280 => {
-                // The bits of SWACNT set the data direction for the corresponding bits of SWCHA, 0
-                // being for input, and 1 for output.
-                // So all this faffing about is to enforce this.
-                // This is also the case for SWCHB/SWBCNT.
-                (self.swcha & self.swacnt) | (self.port_a & (self.swacnt ^ 0xff))
-            },
+            0x0280 => self.swcha_value(),
+
+            // SWACNT  11111111  Port A DDR, 0= input, 1=output
+            0x0281 => self.swacnt,
 
             // SWCHB   11111111  Port B; console switches (read only)
-            0x0282 => (self.swchb & self.swbcnt) | (self.port_b & (self.swbcnt ^ 0xff)),
+            0x0282 => self.swchb_value(),
+
+            // SWBCNT  11111111  Port B DDR (hardwired as input)
+            0x0283 => self.swbcnt,
 
             // INTIM   11111111  Timer output (read only)
             0x0284 => self.intim,
 
             // INSTAT  11......  Timer Status (read only, undocumented)
-            0x0285 => {
-                let rv = self.instat;
-                self.instat &= 0b1011_1111;
-                rv
-            },
+            0x0285 => self.instat,
 
             _ => 0,
         }
     }
 
     fn write(&mut self, address: u16, val: u8) {
+        let address = register(address, true);
+
         match address {
             // RAM
             0x0000 ..= 0x007f => { self.ram[address as usize] = val },
 
+            // SWCHA   11111111  Port A; input or output  (read or write)
+            // Only the output bits are driven, but the whole value is latched, so bits that are
+            // later switched to output start out with what was written
+            0x0280 => {
+                self.swcha = val;
+                self.detect_pa7_edge();
+                self.update_peripherals();
+            },
+
             // SWACNT  11111111  Port A DDR, 0= input, 1=output
-            0x0281 => { self.swacnt = val },
+            0x0281 => {
+                self.swacnt = val;
+                self.detect_pa7_edge();
+                self.update_peripherals();
+            },
+
+            // SWCHB   11111111  Port B; console switches (read only)
+            // Although the switches are read only, the output bits set through SWBCNT aren't
+            0x0282 => { self.swchb = val },
 
             // SWBCNT  11111111  Port B DDR (hardwired as input)
             0x0283 => { self.swbcnt = val },
 
+            // Edge detect control, with D0 of the address selecting a positive edge on PA7 rather
+            // than a negative one. D1 enables the PA7 interrupt, but the RIOT's IRQ line isn't
+            // connected on the 2600.
+            0x0284 ..= 0x0287 => { self.pa7_positive_edge = (address & 0x01) != 0 },
+
             // TIM1T   11111111  set 1 clock interval (838 nsec/interval)
             0x0294 => self.init_timer(val, 1),
 
@@ -198,4 +544,444 @@ impl Bus for RIOT {
             _ => { },
         }
     }
+
+    // The RAM and registers, then the timer, then how the RAM was filled at power on, so that
+    // power cycling after loading fills it the same way
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&self.ram)?;
+        output.write_all(&[self.swcha, self.swacnt, self.swchb, self.swbcnt, self.intim, self.instat])?;
+        output.write_all(&[self.port_a, self.port_b])?;
+
+        output.write_all(&(self.resolution as u64).to_le_bytes())?;
+        output.write_all(&(self.cycle_count as u64).to_le_bytes())?;
+        output.write_all(&[self.underflowed as u8, self.pa7 as u8, self.pa7_positive_edge as u8])?;
+
+        output.write_all(&[self.ram_pattern as u8])?;
+        output.write_all(&self.ram_seed.to_le_bytes())
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        input.read_exact(&mut self.ram)?;
+        let [swcha, swacnt, swchb, swbcnt, intim, instat] = read_bytes(input)?;
+        self.swcha = swcha;
+        self.swacnt = swacnt;
+        self.swchb = swchb;
+        self.swbcnt = swbcnt;
+        self.intim = intim;
+        self.instat = instat;
+        let [port_a, port_b] = read_bytes(input)?;
+        self.port_a = port_a;
+        self.port_b = port_b;
+
+        // A timer that isn't counting at one of the intervals would never be decremented
+        let resolution = u64::from_le_bytes(read_bytes(input)?) as usize;
+        let cycle_count = u64::from_le_bytes(read_bytes(input)?) as usize;
+        if ![1, 8, 64, 1024].contains(&resolution) || cycle_count == 0 || cycle_count > resolution {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the timer isn't at one of its intervals"));
+        }
+        self.resolution = resolution;
+        self.cycle_count = cycle_count;
+        let [underflowed, pa7, pa7_positive_edge] = read_bytes(input)?;
+        self.underflowed = underflowed != 0;
+        self.pa7 = pa7 != 0;
+        self.pa7_positive_edge = pa7_positive_edge != 0;
+
+        let [ram_pattern] = read_bytes(input)?;
+        self.ram_pattern = match ram_pattern {
+            0 => RamPattern::Zero,
+            1 => RamPattern::Ones,
+            2 => RamPattern::Alternating,
+            _ => RamPattern::Random,
+        };
+        self.ram_seed = u64::from_le_bytes(read_bytes(input)?);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::savekey::SaveKey;
+    use std::fs::File;
+    use crate::peripheral::{Direction, Joystick, Source};
+
+    #[test]
+    fn test_power_cycle() {
+        let mut riot = RIOT::new();
+        riot.write(0x0010, 0x42);
+        riot.write(0x0296, 0x10); // TIM64T
+        riot.color();
+
+        riot.power_cycle();
+
+        assert_eq!(riot.read(0x0010), 0x00);
+        assert_eq!(riot.read(0x0284), 0x00);
+
+        // The colour switch stays where it was
+        assert!(!riot.color_switch());
+    }
+
+    #[test]
+    fn test_ram_patterns() {
+        let mut riot = RIOT::new();
+        assert_eq!(riot.read(0x0000), 0x00);
+
+        riot.set_ram_pattern(RamPattern::Ones, 0);
+        assert!((0x00 .. 0x80).all(|a| riot.read(a) == 0xff));
+
+        riot.set_ram_pattern(RamPattern::Alternating, 0);
+        assert_eq!((riot.read(0x0010), riot.read(0x0011)), (0x00, 0xff));
+
+        // The same seed gives the same RAM, even after power cycling
+        riot.set_ram_pattern(RamPattern::Random, 1234);
+        let ram = (0x00 .. 0x80).map(|a| riot.read(a)).collect::<Vec<_>>();
+        assert!(ram.iter().any(|&b| b != ram[0]));
+
+        riot.write(0x0010, !ram[0x10]);
+        riot.power_cycle();
+        assert_eq!((0x00 .. 0x80).map(|a| riot.read(a)).collect::<Vec<_>>(), ram);
+
+        riot.set_ram_pattern(RamPattern::Random, 1235);
+        assert_ne!((0x00 .. 0x80).map(|a| riot.read(a)).collect::<Vec<_>>(), ram);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut riot = RIOT::new();
+        riot.set_ram_pattern(RamPattern::Random, 42);
+        riot.write(0x0010, 0x42);
+        riot.write(0x0281, 0xf0);
+        riot.write(0x0296, 0x10); // TIM64T
+        riot.clock();
+
+        let path = std::env::temp_dir().join(format!("atari2600-riot-state-{}", std::process::id()));
+        riot.save(&mut File::create(&path).unwrap()).unwrap();
+
+        let mut restored = RIOT::new();
+        restored.load(&mut File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.read(0x0010), 0x42);
+        assert_eq!(restored.read(0x0281), 0xf0);
+
+        for _ in 0 .. 64 {
+            riot.clock();
+            restored.clock();
+        }
+        assert_eq!(restored.read(0x0284), riot.read(0x0284));
+
+        // The seed comes along too, so power cycling gives the same RAM
+        riot.power_cycle();
+        restored.power_cycle();
+        assert!((0x00 .. 0x80).all(|a| restored.read(a) == riot.read(a)));
+
+        // A timer without an interval isn't loaded
+        let mut state = vec![];
+        riot.save(&mut state).unwrap();
+        let timer = 128 + 6 + 2;
+        state[timer .. timer + 8].copy_from_slice(&0u64.to_le_bytes());
+        assert_eq!(restored.load(&mut state.as_slice()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_switches() {
+        let mut riot = RIOT::new();
+        riot.select(false);
+        riot.reset(true);
+        riot.color();
+
+        assert_eq!(riot.switches(), Switches {
+            select: false,
+            reset: true,
+            color: false,
+            p0_difficulty_a: true,
+            p1_difficulty_a: true,
+        });
+        assert_eq!(riot.switches().to_string(),
+                   "select: released, reset: pressed, tv type: b/w, left difficulty: A, right difficulty: A");
+
+        let switches = Switches { select: true, reset: false, color: true, p0_difficulty_a: false, p1_difficulty_a: true };
+        riot.set_switches(switches);
+        assert_eq!(riot.switches(), switches);
+    }
+
+    #[test]
+    fn test_timer_underflow() {
+        let mut riot = RIOT::new();
+
+        // TIM64T, which decrements on the next clock
+        riot.write(0x0296, 0x01);
+        riot.clock();
+        assert_eq!(riot.read(0x0284), 0x00);
+
+        for _ in 0 .. 63 {
+            riot.clock();
+        }
+        assert_eq!(riot.read(0x0285), 0x00);
+
+        // Underflowing sets only the timer flag, which reading INTIM on the same cycle doesn't
+        // clear, and then the timer counts down once per clock
+        riot.clock();
+        assert_eq!(riot.read(0x0284), 0xff);
+        assert_eq!(riot.read(0x0285), TIMER_FLAG);
+
+        riot.clock();
+        assert_eq!(riot.read(0x0285), TIMER_FLAG);
+        assert_eq!(riot.read(0x0284), 0xfe);
+        assert_eq!(riot.read(0x0285), 0x00);
+
+        riot.clock();
+        assert_eq!(riot.read(0x0284), 0xfd);
+    }
+
+    #[test]
+    fn test_pa7_edge_detect() {
+        let mut riot = RIOT::new();
+
+        // Negative edge, by default
+        riot.paddle_fire(0, true);
+        assert_eq!(riot.read(0x0285), PA7_FLAG);
+        assert_eq!(riot.read(0x0285), 0x00);
+
+        riot.paddle_fire(0, false);
+        assert_eq!(riot.read(0x0285), 0x00);
+
+        // Positive edge
+        riot.write(0x0285, 0x00);
+        riot.paddle_fire(0, true);
+        assert_eq!(riot.read(0x0285), 0x00);
+
+        riot.paddle_fire(0, false);
+        assert_eq!(riot.read(0x0285), PA7_FLAG);
+
+        // Reading INTIM leaves it alone
+        riot.paddle_fire(0, true);
+        riot.paddle_fire(0, false);
+        riot.read(0x0284);
+        assert_eq!(riot.read(0x0285), PA7_FLAG);
+    }
+
+    #[test]
+    fn test_port_b_output_bits() {
+        let mut riot = RIOT::new();
+        let switches = riot.read(0x0282);
+
+        // Writes don't change the input bits
+        riot.write(0x0282, 0x00);
+        assert_eq!(riot.read(0x0282), switches);
+
+        // But with the top three bits set to output, those read back what was written
+        riot.write(0x0283, 0b1110_0000);
+        assert_eq!(riot.read(0x0283), 0b1110_0000);
+        assert_eq!(riot.read(0x0282), switches & 0b0001_1111);
+
+        riot.write(0x0282, 0xff);
+        assert_eq!(riot.read(0x0282), switches | 0b1110_0000);
+
+        // The same as port A
+        riot.write(0x0280, 0x00);
+        riot.write(0x0281, 0b1000_0000);
+        assert_eq!(riot.read(0x0280) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_port_a_output() {
+        let mut riot = RIOT::new();
+
+        // Everything's an input after power on
+        assert_eq!(riot.port_a_output_mask(), 0x00);
+        assert_eq!(riot.port_a_output(), 0xff);
+
+        // Driving the player 1 port's row select, like a keyboard controller game would
+        riot.write(0x0281, 0b0000_1111);
+        riot.write(0x0280, 0b1010_1011);
+        assert_eq!(riot.port_a_output_mask(), 0b0000_1111);
+        assert_eq!(riot.port_a_output(), 0b1111_1011);
+    }
+
+    #[test]
+    fn test_register_names() {
+        assert_eq!(register_address("swcha"), Some(0x0280));
+        assert_eq!(register_address("T1024T"), Some(0x0297));
+        assert_eq!(register_address("INTIM"), None);
+    }
+
+    #[test]
+    fn test_register_mirrors() {
+        let mut riot = RIOT::new();
+
+        // SWBCNT, at $0283 and $02AB
+        riot.write(0x02ab, 0xf0);
+        assert_eq!(riot.read(0x0283), 0xf0);
+        assert_eq!(riot.read(0x02fb), 0xf0);
+
+        // TIM64T, at $0296 and $02BE (with A3 set for the interrupt enable)
+        riot.write(0x02be, 0x10);
+        riot.clock();
+
+        // INTIM, at $0284 and $028E
+        assert_eq!(riot.read(0x028e), 0x0f);
+        assert_eq!(riot.read(0x02a4), 0x0f);
+
+        // INSTAT, at $0285 and $02A7
+        riot.paddle_fire(0, true);
+        assert_eq!(riot.read(0x02a7), PA7_FLAG);
+
+        // Writing with A4 clear sets the edge detect control, not the timer
+        riot.write(0x0287, 0x00);
+        assert_eq!(riot.read(0x0284), 0x0f);
+        riot.paddle_fire(0, false);
+        assert_eq!(riot.read(0x0285), PA7_FLAG);
+    }
+
+    #[test]
+    fn test_joysticks() {
+        let mut riot = RIOT::new();
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(1))), Rc::new(RefCell::new(Joystick::new(1)))];
+        riot.plug_in(0, joysticks[0].clone());
+        riot.plug_in(1, joysticks[1].clone());
+        assert_eq!(riot.read(0x0280), 0xff);
+
+        joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Left, true);
+        joysticks[1].borrow_mut().set_direction(Source::Keyboard, Direction::Up, true);
+        assert_eq!(riot.read(0x0280), 0b1011_1110);
+
+        // Pushing right on the left joystick sets off the PA7 edge detection when the RIOT's next
+        // clocked
+        riot.write(0x0296, 0x10); // TIM64T
+        joysticks[0].borrow_mut().set_direction(Source::Keyboard, Direction::Right, true);
+        riot.clock();
+        assert_eq!(riot.read(0x0280), 0b0011_1110);
+        assert_eq!(riot.read(0x0285) & PA7_FLAG, PA7_FLAG);
+
+        // Output bits aren't affected by them
+        riot.write(0x0281, 0b1000_0000);
+        riot.write(0x0280, 0b1000_0000);
+        assert_eq!(riot.read(0x0280), 0b1011_1110);
+    }
+
+    #[test]
+    fn test_peripheral_writes() {
+        struct Listener(Rc<RefCell<Vec<u8>>>);
+
+        impl Peripheral for Listener {
+            fn pins(&self) -> u8 { 0x0f }
+            fn write(&mut self, pins: u8) { self.0.borrow_mut().push(pins) }
+        }
+
+        let writes = Rc::new(RefCell::new(vec![]));
+        let mut riot = RIOT::new();
+        riot.plug_in(1, Rc::new(RefCell::new(Listener(writes.clone()))));
+
+        // Only the right port's output pins are driven
+        riot.write(0x0280, 0b0000_0101);
+        riot.write(0x0281, 0b1000_0011);
+        assert_eq!(*writes.borrow(), vec![0b1111, 0b1111, 0b1101]);
+    }
+
+    #[test]
+    fn test_swap_ports() {
+        let mut riot = RIOT::new();
+        let joystick = Rc::new(RefCell::new(Joystick::new(1)));
+        riot.plug_in(0, joystick.clone());
+        riot.paddle_fire(0, true);
+
+        // Everything moves over to the right port
+        riot.set_swap_ports(true);
+        joystick.borrow_mut().set_direction(Source::Keyboard, Direction::Up, true);
+        assert_eq!(riot.read(0x0280), 0b1111_0110);
+
+        riot.set_pins(PlayerType::Player0, 0b1010);
+        riot.paddle_fire(0, false);
+        assert_eq!(riot.read(0x0280), 0b1111_1010);
+    }
+
+    #[test]
+    fn test_paddle_fire() {
+        let mut riot = RIOT::new();
+        riot.paddle_fire(1, true);
+        riot.paddle_fire(3, true);
+        assert_eq!(riot.read(0x0280), 0b1011_1011);
+
+        riot.paddle_fire(0, true);
+        riot.paddle_fire(2, true);
+        riot.paddle_fire(3, false);
+        assert_eq!(riot.read(0x0280), 0b0011_0111);
+    }
+
+    #[test]
+    fn test_savekey() {
+        // With the ports swapped, the SaveKey's on the left port's pins instead, D6 and D7
+        for &(swap, shift) in [(false, 0), (true, 4)].iter() {
+            let path = std::env::temp_dir().join(format!("atari2600-riot-savekey-{}", std::process::id()));
+            let mut riot = RIOT::new();
+            riot.plug_in(1, Rc::new(RefCell::new(SaveKey::open(path).unwrap())));
+            riot.set_swap_ports(swap);
+
+            // Pulls the clock and data lines low by setting them as outputs, or lets go of them
+            let lines = |riot: &mut RIOT, scl: bool, sda: bool| {
+                riot.write(0x0280, 0x00);
+                riot.write(0x0281, ((!scl as u8) << 3 | (!sda as u8) << 2) << shift);
+            };
+
+            // Start, then the control byte for a write
+            lines(&mut riot, true, false);
+            lines(&mut riot, false, false);
+            for bit in (0 .. 8).rev() {
+                let sda = (0xa0 >> bit) & 1 != 0;
+                lines(&mut riot, false, sda);
+                lines(&mut riot, true, sda);
+                lines(&mut riot, false, sda);
+            }
+
+            // The SaveKey acknowledges it by holding the data line low for a clock
+            lines(&mut riot, true, true);
+            assert_eq!(riot.read(0x0280) & 0b0000_0100 << shift, 0x00);
+            lines(&mut riot, false, true);
+            assert_eq!(riot.read(0x0280) & 0b0000_0100 << shift, 0b0000_0100 << shift);
+        }
+    }
+
+    #[test]
+    fn test_setting_timer_clears_flag() {
+        let mut riot = RIOT::new();
+
+        // TIM1T
+        riot.write(0x0294, 0x00);
+        riot.clock();
+        assert_eq!(riot.read(0x0285), TIMER_FLAG);
+
+        riot.write(0x0294, 0x10);
+        assert_eq!(riot.read(0x0285), 0x00);
+        assert_eq!(riot.read(0x0284), 0x10);
+    }
+
+    #[test]
+    fn test_timer_write_timing() {
+        let mut riot = RIOT::new();
+
+        // Part of the way through an interval
+        riot.write(0x0296, 0x10); // TIM64T
+        for _ in 0 .. 10 {
+            riot.clock();
+        }
+        assert_eq!(riot.read(0x0284), 0x0f);
+
+        // TIM8T loads the value as written, decrements it on the next clock, and then every 8
+        // clocks after that, no matter where the old interval was up to
+        riot.write(0x0295, 0x03);
+        assert_eq!(riot.read(0x0284), 0x03);
+
+        riot.clock();
+        assert_eq!(riot.read(0x0284), 0x02);
+
+        for _ in 0 .. 7 {
+            riot.clock();
+        }
+        assert_eq!(riot.read(0x0284), 0x02);
+
+        riot.clock();
+        assert_eq!(riot.read(0x0284), 0x01);
+    }
 }
@@ -0,0 +1,197 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bus::Bus;
+use crate::cpu6507::CPU6507;
+
+// Each state starts with this, then the version of its layout, then the MD5 of the ROM it was saved
+// from
+const MAGIC: &[u8] = b"atari2600 state\n";
+
+// Bumped whenever anything that's saved changes, so that older states are refused rather than
+// misread
+const VERSION: u8 = 1;
+
+// The save state slots, kept in a directory as a file for each slot of each game. The files are
+// named after the ROM's MD5, e.g. `<md5>.3.state`, and the MD5 is checked again when a state's
+// loaded, so that a game never gets another game's state.
+pub struct SaveStates {
+    directory: PathBuf,
+    rom_md5: String,
+}
+
+impl SaveStates {
+    pub fn new(directory: PathBuf, rom_md5: &str) -> Self {
+        Self {
+            directory: directory,
+            rom_md5: rom_md5.to_string(),
+        }
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf { self.file(&slot.to_string()) }
+
+    // The state that's saved on exit, and resumed from on the next launch
+    pub fn autosave_path(&self) -> PathBuf { self.file("auto") }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}.state", self.rom_md5, name))
+    }
+
+    fn header(&self) -> Vec<u8> {
+        [MAGIC, &[VERSION], self.rom_md5.as_bytes()].concat()
+    }
+
+    pub fn save<B: Bus>(&self, slot: usize, cpu: &CPU6507<B>) -> io::Result<()> {
+        self.save_to(&self.path(slot), cpu)
+    }
+
+    pub fn load<B: Bus>(&self, slot: usize, cpu: &mut CPU6507<B>) -> io::Result<()> {
+        self.load_from(&self.path(slot), cpu)
+    }
+
+    pub fn autosave<B: Bus>(&self, cpu: &CPU6507<B>) -> io::Result<()> {
+        self.save_to(&self.autosave_path(), cpu)
+    }
+
+    // Loads the state saved on exit, returning whether there was one
+    pub fn resume<B: Bus>(&self, cpu: &mut CPU6507<B>) -> io::Result<bool> {
+        let path = self.autosave_path();
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        self.load_from(&path, cpu).map(|_| true)
+    }
+
+    // The CPU's save of itself and everything on the bus
+    fn save_to<B: Bus>(&self, path: &Path, cpu: &CPU6507<B>) -> io::Result<()> {
+        let mut state = self.header();
+        cpu.save(&mut state)?;
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(path, state)
+    }
+
+    // A state that can't be loaded leaves the machine as it was
+    fn load_from<B: Bus>(&self, path: &Path, cpu: &mut CPU6507<B>) -> io::Result<()> {
+        let state = fs::read(path)?;
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidData, message));
+
+        if !state.starts_with(MAGIC) {
+            return invalid("it isn't a save state".to_string());
+        }
+
+        match state.get(MAGIC.len()) {
+            Some(&VERSION) => {},
+            Some(version)  => return invalid(format!("it's a version {} save state, not version {}", version, VERSION)),
+            None           => return invalid("it's been cut short".to_string()),
+        }
+
+        let header = self.header();
+        if !state.starts_with(&header) {
+            return invalid("it isn't a save state for this game".to_string());
+        }
+
+        let mut current = vec![];
+        cpu.save(&mut current)?;
+
+        if let Err(e) = cpu.load(&mut &state[header.len() ..]) {
+            cpu.load(&mut current.as_slice())?;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bus::AtariBus;
+    use crate::riot::RIOT;
+    use crate::tia::TIA;
+
+    #[test]
+    fn test_slots() {
+        let directory = std::env::temp_dir().join(format!("atari2600-states-{}", std::process::id()));
+        let states = SaveStates::new(directory.clone(), "0123456789abcdef0123456789abcdef");
+        assert_eq!(states.path(3), directory.join("0123456789abcdef0123456789abcdef.3.state"));
+
+        // LDA #$42; STA $80; LDA #$00; STA $80
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x008].copy_from_slice(&[0xa9, 0x42, 0x85, 0x80, 0xa9, 0x00, 0x85, 0x80]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let mut cpu = CPU6507::new(AtariBus::new(tia, riot, rom));
+        cpu.reset();
+        cpu.step();
+        cpu.step();
+
+        states.save(3, &cpu).unwrap();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.peek(0x80), 0x00);
+
+        states.load(3, &mut cpu).unwrap();
+        assert_eq!((cpu.pc, cpu.a, cpu.peek(0x80)), (0xf004, 0x42, 0x42));
+
+        // Another game's slot 3 is a different file, and a state from another game isn't loaded
+        let other = SaveStates::new(directory.clone(), "fedcba9876543210fedcba9876543210");
+        assert!(other.load(3, &mut cpu).is_err());
+        fs::copy(states.path(3), other.path(3)).unwrap();
+        assert!(other.load(3, &mut cpu).is_err());
+
+        // Nor is one from another version
+        let state = fs::read(states.path(3)).unwrap();
+        let mut old = state.clone();
+        old[MAGIC.len()] = VERSION - 1;
+        fs::write(other.path(3), old).unwrap();
+        assert!(states.load_from(&other.path(3), &mut cpu).unwrap_err().to_string().contains("version 0"));
+
+        // Nor is one that's been cut short, which leaves the machine as it was
+        fs::write(states.path(3), &state[.. state.len() - 10]).unwrap();
+        cpu.step();
+        assert!(states.load(3, &mut cpu).is_err());
+        assert_eq!((cpu.pc, cpu.a), (0xf006, 0x00));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_autosave() {
+        let directory = std::env::temp_dir().join(format!("atari2600-autosave-{}", std::process::id()));
+        let states = SaveStates::new(directory.clone(), "0123456789abcdef0123456789abcdef");
+
+        // LDX #$07; INX
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x003].copy_from_slice(&[0xa2, 0x07, 0xe8]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let mut cpu = CPU6507::new(AtariBus::new(tia, riot, rom));
+        cpu.reset();
+
+        // There's nothing to resume from the first time
+        assert!(!states.resume(&mut cpu).unwrap());
+
+        cpu.step();
+        states.autosave(&cpu).unwrap();
+        assert!(states.autosave_path().exists());
+        cpu.reset();
+
+        assert!(states.resume(&mut cpu).unwrap());
+        assert_eq!((cpu.pc, cpu.x), (0xf002, 0x07));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
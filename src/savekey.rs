@@ -0,0 +1,332 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::peripheral::Peripheral;
+
+// The SaveKey (and the storage half of the AtariVox) is a 32KB 24LC256 EEPROM, plugged into the
+// right controller port, that games talk to over I2C by bit-banging port A. The game is the bus
+// master, driving the clock (SCL) and data (SDA) lines, and the EEPROM drives SDA low to
+// acknowledge each byte it receives, and to send the bits of the bytes that are read.
+
+const EEPROM_SIZE: usize = 0x8000;
+
+// Bytes are written a page at a time, wrapping around within the page
+const PAGE_SIZE: u16 = 64;
+
+// The control byte that selects the EEPROM is 1010xxxR, where R is set for reading
+const CONTROL_MASK: u8 = 0xf0;
+const CONTROL_CODE: u8 = 0xa0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    // Waiting for a start condition
+    Idle,
+    // Receiving the control byte, the address, and data bytes to write
+    Receiving,
+    // Sending data bytes to the game
+    Sending,
+}
+
+pub struct SaveKey {
+    eeprom: Vec<u8>,
+
+    // Where the EEPROM's contents are kept between runs, and whether they've changed since they
+    // were last saved
+    path: PathBuf,
+    dirty: bool,
+
+    // The levels the game's driving on the clock and data lines
+    scl: bool,
+    sda: bool,
+
+    // Whether the EEPROM's releasing the data line (high), or pulling it low
+    output: bool,
+
+    state: State,
+    reading: bool,
+    address: u16,
+
+    // The byte being received or sent, how many of its bits have been clocked (with 9 being the
+    // acknowledge), how many bytes have been received since the start condition, and whether the
+    // game acknowledged the last byte sent to it
+    byte: u8,
+    bit_count: u8,
+    bytes_received: usize,
+    acked: bool,
+}
+
+impl SaveKey {
+    // Opens the EEPROM image at `path`, or starts with an erased EEPROM (all 0xFF) if there isn't
+    // one yet
+    pub fn open(path: PathBuf) -> io::Result<Self> {
+        let mut eeprom = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        eeprom.resize(EEPROM_SIZE, 0xff);
+
+        Ok(Self {
+            eeprom: eeprom,
+            path: path,
+            dirty: false,
+
+            scl: true,
+            sda: true,
+            output: true,
+
+            state: State::Idle,
+            reading: false,
+            address: 0,
+
+            byte: 0,
+            bit_count: 0,
+            bytes_received: 0,
+            acked: false,
+        })
+    }
+
+    // Called with the levels the game's driving the clock and data lines at, whenever they might
+    // have changed
+    pub fn update(&mut self, scl: bool, sda: bool) {
+        let (last_scl, last_sda) = (self.scl, self.sda);
+        self.scl = scl;
+        self.sda = sda;
+
+        match (last_scl, scl) {
+            // The data line only changes while the clock's high for a start or stop condition
+            (true, true) if last_sda && !sda => self.start(),
+            (true, true) if !last_sda && sda => self.stop(),
+            (false, true) => self.clock_rising(),
+            (true, false) => self.clock_falling(),
+            _ => { },
+        }
+    }
+
+    fn start(&mut self) {
+        self.state = State::Receiving;
+        self.output = true;
+        self.byte = 0;
+        self.bit_count = 0;
+        self.bytes_received = 0;
+    }
+
+    fn stop(&mut self) {
+        self.state = State::Idle;
+        self.output = true;
+
+        if self.dirty {
+            if let Err(e) = fs::write(&self.path, &self.eeprom) {
+                error!("SaveKey: unable to save to {}: {}", self.path.display(), e);
+            }
+
+            self.dirty = false;
+        }
+    }
+
+    // Bits are read while the clock's high
+    fn clock_rising(&mut self) {
+        match self.state {
+            State::Receiving if self.bit_count < 8 => {
+                self.byte = (self.byte << 1) | self.sda as u8;
+                self.bit_count += 1;
+            },
+            State::Sending if self.bit_count == 9 => self.acked = !self.sda,
+            _ => { },
+        }
+    }
+
+    // Bits are changed while the clock's low
+    fn clock_falling(&mut self) {
+        match (self.state, self.bit_count) {
+            (State::Receiving, 8) => {
+                self.bit_count = 9;
+
+                if self.receive(self.byte) {
+                    self.output = false;
+                } else {
+                    self.state = State::Idle;
+                }
+            },
+            (State::Receiving, 9) => {
+                self.output = true;
+                self.bit_count = 0;
+
+                if self.reading {
+                    self.state = State::Sending;
+                    self.send_byte();
+                }
+            },
+            (State::Sending, 8) => {
+                // Let go of the data line for the game to acknowledge the byte
+                self.output = true;
+                self.bit_count = 9;
+            },
+            (State::Sending, 9) => {
+                if self.acked {
+                    self.address = (self.address + 1) % EEPROM_SIZE as u16;
+                    self.send_byte();
+                } else {
+                    self.state = State::Idle;
+                }
+            },
+            (State::Sending, _) => self.send_bit(),
+            _ => { },
+        }
+    }
+
+    // Handles a byte from the game, returning whether it's acknowledged
+    fn receive(&mut self, byte: u8) -> bool {
+        let index = self.bytes_received;
+        self.bytes_received += 1;
+
+        if index == 0 {
+            self.reading = (byte & 0x01) != 0;
+            return (byte & CONTROL_MASK) == CONTROL_CODE;
+        }
+
+        match index {
+            1 => self.address = ((byte as u16) << 8 | (self.address & 0x00ff)) % EEPROM_SIZE as u16,
+            2 => self.address = (self.address & 0xff00) | byte as u16,
+            _ => {
+                self.eeprom[self.address as usize] = byte;
+                self.dirty = true;
+
+                let page = self.address & !(PAGE_SIZE - 1);
+                self.address = page | ((self.address + 1) & (PAGE_SIZE - 1));
+            },
+        }
+
+        true
+    }
+
+    fn send_byte(&mut self) {
+        self.byte = self.eeprom[self.address as usize];
+        self.bit_count = 0;
+        self.send_bit();
+    }
+
+    fn send_bit(&mut self) {
+        self.output = (self.byte & (0x80 >> self.bit_count)) != 0;
+        self.bit_count += 1;
+    }
+}
+
+// The clock and data lines are on pins 4 and 3, and are pulled high, so the game drives a line low
+// by setting its bit as an output with a 0 in SWCHA, and lets go of it by setting it as an input.
+// Either end can pull the data line low.
+impl Peripheral for SaveKey {
+    fn pins(&self) -> u8 { 0b1011 | (self.output as u8) << 2 }
+
+    fn write(&mut self, pins: u8) {
+        self.update((pins & 0b1000) != 0, (pins & 0b0100) != 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bit-bangs I2C the way a game does
+    struct Master<'a>(&'a mut SaveKey);
+
+    impl<'a> Master<'a> {
+        fn start(&mut self) {
+            self.0.update(true, true);
+            self.0.update(true, false);
+            self.0.update(false, false);
+        }
+
+        fn stop(&mut self) {
+            self.0.update(false, false);
+            self.0.update(true, false);
+            self.0.update(true, true);
+        }
+
+        fn clock(&mut self, sda: bool) -> bool {
+            self.0.update(false, sda);
+            self.0.update(true, sda);
+            let bit = (self.0.pins() & 0b0100) != 0;
+            self.0.update(false, sda);
+            bit
+        }
+
+        // Returns whether the byte was acknowledged
+        fn write(&mut self, byte: u8) -> bool {
+            for bit in (0 .. 8).rev() {
+                self.clock((byte >> bit) & 1 != 0);
+            }
+
+            !self.clock(true)
+        }
+
+        fn read(&mut self, ack: bool) -> u8 {
+            let byte = (0 .. 8).fold(0, |byte, _| byte << 1 | self.clock(true) as u8);
+            self.clock(!ack);
+            byte
+        }
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("atari2600-savekey-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_write_and_read() {
+        let path = path("write-and-read");
+        let mut savekey = SaveKey::open(path.clone()).unwrap();
+        let mut master = Master(&mut savekey);
+
+        // Write two bytes at $3000
+        master.start();
+        assert!(master.write(0xa0));
+        assert!(master.write(0x30));
+        assert!(master.write(0x00));
+        assert!(master.write(0x12));
+        assert!(master.write(0x34));
+        master.stop();
+
+        // Set the address back to $3000 and read them back, then the erased byte after them
+        master.start();
+        assert!(master.write(0xa0));
+        assert!(master.write(0x30));
+        assert!(master.write(0x00));
+        master.start();
+        assert!(master.write(0xa1));
+        assert_eq!(master.read(true), 0x12);
+        assert_eq!(master.read(true), 0x34);
+        assert_eq!(master.read(false), 0xff);
+        master.stop();
+
+        // Other devices on the bus aren't acknowledged
+        master.start();
+        assert!(!master.write(0x50));
+        master.stop();
+
+        // The EEPROM's saved to disk when the write finishes
+        let reopened = SaveKey::open(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&reopened.eeprom[0x3000 .. 0x3003], &[0x12, 0x34, 0xff]);
+    }
+
+    #[test]
+    fn test_page_write_wraps() {
+        let path = path("page-write-wraps");
+        let mut savekey = SaveKey::open(path.clone()).unwrap();
+        let mut master = Master(&mut savekey);
+
+        master.start();
+        master.write(0xa0);
+        master.write(0x00);
+        master.write(0x3f);
+        master.write(0x01);
+        master.write(0x02);
+        master.stop();
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(savekey.eeprom[0x3f], 0x01);
+        assert_eq!(savekey.eeprom[0x00], 0x02);
+        assert_eq!(savekey.eeprom[0x40], 0xff);
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST};
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::bus::{AtariBus, Bus};
+use crate::overlay::draw_text;
+use crate::peripheral::{Direction, Joystick, Source};
+use crate::riot::RIOT;
+use crate::tia::TIA;
+
+// The functions a script can define, which are called as the game runs
+const HOOKS: [&str; 4] = ["on_frame", "on_instruction", "on_read", "on_write"];
+
+const TEXT_COLOR: Color = Color { r: 255, g: 255, b: 255, a: 255 };
+
+// Text a script's drawn on top of the picture, at a pixel and line of the display
+struct Text {
+    x: i32,
+    y: i32,
+    text: String,
+}
+
+// A Rhai script (https://rhai.rs) that's run alongside the game, for automating it, checking on it,
+// and annotating it. It can define any of these functions, which are called as the game runs:
+//
+//   on_frame()                       at the end of each frame
+//   on_instruction(pc, a, x, y)      before each instruction, with its address and the registers
+//   on_read(address, value)          after each read the CPU makes
+//   on_write(address, value)         after each write the CPU makes
+//
+// and call these:
+//
+//   peek(address)                    reads memory, without any side effects
+//   poke(address, value)             writes to RAM or a TIA or RIOT register
+//   joystick(player, direction, pressed)
+//                                    pushes a joystick "up", "down", "left", or "right"
+//   fire(player, pressed)            presses a joystick's fire button
+//   switch(name, pressed)            presses the "reset" or "select" console switch
+//   text(x, y, text)                 draws text on the picture until the next frame
+//
+// Functions in Rhai can't see the script's variables, so each of them is called with an object
+// map as `this`, for keeping anything they need between calls, e.g.
+// `this.frames = (this.frames ?? 0) + 1`. A function that fails is logged, and isn't called again.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+
+    // The hooks the script's defined, and hasn't failed in
+    hooks: Vec<&'static str>,
+
+    text: Rc<RefCell<Vec<Text>>>,
+}
+
+impl Script {
+    pub fn load(path: &str, tia: Rc<RefCell<TIA>>, riot: Rc<RefCell<RIOT>>, rom: Vec<u8>,
+                joysticks: &[Rc<RefCell<Joystick>>; 2]) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::new(&source, tia, riot, rom, joysticks)
+    }
+
+    // Compiles the script and runs it, which is when it can set anything up that isn't kept
+    // between calls, e.g. constants
+    pub fn new(source: &str, tia: Rc<RefCell<TIA>>, riot: Rc<RefCell<RIOT>>, rom: Vec<u8>,
+               joysticks: &[Rc<RefCell<Joystick>>; 2]) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        let text = Rc::new(RefCell::new(vec![]));
+
+        // Memory's read and written through a bus of its own, so that the CPU's hooks aren't
+        // called for the script's reads and writes
+        let bus = Rc::new(RefCell::new(AtariBus::new(tia, riot.clone(), rom)));
+
+        let peek_bus = bus.clone();
+        engine.register_fn("peek", move |address: i64| peek_bus.borrow().peek(address as u16) as i64);
+
+        // The cartridge is ROM, so it's left alone
+        engine.register_fn("poke", move |address: i64, value: i64| {
+            if address & 0x1000 == 0 {
+                bus.borrow_mut().write(address as u16, value as u8);
+            }
+        });
+
+        let directions = joysticks.clone();
+        engine.register_fn("joystick", move |player: i64, direction: &str, pressed: bool| -> Result<(), Box<EvalAltResult>> {
+            let direction = match direction {
+                "up"    => Direction::Up,
+                "down"  => Direction::Down,
+                "left"  => Direction::Left,
+                "right" => Direction::Right,
+                _       => return Err(format!("unknown direction {}", direction).into()),
+            };
+
+            player_joystick(&directions, player)?.borrow_mut().set_direction(Source::Script, direction, pressed);
+            Ok(())
+        });
+
+        let buttons = joysticks.clone();
+        engine.register_fn("fire", move |player: i64, pressed: bool| -> Result<(), Box<EvalAltResult>> {
+            player_joystick(&buttons, player)?.borrow_mut().set_fire(Source::Script, pressed);
+            Ok(())
+        });
+
+        engine.register_fn("switch", move |name: &str, pressed: bool| -> Result<(), Box<EvalAltResult>> {
+            match name {
+                "reset"  => riot.borrow_mut().reset(pressed),
+                "select" => riot.borrow_mut().select(pressed),
+                _        => return Err(format!("unknown switch {}", name).into()),
+            }
+
+            Ok(())
+        });
+
+        let drawn = text.clone();
+        engine.register_fn("text", move |x: i64, y: i64, text: &str| {
+            drawn.borrow_mut().push(Text { x: x as i32, y: y as i32, text: text.to_string() });
+        });
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| e.to_string())?;
+
+        let hooks = HOOKS.iter()
+            .filter(|&&hook| ast.iter_functions().any(|f| f.name == hook))
+            .cloned()
+            .collect();
+
+        Ok(Self {
+            engine: engine,
+            ast: ast,
+            scope: scope,
+            state: Dynamic::from_map(Map::new()),
+            hooks: hooks,
+            text: text,
+        })
+    }
+
+    pub fn has_hook(&self, hook: &str) -> bool { self.hooks.contains(&hook) }
+
+    fn call(&mut self, hook: &'static str, args: impl FuncArgs) {
+        if !self.has_hook(hook) {
+            return;
+        }
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+        if let Err(e) = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, hook, args) {
+            error!("Script: {} failed, and won't be called again: {}", hook, e);
+            self.hooks.retain(|&h| h != hook);
+        }
+    }
+
+    // The text from the last frame is cleared first, so that it's only there while it's redrawn
+    pub fn on_frame(&mut self) {
+        self.text.borrow_mut().clear();
+        self.call("on_frame", ());
+    }
+
+    pub fn on_instruction(&mut self, pc: u16, a: u8, x: u8, y: u8) {
+        self.call("on_instruction", (pc as i64, a as i64, x as i64, y as i64));
+    }
+
+    pub fn on_read(&mut self, address: u16, value: u8) {
+        self.call("on_read", (address as i64, value as i64));
+    }
+
+    pub fn on_write(&mut self, address: u16, value: u8) {
+        self.call("on_write", (address as i64, value as i64));
+    }
+
+    // Draws the script's text on a picture drawn with each pixel 5 by 3 on the screen
+    pub fn draw(&self, canvas: &mut Canvas<Window>) {
+        for text in self.text.borrow().iter() {
+            draw_text(canvas, text.x * 5, text.y * 3, 2, &text.text, TEXT_COLOR);
+        }
+    }
+}
+
+fn player_joystick(joysticks: &[Rc<RefCell<Joystick>>; 2], player: i64) -> Result<&Rc<RefCell<Joystick>>, Box<EvalAltResult>> {
+    joysticks.get(player as usize).ok_or_else(|| format!("there's no joystick {}", player).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::peripheral::Peripheral;
+
+    fn script(source: &str) -> (Script, AtariBus, [Rc<RefCell<Joystick>>; 2]) {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(3))), Rc::new(RefCell::new(Joystick::new(3)))];
+
+        let script = Script::new(source, tia.clone(), riot.clone(), vec![0; 4096], &joysticks).unwrap();
+        (script, AtariBus::new(tia, riot, vec![0; 4096]), joysticks)
+    }
+
+    #[test]
+    fn test_hooks() {
+        let (mut script, bus, joysticks) = script(r#"
+            fn on_frame() {
+                this.frames = (this.frames ?? 0) + 1;
+                poke(0x80, this.frames);
+                joystick(0, "up", this.frames == 1);
+                text(10, 20, `FRAME ${this.frames}`);
+            }
+
+            fn on_write(address, value) {
+                poke(0x81, peek(0x81) + value);
+            }
+        "#);
+
+        assert!(script.has_hook("on_frame"));
+        assert!(!script.has_hook("on_instruction"));
+
+        script.on_frame();
+        assert_eq!(bus.peek(0x80), 1);
+        assert_eq!(joysticks[0].borrow().pins(), 0b1110);
+
+        script.on_frame();
+        assert_eq!(bus.peek(0x80), 2);
+        assert_eq!(joysticks[0].borrow().pins(), 0b1111);
+        assert_eq!(script.text.borrow().len(), 1);
+        assert_eq!(script.text.borrow()[0].text, "FRAME 2");
+
+        script.on_write(0xf000, 3);
+        script.on_write(0xf000, 4);
+        assert_eq!(bus.peek(0x81), 7);
+    }
+
+    #[test]
+    fn test_failed_hook() {
+        let (mut script, _, _) = script(r#"fn on_frame() { joystick(2, "up", true); }"#);
+
+        script.on_frame();
+        assert!(!script.has_hook("on_frame"));
+
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(3))), Rc::new(RefCell::new(Joystick::new(3)))];
+        let script = Script::new("fn on_frame( {", Rc::new(RefCell::new(TIA::new())), Rc::new(RefCell::new(RIOT::new())),
+                                 vec![0; 4096], &joysticks);
+        assert!(script.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use crate::region::Region;
+use crate::tia::sample_rate;
+
+// The SDL end of the audio: takes the samples generated by the TIA and queues them up for the
+// audio device to play.
+pub struct Sound {
+    audio_subsystem: AudioSubsystem,
+    queue: AudioQueue<i16>,
+    sample_rate: i32,
+    paused: bool,
+
+    // The last sample that was queued, so that we can fade out from it
+    last_sample: i16,
+}
+
+impl Sound {
+    pub fn new(audio_subsystem: &AudioSubsystem, region: Region) -> Self {
+        let sample_rate = sample_rate(region);
+
+        Self {
+            audio_subsystem: audio_subsystem.clone(),
+            queue: open_queue(audio_subsystem, sample_rate),
+            sample_rate: sample_rate,
+            paused: false,
+            last_sample: 0,
+        }
+    }
+
+    // The TIA generates samples at a different rate for each region, so the queue's opened again
+    // at the new rate when the region's detected
+    pub fn set_region(&mut self, region: Region) {
+        let sample_rate = sample_rate(region);
+        if sample_rate == self.sample_rate { return }
+
+        self.queue = open_queue(&self.audio_subsystem, sample_rate);
+        self.sample_rate = sample_rate;
+    }
+
+    // Don't let more than this many samples build up in the queue, otherwise the sound drifts
+    // further and further behind the picture.
+    fn max_queued_samples(&self) -> u32 { self.sample_rate as u32 / 10 }
+
+    // How many samples to take to fade out to silence when pausing (about 10ms)
+    fn fade_samples(&self) -> i32 { self.sample_rate / 100 }
+
+    pub fn play(&mut self, samples: &[i16]) {
+        // Anything generated while paused (e.g. when stepping frames in the debugger) is dropped
+        if self.paused { return }
+
+        // The queue size is in bytes
+        if self.queue.size() / 2 > self.max_queued_samples() { return }
+
+        if let Some(&sample) = samples.last() {
+            self.last_sample = sample;
+        }
+
+        self.queue.queue(samples);
+    }
+
+    // Stop playing whatever is queued, and ramp down from the last sample to silence rather than
+    // cutting off abruptly, which would otherwise be heard as a click.
+    pub fn pause(&mut self) {
+        if self.paused { return }
+        self.paused = true;
+
+        self.queue.clear();
+
+        let fade_samples = self.fade_samples();
+        let fade = (0 .. fade_samples)
+            .map(|i| (self.last_sample as i32 * (fade_samples - i) / fade_samples) as i16)
+            .collect::<Vec<_>>();
+
+        self.queue.queue(&fade);
+        self.last_sample = 0;
+    }
+
+    pub fn resume(&mut self) {
+        if !self.paused { return }
+        self.paused = false;
+
+        self.queue.clear();
+    }
+}
+
+fn open_queue(audio_subsystem: &AudioSubsystem, sample_rate: i32) -> AudioQueue<i16> {
+    let desired_spec = AudioSpecDesired {
+        freq: Some(sample_rate),
+        channels: Some(1),
+        samples: None,
+    };
+
+    let queue = audio_subsystem.open_queue::<i16, _>(None, &desired_spec).unwrap();
+    queue.resume();
+    queue
+}
@@ -0,0 +1,129 @@
+use serde_json::{json, Value};
+
+use crate::bus::Bus;
+use crate::cpu6507::CPU6507;
+use crate::md5::md5;
+use crate::riot::RIOT;
+use crate::tia::{ObjectState, TIA};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn object(state: &ObjectState) -> Value {
+    json!({
+        "position": state.position,
+        "hmove": state.hmove,
+        "moving": state.moving,
+        "graphic": state.graphic,
+        "vdel": state.vdel,
+        "reflected": state.reflected,
+        "latches": state.latches,
+        "size": state.size,
+        "copies": state.copies,
+    })
+}
+
+// The state of the whole machine, for attaching to a bug report: the cartridge, the registers and
+// RAM of each of the chips, and the last frame's picture, as the TIA's colour values, a string of
+// hex for each line of the display. The CPU's save of itself and everything on the bus is there as
+// well, for putting the whole machine back exactly how it was.
+pub fn machine_state<B: Bus>(cpu: &CPU6507<B>, tia: &TIA, riot: &RIOT, rom: &[u8], picture: &[Vec<u8>],
+                             panic: Option<&str>) -> Value {
+    let mut saved = vec![];
+    cpu.save(&mut saved).expect("unable to save the CPU's state");
+
+    let tia = tia.state();
+    let switches = riot.switches();
+    let objects = &tia.objects;
+
+    let mut state = json!({});
+    if let Some(message) = panic {
+        state["panic"] = json!(message);
+    }
+
+    state["cartridge"] = json!({
+        "md5": md5(rom),
+        "size": rom.len(),
+    });
+    state["cpu"] = json!({
+        "pc": cpu.pc,
+        "a": cpu.a,
+        "x": cpu.x,
+        "y": cpu.y,
+        "p": cpu.flags(),
+        "sp": cpu.sp(),
+        "cycles": cpu.cycles(),
+        "jammed": cpu.jammed().is_some(),
+    });
+    state["tia"] = json!({
+        "frame": tia.position.frame,
+        "scanline": tia.position.scanline,
+        "clock": tia.position.clock,
+        "vsync": tia.vsync,
+        "vblank": tia.vblank,
+        "wsync": tia.wsync,
+        "colors": tia.colors,
+        "ctrlpf": tia.ctrlpf,
+        "nusiz": tia.nusiz,
+        "playfield": tia.playfield.registers,
+        "p0": object(&objects.p0),
+        "p1": object(&objects.p1),
+        "m0": object(&objects.m0),
+        "m1": object(&objects.m1),
+        "bl": object(&objects.bl),
+        "collisions": tia.collisions,
+    });
+    state["riot"] = json!({
+        "ram": (0x80 ..= 0xff).map(|address| cpu.peek(address)).collect::<Vec<_>>(),
+        "swcha": cpu.peek(0x0280),
+        "swacnt": cpu.peek(0x0281),
+        "swchb": cpu.peek(0x0282),
+        "swbcnt": cpu.peek(0x0283),
+        "intim": cpu.peek(0x0284),
+        "instat": cpu.peek(0x0285),
+        "select": switches.select,
+        "reset": switches.reset,
+        "color": switches.color,
+        "p0_difficulty_a": switches.p0_difficulty_a,
+        "p1_difficulty_a": switches.p1_difficulty_a,
+    });
+    state["saved"] = json!(hex(&saved));
+    state["picture"] = json!(picture.iter().map(|line| hex(line)).collect::<Vec<_>>());
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bus::AtariBus;
+
+    #[test]
+    fn test_machine_state() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+
+        // LDA #$42; STA $80
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x004].copy_from_slice(&[0xa9, 0x42, 0x85, 0x80]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let mut cpu = CPU6507::new(AtariBus::new(tia.clone(), riot.clone(), rom.clone()));
+        cpu.reset();
+        cpu.step();
+        cpu.step();
+
+        let state = machine_state(&cpu, &tia.borrow(), &riot.borrow(), &rom, &[vec![0x1e; 4]], Some("oops"));
+        assert!(format!("{:#}", state).starts_with("{\n  \"panic\": \"oops\",\n"));
+        assert_eq!(state["cartridge"]["md5"], md5(&rom));
+        assert_eq!((state["cpu"]["pc"].as_u64(), state["cpu"]["a"].as_u64()), (Some(0xf004), Some(0x42)));
+        assert_eq!((state["riot"]["ram"][0].as_u64(), state["riot"]["ram"][1].as_u64()), (Some(0x42), Some(0)));
+        assert_eq!(state["picture"], json!(["1e1e1e1e"]));
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+// The labels from a DASM symbol file, as written with dasm's -s option, for showing and taking
+// addresses by name in the debugger. Each symbol is on a line of its own, with its value in hex,
+// between a header and footer line that start with ---.
+//
+//     --- Symbol List (sorted by symbol)
+//     StartFrame               f01a              (R )
+//     VSYNC                    0000              (R )
+//     --- End of Symbol List.
+//
+// Constants are in there along with the labels, with nothing to tell them apart, so a value can
+// be shown as the name of a constant that happens to have the same value.
+pub struct Symbols {
+    addresses: HashMap<String, u16>,
+    labels: HashMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self {
+            addresses: HashMap::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut symbols = Self::new();
+
+        for line in text.lines().filter(|line| !line.starts_with("---")) {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 2 {
+                continue;
+            }
+
+            let value = match u16::from_str_radix(fields[1], 16) {
+                Ok(value) => value,
+                Err(_)    => continue,
+            };
+
+            // When more than one symbol has the same value, the first one's used for showing it
+            symbols.addresses.insert(fields[0].to_string(), value);
+            symbols.labels.entry(value).or_insert_with(|| fields[0].to_string());
+        }
+
+        symbols
+    }
+
+    pub fn len(&self) -> usize { self.addresses.len() }
+
+    // The address of a symbol, matching the case it was defined with
+    pub fn address(&self, name: &str) -> Option<u16> { self.addresses.get(name).cloned() }
+
+    pub fn label(&self, address: u16) -> Option<&str> { self.labels.get(&address).map(|l| l.as_str()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols() {
+        let symbols = Symbols::parse("\
+--- Symbol List (sorted by symbol)
+1.Loop                   f00a
+StartFrame               f01a              (R )
+VERTICAL_SYNC            0000
+VSYNC                    0000              (R )
+--- End of Symbol List.
+");
+
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.address("StartFrame"), Some(0xf01a));
+        assert_eq!(symbols.address("1.Loop"), Some(0xf00a));
+        assert_eq!(symbols.address("startframe"), None);
+
+        assert_eq!(symbols.label(0xf01a), Some("StartFrame"));
+        assert_eq!(symbols.label(0x0000), Some("VERTICAL_SYNC"));
+        assert_eq!(symbols.label(0xf01b), None);
+    }
+}
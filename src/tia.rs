@@ -1,31 +1,74 @@
+mod audio;
+mod audio_log;
 mod ball;
+mod beam;
 mod color;
 mod counter;
 mod missile;
-mod palette;
+mod object_state;
+mod paddles;
 mod player;
 mod playfield;
+mod write_trace;
 
 use std::rc::Rc;
 use std::cell::RefCell;
-
-use crate::bus::Bus;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crate::bus::{read_bytes, Bus};
+use crate::peripheral::Peripheral;
+use crate::region::Region;
+use crate::tia::audio::Audio;
+use crate::tia::audio_log::AudioLog;
 use crate::tia::ball::Ball;
 use crate::tia::color::Colors;
 use crate::tia::counter::Counter;
 use crate::tia::missile::Missile;
-use crate::tia::palette::{DEFAULT_COLOR, NTSC_PALETTE};
+use crate::tia::paddles::Paddles;
 use crate::tia::player::Player;
 use crate::tia::playfield::Playfield;
+use crate::tia::write_trace::WriteTrace;
 
-use sdl2::pixels::Color;
+pub use crate::tia::audio::sample_rate;
+pub use crate::tia::beam::BeamPosition;
+pub use crate::tia::object_state::{ObjectState, ObjectStates, PlayfieldState, TiaState};
+pub use crate::tia::playfield::register_bit;
+pub use crate::tia::write_trace::register_address;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum PlayerType {
     Player0,
     Player1,
 }
 
+// The objects that draw the picture, for showing which one drew each pixel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Object {
+    Player0,
+    Player1,
+    Missile0,
+    Missile1,
+    Ball,
+    Playfield,
+}
+
+// The objects in the order they're saved in, after 0 for the background
+const OBJECTS: [Object; 6] = [
+    Object::Player0,
+    Object::Player1,
+    Object::Missile0,
+    Object::Missile1,
+    Object::Ball,
+    Object::Playfield,
+];
+
+// A pixel's colour along with the object that drew it, when one of them's drawing it
+fn drawn(color: Option<u8>, object: Object) -> Option<(u8, Option<Object>)> {
+    color.map(|color| (color, Some(object)))
+}
+
 // Set H-SYNC
 const SHS: u8 = 4;
 
@@ -41,28 +84,63 @@ const RHB: u8 = 16;
 // Late RHB
 const LRHB: u8 = 18;
 
+// The colour clock during the HMOVE-extended HBLANK from which RESxx writes are treated as late
+const LRHB_RESX_THRESHOLD: u8 = 73;
+
 // Center
 const CNT: u8 = 36;
 
 // RESET, H-BLANK
 const SHB: u8 = 56;
 
+// The colour drawn while HBLANK is on
+const DEFAULT_COLOR: u8 = 0;
+
+// The luminance (D3-D1 of a colour) that a light gun's sensor picks up
+const LIGHT_GUN_LUMINANCE: u8 = 0x08;
+
 pub struct TIA {
     // HSYNC counter
     ctr: Counter,
 
+    // Total number of colour clocks since power on
+    clocks: u64,
+
+    // The number of frames since power on, and the scanline within the current frame, counted
+    // from the start of VSYNC
+    frame: u64,
+    scanline: u16,
+
     // Vertical sync
     vsync: bool,
     vblank: u8,
     late_reset_hblank: bool,
 
+    // Horizontal motion
+    movement_clock: u8,
+    movement_in_progress: bool,
+
     // Horizontal sync
     wsync: bool,
 
     // Input
-    // I'm only implementing player 0 joystick controls, so only one input port
-    inpt4_port: bool,
-    inpt4_latch: bool,
+    // The logic levels of the INPT4 and INPT5 ports, as pulled low by anything other than the
+    // peripherals, and their latches
+    input_ports: [bool; 2],
+    input_latches: [bool; 2],
+
+    // The peripherals plugged into the left and right controller ports, which can pull INPT4 and
+    // INPT5 low
+    peripherals: [Option<Rc<RefCell<dyn Peripheral>>>; 2],
+
+    // The dumped input ports, INPT0-INPT3
+    paddles: Paddles,
+
+    // The pixel of the current scanline that a light gun is pointed at, if it's on this scanline
+    light_gun: Option<usize>,
+
+    // Whether the controllers are plugged into each other's ports
+    swap_ports: bool,
 
     // Collision registers
     cxm0p: u8,
@@ -74,6 +152,11 @@ pub struct TIA {
     cxblpf: u8,
     cxppmm: u8,
 
+    // The last values written to CTRLPF, NUSIZ0, and NUSIZ1, which are split up between the
+    // objects, for inspecting
+    ctrlpf: u8,
+    nusiz: [u8; 2],
+
     colors: Rc<RefCell<Colors>>,
 
     // Graphics
@@ -84,9 +167,47 @@ pub struct TIA {
     m1: Missile,
     bl: Ball,
 
-    // One scanline of pixels to be rendered. It's up to the calling code to call
-    // `get_scanline_pixels` at the end of each scanline.
-    pixels: Vec<Color>,
+    // Sound
+    audio: Audio,
+    audio_log: Option<AudioLog>,
+    write_trace: Option<WriteTrace>,
+
+    // One scanline of pixels to be rendered, as colour values to be looked up in a palette. It's up
+    // to the calling code to call `get_scanline_pixels` at the end of each scanline.
+    pixels: Vec<u8>,
+
+    // The object that drew each pixel of the scanline, or None for the background
+    pixel_objects: Vec<Option<Object>>,
+}
+
+// A copy of the TIA's state at one point, for the debugger to step backwards to. It's the state of
+// the beam and everything drawn with it, but not the sound, or the settings and peripherals that
+// come from outside.
+pub struct TiaSnapshot {
+    ctr: Counter,
+    clocks: u64,
+    frame: u64,
+    scanline: u16,
+    vsync: bool,
+    vblank: u8,
+    late_reset_hblank: bool,
+    movement_clock: u8,
+    movement_in_progress: bool,
+    wsync: bool,
+    input_latches: [bool; 2],
+    paddles: Paddles,
+    collisions: [u8; 8],
+    ctrlpf: u8,
+    nusiz: [u8; 2],
+    colors: Colors,
+    pf: Playfield,
+    p0: Player,
+    p1: Player,
+    m0: Missile,
+    m1: Missile,
+    bl: Ball,
+    pixels: Vec<u8>,
+    pixel_objects: Vec<Option<Object>>,
 }
 
 impl TIA {
@@ -103,18 +224,32 @@ impl TIA {
         Self {
             ctr: hsync_ctr,
 
+            clocks: 0,
+
+            frame: 0,
+            scanline: 0,
+
             vsync: false,
             vblank: 0,
             late_reset_hblank: false,
 
+            movement_clock: 0,
+            movement_in_progress: false,
+
             wsync: false,
 
             // These two ports have latches that are both enabled by writing a "1" or disabled by
             // writing a "0" to D6 of VBLANK. When disabled, the microprocessor reads the logic
             // level of the port directly. When enabled, the latch is set for logic one and remains
             // that way until its port goes LOW.
-            inpt4_port: false,
-            inpt4_latch: true,
+            input_ports: [true; 2],
+            input_latches: [true; 2],
+            peripherals: [None, None],
+
+            paddles: Paddles::new(),
+
+            light_gun: None,
+            swap_ports: false,
 
             cxm0p: 0,
             cxm1p: 0,
@@ -125,6 +260,9 @@ impl TIA {
             cxblpf: 0,
             cxppmm: 0,
 
+            ctrlpf: 0,
+            nusiz: [0; 2],
+
             colors: colors,
 
             pf: pf,
@@ -134,30 +272,274 @@ impl TIA {
             p0: p0,
             p1: p1,
 
-            pixels: vec![Color::RGB(0, 0, 0); 160],
+            audio: Audio::new(),
+            audio_log: None,
+            write_trace: None,
+
+            pixels: vec![0; 160],
+            pixel_objects: vec![None; 160],
+        }
+    }
+
+    // Clears the registers and the objects, as when the console's turned on. The logs and audio
+    // debugging settings aren't part of the console, and the controllers stay as they are, so they
+    // carry on as they were.
+    pub fn power_cycle(&mut self) {
+        let mut tia = Self::new();
+
+        tia.audio_log = self.audio_log.take();
+        tia.write_trace = self.write_trace.take();
+        self.audio.power_cycle();
+        tia.audio = mem::replace(&mut self.audio, Audio::new());
+
+        // The paddles' timing is based on the colour clock count, so that carries on too
+        tia.input_ports = self.input_ports;
+        tia.clocks = self.clocks;
+        tia.paddles = mem::replace(&mut self.paddles, Paddles::new());
+        tia.paddles.set_dumped(false, tia.clocks);
+        tia.swap_ports = self.swap_ports;
+        tia.peripherals = self.peripherals.clone();
+
+        *self = tia;
+    }
+
+    // A snapshot of the playfield registers, for debugging
+    pub fn playfield(&self) -> PlayfieldState { self.pf.state() }
+
+    // A snapshot of the movable objects' state, for debugging
+    pub fn objects(&self) -> ObjectStates {
+        ObjectStates {
+            p0: self.p0.state(),
+            p1: self.p1.state(),
+            m0: self.m0.state(),
+            m1: self.m1.state(),
+            bl: self.bl.state(),
+        }
+    }
+
+    // A snapshot of all of the TIA's state that affects the picture, for debugging
+    pub fn state(&self) -> TiaState {
+        let colors = self.colors.borrow();
+
+        TiaState {
+            position: self.position(),
+            vsync: self.vsync,
+            vblank: self.vblank,
+            wsync: self.wsync,
+
+            colors: [colors.colup0(), colors.colup1(), colors.colupf(), colors.colubk()],
+
+            ctrlpf: self.ctrlpf,
+            nusiz: self.nusiz,
+
+            playfield: self.playfield(),
+            objects: self.objects(),
+
+            collisions: [self.cxm0p, self.cxm1p, self.cxp0fb, self.cxp1fb, self.cxm0fb, self.cxm1fb, self.cxblpf, self.cxppmm],
+        }
+    }
+
+    pub fn snapshot(&self) -> TiaSnapshot {
+        TiaSnapshot {
+            ctr: self.ctr.clone(),
+            clocks: self.clocks,
+            frame: self.frame,
+            scanline: self.scanline,
+            vsync: self.vsync,
+            vblank: self.vblank,
+            late_reset_hblank: self.late_reset_hblank,
+            movement_clock: self.movement_clock,
+            movement_in_progress: self.movement_in_progress,
+            wsync: self.wsync,
+            input_latches: self.input_latches,
+            paddles: self.paddles.clone(),
+            collisions: [self.cxm0p, self.cxm1p, self.cxp0fb, self.cxp1fb, self.cxm0fb, self.cxm1fb, self.cxblpf, self.cxppmm],
+            ctrlpf: self.ctrlpf,
+            nusiz: self.nusiz,
+            colors: self.colors.borrow().clone(),
+            pf: self.pf.clone(),
+            p0: self.p0.clone(),
+            p1: self.p1.clone(),
+            m0: self.m0.clone(),
+            m1: self.m1.clone(),
+            bl: self.bl.clone(),
+            pixels: self.pixels.clone(),
+            pixel_objects: self.pixel_objects.clone(),
+        }
+    }
+
+    // The objects' copies in the snapshot still share this TIA's colours, which are copied back
+    pub fn restore(&mut self, snapshot: &TiaSnapshot) {
+        self.ctr = snapshot.ctr.clone();
+        self.clocks = snapshot.clocks;
+        self.frame = snapshot.frame;
+        self.scanline = snapshot.scanline;
+        self.vsync = snapshot.vsync;
+        self.vblank = snapshot.vblank;
+        self.late_reset_hblank = snapshot.late_reset_hblank;
+        self.movement_clock = snapshot.movement_clock;
+        self.movement_in_progress = snapshot.movement_in_progress;
+        self.wsync = snapshot.wsync;
+        self.input_latches = snapshot.input_latches;
+        self.paddles = snapshot.paddles.clone();
+
+        let [cxm0p, cxm1p, cxp0fb, cxp1fb, cxm0fb, cxm1fb, cxblpf, cxppmm] = snapshot.collisions;
+        self.cxm0p = cxm0p;
+        self.cxm1p = cxm1p;
+        self.cxp0fb = cxp0fb;
+        self.cxp1fb = cxp1fb;
+        self.cxm0fb = cxm0fb;
+        self.cxm1fb = cxm1fb;
+        self.cxblpf = cxblpf;
+        self.cxppmm = cxppmm;
+
+        self.ctrlpf = snapshot.ctrlpf;
+        self.nusiz = snapshot.nusiz;
+        *self.colors.borrow_mut() = snapshot.colors.clone();
+        self.pf = snapshot.pf.clone();
+        self.p0 = snapshot.p0.clone();
+        self.p1 = snapshot.p1.clone();
+        self.m0 = snapshot.m0.clone();
+        self.m1 = snapshot.m1.clone();
+        self.bl = snapshot.bl.clone();
+        self.pixels = snapshot.pixels.clone();
+        self.pixel_objects = snapshot.pixel_objects.clone();
+    }
+
+    pub fn position(&self) -> BeamPosition {
+        BeamPosition {
+            frame: self.frame,
+            scanline: self.scanline,
+            clock: self.ctr.internal_value,
         }
     }
 
     pub fn in_vblank(&self) -> bool { (self.vblank & 0x02) != 0 }
     pub fn in_vsync(&self) -> bool { self.vsync }
-    pub fn cpu_halt(&self) -> bool { self.wsync }
-    pub fn get_scanline_pixels(&self) -> &Vec<Color> { &self.pixels }
+    // The RDY line into the CPU, pulled low by WSYNC to halt it until the start of the next scanline
+    pub fn rdy(&self) -> bool { !self.wsync }
+    pub fn get_scanline_pixels(&self) -> &Vec<u8> { &self.pixels }
+    pub fn get_scanline_objects(&self) -> &Vec<Option<Object>> { &self.pixel_objects }
+    // How many colour clocks the TIA's been clocked for since it was powered on
+    pub fn clocks(&self) -> u64 { self.clocks }
+    pub fn audio(&self) -> &Audio { &self.audio }
+    pub fn take_audio_samples(&mut self) -> Vec<i16> { self.audio.take_samples() }
+    pub fn toggle_audio_mute(&mut self, channel: usize) -> bool { self.audio.toggle_mute(channel) }
+    pub fn toggle_audio_solo(&mut self, channel: usize) -> bool { self.audio.toggle_solo(channel) }
+
+    pub fn record_audio(&mut self, file: File, region: Region) -> io::Result<()> {
+        self.audio_log = Some(AudioLog::new(file, region)?);
+        Ok(())
+    }
 
-    pub fn joystick_fire(&mut self, pressed: bool) {
-        self.inpt4_port = !pressed;
+    // The audio log's timing is in the region's colour clocks
+    pub fn set_audio_region(&mut self, region: Region) {
+        if let Some(log) = &mut self.audio_log {
+            log.set_region(region);
+        }
+    }
+
+    // Trace writes to the given registers, or to every register if none are given
+    pub fn trace_writes(&mut self, file: File, registers: &[String]) -> io::Result<()> {
+        self.write_trace = Some(WriteTrace::new(file, registers)?);
+        Ok(())
+    }
+
+    // Plugs the controllers into each other's ports, for games that read the "wrong" one. The
+    // controllers keep the state they're in, so anything held down stays held down.
+    pub fn set_swap_ports(&mut self, swap: bool) {
+        if swap != self.swap_ports {
+            self.swap_ports = swap;
+            self.input_ports.swap(0, 1);
+            self.peripherals.swap(0, 1);
+            self.paddles.swap_ports();
+        }
+    }
+
+    // The controller port (0 for the left, and 1 for the right) that a player's controller is
+    // plugged into
+    fn port(&self, player: PlayerType) -> usize {
+        match (player, self.swap_ports) {
+            (PlayerType::Player0, false) | (PlayerType::Player1, true) => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn plug_in(&mut self, port: usize, peripheral: Rc<RefCell<dyn Peripheral>>) {
+        self.peripherals[port] = Some(peripheral);
+    }
+
+    // A Sega Genesis pad's C button, which is on the paddle port that isn't used for the
+    // paddles' fire buttons: INPT1 for the left port, and INPT3 for the right
+    pub fn genesis_button_c(&mut self, player: PlayerType, pressed: bool) {
+        let port = self.port(player);
+        self.paddles.set_button(port * 2 + 1, pressed);
+    }
+
+    // Points a light gun, plugged into the left controller port, at a pixel of the scanline about
+    // to be drawn, or at some other scanline. Its sensor is on INPT4, which goes low when the beam
+    // draws a bright enough pixel where it's pointed, and stays low for the rest of the scanline.
+    pub fn set_light_gun(&mut self, x: Option<usize>) {
+        self.light_gun = x;
+        let port = self.port(PlayerType::Player0);
+        self.set_input_port(port, true);
+    }
 
-        if !self.inpt4_port {
-            // When the port goes LOW the latch goes LOW and remains that way (until re-disabled by
+    pub fn set_paddle(&mut self, paddle: usize, position: u8) {
+        let paddle = if self.swap_ports { paddle ^ 2 } else { paddle };
+        self.paddles.set_position(paddle, position);
+    }
+
+    fn set_input_port(&mut self, port: usize, level: bool) {
+        self.input_ports[port] = level;
+
+        if !level {
+            // When the port goes LOW the latch goes LOW and remains that way (until re-enabled by
             // VBLANK Bit 6) regardless of what the port does
-            self.inpt4_latch = false;
+            self.input_latches[port] = false;
+        }
+    }
+
+    // The dumped ports read the paddles, unless the peripheral in their controller port connects
+    // the pins straight to the supply or ground. Dumping still grounds them.
+    fn read_pot_port(&self, pot: usize) -> u8 {
+        let pins = self.peripherals[pot / 2].as_ref().and_then(|p| p.borrow().pot_pins());
+
+        match pins {
+            Some(levels) if levels[pot % 2] && !self.paddles.dumped() => 0x80,
+            Some(_) => 0x00,
+            None    => self.paddles.read(pot, self.clocks),
         }
     }
 
-    fn reset_latches(&mut self) { self.inpt4_latch = true }
+    // The level of an input port, pulled low by either its peripheral or anything else
+    fn input_level(&self, port: usize) -> bool {
+        let fire = self.peripherals[port].as_ref().is_none_or(|p| p.borrow().fire());
+        self.input_ports[port] && fire
+    }
+
+    fn latches_enabled(&self) -> bool { (self.vblank & 0x40) != 0 }
+
+    // Enabling the latches sets them to logic one, unless their port is already LOW
+    fn reset_latches(&mut self) { self.input_latches = [self.input_level(0), self.input_level(1)] }
+
+    fn read_input_port(&self, port: usize) -> u8 {
+        // When the latch is enabled in D6 of VBLANK, the latch is read instead of the port. A
+        // latch goes low as soon as its port does, which might have been since it was last
+        // clocked.
+        let level = if self.latches_enabled() {
+            self.input_latches[port] && self.input_level(port)
+        } else {
+            self.input_level(port)
+        };
+
+        if level { 0x80 } else { 0x00 }
+    }
 
     // Resolve playfield/player/missile/ball priorities and return the color to
     // be rendered.
-    fn get_pixel_color(&self) -> u8 {
+    // The colour of the current pixel, and the object that drew it
+    fn get_pixel(&self) -> (u8, Option<Object>) {
         if !self.pf.priority() {
             // When pixels of two or more objects overlap each other, only the
             // pixel of the object with topmost priority is drawn to the screen.
@@ -169,13 +551,32 @@ impl TIA {
             //  3            COLUPF   BL, PF  (only BL in SCORE-mode)
             //  4 (lowest)   COLUBK   BK
 
-            self.p0.get_color()
-                .or(self.m0.get_color())
-                .or(self.p1.get_color())
-                .or(self.m1.get_color())
-                .or(self.bl.get_color())
-                .or(self.pf.get_color())
-                .unwrap_or(self.colors.borrow().colubk())
+            if self.pf.score_mode() {
+                // In SCORE-mode each half of the playfield takes on the priority of the player
+                // whose color it's using, so it's drawn over the other player and the ball.
+                let (pf_left, pf_right) = if self.pf.left_half() {
+                    (drawn(self.pf.get_color(), Object::Playfield), None)
+                } else {
+                    (None, drawn(self.pf.get_color(), Object::Playfield))
+                };
+
+                return drawn(self.p0.get_color(), Object::Player0)
+                    .or(drawn(self.m0.get_color(), Object::Missile0))
+                    .or(pf_left)
+                    .or(drawn(self.p1.get_color(), Object::Player1))
+                    .or(drawn(self.m1.get_color(), Object::Missile1))
+                    .or(pf_right)
+                    .or(drawn(self.bl.get_color(), Object::Ball))
+                    .unwrap_or((self.colors.borrow().colubk(), None));
+            }
+
+            drawn(self.p0.get_color(), Object::Player0)
+                .or(drawn(self.m0.get_color(), Object::Missile0))
+                .or(drawn(self.p1.get_color(), Object::Player1))
+                .or(drawn(self.m1.get_color(), Object::Missile1))
+                .or(drawn(self.bl.get_color(), Object::Ball))
+                .or(drawn(self.pf.get_color(), Object::Playfield))
+                .unwrap_or((self.colors.borrow().colubk(), None))
         } else {
             // Optionally, the playfield and ball may be assigned to have higher
             // priority (by setting CTRLPF.2). The priority ordering is then:
@@ -186,13 +587,13 @@ impl TIA {
             //  3            COLUP1   P1, M1
             //  4 (lowest)   COLUBK   BK
 
-            self.pf.get_color()
-                .or(self.bl.get_color())
-                .or(self.p0.get_color())
-                .or(self.m0.get_color())
-                .or(self.p1.get_color())
-                .or(self.m1.get_color())
-                .unwrap_or(self.colors.borrow().colubk())
+            drawn(self.pf.get_color(), Object::Playfield)
+                .or(drawn(self.bl.get_color(), Object::Ball))
+                .or(drawn(self.p0.get_color(), Object::Player0))
+                .or(drawn(self.m0.get_color(), Object::Missile0))
+                .or(drawn(self.p1.get_color(), Object::Player1))
+                .or(drawn(self.m1.get_color(), Object::Missile1))
+                .unwrap_or((self.colors.borrow().colubk(), None))
         }
     }
 
@@ -229,48 +630,115 @@ impl TIA {
         self.late_reset_hblank && self.ctr.value() > RHB && self.ctr.value() <= LRHB
     }
 
+    // Whether HBLANK is on for the current colour clock. It's normally turned off after 68 colour
+    // clocks, but when HMOVE has been strobed during HBLANK, it's extended by another 8 to 76.
+    //
+    // This is what decides which colour clocks the objects receive their motion clocks on: only
+    // while HBLANK is off. So an object normally receives 160 motion clocks per scanline, but only
+    // 152 on a scanline with the extended HBLANK, which is why HMOVE with HMxx set to 0 leaves
+    // objects where they are: the 8 extra clocks it sends during HBLANK make up for the 8 lost.
+    fn in_hblank(&self) -> bool {
+        !self.visible_cycle() || self.in_late_reset()
+    }
+
+    // Objects reset during the visible scanline appear 5 pixels (players) or 4 pixels (missiles
+    // and ball) to the right of the reset position on the following scanlines. If the write takes
+    // place anywhere within horizontal blanking then the position is set to the left edge of the
+    // screen, plus a few pixels: 3 pixels for P0/P1, and only 2 pixels for M0/M1/BL. Towards the
+    // end of an HMOVE-extended HBLANK, that's only one pixel closer.
+    fn resx_offset(&self) -> u8 {
+        if !self.visible_cycle() {
+            2
+        } else if self.in_late_reset() {
+            if self.ctr.internal_value >= LRHB_RESX_THRESHOLD { 1 } else { 2 }
+        } else {
+            0
+        }
+    }
+
+    fn tick_movement(&mut self) {
+        // The HMOVE ripple counter counts down from 15, but the HMxx comparisons are done as if
+        // it were counting up from 0. Once it's done, it stays at 0.
+        let movement_clock = if self.movement_clock > 15 { 0 } else { self.movement_clock };
+        let hblank = self.in_hblank();
+
+        self.p0.movement_tick(movement_clock, hblank);
+        self.p1.movement_tick(movement_clock, hblank);
+        self.m0.movement_tick(movement_clock, hblank);
+        self.m1.movement_tick(movement_clock, hblank);
+        self.bl.movement_tick(movement_clock, hblank);
+
+        self.movement_in_progress =
+               self.p0.moving()
+            || self.p1.moving()
+            || self.m0.moving()
+            || self.m1.moving()
+            || self.bl.moving();
+
+        self.movement_clock = self.movement_clock.saturating_add(1);
+    }
+
     pub fn clock(&mut self) {
         // Clock the horizontal sync counter
         let clocked = self.ctr.clock();
+        self.clocks += 1;
+
+        // The peripherals can pull the input ports low at any time, which the latches hold onto
+        for port in 0 .. 2 {
+            if !self.input_level(port) {
+                self.input_latches[port] = false;
+            }
+        }
+
+        // The audio circuits are clocked at fixed points along each scanline
+        self.audio.clock(self.ctr.internal_value);
 
         if self.visible_cycle() {
             // Playfield is clocked on every visible cycle
             self.pf.clock();
 
-            // Update the collision registers
-            self.update_collisions();
-
-            let color;
-
-            if self.in_late_reset() {
-                // During LRHB we apply extra HMOVE clocks
-                self.p0.apply_hmove();
-                self.p1.apply_hmove();
-                self.m0.apply_hmove();
-                self.m1.apply_hmove();
-                self.bl.apply_hmove();
-
-                color = DEFAULT_COLOR;
+            let (color, object) = if self.in_hblank() {
+                // HBLANK is extended by 8 pixels when HMOVE is strobed, so the left-most pixels
+                // are blanked out, producing the "comb" effect seen on the left of the screen in
+                // many games. Nothing is drawn, so no collisions can happen either.
+                (DEFAULT_COLOR, None)
             } else {
-                // Player, missile, and ball counters only get clocked on visible cycles
+                // Player, missile, and ball counters only get clocked while HBLANK is off
                 self.p0.clock();
                 self.p1.clock();
                 self.m0.clock();
                 self.m1.clock();
                 self.bl.clock();
 
-                color = self.get_pixel_color() as usize
+                // Update the collision registers
+                self.update_collisions();
+
+                self.get_pixel()
             };
 
             let x = self.ctr.internal_value as usize - 68;
-            self.pixels[x] = NTSC_PALETTE[color];
-        } else {
-            // During HBLANK we apply extra HMOVE clocks
-            self.p0.apply_hmove();
-            self.p1.apply_hmove();
-            self.m0.apply_hmove();
-            self.m1.apply_hmove();
-            self.bl.apply_hmove();
+            self.pixels[x] = color;
+            self.pixel_objects[x] = object;
+
+            if self.light_gun == Some(x) && (color & 0x0e) >= LIGHT_GUN_LUMINANCE {
+                let port = self.port(PlayerType::Player0);
+                self.set_input_port(port, false);
+            }
+        }
+
+        // HMOVE sends its extra clocks on every H@1 of the HSYNC counter, i.e. every 4 CLK
+        if clocked && self.movement_in_progress {
+            self.tick_movement();
+        }
+
+        // While RESMPx is set, the missile is continuously kept centered on its player, so that
+        // when it's released it starts from the player's current position.
+        if self.m0.locked() {
+            self.m0.reset_to_player(&self.p0);
+        }
+
+        if self.m1.locked() {
+            self.m1.reset_to_player(&self.p1);
         }
 
         if clocked {
@@ -282,6 +750,7 @@ impl TIA {
                     // electron beam reaches the right edge of the screen.
                     self.wsync = false;
                     self.late_reset_hblank = false;
+                    self.scanline = self.scanline.wrapping_add(1);
                 },
 
                 // Reset HBlank
@@ -303,11 +772,28 @@ impl TIA {
     }
 }
 
+// Optional values are saved as whether there's a value, then the value, or 0 when there isn't one
+fn save_option(output: &mut dyn Write, value: Option<i64>) -> io::Result<()> {
+    output.write_all(&[value.is_some() as u8])?;
+    output.write_all(&value.unwrap_or(0).to_le_bytes())
+}
+
+fn load_option(input: &mut dyn Read) -> io::Result<Option<i64>> {
+    let [some] = read_bytes(input)?;
+    let value = i64::from_le_bytes(read_bytes(input)?);
+    Ok(if some != 0 { Some(value) } else { None })
+}
+
 impl Bus for TIA {
     // https://problemkaputt.de/2k6specs.htm#memoryandiomap
 
-    fn read(&mut self, address: u16) -> u8 {
-        match address {
+    // Reading the TIA has no side effects
+    fn read(&mut self, address: u16) -> u8 { self.peek(address) }
+
+    fn peek(&self, address: u16) -> u8 {
+        // Only the low 4 bits of the address are decoded for reads, so each read register is
+        // mirrored every 16 bytes throughout the TIA's address space.
+        match (address & 0x0f) | 0x30 {
             // CXM0P   11......  read collision M0-P1, M0-P0 (Bit 7,6)
             0x0030 => self.cxm0p,
 
@@ -332,38 +818,64 @@ impl Bus for TIA {
             // CXPPMM  11......  read collision P0-P1, M0-M1
             0x0037 => self.cxppmm,
 
-            // INPT4   1.......  read input
-            0x003C => {
-                // Check the logic level of the port
-                let mut level = self.inpt4_port;
+            // INPT0   1.......  read pot port
+            0x0038 => self.read_pot_port(0),
 
-                // When the latch is enabled in D6 of VBLANK, check the latch value aswell
-                if (self.vblank & 0x40) != 0 {
-                    level = level && self.inpt4_latch;
-                }
+            // INPT1   1.......  read pot port
+            0x0039 => self.read_pot_port(1),
 
-                if level { 0x80 } else { 0x00 }
-            },
+            // INPT2   1.......  read pot port
+            0x003A => self.read_pot_port(2),
+
+            // INPT3   1.......  read pot port
+            0x003B => self.read_pot_port(3),
+
+            // INPT4   1.......  read input
+            0x003C => self.read_input_port(0),
+
+            // INPT5   1.......  read input
+            0x003D => self.read_input_port(1),
 
             _ => 0,
         }
     }
 
     fn write(&mut self, address: u16, val: u8) {
+        if let Some(log) = &mut self.audio_log {
+            log.record(self.clocks, address, val);
+        }
+
+        if let Some(trace) = &mut self.write_trace {
+            trace.record(self.frame, self.scanline, self.ctr.internal_value, address, val);
+        }
+
         match address {
             //
             // Frame timing and synchronisation
             //
 
             // VSYNC   ......1.  vertical sync set-clear
-            0x0000 => { self.vsync = (val & 0x02) != 0 },
+            0x0000 => {
+                let vsync = (val & 0x02) != 0;
+
+                if vsync && !self.vsync {
+                    self.frame += 1;
+                    self.scanline = 0;
+                }
+
+                self.vsync = vsync;
+            },
 
             // VBLANK  11....1.  vertical blank set-clear
             0x0001 => {
+                let latches_were_enabled = self.latches_enabled();
                 self.vblank = val;
 
-                if (val & 0x80) != 0 {
-                    // INPT4-5 latches are reset when D6 of VBLANK is 1
+                // INPT0-3 are grounded while D7 of VBLANK is 1
+                self.paddles.set_dumped((val & 0x80) != 0, self.clocks);
+
+                if self.latches_enabled() && !latches_were_enabled {
+                    // INPT4-5 latches are reset when they're enabled by D6 of VBLANK
                     self.reset_latches();
                 }
             },
@@ -392,8 +904,9 @@ impl Bus for TIA {
 
             // CTRLPF  ..11.111  control playfield ball size & collisions
             0x000a => {
+                self.ctrlpf = val;
                 self.pf.set_control(val);
-                self.bl.set_nusiz(1 << ((val & 0b0011_0000) >> 4));
+                self.bl.set_size(1 << ((val & 0b0011_0000) >> 4));
             },
 
             //
@@ -415,18 +928,16 @@ impl Bus for TIA {
 
             // NUSIZ0  ..111111  number-size player-missile 0
             0x0004 => {
-                let player_copies = val & 0b0000_0111;
-
+                self.nusiz[0] = val;
                 self.m0.set_nusiz(val);
-                self.p0.set_nusiz(player_copies);
+                self.p0.set_nusiz(val);
             },
 
             // NUSIZ1  ..111111  number-size player-missile 1
             0x0005 => {
-                let player_copies = val & 0b0000_0111;
-
+                self.nusiz[1] = val;
                 self.m1.set_nusiz(val);
-                self.p1.set_nusiz(player_copies);
+                self.p1.set_nusiz(val);
             },
 
             // REFP0   ....1...  reflect player 0
@@ -436,45 +947,42 @@ impl Bus for TIA {
             0x000c => { self.p1.set_horizontal_mirror((val & 0b0000_1000) != 0) },
 
             // RESP0   <strobe>  reset player 0
-            0x0010 => {
-                // If the write takes place anywhere within horizontal blanking
-                // then the position is set to the left edge of the screen (plus
-                // a few pixels towards right: 3 pixels for P0/P1, and only 2
-                // pixels for M0/M1/BL).
-                self.p0.reset();
-            },
+            0x0010 => { self.p0.reset(self.resx_offset()) },
 
             // RESP1   <strobe>  reset player 1
-            0x0011 => {
-                self.p1.reset();
-            },
+            0x0011 => { self.p1.reset(self.resx_offset()) },
 
             // RESM0   <strobe>  reset missile 0
-            0x0012 => { self.m0.reset() },
+            0x0012 => { self.m0.reset(self.resx_offset()) },
 
             // RESM1   <strobe>  reset missile 1
-            0x0013 => { self.m1.reset() },
+            0x0013 => { self.m1.reset(self.resx_offset()) },
 
             // RESBL   <strobe>  reset ball
-            0x0014 => { self.bl.reset() },
+            0x0014 => { self.bl.reset(self.resx_offset()) },
+
+            // AUDC0   ....1111  audio control 0
+            0x0015 => { self.audio.set_audc(0, val) },
 
-            // AUDV0
-            0x0015 => { debug!("AUDV0: {}", val) },
+            // AUDC1   ....1111  audio control 1
+            0x0016 => { self.audio.set_audc(1, val) },
 
-            // AUDV1
-            0x0016 => { debug!("AUDV1: {}", val) },
+            // AUDF0   ...11111  audio frequency 0
+            0x0017 => { self.audio.set_audf(0, val) },
 
-            // AUDF0
-            0x0017 => { debug!("AUDF0: {}", val) },
+            // AUDF1   ...11111  audio frequency 1
+            0x0018 => { self.audio.set_audf(1, val) },
 
-            // AUDF1
-            0x0018 => { debug!("AUDF1: {}", val) },
+            // AUDV0   ....1111  audio volume 0
+            0x0019 => { self.audio.set_audv(0, val) },
 
-            // AUDC0
-            0x0019 => { debug!("AUDC0: {}", val) },
+            // AUDV1   ....1111  audio volume 1
+            0x001a => { self.audio.set_audv(1, val) },
 
-            // AUDC1
-            0x001a => { debug!("AUDC1: {}", val) },
+            // Each of GRP0, GRP1, and ENABL are a pair of latches, a "new" one written by the CPU, and
+            // an "old" one drawn from when vertically delayed. Writing GRP0 copies GRP1's new value
+            // into its old one, and writing GRP1 does the same for GRP0 and ENABL. This is what the
+            // 6-digit score and 48-pixel sprite kernels rely on.
 
             // GRP0    11111111  graphics player 0
             0x001b => {
@@ -527,18 +1035,10 @@ impl Bus for TIA {
             0x0027 => { self.bl.set_vdel((val & 0x01) != 0) }
 
             // RESMP0  ......1.  reset missile 0 to player 0
-            0x0028 => {
-                if (val & 0x02) != 0 {
-                    self.m0.reset_to_player(&self.p0);
-                }
-            },
+            0x0028 => { self.m0.set_locked((val & 0x02) != 0) },
 
             // RESMP1  ......1.  reset missile 1 to player 1
-            0x0029 => {
-                if (val & 0x02) != 0 {
-                    self.m1.reset_to_player(&self.p1);
-                }
-            },
+            0x0029 => { self.m1.set_locked((val & 0x02) != 0) },
 
             // HMOVE   <strobe>  apply horizontal motion
             0x002a => {
@@ -548,7 +1048,17 @@ impl Bus for TIA {
                 self.p0.start_hmove();
                 self.p1.start_hmove();
 
-                self.late_reset_hblank = true;
+                self.movement_clock = 0;
+                self.movement_in_progress = true;
+
+                // HBLANK is only extended if HMOVE is strobed during HBLANK. A "late" HMOVE, near
+                // the end of the previous scanline, still moves objects during the following
+                // HBLANK but without the extra 8 pixels of blanking (and without moving objects
+                // 8 pixels right). An HMOVE during the visible part of the scanline delays the
+                // normal motion clocks instead.
+                if !self.visible_cycle() {
+                    self.late_reset_hblank = true;
+                }
             },
 
             // HMCLR   <strobe>  clear horizontal motion registers
@@ -575,5 +1085,874 @@ impl Bus for TIA {
             _ => { }, 
         }
     }
+
+    // The beam, then the registers and latches, then the objects, the sound, the paddles'
+    // capacitors, and the scanline drawn so far. As with a snapshot, the settings and peripherals
+    // that come from outside are left as they are.
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        self.ctr.save(output)?;
+        output.write_all(&self.clocks.to_le_bytes())?;
+        output.write_all(&self.frame.to_le_bytes())?;
+        output.write_all(&self.scanline.to_le_bytes())?;
+        output.write_all(&[self.vsync as u8, self.vblank, self.late_reset_hblank as u8])?;
+        output.write_all(&[self.movement_clock, self.movement_in_progress as u8, self.wsync as u8])?;
+        output.write_all(&[self.input_latches[0] as u8, self.input_latches[1] as u8])?;
+
+        output.write_all(&[self.cxm0p, self.cxm1p, self.cxp0fb, self.cxp1fb, self.cxm0fb, self.cxm1fb, self.cxblpf, self.cxppmm])?;
+        output.write_all(&[self.ctrlpf, self.nusiz[0], self.nusiz[1]])?;
+        self.colors.borrow().save(output)?;
+
+        self.pf.save(output)?;
+        self.p0.save(output)?;
+        self.p1.save(output)?;
+        self.m0.save(output)?;
+        self.m1.save(output)?;
+        self.bl.save(output)?;
+        self.audio.save(output)?;
+        self.paddles.save(output)?;
+
+        output.write_all(&self.pixels)?;
+        let objects = self.pixel_objects.iter()
+            .map(|object| object.and_then(|o| OBJECTS.iter().position(|&other| other == o)).map_or(0, |i| i as u8 + 1))
+            .collect::<Vec<_>>();
+        output.write_all(&objects)
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        self.ctr.load(input)?;
+        self.clocks = u64::from_le_bytes(read_bytes(input)?);
+        self.frame = u64::from_le_bytes(read_bytes(input)?);
+        self.scanline = u16::from_le_bytes(read_bytes(input)?);
+        let [vsync, vblank, late_reset_hblank] = read_bytes(input)?;
+        self.vsync = vsync != 0;
+        self.vblank = vblank;
+        self.late_reset_hblank = late_reset_hblank != 0;
+        let [movement_clock, movement_in_progress, wsync] = read_bytes(input)?;
+        self.movement_clock = movement_clock;
+        self.movement_in_progress = movement_in_progress != 0;
+        self.wsync = wsync != 0;
+        let [latch0, latch1] = read_bytes(input)?;
+        self.input_latches = [latch0 != 0, latch1 != 0];
+
+        let [cxm0p, cxm1p, cxp0fb, cxp1fb, cxm0fb, cxm1fb, cxblpf, cxppmm] = read_bytes(input)?;
+        self.cxm0p = cxm0p;
+        self.cxm1p = cxm1p;
+        self.cxp0fb = cxp0fb;
+        self.cxp1fb = cxp1fb;
+        self.cxm0fb = cxm0fb;
+        self.cxm1fb = cxm1fb;
+        self.cxblpf = cxblpf;
+        self.cxppmm = cxppmm;
+        let [ctrlpf, nusiz0, nusiz1] = read_bytes(input)?;
+        self.ctrlpf = ctrlpf;
+        self.nusiz = [nusiz0, nusiz1];
+        self.colors.borrow_mut().load(input)?;
+
+        self.pf.load(input)?;
+        self.p0.load(input)?;
+        self.p1.load(input)?;
+        self.m0.load(input)?;
+        self.m1.load(input)?;
+        self.bl.load(input)?;
+        self.audio.load(input)?;
+        self.paddles.load(input)?;
+
+        input.read_exact(&mut self.pixels)?;
+        let mut objects = vec![0; self.pixel_objects.len()];
+        input.read_exact(&mut objects)?;
+        self.pixel_objects = objects.iter().map(|&i| OBJECTS.get((i as usize).wrapping_sub(1)).cloned()).collect();
+
+        Ok(())
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peripheral::{Joystick, Keypad, Source};
+
+    fn joysticks(tia: &mut TIA) -> [Rc<RefCell<Joystick>>; 2] {
+        let joysticks = [Rc::new(RefCell::new(Joystick::new(1))), Rc::new(RefCell::new(Joystick::new(1)))];
+        tia.plug_in(0, joysticks[0].clone());
+        tia.plug_in(1, joysticks[1].clone());
+        joysticks
+    }
+
+    fn scanline(tia: &mut TIA) -> Vec<u8> {
+        for _ in 0 .. 228 {
+            tia.clock();
+        }
+
+        tia.get_scanline_pixels().clone()
+    }
+
+    fn color_visible(tia: &mut TIA, color: usize) -> bool {
+        // Render a couple of lines, so objects have a chance to be drawn regardless of where their
+        // counters started
+        let mut pixels = scanline(tia);
+        pixels.extend(scanline(tia));
+
+        pixels.iter().any(|&c| c as usize == color)
+    }
+
+    #[test]
+    fn test_vdelp0_uses_old_graphic() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x0025, 0x01); // VDELP0
+
+        // With VDELP0 set, the new GRP0 value isn't drawn until GRP1 is written
+        tia.write(0x001b, 0xff); // GRP0
+        assert!(!color_visible(&mut tia, 0x1e));
+
+        tia.write(0x001c, 0x00); // GRP1
+        assert!(color_visible(&mut tia, 0x1e));
+
+        // Turning VDELP0 off goes back to drawing the new GRP0 value
+        tia.write(0x001b, 0x00); // GRP0
+        assert!(color_visible(&mut tia, 0x1e));
+        tia.write(0x0025, 0x00); // VDELP0
+        assert!(!color_visible(&mut tia, 0x1e));
+    }
+
+    #[test]
+    fn test_vdelbl_latched_by_grp1() {
+        let mut tia = TIA::new();
+        tia.write(0x0008, 0x1e); // COLUPF
+        tia.write(0x0027, 0x01); // VDELBL
+
+        tia.write(0x001f, 0x02); // ENABL
+        assert!(!color_visible(&mut tia, 0x1e));
+
+        tia.write(0x001c, 0x00); // GRP1
+        assert!(color_visible(&mut tia, 0x1e));
+    }
+
+    #[test]
+    fn test_resmp0_hides_missile() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001d, 0x02); // ENAM0
+        assert!(color_visible(&mut tia, 0x1e));
+
+        tia.write(0x0028, 0x02); // RESMP0
+        assert!(!color_visible(&mut tia, 0x1e));
+
+        tia.write(0x0028, 0x00); // RESMP0
+        assert!(color_visible(&mut tia, 0x1e));
+    }
+
+    fn color_positions(pixels: &[u8], color: usize) -> Vec<usize> {
+        (0 .. pixels.len()).filter(|&x| pixels[x] as usize == color).collect()
+    }
+
+    #[test]
+    fn test_missile_copies_keep_missile_size() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001d, 0x02); // ENAM0
+        scanline(&mut tia);
+        let single = color_positions(&scanline(&mut tia), 0x1e);
+        assert_eq!(single.len(), 1);
+
+        // Three close copies of an 8 pixel wide missile, each 16 pixels apart
+        tia.write(0x0004, 0b0011_0011); // NUSIZ0
+        scanline(&mut tia);
+        let copies = color_positions(&scanline(&mut tia), 0x1e);
+        let starts: Vec<usize> = (0 .. 3).map(|c| single[0] + c * 16).collect();
+        let expected: Vec<usize> = starts.iter().flat_map(|&x| x .. x + 8).collect();
+        assert_eq!(copies, expected);
+
+        // The double and quad sized player settings don't stretch the missile
+        tia.write(0x0004, 0b0000_0111); // NUSIZ0
+        scanline(&mut tia);
+        assert_eq!(color_positions(&scanline(&mut tia), 0x1e), single);
+    }
+
+    #[test]
+    fn test_resmp0_centers_missile_on_player() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001b, 0xff); // GRP0
+        scanline(&mut tia);
+        let player = color_positions(&scanline(&mut tia), 0x1e);
+
+        tia.write(0x001b, 0x00); // GRP0
+        tia.write(0x001d, 0x02); // ENAM0
+        tia.write(0x0028, 0x02); // RESMP0
+        scanline(&mut tia);
+        tia.write(0x0028, 0x00); // RESMP0
+        scanline(&mut tia);
+        let missile = color_positions(&scanline(&mut tia), 0x1e);
+
+        assert_eq!(player.len(), 8);
+        assert_eq!(missile, vec![player[0] + 3]);
+    }
+
+    #[test]
+    fn test_hmove_blanks_left_edge() {
+        let mut tia = TIA::new();
+        tia.write(0x0009, 0x1e); // COLUBK
+        let pixels = scanline(&mut tia);
+        assert_eq!(color_positions(&pixels, 0x1e).len(), 160);
+
+        tia.write(0x002a, 0x00); // HMOVE
+        let pixels = scanline(&mut tia);
+        assert_eq!(color_positions(&pixels, 0x00), (0 .. 8).collect::<Vec<_>>());
+
+        // Only the scanline that HMOVE was strobed on is affected
+        let pixels = scanline(&mut tia);
+        assert_eq!(color_positions(&pixels, 0x1e).len(), 160);
+    }
+
+    // Strobe HMOVE after `clocks` colour clocks of a scanline, and return the line that follows,
+    // along with the scanline HMOVE was strobed on.
+    fn hmove_at(tia: &mut TIA, clocks: usize) -> (Vec<u8>, Vec<u8>) {
+        for _ in 0 .. clocks {
+            tia.clock();
+        }
+
+        tia.write(0x002a, 0x00); // HMOVE
+
+        for _ in clocks .. 228 {
+            tia.clock();
+        }
+
+        let hmove_line = tia.get_scanline_pixels().clone();
+        (hmove_line, scanline(tia))
+    }
+
+    fn p0_after_hmove(hmp0: u8, clocks: usize) -> (usize, Vec<usize>) {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x0009, 0x02); // COLUBK
+        tia.write(0x001b, 0x80); // GRP0
+        scanline(&mut tia);
+        let before = color_positions(&scanline(&mut tia), 0x1e)[0];
+
+        tia.write(0x0020, hmp0); // HMP0
+        let (hmove_line, next_line) = hmove_at(&mut tia, clocks);
+        let after = color_positions(&next_line, 0x1e)[0];
+
+        ((160 + after - before) % 160, color_positions(&hmove_line, 0x00))
+    }
+
+    #[test]
+    fn test_hmove_during_hblank() {
+        // Moves -8 to +7 pixels, with the comb on the left edge
+        assert_eq!(p0_after_hmove(0x70, 0), (160 - 7, (0 .. 8).collect()));
+        assert_eq!(p0_after_hmove(0x00, 0), (0, (0 .. 8).collect()));
+        assert_eq!(p0_after_hmove(0x80, 0), (8, (0 .. 8).collect()));
+    }
+
+    #[test]
+    fn test_late_hmove() {
+        // An HMOVE on the last CPU cycle of the scanline moves objects 8 + HMxx pixels left in the
+        // next HBLANK, and doesn't extend HBLANK
+        assert_eq!(p0_after_hmove(0x70, 224), (160 - 15, vec![]));
+        assert_eq!(p0_after_hmove(0x00, 224), (160 - 8, vec![]));
+        assert_eq!(p0_after_hmove(0x80, 224), (0, vec![]));
+    }
+
+    #[test]
+    fn test_hmove_during_visible_scanline() {
+        // An HMOVE during the visible scanline plugs the normal motion clocks, moving objects right
+        assert_eq!(p0_after_hmove(0x70, 120), (15, vec![]));
+        assert_eq!(p0_after_hmove(0x00, 120), (8, vec![]));
+        assert_eq!(p0_after_hmove(0x80, 120), (0, vec![]));
+    }
+
+    // Strobe a RESxx register after `clocks` colour clocks of a scanline, returning the scanline
+    // the write happened on and the one after it
+    fn reset_at(tia: &mut TIA, address: u16, clocks: usize) -> (Vec<u8>, Vec<u8>) {
+        write_at(tia, address, 0x00, clocks)
+    }
+
+    // Write to a register after `clocks` colour clocks of a scanline, returning the scanline
+    // written on and the one after
+    fn write_at(tia: &mut TIA, address: u16, val: u8, clocks: usize) -> (Vec<u8>, Vec<u8>) {
+        for _ in 0 .. clocks {
+            tia.clock();
+        }
+
+        tia.write(address, val);
+
+        for _ in clocks .. 228 {
+            tia.clock();
+        }
+
+        let write_line = tia.get_scanline_pixels().clone();
+        (write_line, scanline(tia))
+    }
+
+    #[test]
+    fn test_resp0_position() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001b, 0x80); // GRP0
+
+        // During HBLANK, players are reset to 3 pixels in from the left edge
+        reset_at(&mut tia, 0x0010, 30);
+        assert_eq!(color_positions(&scanline(&mut tia), 0x1e), vec![3]);
+
+        // During the visible scanline, 5 pixels to the right of the reset, and the main copy isn't
+        // drawn on the same scanline
+        let (reset_line, next_line) = reset_at(&mut tia, 0x0010, 68 + 99);
+        assert_eq!(color_positions(&reset_line, 0x1e), vec![3]);
+        assert_eq!(color_positions(&next_line, 0x1e), vec![105]);
+
+        // During the HMOVE-extended HBLANK, as if the reset happened during HBLANK
+        tia.write(0x0020, 0x80); // HMP0
+        tia.write(0x002a, 0x00); // HMOVE
+        reset_at(&mut tia, 0x0010, 70);
+        assert_eq!(color_positions(&scanline(&mut tia), 0x1e), vec![11]);
+    }
+
+    #[test]
+    fn test_resbl_position() {
+        let mut tia = TIA::new();
+        tia.write(0x0008, 0x1e); // COLUPF
+        tia.write(0x001f, 0x02); // ENABL
+
+        reset_at(&mut tia, 0x0014, 30);
+        assert_eq!(color_positions(&scanline(&mut tia), 0x1e), vec![2]);
+
+        // The ball starts drawing straight away
+        let (reset_line, next_line) = reset_at(&mut tia, 0x0014, 68 + 99);
+        assert_eq!(color_positions(&reset_line, 0x1e), vec![2, 104]);
+        assert_eq!(color_positions(&next_line, 0x1e), vec![104]);
+    }
+
+    #[test]
+    fn test_nusiz_change_mid_copy() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001b, 0xff); // GRP0
+        tia.write(0x0004, 0x07); // NUSIZ0
+        reset_at(&mut tia, 0x0010, 30);
+
+        let quad = color_positions(&scanline(&mut tia), 0x1e);
+        assert_eq!(quad.len(), 32);
+
+        // Switching to a single width player 10 pixels into the copy finishes off the current
+        // (quad width) pixel of the graphic before drawing the rest at single width
+        let start = 68 + quad[0];
+        for _ in 0 .. start + 10 {
+            tia.clock();
+        }
+
+        tia.write(0x0004, 0x00); // NUSIZ0
+
+        for _ in start + 10 .. 228 {
+            tia.clock();
+        }
+
+        let pixels = color_positions(tia.get_scanline_pixels(), 0x1e);
+        assert_eq!(pixels, (quad[0] .. quad[0] + 12 + 5).collect::<Vec<_>>());
+    }
+
+    // Draw player 1 over the left edge of the playfield, and the ball over the right half, with
+    // the given CTRLPF value
+    fn priority_scanline(ctrlpf: u8) -> TIA {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x0007, 0x3e); // COLUP1
+        tia.write(0x0008, 0x5e); // COLUPF
+        tia.write(0x000a, ctrlpf); // CTRLPF
+        tia.write(0x000d, 0xf0); // PF0
+
+        tia.write(0x001c, 0xff); // GRP1
+        reset_at(&mut tia, 0x0011, 30);
+        tia.write(0x001f, 0x02); // ENABL
+        reset_at(&mut tia, 0x0014, 68 + 79);
+
+        scanline(&mut tia);
+        tia
+    }
+
+    // The colors at the player and the ball
+    fn playfield_priority(ctrlpf: u8) -> (u8, u8) {
+        let pixels = priority_scanline(ctrlpf).get_scanline_pixels().clone();
+        (pixels[3], pixels[84])
+    }
+
+    #[test]
+    fn test_playfield_priority() {
+        // Normal priority
+        assert_eq!(playfield_priority(0x00), (0x3e, 0x5e));
+
+        // SCORE-mode, the playfield is drawn in the player colors with the players' priority
+        assert_eq!(playfield_priority(0x02), (0x1e, 0x3e));
+
+        // Playfield priority
+        assert_eq!(playfield_priority(0x04), (0x5e, 0x5e));
+
+        // Playfield priority, the SCORE-bit is ignored
+        assert_eq!(playfield_priority(0x06), (0x5e, 0x5e));
+    }
+
+    #[test]
+    fn test_pixel_objects() {
+        // Each pixel's object is the one that's drawn there, going by the priorities
+        let tia = priority_scanline(0x00);
+        let objects = tia.get_scanline_objects();
+        assert_eq!(objects[3], Some(Object::Player1));
+        assert_eq!(objects[12], Some(Object::Playfield));
+        assert_eq!(objects[50], None);
+        assert_eq!(objects[84], Some(Object::Ball));
+
+        let tia = priority_scanline(0x04);
+        assert_eq!(tia.get_scanline_objects()[3], Some(Object::Playfield));
+    }
+
+    // Release the dumped ports, and return how many scanlines INPT0 takes to go high
+    fn paddle_scanlines(position: u8) -> usize {
+        let mut tia = TIA::new();
+        tia.set_paddle(0, position);
+
+        tia.write(0x0001, 0x80); // VBLANK, dump the ports
+        scanline(&mut tia);
+        assert_eq!(tia.read(0x0038), 0x00);
+
+        tia.write(0x0001, 0x00); // VBLANK, release the ports
+
+        let mut scanlines = 0;
+        while tia.read(0x0038) == 0x00 {
+            scanline(&mut tia);
+            scanlines += 1;
+        }
+
+        scanlines
+    }
+
+    #[test]
+    fn test_paddle_charge_time() {
+        // Fully clockwise, there's no resistance and the port goes high straight away
+        assert_eq!(paddle_scanlines(255), 0);
+
+        // Fully counter-clockwise takes the length of the visible picture
+        assert_eq!(paddle_scanlines(0), 220);
+
+        assert!(paddle_scanlines(192) < paddle_scanlines(64));
+    }
+
+    #[test]
+    fn test_genesis_button_c() {
+        let mut tia = TIA::new();
+        tia.genesis_button_c(PlayerType::Player0, false);
+        assert_eq!(tia.read(0x0039), 0x80);
+
+        tia.genesis_button_c(PlayerType::Player0, true);
+        assert_eq!(tia.read(0x0039), 0x00);
+        assert_eq!(tia.read(0x003B), 0x00);
+
+        // Dumping the capacitors grounds the line whether it's pressed or not
+        tia.genesis_button_c(PlayerType::Player0, false);
+        tia.write(0x0001, 0x80); // VBLANK
+        assert_eq!(tia.read(0x0039), 0x00);
+        tia.write(0x0001, 0x00); // VBLANK
+        assert_eq!(tia.read(0x0039), 0x80);
+    }
+
+    #[test]
+    fn test_light_gun() {
+        let mut tia = TIA::new();
+        tia.write(0x0009, 0x0e); // COLUBK, white
+        tia.write(0x0001, 0x00); // VBLANK
+
+        // Pointed at the middle of the scanline, the sensor goes off once the beam gets there
+        tia.set_light_gun(Some(80));
+        for _ in 0 .. 68 + 40 {
+            tia.clock();
+        }
+        assert_eq!(tia.read(0x003C), 0x80);
+
+        for _ in 0 .. 120 {
+            tia.clock();
+        }
+        assert_eq!(tia.read(0x003C), 0x00);
+
+        // Dark pixels don't set it off
+        tia.write(0x0009, 0x04); // COLUBK
+        tia.set_light_gun(Some(80));
+        for _ in 0 .. 228 {
+            tia.clock();
+        }
+        assert_eq!(tia.read(0x003C), 0x80);
+    }
+
+    #[test]
+    fn test_swap_ports() {
+        let mut tia = TIA::new();
+        let joysticks = joysticks(&mut tia);
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, true);
+
+        // The held fire button moves over to the right port
+        tia.set_swap_ports(true);
+        assert_eq!(tia.read(0x003C), 0x80);
+        assert_eq!(tia.read(0x003D), 0x00);
+
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, false);
+        joysticks[1].borrow_mut().set_fire(Source::Keyboard, true);
+        assert_eq!(tia.read(0x003C), 0x00);
+        assert_eq!(tia.read(0x003D), 0x80);
+
+        // And paddle 0 is read from INPT2
+        tia.set_paddle(0, 255);
+        assert_eq!(tia.read(0x003A), 0x80);
+    }
+
+    #[test]
+    fn test_keypad() {
+        let mut tia = TIA::new();
+        let keypad = Rc::new(RefCell::new(Keypad::new()));
+        tia.plug_in(1, keypad.clone());
+
+        // The 7 key, in the third row and left column, with its row selected, grounds pin 9
+        keypad.borrow_mut().set_key(6, true);
+        keypad.borrow_mut().write(0b1011);
+        assert_eq!(tia.read(0x003A), 0x00);
+        assert_eq!(tia.read(0x003B), 0x80);
+        assert_eq!(tia.read(0x003D), 0x80);
+
+        // The left port's still the paddles
+        tia.set_paddle(0, 255);
+        assert_eq!(tia.read(0x0038), 0x80);
+
+        tia.write(0x0001, 0x80); // VBLANK
+        assert_eq!(tia.read(0x003B), 0x00);
+    }
+
+    #[test]
+    fn test_inpt4_latch() {
+        let mut tia = TIA::new();
+        let joysticks = joysticks(&mut tia);
+
+        // Latch disabled, the port is read directly
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, true);
+        assert_eq!(tia.read(0x003C), 0x00);
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, false);
+        assert_eq!(tia.read(0x003C), 0x80);
+
+        // Latch enabled, a press is held until the latch is re-enabled
+        tia.write(0x0001, 0x40); // VBLANK
+        assert_eq!(tia.read(0x003C), 0x80);
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, true);
+        tia.clock();
+        joysticks[0].borrow_mut().set_fire(Source::Keyboard, false);
+        assert_eq!(tia.read(0x003C), 0x00);
+        assert_eq!(tia.read(0x003D), 0x80);
+
+        tia.write(0x0001, 0x40); // VBLANK
+        assert_eq!(tia.read(0x003C), 0x00);
+
+        tia.write(0x0001, 0x00); // VBLANK
+        assert_eq!(tia.read(0x003C), 0x80);
+        tia.write(0x0001, 0x40); // VBLANK
+        assert_eq!(tia.read(0x003C), 0x80);
+    }
+
+    #[test]
+    fn test_inpt5_latch() {
+        let mut tia = TIA::new();
+        let joysticks = joysticks(&mut tia);
+
+        tia.write(0x0001, 0x40); // VBLANK
+        joysticks[1].borrow_mut().set_fire(Source::Keyboard, true);
+        tia.clock();
+        joysticks[1].borrow_mut().set_fire(Source::Keyboard, false);
+        assert_eq!(tia.read(0x003D), 0x00);
+        assert_eq!(tia.read(0x003C), 0x80);
+
+        tia.write(0x0001, 0x00); // VBLANK
+        assert_eq!(tia.read(0x003D), 0x80);
+    }
+
+    #[test]
+    fn test_object_states() {
+        let mut tia = TIA::new();
+        tia.write(0x001b, 0xa5); // GRP0
+        tia.write(0x0004, 0x07); // NUSIZ0
+        tia.write(0x0020, 0xf0); // HMP0
+        tia.write(0x0024, 0x70); // HMBL
+        tia.write(0x001f, 0x02); // ENABL
+
+        let objects = tia.objects();
+        assert_eq!(objects.p0.graphic, 0xa5);
+        assert_eq!(objects.p0.size, 4);
+        assert_eq!(objects.p0.copies, 0b111);
+        assert_eq!(objects.p0.hmove, -1);
+        assert_eq!(objects.bl.hmove, 7);
+        assert_eq!(objects.bl.graphic, 1);
+        assert_eq!(objects.m0.graphic, 0);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x0009, 0x84); // COLUBK
+        tia.write(0x000a, 0x31); // CTRLPF
+        tia.write(0x0005, 0x25); // NUSIZ1
+        tia.write(0x0025, 0x01); // VDELP0
+        tia.write(0x001b, 0xa5); // GRP0
+        tia.write(0x001c, 0x00); // GRP1, which latches GRP0's old value
+        tia.write(0x001b, 0x5a); // GRP0
+
+        let state = tia.state();
+        assert_eq!(state.position, tia.position());
+        assert_eq!(state.colors, [0x1e, 0x00, 0x00, 0x84]);
+        assert_eq!(state.ctrlpf, 0x31);
+        assert_eq!(state.nusiz, [0x00, 0x25]);
+        assert!(state.objects.p0.vdel);
+        assert_eq!(state.objects.p0.latches, [0x5a, 0xa5]);
+        assert_eq!(state.collisions, [0; 8]);
+    }
+
+    #[test]
+    fn test_playfield_write_mid_bit() {
+        let mut tia = TIA::new();
+        tia.write(0x0008, 0x5e); // COLUPF
+
+        // Pixels 16-19 are drawn from D7 of PF1. Writing to it part way through those pixels
+        // doesn't change the rest of them until the next scanline.
+        let (write_line, next_line) = write_at(&mut tia, 0x000e, 0x80, 68 + 17);
+        assert_eq!(color_positions(&write_line, 0x5e), vec![96, 97, 98, 99]);
+        assert_eq!(color_positions(&next_line, 0x5e), vec![16, 17, 18, 19, 96, 97, 98, 99]);
+
+        // Writing right before the first of those pixels takes effect straight away
+        tia.write(0x000e, 0x00); // PF1
+        let (write_line, _) = write_at(&mut tia, 0x000e, 0x80, 68 + 15);
+        assert_eq!(color_positions(&write_line, 0x5e), vec![16, 17, 18, 19, 96, 97, 98, 99]);
+    }
+
+    #[test]
+    fn test_playfield_reflect_mid_scanline() {
+        let mut tia = TIA::new();
+        tia.write(0x0008, 0x5e); // COLUPF
+        tia.write(0x000d, 0x10); // PF0
+
+        // Turning on reflection half way through the scanline only affects the right half
+        let (write_line, _) = write_at(&mut tia, 0x000a, 0x01, 68 + 79);
+        assert_eq!(color_positions(&write_line, 0x5e), vec![0, 1, 2, 3, 156, 157, 158, 159]);
+    }
+
+    #[test]
+    fn test_grp_latch_pairs() {
+        let mut tia = TIA::new();
+        tia.write(0x0025, 0x01); // VDELP0
+        tia.write(0x0026, 0x01); // VDELP1
+
+        // The sequence of writes from a 48-pixel sprite kernel, where each write to one player's
+        // graphic releases the previously written graphic of the other
+        tia.write(0x001b, 0x11); // GRP0
+        tia.write(0x001c, 0x22); // GRP1
+        tia.write(0x001b, 0x33); // GRP0
+
+        let objects = tia.objects();
+        assert_eq!((objects.p0.graphic, objects.p1.graphic), (0x11, 0x22));
+
+        tia.write(0x001c, 0x44); // GRP1
+        let objects = tia.objects();
+        assert_eq!((objects.p0.graphic, objects.p1.graphic), (0x33, 0x22));
+
+        tia.write(0x001b, 0x55); // GRP0
+        let objects = tia.objects();
+        assert_eq!((objects.p0.graphic, objects.p1.graphic), (0x33, 0x44));
+    }
+
+    #[test]
+    fn test_cosmic_ark_starfield() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x0004, 0x30); // NUSIZ0, 8 pixel wide missile
+        tia.write(0x001d, 0x02); // ENAM0
+        reset_at(&mut tia, 0x0012, 68 + 40); // RESM0
+        scanline(&mut tia);
+
+        // Without any movement, the missile is drawn 8 pixels wide
+        let pixels = scanline(&mut tia);
+        assert_eq!(color_positions(&pixels, 0x1e).len(), 8);
+
+        // Changing HMM0 part way through an HMOVE to a value the ripple counter has already
+        // passed means the comparison never matches, and the missile keeps on moving
+        tia.write(0x0022, 0x00); // HMM0
+        tia.write(0x002a, 0x00); // HMOVE
+        for _ in 0 .. 20 {
+            tia.clock();
+        }
+        tia.write(0x0022, 0x90); // HMM0
+        for _ in 20 .. 228 {
+            tia.clock();
+        }
+
+        // As the missile's counter now receives fewer than 160 clocks per scanline, it isn't drawn
+        // on every scanline
+        let mut positions = vec![];
+        for _ in 0 .. 8 {
+            let pixels = scanline(&mut tia);
+            let missile = color_positions(&pixels, 0x1e);
+
+            // It's drawn as a dot instead of its full width. The dot is 4 pixels, plus however many
+            // pixels it was stretched by its motion clocks being plugged.
+            if !missile.is_empty() {
+                assert!(missile.len() < 8, "missile: {:?}", missile);
+                positions.push(missile[0]);
+            }
+        }
+
+        // And it's in a different place each time it's drawn
+        assert!(positions.len() >= 2, "positions: {:?}", positions);
+        assert!(positions.windows(2).all(|w| w[0] != w[1]), "positions: {:?}", positions);
+    }
+
+    // Returns how many motion clocks P0 receives (modulo 160) on a scanline where HMOVE is strobed
+    // after `clocks` colour clocks
+    fn p0_motion_clocks(hmp0: u8, clocks: usize) -> usize {
+        let mut tia = TIA::new();
+        tia.write(0x0020, hmp0); // HMP0
+        scanline(&mut tia);
+
+        let start = tia.objects().p0.position as usize;
+        hmove_at(&mut tia, clocks);
+        let end = tia.objects().p0.position as usize;
+
+        (end + 160 - start) % 160
+    }
+
+    #[test]
+    fn test_hblank_motion_clocks() {
+        // HMOVE with no motion during HBLANK extends HBLANK by 8 pixels, so P0 misses out on 8
+        // motion clocks
+        for &clocks in [0, 20, 40, 60].iter() {
+            assert_eq!(p0_motion_clocks(0x80, clocks), 160 - 8, "HMOVE at {}", clocks);
+        }
+
+        // HMOVE with no motion during the visible scanline, doesn't extend HBLANK, so P0 gets all
+        // 160 motion clocks
+        for &clocks in [80, 120, 160, 200].iter() {
+            assert_eq!(p0_motion_clocks(0x80, clocks), 0, "HMOVE at {}", clocks);
+        }
+
+        // The extra clocks sent by HMOVE during HBLANK make up for the 8 missed ones
+        assert_eq!(p0_motion_clocks(0x00, 0), 0);
+        assert_eq!(p0_motion_clocks(0x70, 0), 7);
+        assert_eq!(p0_motion_clocks(0xf0, 0), 160 - 1);
+    }
+
+    #[test]
+    fn test_late_hblank_pixels() {
+        // Objects don't receive motion clocks during the extended HBLANK, so with no motion of its
+        // own, P0 ends up 8 pixels further right, starting from the scanline HMOVE was strobed on
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001b, 0xff); // GRP0
+        tia.write(0x0020, 0x80); // HMP0
+        reset_at(&mut tia, 0x0010, 68 + 20); // RESP0
+
+        let pixels = scanline(&mut tia);
+        assert_eq!(color_positions(&pixels, 0x1e), (26 .. 34).collect::<Vec<_>>());
+
+        let (hmove_line, next_line) = hmove_at(&mut tia, 0);
+        assert_eq!(color_positions(&hmove_line, 0x1e), (34 .. 42).collect::<Vec<_>>());
+        assert_eq!(color_positions(&next_line, 0x1e), (34 .. 42).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_position() {
+        let mut tia = TIA::new();
+        let start = tia.position();
+
+        for _ in 0 .. 228 * 2 + 69 {
+            tia.clock();
+        }
+
+        let position = tia.position();
+        assert_eq!(position.scanline, start.scanline + 2);
+        assert_eq!(position.clock, start.clock + 69);
+
+        // Starting VSYNC starts a new frame
+        tia.write(0x0000, 0x02);
+
+        let position = tia.position();
+        assert_eq!((position.frame, position.scanline), (start.frame + 1, 0));
+        assert_eq!(position.to_string(), format!("frame 1, scanline 0, cycle {} of 76", (start.clock + 69) / 3));
+    }
+
+    #[test]
+    fn test_power_cycle() {
+        let mut tia = TIA::new();
+        tia.write(0x0009, 0x1e); // COLUBK
+        tia.write(0x0019, 0x0f); // AUDV0
+        tia.write(0x0000, 0x02); // VSYNC
+        tia.toggle_audio_mute(1);
+        joysticks(&mut tia)[0].borrow_mut().set_fire(Source::Keyboard, true);
+
+        tia.power_cycle();
+
+        assert_eq!(scanline(&mut tia)[0], 0x00);
+        assert_eq!(tia.audio().audv(0), 0);
+        assert_eq!(tia.position().frame, 0);
+
+        // The mute setting and the joystick carry on
+        assert!(!tia.toggle_audio_mute(1));
+        assert_eq!(tia.read(0x000c) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut tia = TIA::new();
+        tia.write(0x0009, 0x1e); // COLUBK
+        scanline(&mut tia);
+        for _ in 0 .. 100 {
+            tia.clock();
+        }
+
+        let snapshot = tia.snapshot();
+        let state = tia.state();
+        let pixels = tia.get_scanline_pixels().clone();
+
+        tia.write(0x0009, 0x84); // COLUBK
+        tia.write(0x000d, 0xf0); // PF0
+        tia.write(0x0000, 0x02); // VSYNC
+        scanline(&mut tia);
+
+        tia.restore(&snapshot);
+        assert_eq!(tia.state(), state);
+        assert_eq!(tia.get_scanline_pixels(), &pixels);
+
+        // It carries on drawing from where it was, in the colours it had then
+        scanline(&mut tia);
+        assert_eq!(tia.position().scanline, state.position.scanline + 1);
+        assert_eq!(color_positions(tia.get_scanline_pixels(), 0x1e).len(), 160);
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut tia = TIA::new();
+        tia.write(0x0006, 0x1e); // COLUP0
+        tia.write(0x001b, 0xff); // GRP0
+        tia.write(0x0019, 0x0f); // AUDV0
+        for _ in 0 .. 100 {
+            tia.clock();
+        }
+        tia.write(0x0010, 0x00); // RESP0
+        scanline(&mut tia);
+
+        let mut saved = vec![];
+        tia.save(&mut saved).unwrap();
+
+        let mut loaded = TIA::new();
+        loaded.load(&mut saved.as_slice()).unwrap();
+        assert_eq!(loaded.state(), tia.state());
+        assert_eq!(loaded.audio().audv(0), 0x0f);
+
+        // The player's drawn in the same place on the next scanline, as it's positioned by its
+        // counter rather than its registers
+        assert_eq!(color_positions(&scanline(&mut loaded), 0x1e), color_positions(&scanline(&mut tia), 0x1e));
+        assert_eq!(color_positions(tia.get_scanline_pixels(), 0x1e).len(), 8);
+
+        assert!(loaded.load(&mut &saved[.. 10]).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use crate::bus::read_bytes;
+use crate::region::Region;
+
+// The TIA has two identical audio channels, each made up of a frequency divider, a 4-bit "pulse"
+// counter and a 5-bit "noise" counter. The AUDCx register selects how the two counters feed back
+// into each other, AUDFx sets the divider, and AUDVx sets the output volume.
+//
+// This is modelled on the logic described in the TIA schematics, and the way that Stella clocks
+// the channels: each channel has two phases per audio clock, and there are two audio clocks per
+// scanline.
+
+// The colour clocks within a scanline at which the two audio phases are clocked
+const PHASE0_CLOCKS: [u8; 2] = [9, 81];
+const PHASE1_CLOCKS: [u8; 2] = [37, 149];
+
+// Two audio clocks per scanline, at the region's scanlines per frame and frames per second
+pub fn sample_rate(region: Region) -> i32 {
+    2 * region.scanlines() as i32 * region.fps() as i32
+}
+
+// The loudest a single channel can be (AUDVx is a 4-bit value)
+const MAX_VOLUME: i16 = 15;
+
+// How many scanlines worth of each channel's output to keep around for visualising
+const WAVEFORM_LENGTH: usize = 256;
+
+struct Channel {
+    audc: u8,
+    audf: u8,
+    audv: u8,
+
+    clock_enable: bool,
+    noise_feedback: bool,
+    noise_counter_bit4: bool,
+    pulse_counter_hold: bool,
+
+    div_counter: u8,
+    pulse_counter: u8,
+    noise_counter: u8,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            audc: 0,
+            audf: 0,
+            audv: 0,
+
+            clock_enable: false,
+            noise_feedback: false,
+            noise_counter_bit4: false,
+            pulse_counter_hold: false,
+
+            div_counter: 0,
+            pulse_counter: 0,
+            noise_counter: 0,
+        }
+    }
+
+    fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.audc, self.audf, self.audv])?;
+        output.write_all(&[self.clock_enable as u8, self.noise_feedback as u8, self.noise_counter_bit4 as u8, self.pulse_counter_hold as u8])?;
+        output.write_all(&[self.div_counter, self.pulse_counter, self.noise_counter])
+    }
+
+    fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [audc, audf, audv] = read_bytes(input)?;
+        self.audc = audc;
+        self.audf = audf;
+        self.audv = audv;
+
+        let [clock_enable, noise_feedback, noise_counter_bit4, pulse_counter_hold] = read_bytes(input)?;
+        self.clock_enable = clock_enable != 0;
+        self.noise_feedback = noise_feedback != 0;
+        self.noise_counter_bit4 = noise_counter_bit4 != 0;
+        self.pulse_counter_hold = pulse_counter_hold != 0;
+
+        let [div_counter, pulse_counter, noise_counter] = read_bytes(input)?;
+        self.div_counter = div_counter;
+        self.pulse_counter = pulse_counter;
+        self.noise_counter = noise_counter;
+
+        Ok(())
+    }
+
+    fn phase0(&mut self) {
+        if self.clock_enable {
+            self.noise_counter_bit4 = (self.noise_counter & 0x01) != 0;
+
+            self.pulse_counter_hold = match self.audc & 0x03 {
+                0x02 => (self.noise_counter & 0x1e) != 0x02,
+                0x03 => !self.noise_counter_bit4,
+                _    => false,
+            };
+
+            self.noise_feedback = match self.audc & 0x03 {
+                0x00 => {
+                       ((self.pulse_counter ^ self.noise_counter) & 0x01) != 0
+                    || !(self.noise_counter != 0 || self.pulse_counter != 0x0a)
+                    || (self.audc & 0x0c) == 0
+                },
+                _ => {
+                       (((self.noise_counter & 0x04) >> 2) ^ (self.noise_counter & 0x01)) != 0
+                    || self.noise_counter == 0
+                },
+            };
+        }
+
+        self.clock_enable = self.div_counter == self.audf;
+
+        if self.div_counter == self.audf || self.div_counter == 0x1f {
+            self.div_counter = 0;
+        } else {
+            self.div_counter += 1;
+        }
+    }
+
+    fn phase1(&mut self) -> u8 {
+        if self.clock_enable {
+            let pulse_feedback = match self.audc >> 2 {
+                0x00 => {
+                       (((self.pulse_counter & 0x02) >> 1) ^ (self.pulse_counter & 0x01)) != 0
+                    && self.pulse_counter != 0x0a
+                    && (self.audc & 0x03) != 0
+                },
+                0x01 => (self.pulse_counter & 0x08) == 0,
+                0x02 => !self.noise_counter_bit4,
+                _    => !((self.pulse_counter & 0x02) != 0 || (self.pulse_counter & 0x0e) == 0),
+            };
+
+            self.noise_counter >>= 1;
+            if self.noise_feedback {
+                self.noise_counter |= 0x10;
+            }
+
+            if !self.pulse_counter_hold {
+                self.pulse_counter = !(self.pulse_counter >> 1) & 0x07;
+                if pulse_feedback {
+                    self.pulse_counter |= 0x08;
+                }
+            }
+        }
+
+        (self.pulse_counter & 0x01) * self.audv
+    }
+}
+
+pub struct Audio {
+    channels: [Channel; 2],
+
+    // Debugging aids for isolating a single channel's output
+    muted: [bool; 2],
+    solo: Option<usize>,
+
+    // The output of each channel from the last audio clock
+    volumes: [u8; 2],
+
+    // The output of each channel, once per scanline, oldest first
+    waveforms: [VecDeque<u8>; 2],
+
+    // Samples generated since the last call to `take_samples`
+    samples: Vec<i16>,
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self {
+            channels: [Channel::new(), Channel::new()],
+
+            muted: [false, false],
+            solo: None,
+
+            volumes: [0, 0],
+
+            waveforms: [
+                VecDeque::from(vec![0; WAVEFORM_LENGTH]),
+                VecDeque::from(vec![0; WAVEFORM_LENGTH]),
+            ],
+
+            samples: Vec::new(),
+        }
+    }
+
+    // The channels, and their output from the last audio clock. The mute and solo settings are
+    // for debugging, so they aren't part of the console's state.
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        for channel in &self.channels {
+            channel.save(output)?;
+        }
+        output.write_all(&self.volumes)
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        for channel in self.channels.iter_mut() {
+            channel.load(input)?;
+        }
+        self.volumes = read_bytes(input)?;
+
+        Ok(())
+    }
+
+    // Silences both channels, keeping the mute and solo settings
+    pub fn power_cycle(&mut self) {
+        self.channels = [Channel::new(), Channel::new()];
+    }
+
+    pub fn set_audc(&mut self, channel: usize, val: u8) { self.channels[channel].audc = val & 0x0f }
+    pub fn set_audf(&mut self, channel: usize, val: u8) { self.channels[channel].audf = val & 0x1f }
+    pub fn set_audv(&mut self, channel: usize, val: u8) { self.channels[channel].audv = val & 0x0f }
+
+    pub fn audc(&self, channel: usize) -> u8 { self.channels[channel].audc }
+    pub fn audf(&self, channel: usize) -> u8 { self.channels[channel].audf }
+    pub fn audv(&self, channel: usize) -> u8 { self.channels[channel].audv }
+    pub fn waveform(&self, channel: usize) -> &VecDeque<u8> { &self.waveforms[channel] }
+
+    pub fn toggle_mute(&mut self, channel: usize) -> bool {
+        self.muted[channel] = !self.muted[channel];
+        self.muted[channel]
+    }
+
+    pub fn toggle_solo(&mut self, channel: usize) -> bool {
+        self.solo = if self.solo == Some(channel) { None } else { Some(channel) };
+        self.solo.is_some()
+    }
+
+    fn audible(&self, channel: usize) -> bool {
+        match self.solo {
+            Some(c) => c == channel,
+            None    => !self.muted[channel],
+        }
+    }
+
+    // Called on every colour clock with the position within the current scanline
+    pub fn clock(&mut self, color_clock: u8) {
+        if PHASE0_CLOCKS.contains(&color_clock) {
+            for channel in self.channels.iter_mut() {
+                channel.phase0();
+            }
+        } else if PHASE1_CLOCKS.contains(&color_clock) {
+            for (i, channel) in self.channels.iter_mut().enumerate() {
+                self.volumes[i] = channel.phase1();
+            }
+
+            self.mix();
+
+            if color_clock == PHASE1_CLOCKS[1] {
+                for (i, waveform) in self.waveforms.iter_mut().enumerate() {
+                    waveform.pop_front();
+                    waveform.push_back(self.volumes[i]);
+                }
+            }
+        }
+    }
+
+    fn mix(&mut self) {
+        let mut sample = 0;
+
+        for i in 0 .. self.channels.len() {
+            if self.audible(i) {
+                sample += self.volumes[i] as i16;
+            }
+        }
+
+        // Scale the summed volumes into the full range of a signed 16-bit sample
+        self.samples.push(sample * (i16::MAX / (MAX_VOLUME * 2)));
+    }
+
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+}
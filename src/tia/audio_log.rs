@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::region::Region;
+
+// Records every write to the audio registers, along with the colour clock it happened on, so that
+// a game's music can be replayed outside of the emulator.
+//
+// The format is plain text, one write per line:
+//
+//     ; atari2600 tia audio log v1
+//     ; clock-rate 3579545
+//     <colour clock> <register> <value>
+//
+// Where <colour clock> is a decimal count of TIA colour clocks since power on, <register> is one of
+// AUDC0, AUDC1, AUDF0, AUDF1, AUDV0, or AUDV1, and <value> is the byte written, in hex. Lines
+// starting with a semicolon are comments. The clock rate is the region's colour clock frequency, in
+// Hz, and when the region's detected part way through, another clock-rate line gives the rate for
+// the writes after it.
+
+pub struct AudioLog {
+    out: BufWriter<File>,
+}
+
+impl AudioLog {
+    pub fn new(file: File, region: Region) -> io::Result<Self> {
+        let mut out = BufWriter::new(file);
+
+        writeln!(out, "; atari2600 tia audio log v1")?;
+        writeln!(out, "; clock-rate {}", region.clock_rate())?;
+
+        Ok(Self {
+            out: out,
+        })
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        if let Err(e) = writeln!(self.out, "; clock-rate {}", region.clock_rate()) {
+            error!("unable to write audio log: {}", e);
+        }
+    }
+
+    pub fn record(&mut self, clock: u64, address: u16, val: u8) {
+        let register = match address {
+            0x0015 => "AUDC0",
+            0x0016 => "AUDC1",
+            0x0017 => "AUDF0",
+            0x0018 => "AUDF1",
+            0x0019 => "AUDV0",
+            0x001a => "AUDV1",
+            _      => return,
+        };
+
+        if let Err(e) = writeln!(self.out, "{} {} {:02x}", clock, register, val) {
+            error!("unable to write audio log: {}", e);
+        }
+    }
+}
@@ -1,12 +1,17 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::io::{self, Read, Write};
 
+use crate::bus::read_bytes;
+use crate::tia::{load_option, save_option};
 use crate::tia::color::Colors;
 use crate::tia::counter::Counter;
+use crate::tia::object_state::ObjectState;
 
 const INIT_DELAY: isize = 4;
 const GRAPHIC_SIZE: isize = 1;
 
+#[derive(Clone)]
 pub struct Ball {
     colors: Rc<RefCell<Colors>>,
 
@@ -14,8 +19,8 @@ pub struct Ball {
     ctr: Counter,
 
     enabled: bool,
-    // The ball sizee from the CTRLPF register
-    nusiz: usize,
+    // The ball size from the CTRLPF register
+    size: usize,
 
     // The VDELBL register
     vdel: bool,
@@ -36,7 +41,7 @@ impl Ball {
             ctr: Counter::new(40, 39),
 
             enabled: false,
-            nusiz: 1,
+            size: 1,
 
             vdel: false,
             old_value: false,
@@ -47,27 +52,53 @@ impl Ball {
         }
     }
 
+    // The registers, then the position counter, then the graphics scan counter
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.hmove_offset, self.enabled as u8, self.size as u8, self.vdel as u8, self.old_value as u8])?;
+        self.ctr.save(output)?;
+
+        save_option(output, self.graphic_bit_idx.map(|i| i as i64))?;
+        output.write_all(&(self.graphic_bit_copies_written as u64).to_le_bytes())?;
+        save_option(output, self.graphic_bit_value.map(|v| v as i64))
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [hmove_offset, enabled, size, vdel, old_value] = read_bytes(input)?;
+        self.hmove_offset = hmove_offset;
+        self.enabled = enabled != 0;
+        self.size = size as usize;
+        self.vdel = vdel != 0;
+        self.old_value = old_value != 0;
+        self.ctr.load(input)?;
+
+        self.graphic_bit_idx = load_option(input)?.map(|i| i as isize);
+        self.graphic_bit_copies_written = u64::from_le_bytes(read_bytes(input)?) as usize;
+        self.graphic_bit_value = load_option(input)?.map(|v| v != 0);
+
+        Ok(())
+    }
+
     pub fn set_enabled(&mut self, v: bool) { self.enabled = v }
     pub fn set_hmove_value(&mut self, v: u8) { self.hmove_offset = v }
     pub fn set_vdel(&mut self, v: bool) { self.vdel = v }
     pub fn set_vdel_value(&mut self) { self.old_value = self.enabled }
-    pub fn set_nusiz(&mut self, size: usize) { self.nusiz = size }
+    pub fn set_size(&mut self, size: usize) { self.size = size }
     pub fn hmclr(&mut self) { self.hmove_offset = 0 }
-    pub fn reset(&mut self) {
-        self.ctr.reset();
+    pub fn reset(&mut self, offset: u8) {
+        self.ctr.reset_object(offset);
 
-        if self.should_draw_graphic() || self.should_draw_copy() {
-            self.graphic_bit_idx = Some(-1 * INIT_DELAY);
-            self.graphic_bit_copies_written = 0;
-        }
+        // RESBL sends a START signal, so the ball starts drawing immediately
+        self.graphic_bit_idx = Some(-1 * INIT_DELAY);
+        self.graphic_bit_copies_written = 0;
     }
 
     pub fn start_hmove(&mut self) {
-        self.ctr.start_hmove(self.hmove_offset);
-        self.tick_graphic_circuit();
+        self.ctr.start_hmove();
     }
 
-    fn size(&self) -> usize { self.nusiz }
+    pub fn moving(&self) -> bool { self.ctr.moving() }
+
+    fn size(&self) -> usize { self.size }
     fn pixel_bit(&self) -> bool {
         if self.vdel {
             self.old_value
@@ -107,6 +138,10 @@ impl Ball {
     fn should_draw_copy(&self) -> bool { false }
 
     pub fn clock(&mut self) {
+        if self.ctr.take_plugged() {
+            return;
+        }
+
         self.tick_graphic_circuit();
 
         if self.ctr.clock() && (self.should_draw_graphic() || self.should_draw_copy()) {
@@ -115,8 +150,8 @@ impl Ball {
         }
     }
 
-    pub fn apply_hmove(&mut self) {
-        let (moved, counter_clocked) = self.ctr.apply_hmove(self.hmove_offset);
+    pub fn movement_tick(&mut self, movement_clock: u8, hblank: bool) {
+        let (moved, counter_clocked) = self.ctr.movement_tick(movement_clock, self.hmove_offset, hblank);
 
         if counter_clocked && (self.should_draw_graphic() || self.should_draw_copy()) {
             self.graphic_bit_idx = Some(-1 * INIT_DELAY);
@@ -135,4 +170,22 @@ impl Ball {
 
         return None;
     }
+
+    pub fn state(&self) -> ObjectState {
+        ObjectState {
+            position: self.ctr.internal_value,
+            hmove: ObjectState::hmove(self.hmove_offset),
+            moving: self.moving(),
+
+            graphic: self.pixel_bit() as u8,
+            vdel: self.vdel,
+            reflected: false,
+            latches: [self.enabled as u8, self.old_value as u8],
+
+            size: self.size(),
+            copies: 0,
+
+            scan: self.graphic_bit_idx,
+        }
+    }
 }
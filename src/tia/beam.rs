@@ -0,0 +1,32 @@
+use std::fmt;
+
+// The CPU runs at a third of the TIA's colour clock, so there are 76 CPU cycles per scanline
+const CLOCKS_PER_CPU_CYCLE: u8 = 3;
+const CPU_CYCLES_PER_SCANLINE: u8 = 76;
+
+// Where the beam is, for showing timing the way 2600 programs count it: by scanline, and by CPU
+// cycle within the scanline
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BeamPosition {
+    // The number of frames since power on, a frame starting with VSYNC
+    pub frame: u64,
+    // The scanline since the start of the frame
+    pub scanline: u16,
+    // The colour clock within the scanline (0-227, with the visible picture starting at 68)
+    pub clock: u8,
+}
+
+impl BeamPosition {
+    // The CPU cycle within the scanline (0-75)
+    pub fn cycle(&self) -> u8 { self.clock / CLOCKS_PER_CPU_CYCLE }
+}
+
+impl fmt::Display for BeamPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}, scanline {}, cycle {} of {}",
+               self.frame,
+               self.scanline,
+               self.cycle(),
+               CPU_CYCLES_PER_SCANLINE)
+    }
+}
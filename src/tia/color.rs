@@ -1,3 +1,8 @@
+use std::io::{self, Read, Write};
+
+use crate::bus::read_bytes;
+
+#[derive(Clone)]
 pub struct Colors {
     colup0: u8,
     colup1: u8,
@@ -15,6 +20,20 @@ impl Colors {
         }
     }
 
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.colup0, self.colup1, self.colupf, self.colubk])
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [colup0, colup1, colupf, colubk] = read_bytes(input)?;
+        self.colup0 = colup0;
+        self.colup1 = colup1;
+        self.colupf = colupf;
+        self.colubk = colubk;
+
+        Ok(())
+    }
+
     pub fn set_colup0(&mut self, val: u8) {
         self.colup0 = val;
     }
@@ -1,3 +1,8 @@
+use std::io::{self, Read, Write};
+
+use crate::bus::read_bytes;
+
+#[derive(Clone)]
 pub struct Counter {
     period: u8,
     reset_value: u8,
@@ -5,8 +10,11 @@ pub struct Counter {
     pub internal_value: u8,
 
     last_value: u8,
-    ticks_added: u8,
+
+    // The "more movement required" latch, set by HMOVE
     movement_required: bool,
+    // Whether the next motion clock has been plugged by an HMOVE during the visible scanline
+    plugged: bool,
 }
 
 fn hmove_value(v: u8) -> u8 {
@@ -28,11 +36,29 @@ impl Counter {
             reset_delay: 0,
 
             last_value: 0,
-            ticks_added: 0,
+
             movement_required: false,
+            plugged: false,
         }
     }
 
+    // The count and the movement latches. The period and reset value are fixed for each counter.
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.internal_value, self.reset_delay, self.last_value])?;
+        output.write_all(&[self.movement_required as u8, self.plugged as u8])
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [internal_value, reset_delay, last_value, movement_required, plugged] = read_bytes(input)?;
+        self.internal_value = internal_value;
+        self.reset_delay = reset_delay;
+        self.last_value = last_value;
+        self.movement_required = movement_required != 0;
+        self.plugged = plugged != 0;
+
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         self.internal_value = self.reset_value * 4;
     }
@@ -41,6 +67,14 @@ impl Counter {
         self.internal_value / 4
     }
 
+    // Reset an object's position counter from one of the RESxx registers. The counter ends up
+    // `offset` clocks further along depending on where in the scanline the reset happened, and
+    // unlike a normal wrap around, this doesn't count as the counter's value changing.
+    pub fn reset_object(&mut self, offset: u8) {
+        self.internal_value = self.reset_value * 4 + offset;
+        self.last_value = self.value();
+    }
+
     pub fn reset_to(&mut self, v: u8) {
         self.internal_value = v;
     }
@@ -77,21 +111,43 @@ impl Counter {
         }
     }
 
-    pub fn start_hmove(&mut self, hm_val: u8) {
-        self.ticks_added = 0;
-        self.movement_required = hmove_value(hm_val) != 0;
+    pub fn start_hmove(&mut self) {
+        self.movement_required = true;
     }
 
-    pub fn apply_hmove(&mut self, hm_val: u8) -> (bool, bool) {
+    pub fn moving(&self) -> bool { self.movement_required }
+
+    // Called every 4 CLK while an HMOVE is in progress, with the current state of the TIA's HMOVE
+    // ripple counter. Returns whether an extra clock was sent to the counter, and whether that
+    // clock changed the counter's value.
+    pub fn movement_tick(&mut self, movement_clock: u8, hm_val: u8, hblank: bool) -> (bool, bool) {
+        // Once the ripple counter matches the HMxx value the latch is cleared, and no more
+        // movement happens.
+        if movement_clock == hmove_value(hm_val) {
+            self.movement_required = false;
+        }
+
         if !self.movement_required {
             return (false, false);
         }
 
-        let clocked = self.clock();
-        self.ticks_added += 1;
-        self.movement_required = self.ticks_added != hmove_value(hm_val);
+        if hblank {
+            // During HBLANK the extra clock is "stuffed" into the counter, moving it left
+            let clocked = self.clock();
+            return (true, clocked);
+        } else {
+            // During the visible scanline the extra clock lands between the normal motion clocks,
+            // and "plugs" the next one, moving it right
+            self.plugged = true;
+            return (false, false);
+        }
+    }
 
-        return (true, clocked);
+    // Returns whether the next motion clock should be ignored, because it was plugged by HMOVE
+    pub fn take_plugged(&mut self) -> bool {
+        let plugged = self.plugged;
+        self.plugged = false;
+        plugged
     }
 }
 
@@ -101,7 +157,7 @@ mod tests {
 
     #[test]
     fn test_clocking() {
-        let mut ctr = Counter::new_counter(40, 0);
+        let mut ctr = Counter::new(40, 0);
 
         assert_eq!(ctr.value(), 0);
 
@@ -150,7 +206,7 @@ mod tests {
     fn test_scanline_counting() {
         // p0, p0, m0, and m1 use a 40 clock counter, so they should reset back to 0 after a full
         // scanline has finished rendering.
-        let mut ctr = Counter::new_counter(40, 0);
+        let mut ctr = Counter::new(40, 0);
 
         assert_eq!(ctr.value(), 0);
 
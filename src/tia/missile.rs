@@ -1,29 +1,43 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::io::{self, Read, Write};
 
+use crate::bus::read_bytes;
 use crate::tia::PlayerType;
+use crate::tia::{load_option, save_option};
 use crate::tia::color::Colors;
 use crate::tia::counter::Counter;
-use crate::tia::player::Player;
+use crate::tia::object_state::ObjectState;
+use crate::tia::player::{draws_copy, Player};
 
 const INIT_DELAY: isize = 4;
 const GRAPHIC_SIZE: isize = 1;
 
+#[derive(Clone)]
 pub struct Missile {
     colors: Rc<RefCell<Colors>>,
     sibling_player: PlayerType,
 
     enabled: bool,
     hmove_offset: u8,
-    nusiz: u8,
+    ctr: Counter,
+
+    // The size of the missile, from D4-D5 of NUSIZx, and the number of copies, from D0-D2. The
+    // copies are spaced the same as the sibling player's, but unlike the player, the missile isn't
+    // stretched by the double and quad sized player settings.
     size: u8,
     copies: u8,
-    ctr: Counter,
+
+    // The RESMPx register, locking the missile to its player
+    locked: bool,
 
     // Graphics Scan Counter
     graphic_bit_idx: Option<isize>,
     graphic_bit_copies_written: usize,
     graphic_bit_value: Option<bool>,
+
+    // A change to the missile size while it's being drawn only takes effect once it's finished
+    pending_size: Option<u8>,
 }
 
 impl Missile {
@@ -34,49 +48,107 @@ impl Missile {
 
             enabled: false,
             hmove_offset: 0,
-            nusiz: 0,
-            size: 0,
-            copies: 0,
             ctr: Counter::new(40, 39),
 
+            size: 1,
+            copies: 0,
+
+            locked: false,
+
             graphic_bit_idx: None,
             graphic_bit_copies_written: 0,
             graphic_bit_value: None,
+
+            pending_size: None,
         }
     }
 
+    // The registers, then the position counter, then the graphics scan counter
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.enabled as u8, self.hmove_offset, self.size, self.copies, self.locked as u8])?;
+        self.ctr.save(output)?;
+
+        save_option(output, self.graphic_bit_idx.map(|i| i as i64))?;
+        output.write_all(&(self.graphic_bit_copies_written as u64).to_le_bytes())?;
+        save_option(output, self.graphic_bit_value.map(|v| v as i64))?;
+        save_option(output, self.pending_size.map(|s| s as i64))
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [enabled, hmove_offset, size, copies, locked] = read_bytes(input)?;
+        self.enabled = enabled != 0;
+        self.hmove_offset = hmove_offset;
+        self.size = size;
+        self.copies = copies;
+        self.locked = locked != 0;
+        self.ctr.load(input)?;
+
+        self.graphic_bit_idx = load_option(input)?.map(|i| i as isize);
+        self.graphic_bit_copies_written = u64::from_le_bytes(read_bytes(input)?) as usize;
+        self.graphic_bit_value = load_option(input)?.map(|v| v != 0);
+        self.pending_size = load_option(input)?.map(|s| s as u8);
+
+        Ok(())
+    }
+
     pub fn set_enabled(&mut self, en: bool) { self.enabled = en }
     pub fn set_hmove_value(&mut self, v: u8) { self.hmove_offset = v }
     pub fn set_nusiz(&mut self, val: u8) {
-        self.nusiz = val;
-        self.size = 1 << ((val & 0b0011_0000) >> 4);
         self.copies = val & 0x07;
-    }
-    pub fn hmclr(&mut self) { self.hmove_offset = 0 }
-    pub fn reset(&mut self) {
-        self.ctr.reset();
 
-        if self.should_draw_graphic() || self.should_draw_copy() {
-            self.graphic_bit_idx = Some(-1 * INIT_DELAY);
-            self.graphic_bit_copies_written = 0;
+        let size = 1 << ((val & 0b0011_0000) >> 4);
+        if self.drawing() {
+            self.pending_size = Some(size);
+        } else {
+            self.size = size;
+            self.pending_size = None;
         }
     }
+    pub fn set_locked(&mut self, v: bool) { self.locked = v }
+    pub fn locked(&self) -> bool { self.locked }
+    pub fn hmclr(&mut self) { self.hmove_offset = 0 }
+    pub fn reset(&mut self, offset: u8) {
+        // Unlike the ball, resetting the position doesn't send a START signal, so the main copy
+        // isn't drawn until the counter wraps around on the next scanline.
+        self.ctr.reset_object(offset);
+    }
 
     pub fn start_hmove(&mut self) {
-        self.ctr.start_hmove(self.hmove_offset);
-        self.tick_graphic_circuit();
+        self.ctr.start_hmove();
     }
 
+    pub fn moving(&self) -> bool { self.ctr.moving() }
+
     fn size(&self) -> usize { self.size as usize }
-    fn pixel_bit(&self) -> bool { self.enabled }
+    // While locked to its player, the missile is hidden regardless of ENAMx
+    fn pixel_bit(&self) -> bool { self.enabled && !self.locked }
+
+    fn drawing(&self) -> bool {
+        match self.graphic_bit_idx {
+            Some(idx) => idx >= 0,
+            None      => false,
+        }
+    }
+
+    // `starfield` is set when the missile is still receiving HMOVE clocks while being drawn by its
+    // normal motion clocks. This limits it to being drawn at most 4 pixels wide, which is what
+    // turns the continuously moving missiles of Cosmic Ark's starfield into dots rather than a
+    // solid stripe.
+    fn tick_graphic_circuit(&mut self, starfield: bool) {
+        if !self.drawing() {
+            if let Some(size) = self.pending_size.take() {
+                self.size = size;
+            }
+        }
 
-    fn tick_graphic_circuit(&mut self) {
         if let Some(mut idx) = self.graphic_bit_idx {
             if idx >= 0 && idx < 8 {
                 self.graphic_bit_value = Some(self.pixel_bit());
 
+                let size = if starfield { self.size().min(4) } else { self.size() };
+
                 self.graphic_bit_copies_written += 1;
-                if self.graphic_bit_copies_written == self.size() {
+                if self.graphic_bit_copies_written >= size {
                     self.graphic_bit_copies_written = 0;
                     idx += 1;
                 }
@@ -99,15 +171,15 @@ impl Missile {
     }
 
     fn should_draw_copy(&self) -> bool {
-        let count = self.ctr.value();
-
-           (count == 3  && (self.copies == 0b001 || self.copies == 0b011))
-        || (count == 7  && (self.copies == 0b010 || self.copies == 0b011 || self.copies == 0b110))
-        || (count == 15 && (self.copies == 0b100 || self.copies == 0b110))
+        draws_copy(self.copies, self.ctr.value())
     }
 
     pub fn clock(&mut self) {
-        self.tick_graphic_circuit();
+        if self.ctr.take_plugged() {
+            return;
+        }
+
+        self.tick_graphic_circuit(self.moving());
 
         if self.ctr.clock() && (self.should_draw_graphic() || self.should_draw_copy()) {
             self.graphic_bit_idx = Some(-1 * INIT_DELAY);
@@ -115,12 +187,25 @@ impl Missile {
         }
     }
 
+    // Position the missile in the center of its player. The offset from the player's left edge
+    // depends on the player's size.
     pub fn reset_to_player(&mut self, player: &Player) {
-        self.ctr.reset_to(player.counter().internal_value);
+        let offset = match player.size() {
+            1 => 3,
+            2 => 6,
+            _ => 10,
+        };
+
+        // Missiles start drawing one pixel earlier than players do, so lag behind by one more.
+        let period = 160;
+        let position = player.counter().internal_value as usize;
+        let value = (position + period - offset - 1) % period;
+
+        self.ctr.reset_to(value as u8);
     }
 
-    pub fn apply_hmove(&mut self) {
-        let (moved, counter_clocked) = self.ctr.apply_hmove(self.hmove_offset);
+    pub fn movement_tick(&mut self, movement_clock: u8, hblank: bool) {
+        let (moved, counter_clocked) = self.ctr.movement_tick(movement_clock, self.hmove_offset, hblank);
 
         if counter_clocked && (self.should_draw_graphic() || self.should_draw_copy()) {
             self.graphic_bit_idx = Some(-1 * INIT_DELAY);
@@ -128,7 +213,7 @@ impl Missile {
         }
 
         if moved {
-            self.tick_graphic_circuit();
+            self.tick_graphic_circuit(false);
         }
     }
 
@@ -145,14 +230,33 @@ impl Missile {
         return None;
     }
 
+    pub fn state(&self) -> ObjectState {
+        ObjectState {
+            position: self.ctr.internal_value,
+            hmove: ObjectState::hmove(self.hmove_offset),
+            moving: self.moving(),
+
+            graphic: self.pixel_bit() as u8,
+            vdel: false,
+            reflected: false,
+            latches: [0, 0],
+
+            size: self.size(),
+            copies: self.copies,
+
+            scan: self.graphic_bit_idx,
+        }
+    }
+
     pub fn debug(&self) {
         if !self.should_draw_graphic() && !self.should_draw_copy() {
             return
         }
 
-        println!("ctr: {}, nusiz: {:03b}, gv: {:?}",
+        println!("ctr: {}, size: {}, copies: {:03b}, gv: {:?}",
                  self.ctr.value(),
-                 self.nusiz,
+                 self.size,
+                 self.copies,
                  self.graphic_bit_value,
         );
     }
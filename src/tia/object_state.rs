@@ -0,0 +1,167 @@
+use std::fmt;
+
+use crate::tia::beam::BeamPosition;
+use crate::tia::playfield::register_bit;
+
+// A snapshot of the state of one of the TIA's movable objects, so that the debugger (and anything
+// else) can show what an object is doing without reaching into its internals.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectState {
+    // The object's position counter, in pixels (0-159)
+    pub position: u8,
+    // The HMxx motion value, in pixels to the left (-8 to 7)
+    pub hmove: i8,
+    // Whether HMOVE is still moving the object
+    pub moving: bool,
+
+    // The graphic being drawn: GRPx for players (the delayed copy when VDELPx is set), or 1/0 for
+    // whether missiles and the ball are enabled
+    pub graphic: u8,
+    pub vdel: bool,
+    pub reflected: bool,
+    // The new and old values of GRPx for players, or ENABL for the ball, that VDELxx chooses
+    // between. The old value is latched from the new one when the other player's GRPx is written.
+    // Missiles don't have them.
+    pub latches: [u8; 2],
+
+    // How many pixels wide each bit of the graphic is drawn
+    pub size: usize,
+    // The number of copies bits from NUSIZx (always 0 for the ball)
+    pub copies: u8,
+
+    // Which bit of the graphic the scan counter is on, or None when it's not drawing. This is
+    // negative while the scan counter is starting up.
+    pub scan: Option<isize>,
+}
+
+impl ObjectState {
+    pub fn hmove(hmove_offset: u8) -> i8 { (hmove_offset as i8) >> 4 }
+}
+
+impl fmt::Display for ObjectState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pos: {:3}, hm: {:2}{}, grp: {:08b}, vdel: {}, ref: {}, size: {}, copies: {:03b}, scan: {:?}",
+               self.position,
+               self.hmove,
+               if self.moving { " (moving)" } else { "" },
+               self.graphic,
+               self.vdel,
+               self.reflected,
+               self.size,
+               self.copies,
+               self.scan)
+    }
+}
+
+// The state of all of the movable objects
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectStates {
+    pub p0: ObjectState,
+    pub p1: ObjectState,
+    pub m0: ObjectState,
+    pub m1: ObjectState,
+    pub bl: ObjectState,
+}
+
+impl fmt::Display for ObjectStates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "P0: {}", self.p0)?;
+        writeln!(f, "P1: {}", self.p1)?;
+        writeln!(f, "M0: {}", self.m0)?;
+        writeln!(f, "M1: {}", self.m1)?;
+        write!(f, "BL: {}", self.bl)
+    }
+}
+
+// A snapshot of the playfield registers
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayfieldState {
+    // PF0, PF1, and PF2
+    pub registers: [u8; 3],
+    // The reflect bit of CTRLPF
+    pub reflected: bool,
+}
+
+impl PlayfieldState {
+    // Returns bit `idx` of the 20-bit playfield
+    pub fn bit(&self, idx: usize) -> bool {
+        let (register, bit) = register_bit(idx);
+        (self.registers[register] >> bit) & 0x01 != 0
+    }
+
+    // Returns which bit of the 20-bit playfield is drawn in each of the 40 columns of the screen.
+    // The right half of the screen repeats the playfield, or reflects it.
+    pub fn column_bit(&self, column: usize) -> usize {
+        if column < 20 {
+            column
+        } else if self.reflected {
+            39 - column
+        } else {
+            column - 20
+        }
+    }
+}
+
+// A snapshot of everything in the TIA that affects the picture, for inspecting it in the debugger
+#[derive(Clone, Debug, PartialEq)]
+pub struct TiaState {
+    pub position: BeamPosition,
+    pub vsync: bool,
+    pub vblank: u8,
+    pub wsync: bool,
+
+    // COLUP0, COLUP1, COLUPF, and COLUBK
+    pub colors: [u8; 4],
+
+    // The last values written to CTRLPF, NUSIZ0, and NUSIZ1
+    pub ctrlpf: u8,
+    pub nusiz: [u8; 2],
+
+    pub playfield: PlayfieldState,
+    pub objects: ObjectStates,
+
+    // CXM0P, CXM1P, CXP0FB, CXP1FB, CXM0FB, CXM1FB, CXBLPF, and CXPPMM
+    pub collisions: [u8; 8],
+}
+
+const COLLISION_REGISTERS: [&str; 8] = ["CXM0P", "CXM1P", "CXP0FB", "CXP1FB", "CXM0FB", "CXM1FB", "CXBLPF", "CXPPMM"];
+
+impl fmt::Display for TiaState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on = |b: bool| if b { "on" } else { "off" };
+
+        writeln!(f, "Beam: {}, clock {}, vsync: {}, vblank: {:08b}, wsync: {}",
+                 self.position, self.position.clock, on(self.vsync), self.vblank, on(self.wsync))?;
+
+        writeln!(f, "COLUP0: ${:02X}, COLUP1: ${:02X}, COLUPF: ${:02X}, COLUBK: ${:02X}",
+                 self.colors[0], self.colors[1], self.colors[2], self.colors[3])?;
+
+        writeln!(f, "CTRLPF: {:08b} (reflect: {}, score: {}, priority: {}, ball size: {})",
+                 self.ctrlpf,
+                 on(self.ctrlpf & 0x01 != 0),
+                 on(self.ctrlpf & 0x02 != 0),
+                 on(self.ctrlpf & 0x04 != 0),
+                 1 << ((self.ctrlpf >> 4) & 0x03))?;
+
+        for (i, nusiz) in self.nusiz.iter().enumerate() {
+            writeln!(f, "NUSIZ{}: {:08b} (copies: {:03b}, missile size: {})", i, nusiz, nusiz & 0x07, 1 << ((nusiz >> 4) & 0x03))?;
+        }
+
+        writeln!(f, "PF0: {:08b}, PF1: {:08b}, PF2: {:08b}",
+                 self.playfield.registers[0], self.playfield.registers[1], self.playfield.registers[2])?;
+
+        let objects = [("P0", &self.objects.p0), ("P1", &self.objects.p1), ("BL", &self.objects.bl)];
+        writeln!(f, "{}", self.objects)?;
+        for (name, object) in objects.iter() {
+            writeln!(f, "{} latches: new {:08b}, old {:08b}", name, object.latches[0], object.latches[1])?;
+        }
+
+        let collisions = COLLISION_REGISTERS.iter()
+            .zip(self.collisions.iter())
+            .map(|(name, value)| format!("{}: {:02b}", name, value >> 6))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        write!(f, "{}", collisions)
+    }
+}
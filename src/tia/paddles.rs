@@ -0,0 +1,101 @@
+// The four "dumped" input ports, INPT0-INPT3, used for the paddle controllers.
+//
+// Each paddle is a potentiometer in series with a capacitor inside the TIA. Setting D7 of VBLANK
+// grounds ("dumps") the capacitors. Once released, each capacitor charges through its paddle's
+// potentiometer, and the port reads as 0 until the capacitor charges past a threshold, at which
+// point D7 of the port goes high. So the time it takes for the port to go high is proportional
+// to the paddle's resistance, i.e. its position.
+
+use std::io::{self, Read, Write};
+
+use crate::bus::read_bytes;
+
+// How many scanlines it takes for the capacitor to charge with the paddle turned all the way
+// counter-clockwise (the maximum resistance). Games typically read the paddles for the duration
+// of the visible picture.
+const MAX_CHARGE_SCANLINES: u64 = 220;
+const CLOCKS_PER_SCANLINE: u64 = 228;
+
+#[derive(Clone)]
+pub struct Paddles {
+    // The position of each paddle, 0 being fully counter-clockwise and 255 fully clockwise
+    positions: [u8; 4],
+
+    // Ports whose line is held at ground by a button, e.g. a Sega Genesis pad's C button
+    grounded: [bool; 4],
+
+    dumped: bool,
+
+    // The colour clock that the capacitors were last released from ground at
+    released_at: u64,
+}
+
+impl Paddles {
+    pub fn new() -> Self {
+        Self {
+            positions: [128; 4],
+            grounded: [false; 4],
+            dumped: false,
+            released_at: 0,
+        }
+    }
+
+    // Whether the capacitors are dumped, and when they were released. The paddles' positions and
+    // buttons come from the controllers, so they're left as they are.
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.dumped as u8])?;
+        output.write_all(&self.released_at.to_le_bytes())
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [dumped] = read_bytes(input)?;
+        self.dumped = dumped != 0;
+        self.released_at = u64::from_le_bytes(read_bytes(input)?);
+
+        Ok(())
+    }
+
+    pub fn set_position(&mut self, paddle: usize, position: u8) {
+        self.positions[paddle] = position;
+    }
+
+    // A button wired to the port instead of a paddle, which connects the line straight to the
+    // supply (so it charges immediately) when it's released, and to ground when it's pressed
+    pub fn set_button(&mut self, paddle: usize, pressed: bool) {
+        self.positions[paddle] = 255;
+        self.grounded[paddle] = pressed;
+    }
+
+    // Swaps the paddles in the left controller port (0 and 1) with the ones in the right (2 and 3)
+    pub fn swap_ports(&mut self) {
+        self.positions.rotate_left(2);
+        self.grounded.rotate_left(2);
+    }
+
+    pub fn dumped(&self) -> bool { self.dumped }
+
+    pub fn set_dumped(&mut self, dumped: bool, clock: u64) {
+        if self.dumped && !dumped {
+            self.released_at = clock;
+        }
+
+        self.dumped = dumped;
+    }
+
+    fn charge_clocks(&self, paddle: usize) -> u64 {
+        let resistance = 255 - self.positions[paddle] as u64;
+        resistance * MAX_CHARGE_SCANLINES * CLOCKS_PER_SCANLINE / 255
+    }
+
+    pub fn read(&self, paddle: usize, clock: u64) -> u8 {
+        if self.dumped || self.grounded[paddle] {
+            return 0x00;
+        }
+
+        if clock - self.released_at >= self.charge_clocks(paddle) {
+            0x80
+        } else {
+            0x00
+        }
+    }
+}
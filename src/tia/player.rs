@@ -1,9 +1,13 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::io::{self, Read, Write};
 
+use crate::bus::read_bytes;
 use crate::tia::PlayerType;
+use crate::tia::{load_option, save_option};
 use crate::tia::color::Colors;
 use crate::tia::counter::Counter;
+use crate::tia::object_state::ObjectState;
 
 // Player sprites start 1 tick later than other sprites
 const INIT_DELAY: isize = 5;
@@ -11,6 +15,16 @@ const INIT_DELAY: isize = 5;
 // How many bits to a graphic
 const GRAPHIC_SIZE: isize= 8;
 
+// Whether a copy of a player or its missile starts being drawn at the given count of its position
+// counter, for the number of copies from D0-D2 of NUSIZx. Both objects space their copies the same
+// way, 16, 32, or 64 pixels after the main copy.
+pub fn draws_copy(copies: u8, count: u8) -> bool {
+       (count == 3  && (copies == 0b001 || copies == 0b011))
+    || (count == 7  && (copies == 0b010 || copies == 0b011 || copies == 0b110))
+    || (count == 15 && (copies == 0b100 || copies == 0b110))
+}
+
+#[derive(Clone)]
 pub struct Player {
     colors: Rc<RefCell<Colors>>,
     player: PlayerType,
@@ -20,8 +34,8 @@ pub struct Player {
 
     // The REFPx register, for rendering backwards
     horizontal_mirror: bool,
-    // The NUSIZx register
-    nusiz: u8,
+    // The number and size of copies, from D0-D2 of the NUSIZx register
+    copies: u8,
     // The 8-bit graphic to draw
     graphic: u8,
 
@@ -33,6 +47,12 @@ pub struct Player {
     graphic_bit_idx: Option<isize>,
     graphic_bit_copies_written: usize,
     graphic_bit_value: Option<bool>,
+
+    // The player stretch being used by the graphics scan counter. A change to NUSIZx while a copy
+    // is being drawn is latched, and only takes effect when the scan counter moves on to the next
+    // pixel of the graphic.
+    scan_size: usize,
+    pending_scan_size: Option<usize>,
 }
 
 impl Player {
@@ -45,7 +65,7 @@ impl Player {
             ctr: Counter::new(40, 39),
 
             horizontal_mirror: false,
-            nusiz: 0,
+            copies: 0,
             graphic: 0,
 
             vdel: false,
@@ -54,14 +74,50 @@ impl Player {
             graphic_bit_idx: None,
             graphic_bit_copies_written: 0,
             graphic_bit_value: None,
+
+            scan_size: 1,
+            pending_scan_size: None,
         }
     }
 
+    // The registers, then the position counter, then the graphics scan counter. The colours are
+    // saved by the TIA.
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.hmove_offset, self.horizontal_mirror as u8, self.copies, self.graphic])?;
+        output.write_all(&[self.vdel as u8, self.old_value])?;
+        self.ctr.save(output)?;
+
+        save_option(output, self.graphic_bit_idx.map(|i| i as i64))?;
+        output.write_all(&(self.graphic_bit_copies_written as u64).to_le_bytes())?;
+        save_option(output, self.graphic_bit_value.map(|v| v as i64))?;
+        output.write_all(&(self.scan_size as u64).to_le_bytes())?;
+        save_option(output, self.pending_scan_size.map(|s| s as i64))
+    }
+
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [hmove_offset, horizontal_mirror, copies, graphic, vdel, old_value] = read_bytes(input)?;
+        self.hmove_offset = hmove_offset;
+        self.horizontal_mirror = horizontal_mirror != 0;
+        self.copies = copies;
+        self.graphic = graphic;
+        self.vdel = vdel != 0;
+        self.old_value = old_value;
+        self.ctr.load(input)?;
+
+        self.graphic_bit_idx = load_option(input)?.map(|i| i as isize);
+        self.graphic_bit_copies_written = u64::from_le_bytes(read_bytes(input)?) as usize;
+        self.graphic_bit_value = load_option(input)?.map(|v| v != 0);
+        self.scan_size = u64::from_le_bytes(read_bytes(input)?) as usize;
+        self.pending_scan_size = load_option(input)?.map(|s| s as usize);
+
+        Ok(())
+    }
+
     pub fn size(&self) -> usize {
-        match self.nusiz & 0x0f {
-            0b0101 => 2,
-            0b0111 => 4,
-            _      => 1,
+        match self.copies {
+            0b101 => 2,
+            0b111 => 4,
+            _     => 1,
         }
     }
 
@@ -69,24 +125,31 @@ impl Player {
     pub fn set_hmove_value(&mut self, v: u8) { self.hmove_offset = v }
     pub fn set_graphic(&mut self, graphic: u8) { self.graphic = graphic }
     pub fn set_horizontal_mirror(&mut self, reflect: bool) { self.horizontal_mirror = reflect }
-    pub fn set_nusiz(&mut self, v: u8) { self.nusiz = v & 0x0f }
+    pub fn set_nusiz(&mut self, v: u8) {
+        self.copies = v & 0x07;
+
+        if self.drawing() {
+            self.pending_scan_size = Some(self.size());
+        } else {
+            self.scan_size = self.size();
+            self.pending_scan_size = None;
+        }
+    }
     pub fn set_vdel(&mut self, v: bool) { self.vdel = v }
     pub fn set_vdel_value(&mut self) { self.old_value = self.graphic }
     pub fn hmclr(&mut self) { self.hmove_offset = 0 }
-    pub fn reset(&mut self) {
-        self.ctr.reset();
-
-        if self.should_draw_graphic() || self.should_draw_copy() {
-            self.graphic_bit_idx = Some(-1 * INIT_DELAY);
-            self.graphic_bit_copies_written = 0;
-        }
+    pub fn reset(&mut self, offset: u8) {
+        // Unlike the ball, resetting the position doesn't send a START signal, so the main copy
+        // isn't drawn until the counter wraps around on the next scanline.
+        self.ctr.reset_object(offset);
     }
 
     pub fn start_hmove(&mut self) {
-        self.ctr.start_hmove(self.hmove_offset);
-        self.tick_graphic_circuit();
+        self.ctr.start_hmove();
     }
 
+    pub fn moving(&self) -> bool { self.ctr.moving() }
+
     // Based on current state, return whether or not we are rendering this sprite
     fn pixel_bit(&self) -> bool {
         if let Some(x) = self.graphic_bit_idx {
@@ -106,15 +169,34 @@ impl Player {
         }
     }
 
+    fn drawing(&self) -> bool {
+        match self.graphic_bit_idx {
+            Some(idx) => idx >= 0,
+            None      => false,
+        }
+    }
+
+    fn latch_scan_size(&mut self) {
+        if let Some(size) = self.pending_scan_size.take() {
+            self.scan_size = size;
+        }
+    }
+
     fn tick_graphic_circuit(&mut self) {
+        if !self.drawing() {
+            self.latch_scan_size();
+        }
+
         if let Some(mut idx) = self.graphic_bit_idx {
             if idx >= 0 && idx < 8 {
                 self.graphic_bit_value = Some(self.pixel_bit());
 
                 self.graphic_bit_copies_written += 1;
-                if self.graphic_bit_copies_written == self.size() {
+                if self.graphic_bit_copies_written >= self.scan_size {
                     self.graphic_bit_copies_written = 0;
                     idx += 1;
+
+                    self.latch_scan_size();
                 }
 
                 if idx == GRAPHIC_SIZE {
@@ -135,14 +217,14 @@ impl Player {
     }
 
     fn should_draw_copy(&self) -> bool {
-        let count = self.ctr.value();
-
-           (count == 3  && (self.nusiz == 0b001 || self.nusiz == 0b011))
-        || (count == 7  && (self.nusiz == 0b010 || self.nusiz == 0b011 || self.nusiz == 0b110))
-        || (count == 15 && (self.nusiz == 0b100 || self.nusiz == 0b110))
+        draws_copy(self.copies, self.ctr.value())
     }
 
     pub fn clock(&mut self) {
+        if self.ctr.take_plugged() {
+            return;
+        }
+
         self.tick_graphic_circuit();
 
         if self.ctr.clock() && (self.should_draw_graphic() || self.should_draw_copy()) {
@@ -151,8 +233,8 @@ impl Player {
         }
     }
 
-    pub fn apply_hmove(&mut self) {
-        let (moved, counter_clocked) = self.ctr.apply_hmove(self.hmove_offset);
+    pub fn movement_tick(&mut self, movement_clock: u8, hblank: bool) {
+        let (moved, counter_clocked) = self.ctr.movement_tick(movement_clock, self.hmove_offset, hblank);
 
         if counter_clocked && (self.should_draw_graphic() || self.should_draw_copy()) {
             self.graphic_bit_idx = Some(-1 * INIT_DELAY);
@@ -177,18 +259,36 @@ impl Player {
         return None;
     }
 
+    pub fn state(&self) -> ObjectState {
+        ObjectState {
+            position: self.ctr.internal_value,
+            hmove: ObjectState::hmove(self.hmove_offset),
+            moving: self.moving(),
+
+            graphic: if self.vdel { self.old_value } else { self.graphic },
+            vdel: self.vdel,
+            reflected: self.horizontal_mirror,
+            latches: [self.graphic, self.old_value],
+
+            size: self.scan_size,
+            copies: self.copies,
+
+            scan: self.graphic_bit_idx,
+        }
+    }
+
     pub fn debug(&self) {
         if !self.should_draw_graphic() && !self.should_draw_copy() {
             return
         }
 
-        println!("p: {:?}, ctr: {}, grp: {:08b}, gv: {:?}, refp: {}, nusiz: {:03b}, vdel: {}, old_value: {:08b}",
+        println!("p: {:?}, ctr: {}, grp: {:08b}, gv: {:?}, refp: {}, copies: {:03b}, vdel: {}, old_value: {:08b}",
                  self.player,
                  self.ctr.value(),
                  self.graphic,
                  self.graphic_bit_value,
                  self.horizontal_mirror,
-                 self.nusiz,
+                 self.copies,
                  self.vdel,
                  self.old_value,
         );
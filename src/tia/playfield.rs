@@ -1,9 +1,27 @@
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::io::{self, Read, Write};
 
+use crate::bus::read_bytes;
+use crate::tia::{load_option, save_option};
 use crate::tia::color::Colors;
 use crate::tia::counter::Counter;
+use crate::tia::object_state::PlayfieldState;
 
+// Returns which register (0 for PF0, 1 for PF1, or 2 for PF2), and which bit of that register,
+// makes up bit `idx` of the 20-bit playfield
+pub fn register_bit(idx: usize) -> (usize, usize) {
+    match idx {
+        // PF0 is the first 4 bits, in big-endian order
+        0  ..= 3  => (0, idx + 4),
+        // PF1 is the next 8 bits, in little-endian order
+        4  ..= 11 => (1, 11 - idx),
+        // PF2 is the last 8 bits, in big-endian order
+        _         => (2, idx - 12),
+    }
+}
+
+#[derive(Clone)]
 pub struct Playfield {
     colors: Rc<RefCell<Colors>>,
     ctr: Counter,
@@ -14,12 +32,19 @@ pub struct Playfield {
     pf0: u8,
     pf1: u8,
     pf2: u8,
-    pf: [bool; 20],
+
+    // The playfield bit being drawn. Each bit is 4 pixels wide, and is only sampled from the
+    // registers at the start of those 4 pixels, so a write to PFx or CTRLPF part way through
+    // doesn't affect the rest of the bit.
+    bit: bool,
 
     horizontal_mirror: bool,
     score_mode: bool,
     priority: bool,
 
+    // Whether the last pixel was on the left half of the screen
+    left_half: bool,
+
     graphic_bit_value: Option<u8>,
 }
 
@@ -32,42 +57,57 @@ impl Playfield {
             pf0: 0,
             pf1: 0,
             pf2: 0,
-            pf: [false; 20],
+            bit: false,
 
             horizontal_mirror: false,
             score_mode: false,
             priority: false,
 
+            left_half: true,
+
             graphic_bit_value: None,
         }
     }
 
-    pub fn set_pf0(&mut self, val: u8) {
-        self.pf0 = val;
-
-        // PF0 is the first 4 bits, in big-endian order
-        for x in 0 .. 4 {
-            self.pf[x] = (self.pf0 >> (x + 4)) & 0x01 != 0;
-        }
+    // The registers and the bit being drawn, then the counter
+    pub fn save(&self, output: &mut dyn Write) -> io::Result<()> {
+        output.write_all(&[self.pf0, self.pf1, self.pf2, self.bit as u8])?;
+        output.write_all(&[self.horizontal_mirror as u8, self.score_mode as u8, self.priority as u8, self.left_half as u8])?;
+        self.ctr.save(output)?;
 
+        save_option(output, self.graphic_bit_value.map(|v| v as i64))
     }
 
-    pub fn set_pf1(&mut self, val: u8) {
-        self.pf1 = val;
+    pub fn load(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let [pf0, pf1, pf2, bit, horizontal_mirror, score_mode, priority, left_half] = read_bytes(input)?;
+        self.pf0 = pf0;
+        self.pf1 = pf1;
+        self.pf2 = pf2;
+        self.bit = bit != 0;
+        self.horizontal_mirror = horizontal_mirror != 0;
+        self.score_mode = score_mode != 0;
+        self.priority = priority != 0;
+        self.left_half = left_half != 0;
+        self.ctr.load(input)?;
+
+        self.graphic_bit_value = load_option(input)?.map(|v| v as u8);
+
+        Ok(())
+    }
 
-        // PF1 is the next 8 bits, in little-endian order
-        for x in 0 .. 8 {
-            self.pf[x + 4] = (self.pf1 >> (7 - x)) & 0x01 != 0;
-        }
+    pub fn set_pf0(&mut self, val: u8) { self.pf0 = val }
+    pub fn set_pf1(&mut self, val: u8) { self.pf1 = val }
+    pub fn set_pf2(&mut self, val: u8) { self.pf2 = val }
 
+    // Returns bit `idx` of the 20-bit playfield
+    fn pf_bit(&self, idx: usize) -> bool {
+        self.state().bit(idx)
     }
 
-    pub fn set_pf2(&mut self, val: u8) {
-        self.pf2 = val;
-
-        // PF2 is the last 8 bits, in big-endian order
-        for x in 0 .. 8 {
-            self.pf[x + 12] = (self.pf2 >> x) & 0x01 != 0;
+    pub fn state(&self) -> PlayfieldState {
+        PlayfieldState {
+            registers: [self.pf0, self.pf1, self.pf2],
+            reflected: self.horizontal_mirror,
         }
     }
 
@@ -78,39 +118,36 @@ impl Playfield {
     }
 
     fn tick_graphic_circuit(&mut self) {
-        let ctr = self.ctr.value() as usize;
-        let pf_x = ctr % 20;
-
-        if ctr < 20 {
-            if self.pf[pf_x] {
-                if self.score_mode {
-                    self.graphic_bit_value = Some(self.colors.borrow().colup0())
-                } else {
-                    self.graphic_bit_value = Some(self.colors.borrow().colupf())
-                };
-            } else {
-                self.graphic_bit_value = None;
-            }
-        } else {
-            // The playfield also makes up the right-most side of the
-            // screen, optionally mirrored horizontally as denoted by the
-            // CTRLPF register.
-            let idx = if self.horizontal_mirror {
-                self.pf.len() - 1 - pf_x
+        if self.ctr.internal_value.is_multiple_of(4) {
+            let ctr = self.ctr.value() as usize;
+            let pf_x = ctr % 20;
+
+            self.left_half = ctr < 20;
+
+            // The playfield also makes up the right-most side of the screen, optionally mirrored
+            // horizontally as denoted by the CTRLPF register.
+            let idx = if !self.left_half && self.horizontal_mirror {
+                19 - pf_x
             } else {
                 pf_x
             };
 
-            if self.pf[idx] {
-                if self.score_mode {
-                    self.graphic_bit_value = Some(self.colors.borrow().colup1())
-                } else {
-                    self.graphic_bit_value = Some(self.colors.borrow().colupf())
-                };
-            } else {
-                self.graphic_bit_value = None;
-            }
+            self.bit = self.pf_bit(idx);
         }
+
+        self.graphic_bit_value = if self.bit {
+            // In score mode, the left half of the playfield takes the color of player 0, and the
+            // right half takes the color of player 1.
+            let colors = self.colors.borrow();
+
+            match (self.score_mode, self.left_half) {
+                (true, true)  => Some(colors.colup0()),
+                (true, false) => Some(colors.colup1()),
+                (false, _)    => Some(colors.colupf()),
+            }
+        } else {
+            None
+        };
     }
 
     pub fn clock(&mut self) {
@@ -119,6 +156,8 @@ impl Playfield {
     }
 
     pub fn priority(&self) -> bool { self.priority }
+    pub fn score_mode(&self) -> bool { self.score_mode }
+    pub fn left_half(&self) -> bool { self.left_half }
 
     pub fn get_color(&self) -> Option<u8> {
         self.graphic_bit_value
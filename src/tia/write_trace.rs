@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Records writes to the TIA registers, along with where the beam was when they happened, for
+// debugging display kernels.
+//
+// The format is plain text, one write per line:
+//
+//     ; atari2600 tia write trace v1
+//     <frame> <scanline> <colour clock> <register> <value>
+//
+// Where <frame> counts the frames since power on (a frame starting when VSYNC is turned on),
+// <scanline> is the scanline since the start of the frame, <colour clock> is the colour clock
+// within the scanline (0-227, with the visible picture starting at 68), <register> is the name of
+// the register written to, and <value> is the byte written, in hex. Lines starting with a
+// semicolon are comments.
+
+const REGISTERS: [&str; 0x2d] = [
+    "VSYNC",  "VBLANK", "WSYNC",  "RSYNC",  "NUSIZ0", "NUSIZ1", "COLUP0", "COLUP1",
+    "COLUPF", "COLUBK", "CTRLPF", "REFP0",  "REFP1",  "PF0",    "PF1",    "PF2",
+    "RESP0",  "RESP1",  "RESM0",  "RESM1",  "RESBL",  "AUDC0",  "AUDC1",  "AUDF0",
+    "AUDF1",  "AUDV0",  "AUDV1",  "GRP0",   "GRP1",   "ENAM0",  "ENAM1",  "ENABL",
+    "HMP0",   "HMP1",   "HMM0",   "HMM1",   "HMBL",   "VDELP0", "VDELP1", "VDELBL",
+    "RESMP0", "RESMP1", "HMOVE",  "HMCLR",  "CXCLR",
+];
+
+fn register_name(address: u16) -> Option<&'static str> {
+    REGISTERS.get(address as usize).copied()
+}
+
+pub fn register_address(name: &str) -> Option<u16> {
+    REGISTERS.iter().position(|r| r.eq_ignore_ascii_case(name)).map(|a| a as u16)
+}
+
+pub struct WriteTrace {
+    out: BufWriter<File>,
+
+    // Which registers are traced, indexed by address
+    registers: [bool; 0x2d],
+}
+
+impl WriteTrace {
+    // Traces writes to the given registers, or all of them if none are given
+    pub fn new(file: File, registers: &[String]) -> io::Result<Self> {
+        let mut traced = [registers.is_empty(); 0x2d];
+
+        for name in registers {
+            match register_address(name) {
+                Some(address) => traced[address as usize] = true,
+                None          => {
+                    let msg = format!("unknown TIA register: {}", name);
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+                },
+            }
+        }
+
+        let mut out = BufWriter::new(file);
+
+        writeln!(out, "; atari2600 tia write trace v1")?;
+
+        Ok(Self {
+            out: out,
+            registers: traced,
+        })
+    }
+
+    pub fn record(&mut self, frame: u64, scanline: u16, clock: u8, address: u16, val: u8) {
+        let register = match register_name(address) {
+            Some(name) if self.registers[address as usize] => name,
+            _ => return,
+        };
+
+        if let Err(e) = writeln!(self.out, "{} {} {} {} {:02x}", frame, scanline, clock, register, val) {
+            error!("unable to write tia write trace: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_names() {
+        assert_eq!(register_name(0x00), Some("VSYNC"));
+        assert_eq!(register_name(0x1b), Some("GRP0"));
+        assert_eq!(register_name(0x2c), Some("CXCLR"));
+        assert_eq!(register_name(0x2d), None);
+
+        assert_eq!(register_address("colubk"), Some(0x09));
+        assert_eq!(register_address("HMOVE"), Some(0x2a));
+        assert_eq!(register_address("INPT4"), None);
+    }
+}
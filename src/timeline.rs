@@ -0,0 +1,177 @@
+const CYCLES_PER_SCANLINE: usize = 76;
+
+// An instruction the CPU executed, the CPU cycle of the frame it started on, counting from the
+// start of VSYNC, and how many cycles it took, not counting any it spent halted by WSYNC
+struct Executed {
+    pc: u16,
+    start: usize,
+    cycles: usize,
+}
+
+impl Executed {
+    fn end(&self) -> usize { self.start + self.cycles }
+}
+
+// Records which instructions the CPU executes on which cycles of which scanlines of the frame, to
+// show how a kernel's instructions line up with the beam, "racing the beam". Each scanline is shown
+// as a bar of its 76 CPU cycles, with the first letter of each instruction on the cycle it started
+// on, dashes for the rest of its cycles, and dots for the cycles the CPU was halted by WSYNC. A
+// scanline that ends part way through an instruction is marked with a >, which is where a kernel
+// that's blown its 76 cycle budget writes to WSYNC too late, and loses the next scanline.
+pub struct Timeline {
+    instructions: Vec<Executed>,
+
+    // The address of the instruction being executed, and the CPU's cycle count when it started, for
+    // working out how many cycles it takes. It's None until the first instruction's finished.
+    current: Option<(u16, u64)>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            instructions: vec![],
+            current: None,
+        }
+    }
+
+    // Called as each instruction finishes, with the address of the next one, the CPU's cycle count,
+    // and the cycle of the frame after the one it finished on
+    pub fn record(&mut self, pc: u16, cycles: u64, end: usize) {
+        if let Some((start_pc, start_cycles)) = self.current {
+            let length = (cycles - start_cycles) as usize;
+            self.instructions.push(Executed {
+                pc: start_pc,
+                start: end.saturating_sub(length),
+                cycles: length,
+            });
+        }
+
+        self.current = Some((pc, cycles));
+    }
+
+    pub fn len(&self) -> usize { self.instructions.len() }
+    pub fn is_empty(&self) -> bool { self.instructions.is_empty() }
+
+    // Goes back to after the first `count` instructions of the frame, when the debugger steps
+    // backwards, with the CPU at `pc` after `cycles` cycles
+    pub fn rewind(&mut self, count: usize, pc: u16, cycles: u64) {
+        self.instructions.truncate(count);
+        self.current = Some((pc, cycles));
+    }
+
+    // Starts on the next frame, carrying on with the instruction that's being executed
+    pub fn end_frame(&mut self) {
+        self.instructions.clear();
+    }
+
+    // The instructions that executed on at least one cycle of a scanline
+    fn on_scanline(&self, scanline: usize) -> impl Iterator<Item = &Executed> {
+        let (start, end) = (scanline * CYCLES_PER_SCANLINE, (scanline + 1) * CYCLES_PER_SCANLINE);
+        self.instructions.iter().filter(move |i| i.start < end && i.end() > start)
+    }
+
+    // A scanline's bar, and how many of its cycles the CPU was executing instructions on
+    fn bar(&self, scanline: usize, describe: &dyn Fn(u16) -> String) -> (String, usize) {
+        let first = scanline * CYCLES_PER_SCANLINE;
+
+        // Cycles outside of the instructions that have been recorded are left blank, and the ones
+        // between them are where the CPU was halted
+        let recorded = match (self.instructions.first(), self.instructions.last()) {
+            (Some(first), Some(last)) => first.start .. last.end(),
+            _                         => 0 .. 0,
+        };
+        let mut bar = (first .. first + CYCLES_PER_SCANLINE)
+            .map(|cycle| if recorded.contains(&cycle) { '.' } else { ' ' })
+            .collect::<Vec<_>>();
+
+        let mut busy = 0;
+        let mut overrun = false;
+
+        for instruction in self.on_scanline(scanline) {
+            let letter = describe(instruction.pc).chars().next().unwrap_or('?');
+
+            for cycle in instruction.start.max(first) .. instruction.end().min(first + CYCLES_PER_SCANLINE) {
+                bar[cycle - first] = if cycle == instruction.start { letter } else { '-' };
+                busy += 1;
+            }
+
+            overrun |= instruction.end() > first + CYCLES_PER_SCANLINE;
+        }
+
+        let bar = format!("|{}{}", bar.into_iter().collect::<String>(), if overrun { '>' } else { '|' });
+        (bar, busy)
+    }
+
+    // A bar for each scanline of the frame that's been recorded, with `describe` giving the
+    // instruction at an address, e.g. "STA WSYNC"
+    pub fn scanlines(&self, describe: impl Fn(u16) -> String) -> Vec<String> {
+        let (first, last) = match (self.instructions.first(), self.instructions.last()) {
+            (Some(first), Some(last)) => (first.start, last.end() - 1),
+            _                         => return vec![],
+        };
+
+        (first / CYCLES_PER_SCANLINE ..= last / CYCLES_PER_SCANLINE)
+            .map(|scanline| {
+                let (bar, busy) = self.bar(scanline, &describe);
+                format!("{:3} {} {:2}", scanline, bar, busy)
+            })
+            .collect()
+    }
+
+    // A scanline's bar, followed by each of the instructions executed on it, with the cycle of the
+    // scanline it started on, which is negative when it started on the scanline before
+    pub fn scanline(&self, scanline: usize, describe: impl Fn(u16) -> String) -> Vec<String> {
+        let (bar, busy) = self.bar(scanline, &describe);
+        let first = (scanline * CYCLES_PER_SCANLINE) as isize;
+
+        let mut lines = vec![format!("{:3} {} {:2}", scanline, bar, busy)];
+        lines.extend(self.on_scanline(scanline).map(|instruction| {
+            format!("cycle {:3}  {:04X}  {}", instruction.start as isize - first, instruction.pc, describe(instruction.pc))
+        }));
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(pc: u16) -> String {
+        match pc {
+            0xf000 => "LDA #$00".to_string(),
+            _      => "STA WSYNC".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut timeline = Timeline::new();
+
+        // An LDA at $F000 starting on cycle 72 of scanline 1, a STA WSYNC at $F002 that carries on
+        // into scanline 2, where the CPU's halted until the STA WSYNC at $F004 at the end of it
+        timeline.record(0xf000, 100, 76 + 72);
+        timeline.record(0xf002, 102, 76 + 74);
+        timeline.record(0xf004, 105, 76 + 77);
+        timeline.record(0xf000, 108, 2 * 76 + 76);
+
+        assert_eq!(timeline.len(), 3);
+
+        let lines = timeline.scanlines(describe);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("  1 |{}L-S->  4", " ".repeat(72)));
+        assert_eq!(lines[1], format!("  2 |-{}S--|  4", ".".repeat(72)));
+
+        let lines = timeline.scanline(2, describe);
+        assert_eq!(lines[1], "cycle  -2  F002  STA WSYNC");
+        assert_eq!(lines[2], "cycle  73  F004  STA WSYNC");
+
+        // Going back drops the instructions after it
+        timeline.rewind(1, 0xf002, 102);
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline.scanlines(describe), vec![format!("  1 |{}L-  |  2", " ".repeat(72))]);
+
+        timeline.end_frame();
+        assert!(timeline.is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{self, Stdout};
+use std::mem;
+
+use ratatui::Frame;
+use ratatui::Terminal;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{self as crossterm_terminal, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::widgets::{Block, Paragraph};
+
+// What's shown in each of the panes
+pub struct Panes<'a> {
+    pub disassembly: Vec<String>,
+    pub registers: Vec<String>,
+    pub ram: Vec<String>,
+    pub tia: String,
+    pub log: &'a [String],
+}
+
+// A full screen view of the machine for the debugger, in place of printing lines out, with panes
+// for the disassembly, the registers, the RAM, the TIA's state, and the commands typed in so far
+// along with what they printed, above a command line. It takes over the terminal while it's up,
+// and gives it back as the game carries on.
+pub struct Tui {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    input: String,
+}
+
+impl Tui {
+    pub fn start() -> io::Result<Self> {
+        crossterm_terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+
+        Ok(Self {
+            terminal: Terminal::new(CrosstermBackend::new(io::stdout()))?,
+            input: String::new(),
+        })
+    }
+
+    // Waits for a line to be typed in at the command line, with the panes drawn around it. Ctrl-C
+    // and Ctrl-D are the end of the input, which is None.
+    pub fn read_line(&mut self, panes: &Panes) -> io::Result<Option<String>> {
+        loop {
+            let input = &self.input;
+            self.terminal.draw(|frame| draw(frame, panes, input))?;
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+
+            match key.code {
+                KeyCode::Char('c' | 'd') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+                KeyCode::Char(c) => self.input.push(c),
+                KeyCode::Backspace => { self.input.pop(); },
+                KeyCode::Enter => return Ok(Some(mem::take(&mut self.input))),
+                _ => { },
+            }
+        }
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        execute!(io::stdout(), LeaveAlternateScreen).ok();
+        crossterm_terminal::disable_raw_mode().ok();
+    }
+}
+
+// The last lines of some text, as many as fit in an area inside its border
+fn tail(lines: &[String], area: Rect) -> String {
+    let height = area.height.saturating_sub(2) as usize;
+    lines[lines.len().saturating_sub(height) ..].join("\n")
+}
+
+fn draw(frame: &mut Frame, panes: &Panes, input: &str) {
+    let [top, middle, bottom] = Layout::vertical([
+        Constraint::Length(14),
+        Constraint::Min(0),
+        Constraint::Length(3),
+    ]).areas(frame.area());
+
+    let [disassembly, machine] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+    let [registers, ram] = Layout::vertical([Constraint::Length(4), Constraint::Min(0)]).areas(machine);
+    let [tia, log] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(middle);
+
+    frame.render_widget(Paragraph::new(panes.disassembly.join("\n")).block(Block::bordered().title("Disassembly")), disassembly);
+    frame.render_widget(Paragraph::new(panes.registers.join("\n")).block(Block::bordered().title("Registers")), registers);
+    frame.render_widget(Paragraph::new(panes.ram.join("\n")).block(Block::bordered().title("RAM")), ram);
+    frame.render_widget(Paragraph::new(panes.tia.as_str()).block(Block::bordered().title("TIA")), tia);
+    frame.render_widget(Paragraph::new(tail(panes.log, log)).block(Block::bordered().title("Log")), log);
+
+    frame.render_widget(Paragraph::new(format!("> {}", input)).block(Block::bordered().title("Command")), bottom);
+    frame.set_cursor_position((bottom.x + 3 + input.chars().count() as u16, bottom.y + 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_draw() {
+        let log = vec!["> mem 80".to_string(), "0080  01 02".to_string()];
+        let panes = Panes {
+            disassembly: vec!["=>  F000  E8        INX".to_string()],
+            registers: vec!["PC:F000 A:00".to_string()],
+            ram: vec!["0080  01 02".to_string()],
+            tia: "Beam: frame 0, scanline 0".to_string(),
+            log: &log,
+        };
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &panes, "step")).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let rows = (0 .. 30)
+            .map(|y| (0 .. 100).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect::<Vec<_>>();
+
+        assert!(rows[1].contains("=>  F000  E8        INX"));
+        assert!(rows[1].contains("PC:F000 A:00"));
+        assert!(rows[5].contains("0080  01 02"));
+        assert!(rows[15].contains("Beam: frame 0, scanline 0"));
+        assert!(rows[28].contains("> step"));
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::bus::Bus;
+use crate::cpu6507::CPU6507;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Register {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+}
+
+// Something a watch expression's made of: one of the CPU's registers, the byte at an address, or
+// a number
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Register(Register),
+    Memory(u16),
+    Number(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Add,
+    Subtract,
+    And,
+    Or,
+    Xor,
+}
+
+// An expression to watch the value of, e.g. `$D3` or `a+x`. The operators are worked out left to
+// right, with 16 bits of arithmetic that wraps around.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    text: String,
+    first: Operand,
+    rest: Vec<(Operator, Operand)>,
+}
+
+impl Expression {
+    // The registers are a, x, y, p, sp, and pc, numbers start with a # (e.g. #$10 or #16), and
+    // anything else is an address, as it's parsed by `parse_address`
+    pub fn parse(s: &str, parse_address: impl Fn(&str) -> Result<u16, String>) -> Result<Self, String> {
+        let text = s.split_whitespace().collect::<String>();
+
+        let operand = |s: &str| -> Result<Operand, String> {
+            let register = match s.to_lowercase().as_str() {
+                "a"  => Some(Register::A),
+                "x"  => Some(Register::X),
+                "y"  => Some(Register::Y),
+                "p"  => Some(Register::P),
+                "sp" => Some(Register::SP),
+                "pc" => Some(Register::PC),
+                _    => None,
+            };
+
+            if let Some(register) = register {
+                return Ok(Operand::Register(register));
+            }
+
+            match s.strip_prefix('#') {
+                Some(number) => {
+                    let parsed = match number.strip_prefix('$') {
+                        Some(hex) => u16::from_str_radix(hex, 16),
+                        None      => number.parse::<u16>(),
+                    };
+                    parsed.map(Operand::Number).map_err(|_| format!("invalid number: {}", s))
+                },
+                None => parse_address(s).map(Operand::Memory),
+            }
+        };
+
+        let mut rest = vec![];
+        let mut remaining = text.as_str();
+        let end = |s: &str| s.find(|c| "+-&|^".contains(c)).unwrap_or(s.len());
+
+        let first = operand(&remaining[.. end(remaining)])?;
+        remaining = &remaining[end(remaining) ..];
+
+        while let Some(c) = remaining.chars().next() {
+            let operator = match c {
+                '+' => Operator::Add,
+                '-' => Operator::Subtract,
+                '&' => Operator::And,
+                '|' => Operator::Or,
+                _   => Operator::Xor,
+            };
+
+            remaining = &remaining[1 ..];
+            rest.push((operator, operand(&remaining[.. end(remaining)])?));
+            remaining = &remaining[end(remaining) ..];
+        }
+
+        Ok(Self {
+            text: text,
+            first: first,
+            rest: rest,
+        })
+    }
+
+    fn operands(&self) -> impl Iterator<Item = &Operand> {
+        Some(&self.first).into_iter().chain(self.rest.iter().map(|(_, operand)| operand))
+    }
+
+    // The addresses the expression reads
+    fn addresses(&self) -> impl Iterator<Item = u16> + '_ {
+        self.operands().filter_map(|operand| match *operand {
+            Operand::Memory(address) => Some(address),
+            _                        => None,
+        })
+    }
+
+    // Memory's read without side effects, so watching it doesn't change how the game runs
+    fn evaluate<B: Bus>(&self, cpu: &CPU6507<B>) -> u16 {
+        let value = |operand: &Operand| match *operand {
+            Operand::Register(Register::A)  => cpu.a as u16,
+            Operand::Register(Register::X)  => cpu.x as u16,
+            Operand::Register(Register::Y)  => cpu.y as u16,
+            Operand::Register(Register::P)  => cpu.flags() as u16,
+            Operand::Register(Register::SP) => cpu.sp() as u16,
+            Operand::Register(Register::PC) => cpu.pc,
+            Operand::Memory(address)        => cpu.peek(address) as u16,
+            Operand::Number(n)              => n,
+        };
+
+        self.rest.iter().fold(value(&self.first), |total, (operator, operand)| {
+            let operand = value(operand);
+            match operator {
+                Operator::Add      => total.wrapping_add(operand),
+                Operator::Subtract => total.wrapping_sub(operand),
+                Operator::And      => total & operand,
+                Operator::Or       => total | operand,
+                Operator::Xor      => total ^ operand,
+            }
+        })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+struct Watch {
+    expression: Expression,
+    value: u16,
+}
+
+// The expressions being watched, with the values they had when they were last checked. The
+// addresses they read are checked after every instruction as well, to find the instruction that
+// changed each of them.
+pub struct Watches {
+    watches: Vec<Watch>,
+
+    // The value of each address that's watched after the last instruction, and the address of the
+    // instruction that last changed it, since the watches were last checked
+    memory: BTreeMap<u16, (u8, Option<u16>)>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self {
+            watches: vec![],
+            memory: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.watches.is_empty() }
+
+    pub fn add<B: Bus>(&mut self, expression: Expression, cpu: &CPU6507<B>) -> String {
+        for address in expression.addresses() {
+            self.memory.insert(address, (cpu.peek(address), None));
+        }
+
+        let value = expression.evaluate(cpu);
+        let line = format!("Watching {}: {} = ${:02X}", self.watches.len() + 1, expression, value);
+        self.watches.push(Watch { expression: expression, value: value });
+
+        line
+    }
+
+    // Stops watching the numbered expression, counting from 1
+    pub fn remove(&mut self, number: usize) -> Option<Expression> {
+        if number == 0 || number > self.watches.len() {
+            return None;
+        }
+
+        let watch = self.watches.remove(number - 1);
+        let watches = &self.watches;
+        self.memory.retain(|&address, _| watches.iter().any(|w| w.expression.addresses().any(|a| a == address)));
+
+        Some(watch.expression)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.watches.iter()
+            .enumerate()
+            .map(|(i, watch)| format!("{}: {} = ${:02X}", i + 1, watch.expression, watch.value))
+            .collect()
+    }
+
+    // Called after each instruction, with the address of the instruction that's just finished
+    pub fn instruction<B: Bus>(&mut self, pc: u16, cpu: &CPU6507<B>) {
+        for (&address, (value, changed_by)) in self.memory.iter_mut() {
+            let current = cpu.peek(address);
+            if current != *value {
+                *value = current;
+                *changed_by = Some(pc);
+            }
+        }
+    }
+
+    // A line for each expression that's changed since they were last checked, with the instructions
+    // that changed the memory it reads
+    pub fn check<B: Bus>(&mut self, cpu: &CPU6507<B>) -> Vec<String> {
+        let mut lines = vec![];
+        let memory = &self.memory;
+
+        for watch in self.watches.iter_mut() {
+            let value = watch.expression.evaluate(cpu);
+            if value == watch.value {
+                continue;
+            }
+
+            let changed_by = watch.expression.addresses()
+                .filter_map(|address| memory.get(&address).and_then(|&(_, pc)| pc))
+                .map(|pc| format!("${:04X}", pc))
+                .collect::<Vec<_>>();
+
+            let mut line = format!("{}: ${:02X} -> ${:02X}", watch.expression, watch.value, value);
+            if !changed_by.is_empty() {
+                line.push_str(&format!(", changed by {}", changed_by.join(", ")));
+            }
+
+            lines.push(line);
+            watch.value = value;
+        }
+
+        for (_, changed_by) in self.memory.values_mut() {
+            *changed_by = None;
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bus::AtariBus;
+    use crate::riot::RIOT;
+    use crate::tia::TIA;
+
+    fn parse(s: &str) -> Result<Expression, String> {
+        Expression::parse(s, |s| u16::from_str_radix(s.trim_start_matches('$'), 16).map_err(|_| format!("invalid address: {}", s)))
+    }
+
+    #[test]
+    fn test_expression() {
+        let expression = parse("a + x").unwrap();
+        assert_eq!(expression.to_string(), "a+x");
+        assert_eq!(expression.first, Operand::Register(Register::A));
+        assert_eq!(expression.rest, vec![(Operator::Add, Operand::Register(Register::X))]);
+
+        let expression = parse("$D3&#$0F").unwrap();
+        assert_eq!(expression.first, Operand::Memory(0xd3));
+        assert_eq!(expression.rest, vec![(Operator::And, Operand::Number(0x0f))]);
+
+        assert!(parse("a+").is_err());
+        assert!(parse("#zz").is_err());
+    }
+
+    #[test]
+    fn test_watches() {
+        let tia = Rc::new(RefCell::new(TIA::new()));
+        let riot = Rc::new(RefCell::new(RIOT::new()));
+
+        // LDA #$05; STA $D3; LDX #$01
+        let mut rom = vec![0; 4096];
+        rom[0x000 .. 0x006].copy_from_slice(&[0xa9, 0x05, 0x85, 0xd3, 0xa2, 0x01]);
+        rom[0xffc] = 0x00;
+        rom[0xffd] = 0xf0;
+
+        let mut cpu = CPU6507::new(AtariBus::new(tia, riot, rom));
+        cpu.reset();
+
+        let mut watches = Watches::new();
+        assert_eq!(watches.add(parse("$D3").unwrap(), &cpu), "Watching 1: $D3 = $00");
+        watches.add(parse("a+x").unwrap(), &cpu);
+
+        for _ in 0 .. 3 {
+            let pc = cpu.pc;
+            cpu.step();
+            watches.instruction(pc, &cpu);
+        }
+
+        assert_eq!(watches.check(&cpu), vec!["$D3: $00 -> $05, changed by $F002", "a+x: $00 -> $06"]);
+        assert!(watches.check(&cpu).is_empty());
+
+        assert_eq!(watches.remove(1), Some(parse("$D3").unwrap()));
+        assert_eq!(watches.list(), vec!["1: a+x = $06"]);
+        assert!(watches.memory.is_empty());
+        assert_eq!(watches.remove(2), None);
+    }
+}